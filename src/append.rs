@@ -0,0 +1,136 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::Result;
+
+/// Length of each fingerprinted region
+const SAMPLE_LEN: u64 = 64;
+
+/// Number of fingerprinted regions kept in addition to the start of the file
+const MAX_SAMPLES: usize = 8;
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    offset: u64,
+    len: u64,
+    hash: u64,
+}
+
+/// Detects in-place modification of already-read content by periodically
+/// re-reading fingerprinted regions before the read position
+#[derive(Debug)]
+pub(crate) struct AppendGuard {
+    every: u32,
+    reads: u32,
+    head: Option<Sample>,
+    samples: VecDeque<Sample>,
+}
+
+impl AppendGuard {
+    /// Creates a guard which verifies every `every` reads, fingerprinting the
+    /// existing content of `file` up to `pos`
+    pub(crate) fn new(every: u32, file: &mut File, pos: u64) -> Result<AppendGuard> {
+        let mut guard = AppendGuard {
+            every: every.max(1),
+            reads: 0,
+            head: None,
+            samples: VecDeque::with_capacity(MAX_SAMPLES),
+        };
+        guard.rebase(file, pos)?;
+
+        Ok(guard)
+    }
+
+    /// Discards all fingerprints and takes new ones from the content of `file` up to `pos`
+    pub(crate) fn rebase(&mut self, file: &mut File, pos: u64) -> Result<()> {
+        self.samples.clear();
+        self.head = sample(file, 0, pos.min(SAMPLE_LEN))?;
+        if pos > SAMPLE_LEN {
+            let offset = pos.saturating_sub(SAMPLE_LEN).max(SAMPLE_LEN);
+            if let Some(s) = sample(file, offset, pos - offset)? {
+                self.samples.push_back(s);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fingerprints the start of freshly read data
+    pub(crate) fn observe(&mut self, offset: u64, data: &[u8]) {
+        let len = (data.len() as u64).min(SAMPLE_LEN);
+        if len == 0 {
+            return;
+        }
+        let s = Sample {
+            offset,
+            len,
+            hash: hash(&data[..len as usize]),
+        };
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(s);
+    }
+
+    /// Counts a read, returning `true` when verification is due
+    pub(crate) fn due(&mut self) -> bool {
+        self.reads += 1;
+        if self.reads >= self.every {
+            self.reads = 0;
+            return true;
+        }
+
+        false
+    }
+
+    /// Re-reads every fingerprinted region, returning the offset of the first
+    /// one which no longer matches
+    pub(crate) fn verify(&mut self, file: &mut File) -> Result<Option<u64>> {
+        for s in self.head.iter().chain(self.samples.iter()) {
+            match sample(file, s.offset, s.len)? {
+                Some(now) if now.hash == s.hash => {}
+                _ => return Ok(Some(s.offset)),
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+fn hash(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(data);
+    hasher.finish()
+}
+
+fn sample(file: &mut File, offset: u64, len: u64) -> Result<Option<Sample>> {
+    if len == 0 {
+        return Ok(None);
+    }
+    let mut buf = vec![0u8; len as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    let mut read = 0;
+    while read < buf.len() {
+        match file.read(&mut buf[read..])? {
+            0 => return Ok(None),
+            n => read += n,
+        }
+    }
+
+    Ok(Some(Sample {
+        offset,
+        len,
+        hash: hash(&buf),
+    }))
+}
@@ -0,0 +1,169 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::ErrorKind;
+use std::time::Duration;
+
+use crate::StaartError;
+
+/// How long to wait between attempts at an operation that failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backoff {
+    /// Wait the same time after every attempt
+    Fixed(Duration),
+    /// Wait `initial` after the first attempt, doubling the wait after each
+    /// further attempt up to `max`
+    Exponential {
+        /// Wait after the first attempt
+        initial: Duration,
+        /// Longest wait between attempts
+        max: Duration,
+    },
+}
+
+impl Backoff {
+    /// Returns the wait after the failed attempt numbered `attempt`, starting
+    /// from zero
+    pub fn delay(&self, attempt: u32) -> Duration {
+        match *self {
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential { initial, max } => initial
+                .checked_mul(2u32.saturating_pow(attempt))
+                .map_or(max, |delay| delay.min(max)),
+        }
+    }
+}
+
+impl Default for Backoff {
+    /// Waits 100ms after the first attempt, up to 5s
+    fn default() -> Backoff {
+        Backoff::Exponential {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(5),
+        }
+    }
+}
+
+/// How reads of a [`crate::TailedFile`] are retried after transient errors:
+/// a missing file, a denied permission, or an interrupted read.
+///
+/// This lets a file being replaced non-atomically, or having its permissions
+/// reset by a rotation tool, be read without surfacing an error. Other
+/// errors, and errors remaining once `max_attempts` reads failed, are handled
+/// by the [`crate::ErrorPolicy`] as usual.
+///
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+/// use staart::{Backoff, RetryPolicy, TailedFile};
+///
+/// let f = TailedFile::builder("/var/log/syslog")
+///     .retry(
+///         RetryPolicy::new(5)
+///             .backoff(Backoff::Fixed(Duration::from_millis(50)))
+///             .jitter(0.2),
+///     )
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    backoff: Backoff,
+    jitter: f64,
+}
+
+impl RetryPolicy {
+    /// Makes up to `max_attempts` reads, including the first, with the default
+    /// [`Backoff`] and no jitter
+    pub fn new(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            backoff: Backoff::default(),
+            jitter: 0.0,
+        }
+    }
+
+    /// Sets the wait between attempts
+    pub fn backoff(mut self, backoff: Backoff) -> RetryPolicy {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Randomly shortens or lengthens each wait by up to `fraction` of it, so
+    /// that many files failing together do not retry in lockstep. The
+    /// fraction is clamped between 0 and 1.
+    pub fn jitter(mut self, fraction: f64) -> RetryPolicy {
+        self.jitter = fraction.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Returns the number of reads made before an error is returned
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Returns the wait after the failed attempt numbered `attempt`, starting
+    /// from zero, including jitter
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let delay = self.backoff.delay(attempt);
+        if self.jitter == 0.0 {
+            return delay;
+        }
+        let unit = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+        delay.mul_f64(1.0 + self.jitter * (2.0 * unit - 1.0))
+    }
+
+    /// Returns `true` if a read that failed with `e` at the attempt numbered
+    /// `attempt` should be retried
+    pub(crate) fn retries(&self, e: &StaartError, attempt: u32) -> bool {
+        let transient = matches!(
+            e,
+            StaartError::IO(e) if matches!(
+                e.kind(),
+                ErrorKind::NotFound | ErrorKind::PermissionDenied | ErrorKind::Interrupted
+            )
+        );
+        transient && attempt + 1 < self.max_attempts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exponential() {
+        let backoff = Backoff::default();
+        let delays: Vec<_> = (0..8).map(|n| backoff.delay(n).as_millis()).collect();
+        assert_eq!(delays, [100, 200, 400, 800, 1600, 3200, 5000, 5000]);
+        assert_eq!(backoff.delay(u32::MAX), Duration::from_secs(5));
+        assert_eq!(
+            Backoff::Fixed(Duration::from_secs(1)).delay(10),
+            Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let policy = RetryPolicy::new(3)
+            .backoff(Backoff::Fixed(Duration::from_millis(100)))
+            .jitter(0.5);
+        for attempt in 0..10 {
+            let delay = policy.delay(attempt).as_millis();
+            assert!((50..=150).contains(&delay));
+        }
+
+        let missing = StaartError::IO(ErrorKind::NotFound.into());
+        assert!(policy.retries(&missing, 1));
+        assert!(!policy.retries(&missing, 2));
+        assert!(!policy.retries(&StaartError::Modified(0), 0));
+    }
+}
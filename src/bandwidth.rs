@@ -0,0 +1,196 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::clock;
+
+/// How long a file keeps its share after its last write
+const IDLE: Duration = Duration::from_secs(1);
+
+/// An egress budget in bytes per second, shared by every sink holding a clone.
+///
+/// The budget is split evenly between the files written within the last
+/// second, so a busy file can not starve a quiet one. A file starts with an
+/// empty share, which fills at its part of the budget up to one second of it,
+/// so the files together never send more than the budget allows. A write
+/// blocks until its file's share covers it, and a write larger than one second
+/// of the share is let through once the share is full, leaving the file in
+/// debt.
+///
+/// Sinks charge the size of the records they are handed, before any
+/// compression.
+///
+/// # Example
+/// ```no_run
+/// use std::path::Path;
+/// use staart::Bandwidth;
+///
+/// // 64 KiB/s for everything leaving the host, passed to each network sink
+/// // or charged directly by a custom one
+/// let budget = Bandwidth::new(64 * 1024);
+/// budget.acquire(Path::new("/var/log/syslog"), 512);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Bandwidth {
+    inner: Arc<Mutex<State>>,
+}
+
+#[derive(Debug)]
+struct State {
+    rate: f64,
+    last: Instant,
+    files: HashMap<PathBuf, Share>,
+}
+
+#[derive(Debug)]
+struct Share {
+    tokens: f64,
+    seen: Instant,
+}
+
+impl Bandwidth {
+    /// Creates a budget of `bytes_per_sec`, which must not be zero
+    pub fn new(bytes_per_sec: u64) -> Bandwidth {
+        Bandwidth {
+            inner: Arc::new(Mutex::new(State {
+                rate: bytes_per_sec.max(1) as f64,
+                last: clock::now(),
+                files: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Returns the budget in bytes per second
+    pub fn rate(&self) -> u64 {
+        self.lock().rate as u64
+    }
+
+    /// Blocks until the share of `path` covers `bytes`, and charges them
+    pub fn acquire(&self, path: &Path, bytes: u64) {
+        loop {
+            let wait = self.reserve(path, bytes);
+            if wait.is_zero() {
+                return;
+            }
+            std::thread::sleep(wait);
+        }
+    }
+
+    /// Charges `bytes` to the share of `path` if it covers them, otherwise
+    /// returns how long until it will
+    fn reserve(&self, path: &Path, bytes: u64) -> Duration {
+        self.reserve_at(path, bytes, clock::now())
+    }
+
+    fn reserve_at(&self, path: &Path, bytes: u64, now: Instant) -> Duration {
+        let mut state = self.lock();
+        let elapsed = now.saturating_duration_since(state.last).as_secs_f64();
+        state.last = now;
+
+        state
+            .files
+            .retain(|p, share| p == path || now.saturating_duration_since(share.seen) < IDLE);
+        let active = state.files.len() + usize::from(!state.files.contains_key(path));
+        let per = state.rate / active as f64;
+        for share in state.files.values_mut() {
+            share.tokens = (share.tokens + elapsed * per).min(per);
+        }
+        // A new file starts with an empty share, so that it can not add to what
+        // the others already hold
+        let share = state.files.entry(path.to_path_buf()).or_insert(Share {
+            tokens: 0.0,
+            seen: now,
+        });
+        share.seen = now;
+
+        let needed = (bytes as f64).min(per);
+        if share.tokens >= needed {
+            share.tokens -= bytes as f64;
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((needed - share.tokens) / per).max(Duration::from_nanos(1))
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn between(wait: Duration, low: u64, high: u64) -> bool {
+        wait > Duration::from_millis(low) && wait <= Duration::from_millis(high)
+    }
+
+    #[test]
+    fn test_fair_share() {
+        let budget = Bandwidth::new(1000);
+        let (a, b) = (Path::new("a.log"), Path::new("b.log"));
+        let t = budget.lock().last;
+
+        // New files start empty, and once both are active each gets 500
+        // bytes per second
+        assert!(between(budget.reserve_at(a, 500, t), 400, 500));
+        assert!(between(budget.reserve_at(b, 500, t), 900, 1000));
+
+        let t = t + Duration::from_millis(900);
+        assert!(budget.reserve_at(a, 450, t).is_zero());
+        assert!(budget.reserve_at(b, 450, t).is_zero());
+        assert!(between(budget.reserve_at(a, 500, t), 900, 1000));
+        assert!(between(budget.reserve_at(b, 250, t), 400, 500));
+    }
+
+    #[test]
+    fn test_oversized() {
+        let budget = Bandwidth::new(100);
+        let path = Path::new("a.log");
+        let t = budget.lock().last;
+        assert!(between(budget.reserve_at(path, 1000, t), 900, 1000));
+
+        let t = t + Duration::from_secs(1);
+        assert!(budget.reserve_at(path, 1000, t).is_zero());
+        assert!(budget.reserve_at(path, 1, t).as_secs_f64() > 9.0);
+    }
+
+    #[test]
+    fn test_rate() {
+        let budget = Bandwidth::new(1000);
+        let paths = [Path::new("a.log"), Path::new("b.log"), Path::new("c.log")];
+        let start = budget.lock().last;
+        let end = start + Duration::from_secs(3);
+
+        // Three files write as fast as they are let, for three seconds
+        let (mut now, mut last, mut sent) = (start, start, 0);
+        while now < end {
+            let mut wait = Duration::MAX;
+            for path in paths {
+                loop {
+                    let next = budget.reserve_at(path, 100, now);
+                    if !next.is_zero() {
+                        wait = wait.min(next);
+                        break;
+                    }
+                    sent += 100;
+                    last = now;
+                }
+            }
+            now += wait;
+        }
+
+        let allowed = 1000.0 * (last - start).as_secs_f64();
+        assert!(f64::from(sent) <= allowed + 1.0, "{sent} > {allowed}");
+        assert!(sent >= 2500, "{sent}");
+    }
+}
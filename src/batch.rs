@@ -0,0 +1,182 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::{clock, lines, Result, TailState, TailedFile};
+
+/// A complete line read from a followed file, without its line ending or
+/// record delimiter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    /// The line, with invalid UTF-8 replaced by U+FFFD
+    pub text: String,
+    /// Offset of the start of the line in the file it was read from
+    pub offset: u64,
+}
+
+/// Batches of lines read from a [`TailedFile`], as returned by
+/// [`TailedFile::batches`].
+///
+/// A batch is yielded once it holds `max_lines` lines, or once its oldest line
+/// has waited for `max_latency`, whichever comes first, so that a quiet file
+/// still delivers its lines promptly. Iteration blocks while waiting for data,
+/// and ends once the file is stopped or disabled and every line was yielded.
+pub struct LineBatches<'a, T: AsRef<Path>> {
+    file: &'a mut TailedFile<T>,
+    max_lines: usize,
+    max_latency: Duration,
+    pending: Vec<Line>,
+    oldest: Option<Instant>,
+}
+
+impl<'a, T: AsRef<Path>> LineBatches<'a, T> {
+    pub(crate) fn new(
+        file: &'a mut TailedFile<T>,
+        max_lines: usize,
+        max_latency: Duration,
+    ) -> LineBatches<'a, T> {
+        LineBatches {
+            file,
+            max_lines: max_lines.max(1),
+            max_latency,
+            pending: Vec::new(),
+            oldest: None,
+        }
+    }
+
+    /// Reads the complete lines available from the file, returning `false` if
+    /// there were none
+    fn fill(&mut self) -> Result<bool> {
+        let found = read_lines(self.file, &mut self.pending)?;
+        if !self.pending.is_empty() {
+            self.oldest.get_or_insert_with(clock::now);
+        }
+
+        Ok(found)
+    }
+
+    fn take(&mut self, n: usize) -> Vec<Line> {
+        let batch: Vec<Line> = self.pending.drain(..n).collect();
+        if self.pending.is_empty() {
+            self.oldest = None;
+        }
+        batch
+    }
+}
+
+impl<'a, T: AsRef<Path>> Iterator for LineBatches<'a, T> {
+    type Item = Result<Vec<Line>>;
+
+    fn next(&mut self) -> Option<Result<Vec<Line>>> {
+        loop {
+            if self.pending.len() >= self.max_lines {
+                return Some(Ok(self.take(self.max_lines)));
+            }
+            let deadline = self.oldest.map(|at| at + self.max_latency);
+            if matches!(deadline, Some(at) if clock::now() >= at) {
+                return Some(Ok(self.take(self.pending.len())));
+            }
+            if matches!(self.file.state(), TailState::Stopped | TailState::Disabled) {
+                if self.pending.is_empty() {
+                    return None;
+                }
+                return Some(Ok(self.take(self.pending.len())));
+            }
+
+            match self.fill() {
+                Ok(true) => continue,
+                Ok(false) => {}
+                Err(e) => return Some(Err(e)),
+            }
+            let timeout = match deadline {
+                Some(at) => at.saturating_duration_since(clock::now()),
+                None => self.max_latency,
+            };
+            if let Err(e) = self.file.wait_for_change(timeout) {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+/// Reads the complete lines available from `file` into `out`, each gap
+/// ahead of them as a marker line, returning `false` if there were none.
+/// Records dropped by the size limit, filter or pipeline still count.
+pub(crate) fn read_lines<T: AsRef<Path>>(
+    file: &mut TailedFile<T>,
+    out: &mut Vec<Line>,
+) -> Result<bool> {
+    let complete = file.complete_lines()?.to_vec();
+    let gaps = file.take_gaps();
+    for gap in &gaps {
+        out.push(Line {
+            text: gap.to_string(),
+            offset: gap.offset,
+        });
+    }
+    if complete.is_empty() {
+        return Ok(!gaps.is_empty());
+    }
+
+    let end = file.checkpoint().offset;
+    let mut offset = end.saturating_sub(complete.len() as u64);
+    let delimiter = &file.delimiter;
+    let mut rest = &complete[..];
+    while !rest.is_empty() {
+        let len = lines::find(rest, delimiter).map_or(rest.len(), |i| i + delimiter.len());
+        // A record dropped for its size or by the filter yields nothing
+        if let Some(text) = file.records(&rest[..len]).next() {
+            out.push(Line {
+                text: text.into_owned(),
+                offset,
+            });
+        }
+        offset += len as u64;
+        rest = &rest[len..];
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::TailedFile;
+    use std::io::Write;
+    use std::time::Duration;
+
+    #[test]
+    fn test_batches() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.file");
+        let mut file = std::fs::File::create(&path).unwrap();
+        let mut f = TailedFile::new(path).unwrap();
+
+        file.write_all(b"one\ntwo\r\nthree\nfour\nfi").unwrap();
+        let mut batches = f.batches(3, Duration::from_millis(20));
+        let batch = batches.next().unwrap().unwrap();
+        let texts: Vec<_> = batch.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(texts, ["one", "two", "three"]);
+        let offsets: Vec<_> = batch.iter().map(|l| l.offset).collect();
+        assert_eq!(offsets, [0, 4, 9]);
+
+        let batch = batches.next().unwrap().unwrap();
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].text, "four");
+        assert_eq!(batch[0].offset, 15);
+
+        file.write_all(b"ve\n").unwrap();
+        let batch = batches.next().unwrap().unwrap();
+        assert_eq!(batch[0].text, "five");
+        drop(batches);
+
+        f.stop();
+        assert!(f.batches(3, Duration::from_millis(20)).next().is_none());
+    }
+}
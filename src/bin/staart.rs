@@ -6,47 +6,315 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
-use std::path::Path;
-use std::thread::sleep;
-use std::time::Duration;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
 
-use staart::{StaartError, TailedFile};
+use staart::{
+    ErrorBudget, RotatingFile, ShutdownHandle, StaartError, StartPos, TailState, TailStats,
+    TailedFile,
+};
 
 type Result<T> = std::result::Result<T, StaartError>;
 
-fn main() -> Result<()> {
-    const DEFAULT_DELAY: Duration = Duration::from_millis(100);
-    const OPEN_ERR_LIMIT: u8 = 3;
-
-    let args: Vec<String> = std::env::args().collect();
-    let path = Path::new(&args[1]);
-    let mut f = TailedFile::new(path)?;
-    let mut open_errors: u8 = 0;
-
-    loop {
-        if let Err(e) = f.read_and(|d| {
-	    if let Ok(s) = std::str::from_utf8(d) {
-		print!("{s}")
-	    }
-	}) {
-            match e {
-                StaartError::IO(err) if err.kind() == std::io::ErrorKind::NotFound => {
-                    if open_errors >= OPEN_ERR_LIMIT {
-			let path_str = path.display();
-                        eprintln!(
-                            "Failed to open: {path_str}, more than {open_errors} times. Exiting!");
-                        std::process::exit(1);
-                    } else {
-                        open_errors += 1;
-                    }
+const USAGE: &str = "Usage: staart [-n LINES] [--progress SECS] [--metrics-addr ADDR] \
+[-o OUTPUT [--rotate-every SECS] [--rotate-size BYTES] [--keep N] [--keep-days DAYS]] \
+<path/to/file.ext>";
+
+/// Command line arguments
+struct Args {
+    path: String,
+    lines: Option<usize>,
+    progress: Option<u64>,
+    metrics_addr: Option<String>,
+    output: Option<String>,
+    rotate_every: Option<u64>,
+    rotate_size: Option<u64>,
+    keep: Option<usize>,
+    keep_days: Option<u64>,
+}
+
+impl Args {
+    /// Parses arguments, exiting with usage information if they are invalid
+    fn parse() -> Args {
+        let mut parsed = Args {
+            path: String::new(),
+            lines: None,
+            progress: None,
+            metrics_addr: None,
+            output: None,
+            rotate_every: None,
+            rotate_size: None,
+            keep: None,
+            keep_days: None,
+        };
+        let mut path = None;
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-n" => parsed.lines = Some(number(args.next())),
+                "--progress" => parsed.progress = Some(number(args.next())),
+                "--metrics-addr" => {
+                    parsed.metrics_addr = Some(args.next().unwrap_or_else(|| usage()))
                 }
-                StaartError::Utf8(_) => {
-                    eprintln!("encountered non-utf8 bytes on read")
+                "-o" | "--output" => parsed.output = Some(args.next().unwrap_or_else(|| usage())),
+                "--rotate-every" => parsed.rotate_every = Some(number(args.next())),
+                "--rotate-size" => parsed.rotate_size = Some(number(args.next())),
+                "--keep" => parsed.keep = Some(number(args.next())),
+                "--keep-days" => parsed.keep_days = Some(number(args.next())),
+                "-h" | "--help" => {
+                    println!("{USAGE}");
+                    std::process::exit(0);
                 }
-                _ => return Err(e),
+                _ if path.is_none() => path = Some(arg),
+                _ => usage(),
             }
         }
 
-        sleep(DEFAULT_DELAY);
+        let rotating = parsed.rotate_every.is_some()
+            || parsed.rotate_size.is_some()
+            || parsed.keep.is_some()
+            || parsed.keep_days.is_some();
+        if rotating && parsed.output.is_none() {
+            usage();
+        }
+        parsed.path = path.unwrap_or_else(|| usage());
+        parsed
+    }
+
+    /// Opens the output file, rotated as requested, or returns stdout
+    fn output(&self) -> Result<Box<dyn Write>> {
+        let Some(path) = &self.output else {
+            return Ok(Box::new(std::io::stdout()));
+        };
+        let mut out = RotatingFile::create(path)?;
+        if let Some(secs) = self.rotate_every {
+            out = out.rotate_every(Duration::from_secs(secs));
+        }
+        if let Some(bytes) = self.rotate_size {
+            out = out.max_size(bytes);
+        }
+        if let Some(keep) = self.keep {
+            out = out.keep(keep);
+        }
+        if let Some(days) = self.keep_days {
+            let secs = days.checked_mul(24 * 3600).unwrap_or_else(|| usage());
+            out = out.keep_for(Duration::from_secs(secs));
+        }
+        Ok(Box::new(out))
     }
 }
+
+/// Parses the value of an option, exiting with usage information if it is
+/// missing or not a number
+fn number<N: std::str::FromStr>(arg: Option<String>) -> N {
+    arg.and_then(|n| n.parse().ok()).unwrap_or_else(|| usage())
+}
+
+fn usage() -> ! {
+    eprintln!("{USAGE}");
+    std::process::exit(2);
+}
+
+/// Counters served by [`serve_metrics`]
+#[derive(Default)]
+struct Metrics {
+    stats: TailStats,
+    lines: u64,
+    errors: u64,
+}
+
+impl Metrics {
+    /// Renders the counters in the Prometheus text exposition format
+    fn render(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: u64| {
+            out.push_str(&format!(
+                "# HELP staart_{name} {help}\n# TYPE staart_{name} {kind}\nstaart_{name} {value}\n"
+            ));
+        };
+        let stats = &self.stats;
+        metric(
+            "bytes_read_total",
+            "counter",
+            "Bytes read from the file.",
+            stats.bytes_read,
+        );
+        metric(
+            "lines_total",
+            "counter",
+            "Lines written to the output.",
+            self.lines,
+        );
+        metric(
+            "rotations_total",
+            "counter",
+            "Times the file was rotated.",
+            stats.rotations,
+        );
+        metric(
+            "truncations_total",
+            "counter",
+            "Times the file was truncated.",
+            stats.truncations,
+        );
+        metric(
+            "reopen_failures_total",
+            "counter",
+            "Failed attempts to open the file again.",
+            stats.reopen_failures,
+        );
+        metric("errors_total", "counter", "Reads that failed.", self.errors);
+        let last_data = stats
+            .last_data
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_secs());
+        metric(
+            "last_data_timestamp_seconds",
+            "gauge",
+            "When data was last read, as a Unix timestamp.",
+            last_data,
+        );
+        out
+    }
+}
+
+/// Serves `metrics` at `/metrics` to Prometheus from a background thread.
+/// Requests are answered one at a time, which is plenty for a scraper.
+fn serve_metrics(addr: &str, metrics: Arc<Mutex<Metrics>>) -> Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    std::thread::spawn(move || {
+        for conn in listener.incoming().flatten() {
+            // A scraper that goes away mid-request is of no concern
+            let _ = respond(conn, &metrics);
+        }
+    });
+
+    Ok(())
+}
+
+/// Answers a single HTTP request on `conn`
+fn respond(mut conn: TcpStream, metrics: &Mutex<Metrics>) -> Result<()> {
+    conn.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(&conn);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+        header.clear();
+    }
+
+    let path = request.split_whitespace().nth(1).unwrap_or_default();
+    let (status, body) = if !request.starts_with("GET ") {
+        ("405 Method Not Allowed", String::new())
+    } else if path == "/metrics" {
+        let body = match metrics.lock() {
+            Ok(metrics) => metrics.render(),
+            Err(poisoned) => poisoned.into_inner().render(),
+        };
+        ("200 OK", body)
+    } else {
+        ("404 Not Found", String::new())
+    };
+    write!(
+        conn,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+
+    Ok(())
+}
+
+/// Signals `shutdown` on SIGINT or SIGTERM. The signals are blocked before
+/// any other thread starts and waited for on a dedicated thread, so the main
+/// loop can flush the output before exiting. A second signal exits at once,
+/// in case the main loop is stuck.
+#[cfg(target_os = "linux")]
+fn handle_signals(shutdown: ShutdownHandle) -> Result<()> {
+    let mut set: libc::sigset_t = unsafe { std::mem::zeroed() };
+    unsafe {
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGINT);
+        libc::sigaddset(&mut set, libc::SIGTERM);
+    }
+    let rc = unsafe { libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut()) };
+    if rc != 0 {
+        return Err(std::io::Error::from_raw_os_error(rc).into());
+    }
+    std::thread::spawn(move || {
+        let mut signal = 0;
+        unsafe { libc::sigwait(&set, &mut signal) };
+        shutdown.signal();
+        unsafe { libc::sigwait(&set, &mut signal) };
+        std::process::exit(128 + signal);
+    });
+
+    Ok(())
+}
+
+/// Leaves SIGINT and SIGTERM to terminate the process
+#[cfg(not(target_os = "linux"))]
+fn handle_signals(_shutdown: ShutdownHandle) -> Result<()> {
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+    const OPEN_ERR_LIMIT: u32 = 3;
+
+    let args = Args::parse();
+    let shutdown = ShutdownHandle::new();
+    handle_signals(shutdown.clone())?;
+    // Following one file needs few descriptors, so a failure here is harmless
+    let _ = staart::raise_descriptor_limit();
+    let start = args.lines.map_or(StartPos::End, StartPos::LastLines);
+    let mut out = args.output()?;
+    let metrics = Arc::new(Mutex::new(Metrics::default()));
+    if let Some(addr) = &args.metrics_addr {
+        serve_metrics(addr, metrics.clone())?;
+    }
+    let mut f = TailedFile::builder(args.path)
+        .start(start)
+        .error_budget(ErrorBudget::new(OPEN_ERR_LIMIT))
+        .build()?;
+    if let Some(secs) = args.progress {
+        f.on_progress(Duration::from_secs(secs), |path, progress| {
+            eprintln!("{}: {progress}", path.display());
+        });
+    }
+
+    while !shutdown.is_signaled() {
+        let read = f.read_string_lossy();
+        if let Ok(mut metrics) = metrics.lock() {
+            metrics.stats = f.stats();
+            match &read {
+                Ok(s) => metrics.lines += s.matches('\n').count() as u64,
+                // A missing file is counted among the reopen failures
+                Err(StaartError::IO(e)) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(_) => metrics.errors += 1,
+            }
+        }
+        match read {
+            Ok(s) => {
+                out.write_all(s.as_bytes())?;
+                out.flush()?;
+            }
+            Err(e) if f.state() == TailState::Disabled => {
+                let path_str = f.path().display();
+                eprintln!(
+                    "Failed to read: {path_str}, more than {OPEN_ERR_LIMIT} times: {e}. Exiting!"
+                );
+                std::process::exit(1);
+            }
+            Err(StaartError::IO(err)) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        f.wait_for_change(DEFAULT_DELAY)?;
+    }
+
+    out.flush()?;
+    Ok(())
+}
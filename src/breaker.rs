@@ -0,0 +1,341 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::budget::BudgetTracker;
+use crate::{clock, Backoff, ErrorBudget, Line, Result, Sink};
+
+/// How many bytes a [`CircuitBreaker`] buffers by default
+pub const DEFAULT_BREAKER_BUFFER: usize = 1 << 20;
+
+/// Whether a [`CircuitBreaker`] lets writes through to its sink
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Circuit {
+    /// The sink is healthy, and writes go through
+    Closed,
+    /// The sink failed too often, and writes are buffered until the next probe
+    Open,
+    /// A probe is under way, after which the circuit closes or opens again
+    HalfOpen,
+}
+
+impl fmt::Display for Circuit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Circuit::Closed => write!(f, "closed"),
+            Circuit::Open => write!(f, "open"),
+            Circuit::HalfOpen => write!(f, "half-open"),
+        }
+    }
+}
+
+/// The health of the sink wrapped by a [`CircuitBreaker`], as returned by
+/// [`CircuitBreaker::health`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SinkHealth {
+    /// Whether writes currently go through
+    pub circuit: Circuit,
+    /// Writes and flushes that failed since the breaker was created
+    pub failures: u64,
+    /// Bytes waiting to be written
+    pub buffered: usize,
+    /// Bytes dropped because the buffer was full
+    pub dropped: u64,
+}
+
+/// A write waiting in the buffer of a [`CircuitBreaker`]
+#[derive(Debug)]
+enum Pending {
+    Chunk(PathBuf, Vec<u8>),
+    Lines(PathBuf, Vec<Line>),
+}
+
+impl Pending {
+    fn len(&self) -> usize {
+        match self {
+            Pending::Chunk(_, data) => data.len(),
+            Pending::Lines(_, lines) => lines.iter().map(|line| line.text.len() + 1).sum(),
+        }
+    }
+
+    fn send<S: Sink>(&self, sink: &mut S) -> Result<()> {
+        match self {
+            Pending::Chunk(path, data) => sink.write_chunk(path, data),
+            Pending::Lines(path, lines) => sink.write_lines(path, lines),
+        }
+    }
+}
+
+/// A [`Sink`] that stops sending to an unhealthy sink instead of hammering a
+/// dead endpoint and stalling the pipeline.
+///
+/// Failures of the wrapped sink are counted against an [`ErrorBudget`]. Once
+/// it is exhausted the circuit opens: writes are buffered in memory, and the
+/// sink is probed with the oldest buffered write at intervals set by a
+/// [`Backoff`]. When a probe succeeds, the rest of the buffer is written in
+/// order and the circuit closes again. A failed write is buffered as well,
+/// so it is retried by the next write, flush or probe, and may be received
+/// twice if the sink failed part way through it.
+///
+/// Errors of the wrapped sink are never returned, so a [`crate::FanOut`]
+/// carries on with its other sinks. [`CircuitBreaker::health`] reports how
+/// the sink is doing. Once more than the buffer capacity is waiting, the
+/// oldest writes are dropped and counted.
+///
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+/// use staart::{Backoff, CircuitBreaker, ErrorBudget, TailedFile, TcpSink};
+///
+/// let budget = ErrorBudget::new(5).window(Duration::from_secs(60));
+/// let sink = CircuitBreaker::new(TcpSink::new("logs.example.com:5140"), budget)
+///     .probe(Backoff::Exponential {
+///         initial: Duration::from_secs(1),
+///         max: Duration::from_secs(60),
+///     })
+///     .buffer(16 << 20);
+/// let mut f = TailedFile::new("/var/log/syslog").unwrap();
+/// f.follow(sink, Duration::from_secs(1)).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct CircuitBreaker<S: Sink> {
+    inner: S,
+    tracker: BudgetTracker,
+    probe: Backoff,
+    capacity: usize,
+    circuit: Circuit,
+    probes: u32,
+    next_probe: Instant,
+    queue: VecDeque<Pending>,
+    buffered: usize,
+    failures: u64,
+    dropped: u64,
+}
+
+impl<S: Sink> CircuitBreaker<S> {
+    /// Creates a breaker around `inner`, opening once `budget` is exhausted
+    pub fn new(inner: S, budget: ErrorBudget) -> CircuitBreaker<S> {
+        CircuitBreaker {
+            inner,
+            tracker: BudgetTracker::new(budget),
+            probe: Backoff::default(),
+            capacity: DEFAULT_BREAKER_BUFFER,
+            circuit: Circuit::Closed,
+            probes: 0,
+            next_probe: clock::now(),
+            queue: VecDeque::new(),
+            buffered: 0,
+            failures: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Sets how long to wait between probes while the circuit is open,
+    /// defaults to [`Backoff::default`]
+    pub fn probe(mut self, backoff: Backoff) -> CircuitBreaker<S> {
+        self.probe = backoff;
+        self
+    }
+
+    /// Sets how many bytes are buffered while the sink is unhealthy, defaults
+    /// to [`DEFAULT_BREAKER_BUFFER`]
+    pub fn buffer(mut self, bytes: usize) -> CircuitBreaker<S> {
+        self.capacity = bytes;
+        self
+    }
+
+    /// Returns the health of the wrapped sink
+    pub fn health(&self) -> SinkHealth {
+        SinkHealth {
+            circuit: self.circuit,
+            failures: self.failures,
+            buffered: self.buffered,
+            dropped: self.dropped,
+        }
+    }
+
+    /// Returns the inner sink, dropping any buffered writes
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn write(&mut self, pending: Pending) {
+        if self.circuit == Circuit::Closed && self.queue.is_empty() {
+            match pending.send(&mut self.inner) {
+                Ok(()) => self.tracker.success(),
+                Err(_) => {
+                    self.enqueue(pending);
+                    self.failed();
+                }
+            }
+            return;
+        }
+        self.enqueue(pending);
+        self.drain();
+    }
+
+    fn enqueue(&mut self, pending: Pending) {
+        self.buffered += pending.len();
+        self.queue.push_back(pending);
+        while self.buffered > self.capacity {
+            let Some(oldest) = self.queue.pop_front() else {
+                break;
+            };
+            self.buffered -= oldest.len();
+            self.dropped += oldest.len() as u64;
+        }
+    }
+
+    /// Writes the buffer in order, probing first if the circuit is open.
+    /// Returns `true` if the buffer was emptied.
+    fn drain(&mut self) -> bool {
+        if self.circuit == Circuit::Open {
+            if clock::now() < self.next_probe {
+                return false;
+            }
+            self.circuit = Circuit::HalfOpen;
+        }
+        while let Some(pending) = self.queue.front() {
+            if pending.send(&mut self.inner).is_err() {
+                self.failed();
+                return false;
+            }
+            self.buffered -= pending.len();
+            self.queue.pop_front();
+        }
+        if self.circuit == Circuit::HalfOpen {
+            self.circuit = Circuit::Closed;
+            self.probes = 0;
+            self.tracker.reset();
+        }
+
+        true
+    }
+
+    fn failed(&mut self) {
+        self.failures += 1;
+        let now = clock::now();
+        match self.circuit {
+            Circuit::Closed if !self.tracker.error(now) => return,
+            Circuit::Closed => self.probes = 0,
+            Circuit::Open | Circuit::HalfOpen => self.probes = self.probes.saturating_add(1),
+        }
+        self.circuit = Circuit::Open;
+        self.next_probe = now + self.probe.delay(self.probes);
+    }
+}
+
+impl<S: Sink> Sink for CircuitBreaker<S> {
+    fn write_chunk(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        self.write(Pending::Chunk(path.to_path_buf(), data.to_vec()));
+        Ok(())
+    }
+
+    fn write_lines(&mut self, path: &Path, lines: &[Line]) -> Result<()> {
+        self.write(Pending::Lines(path.to_path_buf(), lines.to_vec()));
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.drain() && self.inner.flush().is_err() {
+            self.failed();
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.drain() && self.inner.finish().is_err() {
+            self.failed();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StaartError;
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct Flaky {
+        down: bool,
+        attempts: usize,
+        out: Vec<u8>,
+    }
+
+    impl Sink for Flaky {
+        fn write_chunk(&mut self, _path: &Path, data: &[u8]) -> Result<()> {
+            self.attempts += 1;
+            if self.down {
+                return Err(StaartError::Sink("down".to_string()));
+            }
+            self.out.extend_from_slice(data);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_open_and_recover() {
+        let path = Path::new("test.log");
+        let mut breaker = CircuitBreaker::new(Flaky::default(), ErrorBudget::new(1))
+            .probe(Backoff::Fixed(Duration::from_secs(3600)));
+        breaker.inner.down = true;
+
+        breaker.write_chunk(path, b"one\n").unwrap();
+        assert_eq!(breaker.health().circuit, Circuit::Closed);
+        breaker.write_chunk(path, b"two\n").unwrap();
+        assert_eq!(breaker.health().circuit, Circuit::Open);
+        assert_eq!(breaker.inner.attempts, 2);
+
+        // An open circuit buffers without touching the sink until the probe
+        breaker.write_chunk(path, b"three\n").unwrap();
+        breaker.flush().unwrap();
+        assert_eq!(breaker.inner.attempts, 2);
+        assert_eq!(
+            breaker.health(),
+            SinkHealth {
+                circuit: Circuit::Open,
+                failures: 2,
+                buffered: 14,
+                dropped: 0,
+            }
+        );
+
+        breaker.inner.down = false;
+        breaker.next_probe = clock::now();
+        breaker.flush().unwrap();
+        assert_eq!(breaker.health().circuit, Circuit::Closed);
+        assert_eq!(breaker.health().buffered, 0);
+        assert_eq!(breaker.into_inner().out, b"one\ntwo\nthree\n");
+    }
+
+    #[test]
+    fn test_buffer_limit() {
+        let path = Path::new("test.log");
+        let mut breaker = CircuitBreaker::new(Flaky::default(), ErrorBudget::new(0))
+            .probe(Backoff::Fixed(Duration::ZERO))
+            .buffer(8);
+        breaker.inner.down = true;
+
+        breaker.write_chunk(path, b"one\n").unwrap();
+        breaker.write_chunk(path, b"two\n").unwrap();
+        breaker.write_chunk(path, b"three\n").unwrap();
+        let health = breaker.health();
+        assert_eq!((health.buffered, health.dropped), (6, 8));
+
+        breaker.inner.down = false;
+        breaker.flush().unwrap();
+        assert_eq!(breaker.health().circuit, Circuit::Closed);
+        assert_eq!(breaker.into_inner().out, b"three\n");
+    }
+}
@@ -0,0 +1,121 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How many read errors a [`crate::TailedFile`] tolerates before it is
+/// disabled.
+///
+/// Without a window the budget counts consecutive errors, and any successful
+/// read refills it. With a window it counts every error within the window,
+/// regardless of successful reads in between.
+///
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+/// use staart::{ErrorBudget, TailedFileBuilder};
+///
+/// // Give up on the file after more than 10 errors in a minute
+/// let budget = ErrorBudget::new(10).window(Duration::from_secs(60));
+/// let f = TailedFileBuilder::new("/var/log/syslog").error_budget(budget).build();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorBudget {
+    limit: u32,
+    window: Option<Duration>,
+}
+
+impl ErrorBudget {
+    /// Creates a budget which tolerates `limit` errors, and is exhausted by the
+    /// next one
+    pub fn new(limit: u32) -> ErrorBudget {
+        ErrorBudget {
+            limit,
+            window: None,
+        }
+    }
+
+    /// Only counts errors that occurred within `window` of the latest one
+    pub fn window(mut self, window: Duration) -> ErrorBudget {
+        self.window = Some(window);
+        self
+    }
+}
+
+/// Tracks errors against an [`ErrorBudget`]
+#[derive(Debug, Clone)]
+pub(crate) struct BudgetTracker {
+    budget: ErrorBudget,
+    errors: VecDeque<Instant>,
+}
+
+impl BudgetTracker {
+    pub(crate) fn new(budget: ErrorBudget) -> BudgetTracker {
+        BudgetTracker {
+            budget,
+            errors: VecDeque::new(),
+        }
+    }
+
+    /// Records an error at `now`, returning `true` if the budget is exhausted
+    pub(crate) fn error(&mut self, now: Instant) -> bool {
+        if let Some(window) = self.budget.window {
+            while matches!(self.errors.front(), Some(at) if now.duration_since(*at) > window) {
+                self.errors.pop_front();
+            }
+        }
+        self.errors.push_back(now);
+
+        self.errors.len() > self.budget.limit as usize
+    }
+
+    /// Records a successful read
+    pub(crate) fn success(&mut self) {
+        if self.budget.window.is_none() {
+            self.errors.clear();
+        }
+    }
+
+    /// Refills the budget
+    pub(crate) fn reset(&mut self) {
+        self.errors.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_consecutive() {
+        let now = Instant::now();
+        let mut tracker = BudgetTracker::new(ErrorBudget::new(2));
+
+        assert!(!tracker.error(now));
+        assert!(!tracker.error(now));
+        tracker.success();
+        assert!(!tracker.error(now));
+        assert!(!tracker.error(now));
+        assert!(tracker.error(now));
+
+        tracker.reset();
+        assert!(!tracker.error(now));
+    }
+
+    #[test]
+    fn test_window() {
+        let now = Instant::now();
+        let mut tracker = BudgetTracker::new(ErrorBudget::new(1).window(Duration::from_secs(10)));
+
+        assert!(!tracker.error(now));
+        tracker.success();
+        assert!(!tracker.error(now + Duration::from_secs(11)));
+        assert!(tracker.error(now + Duration::from_secs(12)));
+    }
+}
@@ -0,0 +1,1000 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::fs::{File, Metadata};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::append::AppendGuard;
+use crate::budget::{BudgetTracker, ErrorBudget};
+use crate::clock;
+use crate::fdlimit::Handle;
+use crate::format::{self, detect, Detection};
+use crate::hooks::Hooks;
+use crate::identity::Stat;
+use crate::journal::{Journal, JournalEntry};
+use crate::lines::RecordLimit;
+use crate::timeline::{Lifecycle, Timeline, DEFAULT_TIMELINE_CAPACITY};
+use crate::{
+    Backoff, Checkpoint, DecisionTable, Encoding, LineFilter, PauseHandle, Pipeline, Result,
+    RetryPolicy, ShutdownHandle, StaartError, TailState, TailStats, TailedFile,
+};
+
+/// Default size of the buffer used for each read
+pub const DEFAULT_BUFFER_SIZE: usize = 65536;
+
+/// Where reading starts when a file is first opened
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StartPos {
+    /// Replay the existing content of the file before following it
+    Beginning,
+    /// Follow only data written after the file was opened
+    #[default]
+    End,
+    /// Start at a byte offset, clamped to the length of the file
+    Offset(u64),
+    /// Start at the beginning of the last N lines of the file, like `tail -n`
+    LastLines(usize),
+    /// Start N bytes before the end of the file, like `tail -c`
+    LastBytes(u64),
+}
+
+/// Chooses the [`StartPos`] of a file depending on whether it already existed
+/// when following started, or was created afterwards.
+///
+/// Shippers usually skip the backlog of files that were already there, but
+/// must not miss the first lines of files created later. Files discovered
+/// after they were created can start with some context instead, using
+/// [`StartPos::LastLines`] or [`StartPos::LastBytes`]. A file counts as
+/// created afterwards if its creation time, or its modification time where
+/// creation time is unavailable, is later than the startup time.
+///
+/// # Example
+/// ```no_run
+/// use std::time::SystemTime;
+/// use staart::{StartPolicy, TailedFileBuilder};
+///
+/// let policy = StartPolicy::new(SystemTime::now());
+/// // ... later, when a file is discovered
+/// let f = TailedFileBuilder::new("/var/log/app/new.log").start_policy(policy).build();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StartPolicy {
+    since: SystemTime,
+    existing: StartPos,
+    created: StartPos,
+}
+
+impl StartPolicy {
+    /// Creates a policy for following that started at `since`, which starts
+    /// existing files at [`StartPos::End`] and new files at [`StartPos::Beginning`]
+    pub fn new(since: SystemTime) -> StartPolicy {
+        StartPolicy {
+            since,
+            existing: StartPos::End,
+            created: StartPos::Beginning,
+        }
+    }
+
+    /// Sets where files that existed at startup start
+    pub fn existing(mut self, start: StartPos) -> StartPolicy {
+        self.existing = start;
+        self
+    }
+
+    /// Sets where files created after startup start
+    pub fn created(mut self, start: StartPos) -> StartPolicy {
+        self.created = start;
+        self
+    }
+
+    /// Returns the [`StartPos`] for a file with metadata `meta`
+    pub fn start_for(&self, meta: &Metadata) -> StartPos {
+        match meta.created().or_else(|_| meta.modified()) {
+            Ok(at) if at > self.since => self.created,
+            _ => self.existing,
+        }
+    }
+}
+
+/// Size of the blocks read backwards from the end when locating the last lines
+const LINE_SEEK_BLOCK: u64 = 8192;
+
+/// Finds the offset of the start of the last `n` lines by reading backwards
+/// from the end of the file in blocks. A trailing newline does not start a new line.
+fn last_lines_offset(f: &mut File, len: u64, n: usize) -> Result<u64> {
+    if n == 0 {
+        return Ok(len);
+    }
+
+    let mut buf = vec![0u8; LINE_SEEK_BLOCK as usize];
+    let mut end = len;
+    let mut seen = 0;
+    let mut skip_trailing = true;
+    while end > 0 {
+        let start = end.saturating_sub(LINE_SEEK_BLOCK);
+        let block = &mut buf[..(end - start) as usize];
+        f.seek(SeekFrom::Start(start))?;
+        f.read_exact(block)?;
+
+        let mut i = block.len();
+        if skip_trailing {
+            skip_trailing = false;
+            if block.last() == Some(&b'\n') {
+                i -= 1;
+            }
+        }
+        while let Some(idx) = block[..i].iter().rposition(|b| *b == b'\n') {
+            seen += 1;
+            if seen == n {
+                return Ok(start + idx as u64 + 1);
+            }
+            i = idx;
+        }
+        end = start;
+    }
+
+    Ok(0)
+}
+
+/// How file rotation is detected before each read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RotationCheck {
+    /// Compare the identity of the file at the path to the one being followed,
+    /// by device and inode on Unix systems, and by volume serial number and
+    /// file index on Windows
+    #[default]
+    FileId,
+    /// Keep following the file that was originally opened even if the path is
+    /// replaced or removed, like `tail --follow=descriptor`. Truncation is
+    /// still detected.
+    Disabled,
+}
+
+/// How errors encountered while reading are handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    /// Return every error to the caller
+    #[default]
+    Propagate,
+    /// Treat a missing file as having no new data, leaving the file in
+    /// [`TailState::WaitingForFile`] until it reappears
+    IgnoreMissing,
+}
+
+/// What happens to a file detected as binary by [`crate::is_binary`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BinaryPolicy {
+    /// Read the file like any other
+    #[default]
+    Raw,
+    /// Stop reading the file, leaving it in [`TailState::Disabled`] with a
+    /// [`Lifecycle::Binary`] warning in its timeline
+    Skip,
+    /// Hex-encode the records returned by [`TailedFile::lines_str`] and
+    /// [`TailedFile::batches`]. Raw reads are not affected.
+    HexEncode,
+}
+
+/// Text appended to records cut short by [`OversizePolicy::Truncate`]
+pub const TRUNCATION_MARKER: &str = "[truncated]";
+
+/// What happens to records longer than the limit set with
+/// [`TailedFileBuilder::max_record_size`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OversizePolicy {
+    /// Keep the start of the record up to the limit, followed by
+    /// [`TRUNCATION_MARKER`]
+    #[default]
+    Truncate,
+    /// Leave the record out
+    Drop,
+    /// Return the record unchanged, only counting it
+    PassThrough,
+}
+
+/// What happens when a read fills the buffer set with
+/// [`TailedFileBuilder::buffer_size`] and more data is waiting in the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backpressure {
+    /// Leave the rest for the following reads
+    #[default]
+    CarryOver,
+    /// Skip the rest, up to the end of the file, reporting it as a
+    /// [`crate::Gap`] when [`TailedFileBuilder::gap_markers`] is set. Data of
+    /// compressed files is always carried over.
+    SkipToEnd,
+}
+
+/// Configures and creates a [`TailedFile`]
+///
+/// # Example
+/// ```no_run
+/// use staart::{StartPos, TailedFileBuilder};
+///
+/// let f = TailedFileBuilder::new("/var/log/syslog")
+///     .start(StartPos::Beginning)
+///     .buffer_size(1 << 20)
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct TailedFileBuilder<T> {
+    path: T,
+    start: StartPos,
+    start_policy: Option<StartPolicy>,
+    resume: Option<Checkpoint>,
+    buffer_size: usize,
+    drain_limit: Option<u64>,
+    backpressure: Backpressure,
+    align_records: bool,
+    flush_partial: Option<Duration>,
+    delimiter: Vec<u8>,
+    record_limit: Option<RecordLimit>,
+    binary_policy: BinaryPolicy,
+    encoding: Encoding,
+    filter: Option<LineFilter>,
+    gap_markers: bool,
+    header_limit: Option<usize>,
+    pipeline: Pipeline,
+    catch_up: Option<crate::CatchUp>,
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    decompress: Option<crate::Decompression>,
+    rotation: RotationCheck,
+    errors: ErrorPolicy,
+    decisions: DecisionTable,
+    format: Option<Detection>,
+    timeline_capacity: usize,
+    journal: Option<PathBuf>,
+    verify_every: Option<u32>,
+    budget: Option<ErrorBudget>,
+    ignore_older: Option<Duration>,
+    close_inactive: Option<Duration>,
+    idle_after: Option<Duration>,
+    wait_for_file: Option<(Backoff, Option<Duration>)>,
+    retry: Option<RetryPolicy>,
+    shutdown: Option<ShutdownHandle>,
+    checkpoint_on_shutdown: Option<PathBuf>,
+    inherited: Option<Arc<File>>,
+}
+
+impl<T> TailedFileBuilder<T>
+where
+    T: AsRef<Path>,
+{
+    /// Creates a builder with the same defaults as [`TailedFile::new`]
+    pub fn new(path: T) -> TailedFileBuilder<T> {
+        TailedFileBuilder {
+            path,
+            start: StartPos::default(),
+            start_policy: None,
+            resume: None,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            drain_limit: None,
+            backpressure: Backpressure::default(),
+            align_records: false,
+            flush_partial: None,
+            delimiter: b"\n".to_vec(),
+            record_limit: None,
+            binary_policy: BinaryPolicy::default(),
+            encoding: Encoding::default(),
+            filter: None,
+            gap_markers: false,
+            header_limit: None,
+            pipeline: Pipeline::default(),
+            catch_up: None,
+            #[cfg(any(feature = "gzip", feature = "zstd"))]
+            decompress: None,
+            rotation: RotationCheck::default(),
+            errors: ErrorPolicy::default(),
+            decisions: DecisionTable::default(),
+            format: None,
+            timeline_capacity: DEFAULT_TIMELINE_CAPACITY,
+            journal: None,
+            verify_every: None,
+            budget: None,
+            ignore_older: None,
+            close_inactive: None,
+            idle_after: None,
+            wait_for_file: None,
+            retry: None,
+            shutdown: None,
+            checkpoint_on_shutdown: None,
+            inherited: None,
+        }
+    }
+
+    /// Sets where reading starts, defaults to [`StartPos::End`]
+    pub fn start(mut self, start: StartPos) -> TailedFileBuilder<T> {
+        self.start = start;
+        self
+    }
+
+    /// Chooses where reading starts with `policy`, depending on when the file
+    /// was created. Overrides [`TailedFileBuilder::start`].
+    pub fn start_policy(mut self, policy: StartPolicy) -> TailedFileBuilder<T> {
+        self.start_policy = Some(policy);
+        self
+    }
+
+    /// Resumes reading from `checkpoint` if it refers to the file at the path
+    /// and does not lie past its end. Otherwise the file was rotated or
+    /// truncated since, and is read from the beginning. Overrides
+    /// [`TailedFileBuilder::start`] and [`TailedFileBuilder::start_policy`].
+    pub fn resume(mut self, checkpoint: Checkpoint) -> TailedFileBuilder<T> {
+        self.resume = Some(checkpoint);
+        self
+    }
+
+    /// Sets the maximum number of bytes returned by a single read, defaults to
+    /// 64KiB. See [`TailedFileBuilder::backpressure`] for what happens to the
+    /// data past it.
+    pub fn buffer_size(mut self, size: usize) -> TailedFileBuilder<T> {
+        self.buffer_size = size;
+        self
+    }
+
+    /// Bounds how many bytes [`TailedFile::read_all_new`] and
+    /// [`TailedFile::read_and`] consume in one call, defaults to unbounded.
+    /// Reading stops at the first buffer boundary at or past the limit.
+    pub fn drain_limit(mut self, bytes: u64) -> TailedFileBuilder<T> {
+        self.drain_limit = Some(bytes);
+        self
+    }
+
+    /// Sets what happens when a read fills the buffer and more data is
+    /// waiting, defaults to [`Backpressure::CarryOver`]. Reads of
+    /// [`TailedFile::read_mapped`] are bounded by the drain limit instead.
+    pub fn backpressure(mut self, policy: Backpressure) -> TailedFileBuilder<T> {
+        self.backpressure = policy;
+        self
+    }
+
+    /// Cuts every chunk returned by the read methods at the end of the last
+    /// complete record, so that records are never split
+    /// across reads. An incomplete trailing record is returned by a later read
+    /// once its terminator has been written. A record longer than the buffer
+    /// size is still split, since it can not otherwise be returned.
+    pub fn align_to_records(mut self, align: bool) -> TailedFileBuilder<T> {
+        self.align_records = align;
+        self
+    }
+
+    /// Aligns reads to records as with [`TailedFileBuilder::align_to_records`],
+    /// but hands out an incomplete trailing record once it has stopped growing
+    /// for `timeout`, so that a final unterminated line is not held back
+    /// forever
+    pub fn flush_partial_after(mut self, timeout: Duration) -> TailedFileBuilder<T> {
+        self.align_records = true;
+        self.flush_partial = Some(timeout);
+        self
+    }
+
+    /// Sets the bytes ending each record, defaults to a newline. The delimiter
+    /// applies to [`TailedFile::lines_str`], [`TailedFile::batches`] and
+    /// [`TailedFileBuilder::align_to_records`], and may be several bytes long,
+    /// e.g. `b"\0"` or `b"\n--\n"`. [`StartPos::LastLines`] still counts
+    /// newlines.
+    pub fn delimiter<D: Into<Vec<u8>>>(mut self, delimiter: D) -> TailedFileBuilder<T> {
+        self.delimiter = delimiter.into();
+        self
+    }
+
+    /// Limits records returned by [`TailedFile::lines_str`] and
+    /// [`TailedFile::batches`] to `bytes`, excluding the delimiter, applying
+    /// `policy` to longer ones. Oversized records are counted by
+    /// [`TailedFile::oversized_records`]. Raw reads are not affected.
+    pub fn max_record_size(mut self, bytes: usize, policy: OversizePolicy) -> TailedFileBuilder<T> {
+        self.record_limit = Some(RecordLimit { max: bytes, policy });
+        self
+    }
+
+    /// Sets what happens once the first data read from the file, or from a file
+    /// replacing it, is detected as binary, defaults to [`BinaryPolicy::Raw`].
+    /// Files delimited by NUL bytes are never detected as binary.
+    pub fn binary_policy(mut self, policy: BinaryPolicy) -> TailedFileBuilder<T> {
+        self.binary_policy = policy;
+        self
+    }
+
+    /// Sets the character encoding of the file, defaults to [`Encoding::Utf8`].
+    /// Records returned by [`TailedFile::lines_str`], [`TailedFile::batches`]
+    /// and the lossy string APIs are transcoded to UTF-8. The delimiter is
+    /// matched against the bytes as written.
+    pub fn encoding(mut self, encoding: Encoding) -> TailedFileBuilder<T> {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Delivers only the records kept by `filter` from [`TailedFile::lines_str`]
+    /// and [`TailedFile::batches`]. Raw reads are not affected.
+    pub fn filter(mut self, filter: LineFilter) -> TailedFileBuilder<T> {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Reports data known to have been missed, because the file was truncated
+    /// before it was read or skipped by the [`DecisionTable`] or under
+    /// [`Backpressure::SkipToEnd`], as a [`crate::Gap`].
+    /// [`TailedFile::lines_str`] and [`TailedFile::batches`] hand each gap out
+    /// as a marker record ahead of the data read after it, and
+    /// [`TailedFile::take_gaps`] returns them for raw reads.
+    pub fn gap_markers(mut self, enabled: bool) -> TailedFileBuilder<T> {
+        self.gap_markers = enabled;
+        self
+    }
+
+    /// Captures the first record of each file attached at the path, up to
+    /// `max_bytes`, e.g. a CSV header or a format banner used to choose a
+    /// parser. The header is read from the start of the file whatever the
+    /// [`StartPos`], and is returned by [`TailedFile::header`] and passed to
+    /// [`TailedFile::on_header`].
+    pub fn capture_header(mut self, max_bytes: usize) -> TailedFileBuilder<T> {
+        self.header_limit = Some(max_bytes);
+        self
+    }
+
+    /// Runs records returned by [`TailedFile::lines_str`] and
+    /// [`TailedFile::batches`] through `pipeline`, after they are decoded and
+    /// filtered. Raw reads are not affected.
+    pub fn pipeline(mut self, pipeline: Pipeline) -> TailedFileBuilder<T> {
+        self.pipeline = pipeline;
+        self
+    }
+
+    /// Reads the rotated predecessors named by `catch_up`, oldest first,
+    /// before the live file, e.g. to recover data rotated and compressed while
+    /// staart was not running. The live file is then read from the
+    /// [`StartPos`].
+    pub fn catch_up(mut self, catch_up: crate::CatchUp) -> TailedFileBuilder<T> {
+        self.catch_up = Some(catch_up);
+        self
+    }
+
+    /// Decodes the file as a compressed stream, for logs written directly
+    /// as gzip or zstd. Records, filters and hooks then see the decoded data,
+    /// while offsets and checkpoints refer to the compressed file. A
+    /// checkpoint taken while decoded data is held back points at its start.
+    ///
+    /// A compressed stream can only be decoded from its start, so the file is
+    /// read from its beginning whatever the [`StartPos`], and again from the
+    /// beginning once it is truncated or replaced.
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    pub fn decompress(mut self, decompression: crate::Decompression) -> TailedFileBuilder<T> {
+        self.decompress = Some(decompression);
+        self
+    }
+
+    /// Sets how rotation is detected, defaults to [`RotationCheck::FileId`]
+    pub fn rotation_check(mut self, rotation: RotationCheck) -> TailedFileBuilder<T> {
+        self.rotation = rotation;
+        self
+    }
+
+    /// Sets how read errors are handled, defaults to [`ErrorPolicy::Propagate`]
+    pub fn error_policy(mut self, errors: ErrorPolicy) -> TailedFileBuilder<T> {
+        self.errors = errors;
+        self
+    }
+
+    /// Sets the action taken for each combination of rotation and length
+    /// change, defaults to [`DecisionTable::default`]
+    pub fn decisions(mut self, decisions: DecisionTable) -> TailedFileBuilder<T> {
+        self.decisions = decisions;
+        self
+    }
+
+    /// Sets the record format instead of detecting it from the file content
+    pub fn format(mut self, format: Detection) -> TailedFileBuilder<T> {
+        self.format = Some(format);
+        self
+    }
+
+    /// Sets how many lifecycle events are kept in the file's timeline, defaults to 32
+    pub fn timeline_capacity(mut self, capacity: usize) -> TailedFileBuilder<T> {
+        self.timeline_capacity = capacity;
+        self
+    }
+
+    /// Records the identity and length of the file before each read, and the
+    /// offset and length of each read, to a journal at `path`. No file content
+    /// is recorded. The journal can be replayed with [`crate::replay_journal`]
+    /// to reproduce rotation and truncation handling issues.
+    pub fn journal<P: AsRef<Path>>(mut self, path: P) -> TailedFileBuilder<T> {
+        self.journal = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Verifies that the file is only ever appended to, as expected of audit
+    /// logs. Regions of already-read content are fingerprinted and re-checked
+    /// every `every_n_reads` reads. If they changed, or the file shrank, the
+    /// read fails with [`StaartError::Modified`] naming the offset of the
+    /// change, and the fingerprints are retaken from the current content.
+    pub fn verify_append_only(mut self, every_n_reads: u32) -> TailedFileBuilder<T> {
+        self.verify_every = Some(every_n_reads);
+        self
+    }
+
+    /// Disables the file, see [`TailState::Disabled`], once read errors of any
+    /// kind exceed `budget`. Defaults to never disabling the file.
+    pub fn error_budget(mut self, budget: ErrorBudget) -> TailedFileBuilder<T> {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Refuses to follow a file that was last modified longer than `age` ago,
+    /// so that directories full of old logs don't each hold a descriptor.
+    /// [`TailedFileBuilder::build`] then fails with [`StaartError::Ignored`].
+    pub fn ignore_older(mut self, age: Duration) -> TailedFileBuilder<T> {
+        self.ignore_older = Some(age);
+        self
+    }
+
+    /// Closes the handle of the file once no data has been read from it for
+    /// `idle`, see [`TailState::Closed`]. The read position is kept, and the
+    /// file is reopened by the first read after it changes. Has no effect with
+    /// [`RotationCheck::Disabled`], which depends on the original handle.
+    pub fn close_inactive(mut self, idle: Duration) -> TailedFileBuilder<T> {
+        self.close_inactive = Some(idle);
+        self
+    }
+
+    /// Reports [`crate::TailEvent::Idle`] from [`TailedFile::poll_event`] once no
+    /// data has been read for `quiet`, defaults to never. It is reported once
+    /// per quiet period, so downstream systems can tell a quiet log from a
+    /// stuck tailer without being flooded.
+    pub fn idle_after(mut self, quiet: Duration) -> TailedFileBuilder<T> {
+        self.idle_after = Some(quiet);
+        self
+    }
+
+    /// Stops [`TailedFile::follow`] and [`TailedFile::spawn`] cleanly once
+    /// `handle` is signaled: a partial record held back by
+    /// [`TailedFileBuilder::align_to_records`] is handed out, the sink is
+    /// flushed, the checkpoint set with
+    /// [`TailedFileBuilder::checkpoint_on_shutdown`] is saved, and the file is
+    /// stopped
+    pub fn shutdown(mut self, handle: ShutdownHandle) -> TailedFileBuilder<T> {
+        self.shutdown = Some(handle);
+        self
+    }
+
+    /// Saves a [`Checkpoint`] to `path` when following stops because the
+    /// [`TailedFileBuilder::shutdown`] handle was signaled
+    pub fn checkpoint_on_shutdown<P: Into<PathBuf>>(mut self, path: P) -> TailedFileBuilder<T> {
+        self.checkpoint_on_shutdown = Some(path.into());
+        self
+    }
+
+    /// Waits for the file to appear if it does not exist yet, trying again
+    /// after each `backoff` delay until `timeout`, if any, elapses. A file that
+    /// had to be waited for is read from its beginning unless a
+    /// [`StartPolicy`] or checkpoint says otherwise, as `tail -F` does.
+    ///
+    /// Missing files are also ignored once followed, as with
+    /// [`ErrorPolicy::IgnoreMissing`], so that a file removed from the path is
+    /// followed again when it reappears.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use staart::{Backoff, TailedFile};
+    ///
+    /// let f = TailedFile::builder("/var/log/app.log")
+    ///     .wait_for_file(Backoff::default(), None)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn wait_for_file(
+        mut self,
+        backoff: Backoff,
+        timeout: Option<Duration>,
+    ) -> TailedFileBuilder<T> {
+        self.wait_for_file = Some((backoff, timeout));
+        self.errors = ErrorPolicy::IgnoreMissing;
+        self
+    }
+
+    /// Retries reads that fail with transient errors according to `policy`
+    pub fn retry(mut self, policy: RetryPolicy) -> TailedFileBuilder<T> {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Follows `file`, which is already open, instead of opening the path,
+    /// e.g. a file handed over by another process with [`crate::take_over`].
+    /// The path is still used to detect rotation. Combined with
+    /// [`TailedFileBuilder::resume`], reading continues where the previous
+    /// owner of the file left off, even if the file was rotated away since.
+    pub fn inherit(mut self, file: File) -> TailedFileBuilder<T> {
+        self.inherited = Some(Arc::new(file));
+        self
+    }
+
+    /// Opens the file, waiting for it to appear if configured to, and returns
+    /// whether it had to be waited for
+    fn open(&self) -> Result<(File, bool)> {
+        if let Some(file) = &self.inherited {
+            return Ok((file.try_clone()?, false));
+        }
+        let Some((backoff, timeout)) = self.wait_for_file else {
+            return Ok((File::open(self.path.as_ref())?, false));
+        };
+
+        let started = clock::now();
+        let mut attempt = 0;
+        loop {
+            match File::open(self.path.as_ref()) {
+                Ok(f) => return Ok((f, attempt > 0)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    let delay = backoff.delay(attempt);
+                    let waited = clock::now().duration_since(started);
+                    if matches!(timeout, Some(max) if waited + delay > max) {
+                        return Err(e.into());
+                    }
+                    std::thread::sleep(delay);
+                    attempt = attempt.saturating_add(1);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Opens the file and creates the configured [`TailedFile`]
+    ///
+    /// # Propagates Errors
+    /// - If the buffer size is zero, or the delimiter is empty
+    /// - If the file is older than allowed by [`TailedFileBuilder::ignore_older`]
+    /// - If the journal can not be created
+    /// - If the path provided does not exist, or did not appear before the
+    ///   timeout of [`TailedFileBuilder::wait_for_file`], or is not readable by
+    ///   the current user
+    /// - If file metadata can not be read
+    pub fn build(self) -> Result<TailedFile<T>> {
+        if self.buffer_size == 0 {
+            return Err(StaartError::Config(
+                "buffer size must be greater than zero".to_string(),
+            ));
+        }
+        if self.delimiter.is_empty() {
+            return Err(StaartError::Config(
+                "record delimiter must not be empty".to_string(),
+            ));
+        }
+
+        let (mut f, waited) = self.open()?;
+        let meta = Stat::of(&f)?;
+        let age = meta
+            .modified()
+            .ok()
+            .and_then(|at| clock::system_now().duration_since(at).ok());
+        if let (Some(max), Some(age)) = (self.ignore_older, age) {
+            if age > max {
+                return Err(StaartError::Ignored(age));
+            }
+        }
+        let start = match self.start_policy {
+            Some(policy) => policy.start_for(&meta),
+            None if waited => StartPos::Beginning,
+            None => self.start,
+        };
+        let start = match self.resume {
+            Some(checkpoint)
+                if checkpoint.device == meta.id.device
+                    && checkpoint.inode == meta.id.index
+                    && checkpoint.offset <= meta.len() =>
+            {
+                StartPos::Offset(checkpoint.offset)
+            }
+            Some(_) => StartPos::Beginning,
+            None => start,
+        };
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        let start = match self.decompress {
+            Some(_) => StartPos::Beginning,
+            None => start,
+        };
+        let pos = match start {
+            StartPos::Beginning => 0,
+            StartPos::End => meta.len(),
+            StartPos::Offset(offset) => offset.min(meta.len()),
+            StartPos::LastLines(n) => last_lines_offset(&mut f, meta.len(), n)?,
+            StartPos::LastBytes(n) => meta.len().saturating_sub(n),
+        };
+
+        let format = match self.format {
+            Some(format) => Some(format),
+            None => {
+                let mut sample = Vec::with_capacity(format::SAMPLE_LEN);
+                f.rewind()?;
+                (&mut f)
+                    .take(format::SAMPLE_LEN as u64)
+                    .read_to_end(&mut sample)?;
+                detect(&sample)
+            }
+        };
+
+        let mut timeline = Timeline::new(self.timeline_capacity);
+        #[cfg(feature = "log")]
+        timeline.set_path(self.path.as_ref());
+        timeline.record(Lifecycle::Opened);
+
+        let journal = match self.journal {
+            Some(path) => {
+                let mut journal = Journal::create(&path)?;
+                journal.record(JournalEntry::Open {
+                    id: meta.id.index,
+                    len: meta.len(),
+                    buffer: self.buffer_size,
+                    pos,
+                })?;
+                Some(journal)
+            }
+            None => None,
+        };
+
+        let append_guard = match self.verify_every {
+            Some(every) => Some(AppendGuard::new(every, &mut f, pos)?),
+            None => None,
+        };
+
+        let seen_len = meta.len();
+        Ok(TailedFile {
+            path: self.path,
+            file: Some(Handle::new(f)),
+            pos,
+            meta,
+            buffer_size: self.buffer_size,
+            read_buf: Vec::new(),
+            drain_limit: self.drain_limit,
+            backpressure: self.backpressure,
+            align_records: self.align_records,
+            flush_partial: self.flush_partial,
+            held: None,
+            delimiter: self.delimiter,
+            record_limit: self.record_limit,
+            oversized: 0,
+            binary_policy: self.binary_policy,
+            binary: None,
+            encoding: self.encoding,
+            filter: self.filter,
+            gap_markers: self.gap_markers,
+            gaps: Vec::new(),
+            header_limit: self.header_limit,
+            header: None,
+            pipeline: self.pipeline,
+            catch_up: self.catch_up.map(|c| c.open()).transpose()?,
+            #[cfg(any(feature = "gzip", feature = "zstd"))]
+            decoder: self.decompress.map(|kind| (kind, None)),
+            rotation: self.rotation,
+            errors: self.errors,
+            retry: self.retry,
+            decisions: self.decisions,
+            format,
+            state: TailState::Opening,
+            timeline,
+            journal,
+            append_guard,
+            budget: self.budget.map(BudgetTracker::new),
+            change: None,
+            replaced: None,
+            pending: None,
+            close_inactive: self.close_inactive,
+            last_data: clock::now(),
+            idle_after: self.idle_after,
+            quiet_since: clock::system_now(),
+            idle_reported: false,
+            seen_len,
+            lost: 0,
+            line_buf: Vec::new(),
+            line_consumed: 0,
+            watcher: None,
+            hooks: Hooks::default(),
+            progress: None,
+            shutdown: self.shutdown,
+            shutdown_checkpoint: self.checkpoint_on_shutdown,
+            paused: PauseHandle::new(),
+            stats: TailStats::default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn test_build() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+        let mut f = File::create(path).unwrap();
+        f.write_all(b"existing\n").unwrap();
+
+        let mut tailed_file = TailedFileBuilder::new(&path)
+            .start(StartPos::Beginning)
+            .buffer_size(4)
+            .build()
+            .unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b"exis");
+        assert_eq!(tailed_file.read().unwrap(), b"ting");
+    }
+
+    #[test]
+    fn test_start_offset() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+        let mut f = File::create(path).unwrap();
+        f.write_all(b"existing\n").unwrap();
+
+        let mut tailed_file = TailedFile::new_with_start(&path, StartPos::Offset(4)).unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b"ting\n");
+
+        let mut tailed_file = TailedFile::new_with_start(&path, StartPos::Offset(100)).unwrap();
+        f.write_all(b"more").unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b"more");
+    }
+
+    #[test]
+    fn test_last_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+        let mut f = File::create(path).unwrap();
+        let lines: Vec<String> = (0..5000).map(|i| format!("line {i}\n")).collect();
+        f.write_all(lines.concat().as_bytes()).unwrap();
+
+        let mut tailed_file = TailedFile::new_with_start(&path, StartPos::LastLines(3)).unwrap();
+        assert_eq!(
+            tailed_file.read().unwrap(),
+            b"line 4997\nline 4998\nline 4999\n"
+        );
+
+        f.write_all(b"partial").unwrap();
+        let mut tailed_file = TailedFile::new_with_start(&path, StartPos::LastLines(2)).unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b"line 4999\npartial");
+
+        let tailed_file = TailedFile::new_with_start(&path, StartPos::LastLines(10000)).unwrap();
+        assert_eq!(tailed_file.pos, 0);
+    }
+
+    #[test]
+    fn test_start_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+        File::create(path)
+            .unwrap()
+            .write_all(b"existing\n")
+            .unwrap();
+
+        let started = StartPolicy::new(SystemTime::now() + Duration::from_secs(3600));
+        let mut tailed_file = TailedFileBuilder::new(&path)
+            .start_policy(started)
+            .build()
+            .unwrap();
+        assert!(tailed_file.read().unwrap().is_empty());
+
+        let started = StartPolicy::new(SystemTime::UNIX_EPOCH);
+        let mut tailed_file = TailedFileBuilder::new(&path)
+            .start_policy(started)
+            .build()
+            .unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b"existing\n");
+
+        let mut tailed_file = TailedFileBuilder::new(&path)
+            .start_policy(started.created(StartPos::LastLines(0)))
+            .build()
+            .unwrap();
+        assert!(tailed_file.read().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_wait_for_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.file");
+        assert!(TailedFile::builder(path.clone())
+            .wait_for_file(
+                Backoff::Fixed(Duration::from_millis(10)),
+                Some(Duration::from_millis(30))
+            )
+            .build()
+            .is_err());
+
+        let writer = {
+            let path = path.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(50));
+                std::fs::write(path, b"appeared").unwrap();
+            })
+        };
+        let mut f = TailedFile::builder(path.clone())
+            .wait_for_file(
+                Backoff::Fixed(Duration::from_millis(10)),
+                Some(Duration::from_secs(5)),
+            )
+            .build()
+            .unwrap();
+        writer.join().unwrap();
+        assert_eq!(f.read().unwrap(), b"appeared");
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(f.read().unwrap().is_empty());
+        assert_eq!(f.state(), TailState::WaitingForFile);
+        std::fs::write(&path, b"back").unwrap();
+        assert_eq!(f.read().unwrap(), b"back");
+    }
+
+    #[test]
+    fn test_ignore_older() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+        File::create(path).unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+
+        let tailed_file = TailedFileBuilder::new(&path)
+            .ignore_older(Duration::from_secs(3600))
+            .build();
+        assert!(tailed_file.is_ok());
+
+        let tailed_file = TailedFileBuilder::new(&path)
+            .ignore_older(Duration::from_millis(1))
+            .build();
+        assert!(matches!(tailed_file, Err(StaartError::Ignored(_))));
+    }
+
+    #[test]
+    fn test_last_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+        File::create(path)
+            .unwrap()
+            .write_all(b"existing\n")
+            .unwrap();
+
+        let policy = StartPolicy::new(SystemTime::UNIX_EPOCH).created(StartPos::LastBytes(4));
+        let mut tailed_file = TailedFileBuilder::new(&path)
+            .start_policy(policy)
+            .build()
+            .unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b"ing\n");
+
+        let mut tailed_file = TailedFileBuilder::new(&path)
+            .start(StartPos::LastBytes(100))
+            .build()
+            .unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b"existing\n");
+    }
+
+    #[test]
+    fn test_build_invalid() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+        File::create(path).unwrap();
+
+        assert!(TailedFileBuilder::new(&path)
+            .buffer_size(0)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_ignore_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+        File::create(path).unwrap();
+
+        let mut tailed_file = TailedFileBuilder::new(&path)
+            .error_policy(ErrorPolicy::IgnoreMissing)
+            .build()
+            .unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert!(tailed_file.read().unwrap().is_empty());
+        assert_eq!(tailed_file.state(), TailState::WaitingForFile);
+    }
+}
@@ -0,0 +1,338 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::{Result, StaartError};
+
+type DecoderFn = Arc<dyn Fn(File) -> io::Result<Box<dyn Read + Send>> + Send + Sync>;
+
+/// Placeholder for the rotation number in a [`CatchUp`] pattern
+const NUMBER: &str = "{n}";
+
+/// Most predecessors looked for by default
+pub const DEFAULT_CATCH_UP_FILES: usize = 32;
+
+/// Decoders for compressed predecessors read by a [`CatchUp`], chosen by the
+/// file name suffix.
+///
+/// The default registry decodes `.gz` with the `gzip` feature and `.zst`
+/// with the `zstd` feature. Other formats, including proprietary ones, are
+/// plugged in with [`Decompressors::register`], e.g. `.xz` with the `xz2`
+/// crate:
+///
+/// ```ignore
+/// let decompressors = Decompressors::default().register("xz", |file| {
+///     Ok(Box::new(xz2::read::XzDecoder::new_multi_decoder(file)))
+/// });
+/// ```
+#[derive(Clone)]
+pub struct Decompressors {
+    decoders: Vec<(String, DecoderFn)>,
+}
+
+impl Decompressors {
+    /// Creates a registry without any decoders, reading every predecessor as
+    /// is
+    pub fn new() -> Decompressors {
+        Decompressors {
+            decoders: Vec::new(),
+        }
+    }
+
+    /// Decodes files ending in `.<suffix>` with `decoder`, which wraps the
+    /// opened file. Replaces any decoder registered for the suffix before.
+    pub fn register<F>(mut self, suffix: &str, decoder: F) -> Decompressors
+    where
+        F: Fn(File) -> io::Result<Box<dyn Read + Send>> + Send + Sync + 'static,
+    {
+        let suffix = suffix.trim_start_matches('.').to_string();
+        self.decoders.retain(|(s, _)| *s != suffix);
+        self.decoders.push((suffix, Arc::new(decoder)));
+        self
+    }
+
+    /// Returns the registered suffixes, without their leading dot
+    pub fn suffixes(&self) -> impl Iterator<Item = &str> {
+        self.decoders.iter().map(|(suffix, _)| suffix.as_str())
+    }
+
+    /// Returns the suffix of `name` with a registered decoder, with its dot
+    fn suffix_of<'n>(&self, name: &'n str) -> Option<&'n str> {
+        self.suffixes().find_map(|suffix| {
+            let stem = name.strip_suffix(suffix)?.strip_suffix('.')?;
+            Some(&name[stem.len()..])
+        })
+    }
+
+    /// Opens `path`, decoding it if its suffix is registered
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        let file = File::open(path)?;
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        let decoder = self.decoders.iter().find(|(suffix, _)| {
+            matches!(name.strip_suffix(suffix.as_str()), Some(stem) if stem.ends_with('.'))
+        });
+        match decoder {
+            Some((_, decoder)) => decoder(file),
+            None => Ok(Box::new(file)),
+        }
+    }
+}
+
+impl Default for Decompressors {
+    /// Creates a registry with the decoders enabled by features
+    fn default() -> Decompressors {
+        #[allow(unused_mut)]
+        let mut decompressors = Decompressors::new();
+        #[cfg(feature = "gzip")]
+        {
+            decompressors = decompressors.register("gz", |file| {
+                Ok(Box::new(flate2::read::MultiGzDecoder::new(file)))
+            });
+        }
+        #[cfg(feature = "zstd")]
+        {
+            decompressors = decompressors.register("zst", |file| {
+                Ok(Box::new(zstd::stream::read::Decoder::new(file)?))
+            });
+        }
+        decompressors
+    }
+}
+
+impl fmt::Debug for Decompressors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.suffixes()).finish()
+    }
+}
+
+/// Rotated predecessors of a followed file to read, oldest first, before the
+/// live file, set with [`crate::TailedFileBuilder::catch_up`].
+///
+/// The pattern names the predecessors with `{n}` in place of the rotation
+/// number, as logrotate numbers them: `app.log.1.gz` is the newest and higher
+/// numbers are older. For each number the name is tried as is, and with any
+/// suffix of the [`Decompressors`] removed or added, so `app.log.{n}` and
+/// `app.log.{n}.gz` both find `app.log.1` left uncompressed by logrotate's
+/// `delaycompress` next to `app.log.2.gz`. Predecessors with a registered
+/// suffix are decoded, others are read as is. Predecessors are read whole,
+/// so one that was partly read before a restart is delivered again.
+///
+/// # Example
+/// ```no_run
+/// use std::time::{Duration, SystemTime};
+/// use staart::{CatchUp, StartPos, TailedFile};
+///
+/// let stopped = SystemTime::now() - Duration::from_secs(3600);
+/// let mut f = TailedFile::builder("/var/log/app.log")
+///     .catch_up(CatchUp::new("/var/log/app.log.{n}.gz").newer_than(stopped))
+///     .start(StartPos::Beginning)
+///     .build()
+///     .unwrap();
+/// let backlog = f.read().unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct CatchUp {
+    pattern: String,
+    newer_than: Option<SystemTime>,
+    max_files: usize,
+    decompressors: Decompressors,
+}
+
+impl CatchUp {
+    /// Reads the predecessors named by `pattern`, which must contain `{n}`
+    pub fn new(pattern: &str) -> CatchUp {
+        CatchUp {
+            pattern: pattern.to_string(),
+            newer_than: None,
+            max_files: DEFAULT_CATCH_UP_FILES,
+            decompressors: Decompressors::default(),
+        }
+    }
+
+    /// Decodes predecessors with `decompressors`, defaults to
+    /// [`Decompressors::default`]
+    pub fn decompressors(mut self, decompressors: Decompressors) -> CatchUp {
+        self.decompressors = decompressors;
+        self
+    }
+
+    /// Skips predecessors last modified at or before `time`, e.g. when
+    /// staart was stopped
+    pub fn newer_than(mut self, time: SystemTime) -> CatchUp {
+        self.newer_than = Some(time);
+        self
+    }
+
+    /// Looks for at most `files` predecessors, defaults to
+    /// [`DEFAULT_CATCH_UP_FILES`]
+    pub fn max_files(mut self, files: usize) -> CatchUp {
+        self.max_files = files;
+        self
+    }
+
+    /// Returns the paths of the predecessors to read, oldest first
+    ///
+    /// # Propagates Errors
+    /// - If the pattern does not contain `{n}`
+    /// - If the metadata of a predecessor can not be read
+    pub fn predecessors(&self) -> Result<Vec<PathBuf>> {
+        if !self.pattern.contains(NUMBER) {
+            return Err(StaartError::Pattern(format!(
+                "catch-up pattern {} does not contain {NUMBER}",
+                self.pattern
+            )));
+        }
+
+        let mut paths = Vec::new();
+        let base = match self.decompressors.suffix_of(&self.pattern) {
+            Some(suffix) => &self.pattern[..self.pattern.len() - suffix.len()],
+            None => &self.pattern,
+        };
+        for n in 1..=self.max_files {
+            let base = base.replace(NUMBER, &n.to_string());
+            let candidates = std::iter::once(base.clone()).chain(
+                self.decompressors
+                    .suffixes()
+                    .map(|suffix| format!("{base}.{suffix}")),
+            );
+            let mut found = None;
+            for path in candidates {
+                match std::fs::metadata(&path) {
+                    Ok(meta) => {
+                        found = Some((PathBuf::from(path), meta));
+                        break;
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            let Some((path, meta)) = found else {
+                break;
+            };
+            if let Some(time) = self.newer_than {
+                if meta.modified()? <= time {
+                    break;
+                }
+            }
+            paths.push(path);
+        }
+        paths.reverse();
+
+        Ok(paths)
+    }
+
+    pub(crate) fn open(&self) -> Result<Predecessors> {
+        Ok(Predecessors {
+            paths: self.predecessors()?.into(),
+            decompressors: self.decompressors.clone(),
+            current: None,
+        })
+    }
+}
+
+/// The predecessors left to read, and the one being read
+pub(crate) struct Predecessors {
+    paths: VecDeque<PathBuf>,
+    decompressors: Decompressors,
+    current: Option<Box<dyn Read + Send>>,
+}
+
+impl Predecessors {
+    /// Reads the next data of the predecessors into `out`, returning 0 once
+    /// they have all been read
+    pub(crate) fn read(&mut self, out: &mut [u8]) -> Result<usize> {
+        loop {
+            if let Some(current) = self.current.as_mut() {
+                match current.read(out)? {
+                    0 => self.current = None,
+                    n => return Ok(n),
+                }
+            }
+            let Some(path) = self.paths.pop_front() else {
+                return Ok(0);
+            };
+            self.current = Some(self.decompressors.open(&path)?);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_all(predecessors: &mut Predecessors) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut buf = [0; 64];
+        loop {
+            match predecessors.read(&mut buf).unwrap() {
+                0 => return data,
+                n => data.extend_from_slice(&buf[..n]),
+            }
+        }
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_predecessors() {
+        use std::io::Write;
+
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("app.log");
+        std::fs::write(log.with_extension("log.1"), b"three\n").unwrap();
+        let gz = File::create(log.with_extension("log.2.gz")).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(gz, flate2::Compression::fast());
+        encoder.write_all(b"two\n").unwrap();
+        encoder.finish().unwrap();
+        std::fs::write(log.with_extension("log.4.gz"), b"after a gap\n").unwrap();
+
+        let pattern = format!("{}.{{n}}.gz", log.display());
+        let catch_up = CatchUp::new(&pattern);
+        assert_eq!(
+            catch_up.predecessors().unwrap(),
+            [log.with_extension("log.2.gz"), log.with_extension("log.1")]
+        );
+        let mut predecessors = catch_up.open().unwrap();
+        assert_eq!(read_all(&mut predecessors), b"two\nthree\n");
+
+        assert!(CatchUp::new("app.log.1.gz").predecessors().is_err());
+    }
+
+    #[test]
+    fn test_custom_decompressor() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("app.log");
+        std::fs::write(log.with_extension("log.1"), b"plain\n").unwrap();
+        std::fs::write(log.with_extension("log.2.rev"), b"\ndesrever").unwrap();
+
+        // A toy format storing the data backwards
+        let decompressors = Decompressors::new().register(".rev", |mut file| {
+            let mut data = Vec::new();
+            file.read_to_end(&mut data)?;
+            data.reverse();
+            Ok(Box::new(io::Cursor::new(data)))
+        });
+        assert_eq!(format!("{decompressors:?}"), r#"["rev"]"#);
+        let pattern = format!("{}.{{n}}.rev", log.display());
+        let catch_up = CatchUp::new(&pattern).decompressors(decompressors);
+        assert_eq!(
+            catch_up.predecessors().unwrap(),
+            [log.with_extension("log.2.rev"), log.with_extension("log.1")]
+        );
+        assert_eq!(
+            read_all(&mut catch_up.open().unwrap()),
+            b"reversed\nplain\n"
+        );
+    }
+}
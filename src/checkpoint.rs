@@ -0,0 +1,137 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::{Result, StaartError};
+
+/// The identity of a followed file and the read position within it, from
+/// which following can resume after a restart, see
+/// [`crate::TailedFile::resume`].
+///
+/// A checkpoint is persisted as a single line of text:
+/// `device=2049 inode=1234 offset=5678`.
+///
+/// # Example
+/// ```no_run
+/// use staart::{Checkpoint, TailedFile};
+///
+/// let mut f = match Checkpoint::load("/var/lib/app/syslog.pos") {
+///     Ok(checkpoint) => TailedFile::resume("/var/log/syslog", checkpoint).unwrap(),
+///     Err(_) => TailedFile::new("/var/log/syslog").unwrap(),
+/// };
+/// f.read().unwrap();
+/// f.checkpoint().save("/var/lib/app/syslog.pos").unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Checkpoint {
+    /// Device holding the file, or volume serial number on Windows
+    pub device: u64,
+    /// Inode of the file, or file index on Windows
+    pub inode: u64,
+    /// Offset up to which the file has been read
+    pub offset: u64,
+}
+
+impl Checkpoint {
+    /// Writes the checkpoint to `path`. The file is replaced atomically, so a
+    /// crash never leaves a partially written checkpoint behind.
+    ///
+    /// # Propagates Errors
+    /// - If the checkpoint can not be written
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let mut tmp = path.as_os_str().to_os_string();
+        tmp.push(".tmp");
+        std::fs::write(&tmp, format!("{self}\n"))?;
+        std::fs::rename(&tmp, path)?;
+
+        Ok(())
+    }
+
+    /// Reads a checkpoint written by [`Checkpoint::save`] from `path`
+    ///
+    /// # Propagates Errors
+    /// - If the checkpoint can not be read or is invalid
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Checkpoint> {
+        std::fs::read_to_string(path)?.trim().parse()
+    }
+}
+
+impl fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "device={} inode={} offset={}",
+            self.device, self.inode, self.offset
+        )
+    }
+}
+
+impl FromStr for Checkpoint {
+    type Err = StaartError;
+
+    fn from_str(s: &str) -> Result<Checkpoint> {
+        let invalid = || StaartError::Config(format!("invalid checkpoint: {s}"));
+        let mut words = s.split_whitespace();
+        let mut field = |name: &str| -> Result<u64> {
+            words
+                .next()
+                .and_then(|w| w.strip_prefix(name))
+                .and_then(|w| w.strip_prefix('='))
+                .and_then(|v| v.parse().ok())
+                .ok_or_else(invalid)
+        };
+
+        Ok(Checkpoint {
+            device: field("device")?,
+            inode: field("inode")?,
+            offset: field("offset")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.pos");
+        let checkpoint = Checkpoint {
+            device: 2049,
+            inode: 1234,
+            offset: 5678,
+        };
+
+        checkpoint.save(&path).unwrap();
+        assert_eq!(Checkpoint::load(&path).unwrap(), checkpoint);
+        assert!("device=1 inode=2".parse::<Checkpoint>().is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let checkpoint = Checkpoint {
+            device: 2049,
+            inode: 1234,
+            offset: 5678,
+        };
+
+        let json = serde_json::to_string(&checkpoint).unwrap();
+        assert_eq!(json, r#"{"device":2049,"inode":1234,"offset":5678}"#);
+        assert_eq!(
+            serde_json::from_str::<Checkpoint>(&json).unwrap(),
+            checkpoint
+        );
+    }
+}
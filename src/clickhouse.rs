@@ -0,0 +1,476 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+use serde_json::Value;
+
+use crate::http::Endpoint;
+use crate::sink::Partials;
+use crate::{clock, Bandwidth, Compression, Line, LinesStr, Result, Sink, StaartError};
+
+/// Default number of rows sent in one insert
+pub const DEFAULT_CLICKHOUSE_BATCH: usize = 1000;
+
+/// Default number of rows held while ClickHouse can not be reached
+pub const DEFAULT_CLICKHOUSE_MAX_PENDING: usize = 100 * DEFAULT_CLICKHOUSE_BATCH;
+
+/// A field of the record stored for each line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordField {
+    /// Path of the file the line was read from, column `path` by default
+    Path,
+    /// Time the line was stored, in seconds since the Unix epoch with
+    /// millisecond precision, column `ts` by default
+    Timestamp,
+    /// Offset of the line in the file, or `null` if unknown, column `offset`
+    /// by default
+    Offset,
+    /// Text of the line, column `line` by default
+    Line,
+}
+
+const FIELDS: [RecordField; 4] = [
+    RecordField::Path,
+    RecordField::Timestamp,
+    RecordField::Offset,
+    RecordField::Line,
+];
+
+/// A [`Sink`] inserting lines into a ClickHouse table through its HTTP
+/// interface, enabled by the `clickhouse` feature.
+///
+/// Rows are sent in the `JSONEachRow` format once `batch_size` lines are
+/// buffered, and on every [`Sink::flush`]. The rows of a failed insert are
+/// kept for the next attempt, made after another `batch_size` lines or on the
+/// next flush. The rows of a write are always kept, even when it fails, but
+/// once [`ClickHouseSink::max_pending`] rows are held every write returns an
+/// error, so that a caller such as
+/// [`TailedFile::follow`](crate::TailedFile::follow) stops reading until
+/// ClickHouse catches up. A partial line ending a chunk is held back until
+/// the rest of it is written.
+///
+/// Each [`RecordField`] of a line maps to a column, which can be renamed or
+/// left out to match an existing table, e.g.
+///
+/// ```sql
+/// CREATE TABLE logs (path String, ts DateTime64(3), offset Nullable(UInt64), line String)
+/// ENGINE = MergeTree ORDER BY ts
+/// ```
+///
+/// Only plain `http://` endpoints are supported.
+///
+/// # Example
+/// ```no_run
+/// use staart::{ClickHouseSink, RecordField, Sink};
+///
+/// let mut sink = ClickHouseSink::new("http://localhost:8123", "logs")
+///     .unwrap()
+///     .column(RecordField::Line, "message")
+///     .credentials("default", "secret")
+///     .unwrap();
+/// sink.write_chunk(std::path::Path::new("/var/log/syslog"), b"hello\n").unwrap();
+/// sink.flush().unwrap();
+/// ```
+pub struct ClickHouseSink {
+    endpoint: Endpoint,
+    table: String,
+    columns: [Option<String>; 4],
+    credentials: Option<(String, String)>,
+    batch_size: usize,
+    max_pending: usize,
+    timeout: Duration,
+    compression: Compression,
+    bandwidth: Option<Bandwidth>,
+    partials: Partials,
+    body: Vec<u8>,
+    rows: usize,
+    send_at: usize,
+}
+
+impl ClickHouseSink {
+    /// Creates a sink inserting into `table` through the HTTP interface at
+    /// `url`, such as `http://localhost:8123`
+    ///
+    /// # Propagates Errors
+    /// - If `url` is not a plain `http://` URL
+    /// - If `table` is not a plain identifier, optionally qualified by its
+    ///   database as in `db.table`
+    pub fn new(url: &str, table: &str) -> Result<ClickHouseSink> {
+        let endpoint = Endpoint::parse(url, 8123)
+            .ok_or_else(|| StaartError::Config(format!("unsupported ClickHouse URL: {url}")))?;
+        if !valid_table(table) {
+            return Err(StaartError::Config(format!(
+                "invalid ClickHouse table: {table:?}"
+            )));
+        }
+
+        Ok(ClickHouseSink {
+            endpoint,
+            table: table.to_string(),
+            columns: FIELDS.map(|field| Some(default_column(field).to_string())),
+            credentials: None,
+            batch_size: DEFAULT_CLICKHOUSE_BATCH,
+            max_pending: DEFAULT_CLICKHOUSE_MAX_PENDING,
+            timeout: Duration::from_secs(10),
+            compression: Compression::None,
+            bandwidth: None,
+            partials: Partials::default(),
+            body: Vec::new(),
+            rows: 0,
+            send_at: DEFAULT_CLICKHOUSE_BATCH,
+        })
+    }
+
+    /// Stores `field` in the column `name`
+    pub fn column(mut self, field: RecordField, name: &str) -> ClickHouseSink {
+        self.columns[field as usize] = Some(name.to_string());
+        self
+    }
+
+    /// Leaves `field` out of the inserted rows
+    pub fn without(mut self, field: RecordField) -> ClickHouseSink {
+        self.columns[field as usize] = None;
+        self
+    }
+
+    /// Authenticates as `user` with `password`
+    ///
+    /// # Propagates Errors
+    /// - If `user` or `password` contains control characters, which would
+    ///   corrupt the request headers
+    pub fn credentials(mut self, user: &str, password: &str) -> Result<ClickHouseSink> {
+        if user.chars().chain(password.chars()).any(char::is_control) {
+            return Err(StaartError::Config(
+                "invalid ClickHouse credentials: control characters are not allowed".to_string(),
+            ));
+        }
+        self.credentials = Some((user.to_string(), password.to_string()));
+        Ok(self)
+    }
+
+    /// Sends an insert once `rows` lines are buffered
+    pub fn batch_size(mut self, rows: usize) -> ClickHouseSink {
+        self.batch_size = rows.max(1);
+        self.send_at = self.batch_size;
+        self
+    }
+
+    /// Fails every write once `rows` are waiting to be sent, defaults to
+    /// [`DEFAULT_CLICKHOUSE_MAX_PENDING`]
+    pub fn max_pending(mut self, rows: usize) -> ClickHouseSink {
+        self.max_pending = rows.max(1);
+        self
+    }
+
+    /// Gives up on a request to ClickHouse after `timeout`
+    pub fn timeout(mut self, timeout: Duration) -> ClickHouseSink {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Compresses each insert, sent with the matching `Content-Encoding`
+    pub fn compression(mut self, compression: Compression) -> ClickHouseSink {
+        self.compression = compression;
+        self
+    }
+
+    /// Charges every write to `bandwidth`, blocking while it is exhausted
+    pub fn bandwidth(mut self, bandwidth: Bandwidth) -> ClickHouseSink {
+        self.bandwidth = Some(bandwidth);
+        self
+    }
+
+    /// Returns the number of rows buffered and not yet sent
+    pub fn pending(&self) -> usize {
+        self.rows
+    }
+
+    fn push(&mut self, path: &Path, offset: Option<u64>, line: &str) -> Result<()> {
+        // Written by hand rather than through a map to keep the columns in
+        // field order
+        self.body.push(b'{');
+        let mut first = true;
+        for field in FIELDS {
+            let Some(column) = &self.columns[field as usize] else {
+                continue;
+            };
+            if !first {
+                self.body.push(b',');
+            }
+            first = false;
+            let value = match field {
+                RecordField::Path => Value::from(path.to_string_lossy()),
+                RecordField::Timestamp => Value::from(now()),
+                RecordField::Offset => offset.map_or(Value::Null, Value::from),
+                RecordField::Line => Value::from(line),
+            };
+            serde_json::to_writer(&mut self.body, column)
+                .map_err(|e| StaartError::Sink(e.to_string()))?;
+            self.body.push(b':');
+            serde_json::to_writer(&mut self.body, &value)
+                .map_err(|e| StaartError::Sink(e.to_string()))?;
+        }
+        self.body.extend_from_slice(b"}\n");
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// Sends the buffered rows once a batch is complete, and fails if too
+    /// many are waiting. Called after all the rows of a write are buffered,
+    /// so that none of them are lost when the insert fails.
+    fn settle(&mut self) -> Result<()> {
+        // Rows kept after a failed insert are only sent again once another
+        // batch is complete, rather than with every write
+        if self.rows >= self.send_at {
+            self.send_at = self.rows + self.batch_size;
+            self.send()?;
+        }
+        if self.rows >= self.max_pending {
+            return Err(StaartError::Sink(format!(
+                "{} rows are waiting for ClickHouse",
+                self.rows
+            )));
+        }
+        Ok(())
+    }
+
+    /// Sends the buffered rows in a single insert. The rows are kept for the
+    /// next attempt if the insert fails.
+    fn send(&mut self) -> Result<()> {
+        if self.rows == 0 {
+            return Ok(());
+        }
+
+        let query = format!("INSERT INTO {} FORMAT JSONEachRow", self.table);
+        let body = self.compression.compress(&self.body)?;
+        let mut headers = vec![("Content-Type", "application/x-ndjson")];
+        if let Some(encoding) = self.compression.encoding() {
+            headers.push(("Content-Encoding", encoding));
+        }
+        if let Some((user, password)) = &self.credentials {
+            headers.push(("X-ClickHouse-User", user));
+            headers.push(("X-ClickHouse-Key", password));
+        }
+        let path = format!("/?query={}", encode(&query));
+        let response = self.endpoint.post(&path, &headers, &body, self.timeout)?;
+        if response.status() != "200" {
+            return Err(StaartError::Sink(format!(
+                "ClickHouse insert failed with status {}: {}",
+                response.status(),
+                response.body()
+            )));
+        }
+
+        self.body.clear();
+        self.rows = 0;
+        self.send_at = self.batch_size;
+        Ok(())
+    }
+}
+
+fn default_column(field: RecordField) -> &'static str {
+    match field {
+        RecordField::Path => "path",
+        RecordField::Timestamp => "ts",
+        RecordField::Offset => "offset",
+        RecordField::Line => "line",
+    }
+}
+
+/// Returns whether `table` is an identifier, optionally qualified by its
+/// database, that can be put into a query as is
+fn valid_table(table: &str) -> bool {
+    let mut parts = table.split('.');
+    let valid = |part: &str| {
+        part.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(name), None, None) => valid(name),
+        (Some(db), Some(name), None) => valid(db) && valid(name),
+        _ => false,
+    }
+}
+
+/// Seconds since the Unix epoch, rounded to milliseconds
+fn now() -> f64 {
+    let millis = clock::system_now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis());
+    millis as f64 / 1000.0
+}
+
+/// Percent-encodes `s` for use in a query string
+fn encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+impl Sink for ClickHouseSink {
+    fn write_chunk(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        if let Some(bandwidth) = &self.bandwidth {
+            bandwidth.acquire(path, data.len() as u64);
+        }
+        let data = self.partials.complete(path, data);
+        for line in LinesStr::new(&data) {
+            self.push(path, None, &line)?;
+        }
+        self.settle()
+    }
+
+    fn write_lines(&mut self, path: &Path, lines: &[Line]) -> Result<()> {
+        if let Some(bandwidth) = &self.bandwidth {
+            let bytes = lines.iter().map(|line| line.text.len() as u64 + 1).sum();
+            bandwidth.acquire(path, bytes);
+        }
+        for line in lines {
+            self.push(path, Some(line.offset), &line.text)?;
+        }
+        self.settle()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.send()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        for (path, line) in self.partials.take_all() {
+            self.write_chunk(&path, &line)?;
+        }
+        self.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    fn serve_once(listener: TcpListener, status: &'static str) -> std::thread::JoinHandle<String> {
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut head = String::new();
+            let mut len = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(v) = line.strip_prefix("Content-Length: ") {
+                    len = v.trim().parse().unwrap();
+                }
+                head.push_str(&line);
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; len];
+            reader.read_exact(&mut body).unwrap();
+            let response = format!("HTTP/1.1 {status}\r\nContent-Length: 2\r\n\r\nno");
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+            head + &String::from_utf8_lossy(&body)
+        })
+    }
+
+    #[test]
+    fn test_clickhouse_insert() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = serve_once(listener, "200 OK");
+
+        let mut sink = ClickHouseSink::new(&url, "logs")
+            .unwrap()
+            .column(RecordField::Line, "message")
+            .without(RecordField::Timestamp)
+            .batch_size(2);
+        let lines = [
+            Line {
+                text: "one".to_string(),
+                offset: 0,
+            },
+            Line {
+                text: "two \"quoted\"".to_string(),
+                offset: 4,
+            },
+        ];
+        sink.write_lines(Path::new("/var/log/app.log"), &lines)
+            .unwrap();
+        assert_eq!(sink.pending(), 0);
+
+        let request = server.join().unwrap();
+        assert!(request
+            .starts_with("POST /?query=INSERT%20INTO%20logs%20FORMAT%20JSONEachRow HTTP/1.1\r\n"));
+        assert!(request.ends_with(concat!(
+            r#"{"path":"/var/log/app.log","offset":0,"message":"one"}"#,
+            "\n",
+            r#"{"path":"/var/log/app.log","offset":4,"message":"two \"quoted\""}"#,
+            "\n"
+        )));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_clickhouse_gzip() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = serve_once(listener, "200 OK");
+
+        let mut sink = ClickHouseSink::new(&url, "logs")
+            .unwrap()
+            .compression(Compression::Gzip(6));
+        sink.write_chunk(Path::new("a.log"), b"data\n").unwrap();
+        sink.flush().unwrap();
+
+        let request = server.join().unwrap();
+        assert!(request.contains("Content-Encoding: gzip\r\n"));
+        // Gzip magic bytes, the second of which is not valid UTF-8
+        let body = request.split("\r\n\r\n").nth(1).unwrap();
+        assert!(body.starts_with("\u{1f}\u{fffd}"));
+    }
+
+    #[test]
+    fn test_clickhouse_failure() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = serve_once(listener.try_clone().unwrap(), "500 Internal Server Error");
+
+        let mut sink = ClickHouseSink::new(&url, "logs").unwrap().max_pending(2);
+        sink.write_chunk(Path::new("a.log"), b"da").unwrap();
+        assert_eq!(sink.pending(), 0);
+        sink.write_chunk(Path::new("a.log"), b"ta\n").unwrap();
+        assert!(sink.flush().is_err());
+        assert_eq!(sink.pending(), 1);
+        server.join().unwrap();
+
+        // Rows past the limit fail the write but are kept
+        assert!(sink.write_chunk(Path::new("a.log"), b"more\n").is_err());
+        assert_eq!(sink.pending(), 2);
+        let server = serve_once(listener, "200 OK");
+        sink.flush().unwrap();
+        assert_eq!(sink.pending(), 0);
+        let request = server.join().unwrap();
+        assert!(request.contains(r#""line":"data"}"#));
+        assert!(request.contains(r#""line":"more"}"#));
+
+        assert!(ClickHouseSink::new("https://localhost", "logs").is_err());
+        assert!(ClickHouseSink::new("http://localhost", "default.logs").is_ok());
+        for table in ["logs; DROP TABLE x", "a.b.c", "1logs", ""] {
+            assert!(ClickHouseSink::new("http://localhost", table).is_err());
+        }
+        let sink = ClickHouseSink::new("http://localhost", "logs").unwrap();
+        assert!(sink.credentials("user", "secret\r\nX-Evil: 1").is_err());
+    }
+}
@@ -0,0 +1,54 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::time::{Instant, SystemTime};
+
+#[cfg(feature = "testing")]
+use std::cell::Cell;
+#[cfg(feature = "testing")]
+use std::time::Duration;
+
+#[cfg(feature = "testing")]
+thread_local! {
+    /// How far [`crate::testing::TestClock`] moved time forward on this thread
+    static OFFSET: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+}
+
+/// Returns the current instant, as seen by the followed files
+#[cfg(not(feature = "testing"))]
+pub(crate) fn now() -> Instant {
+    Instant::now()
+}
+
+/// Returns the current system time, as seen by the followed files
+#[cfg(not(feature = "testing"))]
+pub(crate) fn system_now() -> SystemTime {
+    SystemTime::now()
+}
+
+/// Returns the current instant, moved forward by the test clock
+#[cfg(feature = "testing")]
+pub(crate) fn now() -> Instant {
+    Instant::now() + offset()
+}
+
+/// Returns the current system time, moved forward by the test clock
+#[cfg(feature = "testing")]
+pub(crate) fn system_now() -> SystemTime {
+    SystemTime::now() + offset()
+}
+
+#[cfg(feature = "testing")]
+pub(crate) fn offset() -> Duration {
+    OFFSET.with(Cell::get)
+}
+
+#[cfg(feature = "testing")]
+pub(crate) fn set_offset(offset: Duration) {
+    OFFSET.with(|o| o.set(offset));
+}
@@ -0,0 +1,128 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::borrow::Cow;
+
+use crate::Result;
+
+/// How a network sink compresses what it sends.
+///
+/// Gzip is available with the `gzip` feature and zstd with the `zstd` feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    /// Data is sent as is
+    #[default]
+    None,
+    /// Gzip at the given level, from 0 to 9
+    #[cfg(feature = "gzip")]
+    Gzip(u32),
+    /// Zstandard at the given level, from 1 to 22
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+}
+
+impl Compression {
+    /// Returns `true` unless data is sent as is
+    pub fn is_enabled(&self) -> bool {
+        *self != Compression::None
+    }
+
+    /// Returns the name of the encoding for an HTTP `Content-Encoding` header
+    pub fn encoding(&self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            #[cfg(feature = "gzip")]
+            Compression::Gzip(_) => Some("gzip"),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(_) => Some("zstd"),
+        }
+    }
+
+    /// Compresses `data`, borrowing it when sent as is
+    pub(crate) fn compress<'a>(&self, data: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        match *self {
+            Compression::None => Ok(Cow::Borrowed(data)),
+            #[cfg(feature = "gzip")]
+            Compression::Gzip(level) => {
+                use std::io::Write;
+
+                let level = flate2::Compression::new(level.min(9));
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), level);
+                encoder.write_all(data)?;
+                Ok(Cow::Owned(encoder.finish()?))
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(level) => Ok(Cow::Owned(zstd::encode_all(data, level)?)),
+        }
+    }
+
+    /// Splits `lines` into the payloads of messages: one per line when sent as
+    /// is, or a single compressed frame of newline-terminated lines otherwise
+    #[cfg(any(feature = "nats", feature = "mqtt"))]
+    pub(crate) fn frames<'l, I>(&self, lines: I) -> Result<Vec<Cow<'l, [u8]>>>
+    where
+        I: IntoIterator<Item = &'l str>,
+    {
+        if !self.is_enabled() {
+            return Ok(lines
+                .into_iter()
+                .map(|l| Cow::Borrowed(l.as_bytes()))
+                .collect());
+        }
+
+        let mut frame = Vec::new();
+        for line in lines {
+            frame.extend_from_slice(line.as_bytes());
+            frame.push(b'\n');
+        }
+        if frame.is_empty() {
+            return Ok(Vec::new());
+        }
+        let compressed = self.compress(&frame)?.into_owned();
+        Ok(vec![Cow::Owned(compressed)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_none() {
+        let data = b"unchanged";
+        assert!(matches!(
+            Compression::None.compress(data).unwrap(),
+            Cow::Borrowed(d) if d == data
+        ));
+        assert_eq!(Compression::default().encoding(), None);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip() {
+        use std::io::Read;
+
+        let data = b"line\n".repeat(100);
+        let compressed = Compression::Gzip(6).compress(&data).unwrap();
+        assert!(compressed.len() < data.len());
+        let mut decoded = Vec::new();
+        flate2::read::GzDecoder::new(&compressed[..])
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd() {
+        let data = b"line\n".repeat(100);
+        let compressed = Compression::Zstd(3).compress(&data).unwrap();
+        assert!(compressed.len() < data.len());
+        assert_eq!(zstd::decode_all(&compressed[..]).unwrap(), data);
+    }
+}
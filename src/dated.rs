@@ -0,0 +1,337 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::timestamp::{civil_from_days, days_from_civil};
+use crate::{clock, Result, StaartError, StartPos, TailedFile};
+
+/// How long before a boundary the next file is opened by default
+pub const DEFAULT_PREOPEN: Duration = Duration::from_secs(5);
+
+/// A part of a [`DatedTailer`] template
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Item {
+    Literal(String),
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+}
+
+/// Follows a file whose path holds the date, such as
+/// `/var/log/app/%Y/%m/%d/app.log`, moving on to the next path when the date
+/// in it changes.
+///
+/// The template may contain `%Y` (year), `%m` (month), `%d` (day), `%H`
+/// (hour) and `%M` (minute), zero padded, and `%%` for a literal `%`. The
+/// finest of them sets the boundary at which the path rolls over. At a
+/// boundary the previous file is drained before the next one is read from
+/// its beginning. The next file is opened up to [`DatedTailer::preopen`]
+/// early, as soon as it exists, so that nothing written to it around the
+/// boundary is missed.
+///
+/// The file for the current date is followed from its end if it exists when
+/// the tailer is created, and from its beginning if it appears later.
+///
+/// # Example
+/// ```no_run
+/// use std::thread::sleep;
+/// use std::time::Duration;
+/// use staart::DatedTailer;
+///
+/// let mut tailer = DatedTailer::new("/var/log/app/%Y/%m/%d/app.log").unwrap();
+/// loop {
+///     let data = tailer.read().unwrap();
+///     print!("{}", String::from_utf8_lossy(&data));
+///     sleep(Duration::from_millis(100));
+/// }
+/// ```
+pub struct DatedTailer {
+    items: Vec<Item>,
+    offset: i64,
+    preopen: Duration,
+    path: PathBuf,
+    boundary: SystemTime,
+    current: Option<TailedFile<PathBuf>>,
+    next: Option<TailedFile<PathBuf>>,
+}
+
+impl DatedTailer {
+    /// Creates a tailer following the path `template` gives for the current
+    /// UTC date
+    ///
+    /// # Propagates Errors
+    /// - If the template contains no date placeholder or an unknown one
+    /// - If the file for the current date exists but can not be opened
+    pub fn new(template: &str) -> Result<DatedTailer> {
+        let items = parse(template)?;
+        let mut tailer = DatedTailer {
+            items,
+            offset: 0,
+            preopen: DEFAULT_PREOPEN,
+            path: PathBuf::new(),
+            boundary: UNIX_EPOCH,
+            current: None,
+            next: None,
+        };
+        tailer.roll(clock::system_now(), StartPos::End)?;
+
+        Ok(tailer)
+    }
+
+    /// Renders dates with the UTC offset `seconds_east` instead of UTC
+    ///
+    /// # Propagates Errors
+    /// - If the file for the current date exists but can not be opened
+    pub fn utc_offset(mut self, seconds_east: i32) -> Result<DatedTailer> {
+        self.offset = i64::from(seconds_east);
+        self.current = None;
+        self.next = None;
+        self.roll(clock::system_now(), StartPos::End)?;
+
+        Ok(self)
+    }
+
+    /// Sets how long before a boundary the next file is opened, defaults to
+    /// [`DEFAULT_PREOPEN`]
+    pub fn preopen(mut self, lead: Duration) -> DatedTailer {
+        self.preopen = lead;
+        self
+    }
+
+    /// Returns the path for the current date
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns when the path next rolls over
+    pub fn boundary(&self) -> SystemTime {
+        self.boundary
+    }
+
+    /// Returns the data written to the file for the current date since the
+    /// last read, first draining the previous file if a boundary was passed.
+    ///
+    /// # Propagates Errors
+    /// - If a file exists but can not be read
+    pub fn read(&mut self) -> Result<Vec<u8>> {
+        self.read_at(clock::system_now())
+    }
+
+    fn read_at(&mut self, now: SystemTime) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        if now >= self.boundary {
+            if let Some(mut previous) = self.current.take() {
+                drain(&mut previous, &mut data)?;
+            }
+            self.roll(now, StartPos::Beginning)?;
+        }
+        if self.current.is_none() {
+            self.current = open(&self.path, StartPos::Beginning)?;
+        }
+        if let Some(file) = self.current.as_mut() {
+            file.read_into(&mut data)?;
+        }
+        if self.next.is_none() && now + self.preopen >= self.boundary {
+            self.next = open(&self.render(self.boundary), StartPos::Beginning)?;
+        }
+
+        Ok(data)
+    }
+
+    /// Moves to the path for `now`, using the pre-opened file if it is the one
+    fn roll(&mut self, now: SystemTime, start: StartPos) -> Result<()> {
+        self.path = self.render(now);
+        self.boundary = self.next_boundary(now);
+        self.current = match self.next.take() {
+            Some(next) if next.path() == self.path => Some(next),
+            _ => open(&self.path, start)?,
+        };
+
+        Ok(())
+    }
+
+    /// Renders the template for the local date at `time`
+    fn render(&self, time: SystemTime) -> PathBuf {
+        let (y, mo, d, h, mi) = fields(self.local_secs(time));
+        let mut path = String::new();
+        for item in &self.items {
+            match item {
+                Item::Literal(s) => path.push_str(s),
+                Item::Year => path.push_str(&format!("{y:04}")),
+                Item::Month => path.push_str(&format!("{mo:02}")),
+                Item::Day => path.push_str(&format!("{d:02}")),
+                Item::Hour => path.push_str(&format!("{h:02}")),
+                Item::Minute => path.push_str(&format!("{mi:02}")),
+            }
+        }
+        PathBuf::from(path)
+    }
+
+    /// Returns the first time after `time` at which the rendered path changes
+    fn next_boundary(&self, time: SystemTime) -> SystemTime {
+        let secs = self.local_secs(time);
+        let (y, mo, _, _, _) = fields(secs);
+        let has = |item: Item| self.items.contains(&item);
+        let next = if has(Item::Minute) {
+            (secs.div_euclid(60) + 1) * 60
+        } else if has(Item::Hour) {
+            (secs.div_euclid(3600) + 1) * 3600
+        } else if has(Item::Day) {
+            (secs.div_euclid(86400) + 1) * 86400
+        } else if has(Item::Month) {
+            let (y, mo) = if mo == 12 { (y + 1, 1) } else { (y, mo + 1) };
+            days_from_civil(y, mo, 1) * 86400
+        } else {
+            days_from_civil(y + 1, 1, 1) * 86400
+        };
+        let utc = next - self.offset;
+        if utc >= 0 {
+            UNIX_EPOCH + Duration::from_secs(utc as u64)
+        } else {
+            UNIX_EPOCH - Duration::from_secs(utc.unsigned_abs())
+        }
+    }
+
+    fn local_secs(&self, time: SystemTime) -> i64 {
+        let secs = match time.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs() as i64),
+        };
+        secs + self.offset
+    }
+}
+
+fn parse(template: &str) -> Result<Vec<Item>> {
+    let mut items = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            literal.push(c);
+            continue;
+        }
+        let item = match chars.next() {
+            Some('%') => {
+                literal.push('%');
+                continue;
+            }
+            Some('Y') => Item::Year,
+            Some('m') => Item::Month,
+            Some('d') => Item::Day,
+            Some('H') => Item::Hour,
+            Some('M') => Item::Minute,
+            other => {
+                return Err(StaartError::Pattern(format!(
+                    "unknown placeholder %{} in {template}",
+                    other.map(String::from).unwrap_or_default()
+                )))
+            }
+        };
+        if !literal.is_empty() {
+            items.push(Item::Literal(std::mem::take(&mut literal)));
+        }
+        items.push(item);
+    }
+    if !items.iter().any(|i| !matches!(i, Item::Literal(_))) {
+        return Err(StaartError::Pattern(format!(
+            "no date placeholder in {template}"
+        )));
+    }
+    if !literal.is_empty() {
+        items.push(Item::Literal(literal));
+    }
+
+    Ok(items)
+}
+
+/// Splits local seconds since the epoch into year, month, day, hour and minute
+fn fields(secs: i64) -> (i64, u32, u32, i64, i64) {
+    let (y, mo, d) = civil_from_days(secs.div_euclid(86400));
+    let rem = secs.rem_euclid(86400);
+    (y, mo, d, rem / 3600, rem % 3600 / 60)
+}
+
+/// Opens `path` if it exists
+fn open(path: &Path, start: StartPos) -> Result<Option<TailedFile<PathBuf>>> {
+    match TailedFile::new_with_start(path.to_path_buf(), start) {
+        Ok(file) => Ok(Some(file)),
+        Err(StaartError::IO(e)) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn drain(file: &mut TailedFile<PathBuf>, data: &mut Vec<u8>) -> Result<()> {
+    loop {
+        let len = data.len();
+        file.read_into(data)?;
+        if data.len() == len {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{File, OpenOptions};
+    use std::io::Write;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_render() {
+        let tailer = DatedTailer::new("/nonexistent/%Y/%m/%d/app-%H%%.log").unwrap();
+        // 2024-02-29T23:30:00Z
+        let time = at(1_709_249_400);
+        assert_eq!(
+            tailer.render(time),
+            Path::new("/nonexistent/2024/02/29/app-23%.log")
+        );
+        assert_eq!(tailer.next_boundary(time), at(1_709_251_200));
+
+        let monthly = DatedTailer::new("/nonexistent/%Y-%m.log").unwrap();
+        // 2024-03-01T00:00:00Z
+        assert_eq!(monthly.next_boundary(time), at(1_709_251_200));
+        let eastern = monthly.utc_offset(3600).unwrap();
+        assert_eq!(eastern.render(time), Path::new("/nonexistent/2024-03.log"));
+
+        assert!(DatedTailer::new("/var/log/app.log").is_err());
+        assert!(DatedTailer::new("/var/log/%Q.log").is_err());
+    }
+
+    #[test]
+    fn test_roll_over() {
+        let dir = tempfile::tempdir().unwrap();
+        let template = dir.path().join("app-%H.log");
+        let mut tailer = DatedTailer::new(template.to_str().unwrap()).unwrap();
+
+        // 1970-01-01T00:59:50Z, before the 01:00 boundary
+        tailer.roll(at(3590), StartPos::End).unwrap();
+        let first = dir.path().join("app-00.log");
+        let second = dir.path().join("app-01.log");
+        assert_eq!(tailer.path(), first);
+        File::create(&first).unwrap().write_all(b"one\n").unwrap();
+        File::create(&second)
+            .unwrap()
+            .write_all(b"early\n")
+            .unwrap();
+        assert_eq!(tailer.read_at(at(3596)).unwrap(), b"one\n");
+        assert!(tailer.next.is_some());
+
+        let mut f1 = OpenOptions::new().append(true).open(&first).unwrap();
+        f1.write_all(b"two\n").unwrap();
+        assert_eq!(tailer.read_at(at(3601)).unwrap(), b"two\nearly\n");
+        assert_eq!(tailer.path(), second);
+    }
+}
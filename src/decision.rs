@@ -0,0 +1,151 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+/// A combination of file identity change and length change, relative to the
+/// read position, observed before a read
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Branch {
+    /// Same file, longer than the read position
+    Grew,
+    /// Same file, exactly at the read position
+    Unchanged,
+    /// Same file, shorter than the read position, i.e. truncated
+    Shrank,
+    /// Different file, longer than the read position
+    RotatedGrew,
+    /// Different file, exactly as long as the read position
+    RotatedUnchanged,
+    /// Different file, shorter than the read position
+    RotatedShrank,
+}
+
+impl Branch {
+    pub(crate) fn new(rotated: bool, len: Ordering) -> Branch {
+        match (rotated, len) {
+            (false, Ordering::Greater) => Branch::Grew,
+            (false, Ordering::Equal) => Branch::Unchanged,
+            (false, Ordering::Less) => Branch::Shrank,
+            (true, Ordering::Greater) => Branch::RotatedGrew,
+            (true, Ordering::Equal) => Branch::RotatedUnchanged,
+            (true, Ordering::Less) => Branch::RotatedShrank,
+        }
+    }
+
+    /// Returns `true` if the file at the path was replaced
+    pub fn is_rotation(&self) -> bool {
+        matches!(
+            self,
+            Branch::RotatedGrew | Branch::RotatedUnchanged | Branch::RotatedShrank
+        )
+    }
+
+    /// Returns `true` for branches other than ordinary growth of the same file
+    pub(crate) fn is_notable(&self) -> bool {
+        !matches!(self, Branch::Grew | Branch::Unchanged)
+    }
+}
+
+impl fmt::Display for Branch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Branch::Grew => "grew",
+            Branch::Unchanged => "unchanged",
+            Branch::Shrank => "shrank",
+            Branch::RotatedGrew => "rotated-grew",
+            Branch::RotatedUnchanged => "rotated-unchanged",
+            Branch::RotatedShrank => "rotated-shrank",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// What happens to the read position when a [`Branch`] fires
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    /// Keep the current read position
+    Continue,
+    /// Read the file from its beginning
+    FromStart,
+    /// Skip to the current end of the file
+    SkipToEnd,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Action::Continue => "continue",
+            Action::FromStart => "from-start",
+            Action::SkipToEnd => "skip-to-end",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// The [`Action`] taken for each [`Branch`].
+///
+/// By default growth continues from the read position, while truncation and
+/// every kind of rotation read the file from its beginning.
+///
+/// # Example
+/// ```no_run
+/// use staart::{Action, Branch, DecisionTable, TailedFileBuilder};
+///
+/// // A replacement that is already longer than what was read is more likely
+/// // a copy of the old file than a fresh one, so don't replay it.
+/// let table = DecisionTable::default().set(Branch::RotatedGrew, Action::SkipToEnd);
+/// let f = TailedFileBuilder::new("/var/log/syslog").decisions(table).build();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecisionTable {
+    actions: [Action; 6],
+}
+
+impl Default for DecisionTable {
+    fn default() -> DecisionTable {
+        DecisionTable {
+            actions: [
+                Action::Continue,
+                Action::Continue,
+                Action::FromStart,
+                Action::FromStart,
+                Action::FromStart,
+                Action::FromStart,
+            ],
+        }
+    }
+}
+
+impl DecisionTable {
+    /// Sets the action taken when `branch` fires
+    pub fn set(mut self, branch: Branch, action: Action) -> DecisionTable {
+        self.actions[branch as usize] = action;
+        self
+    }
+
+    /// Returns the action taken when `branch` fires
+    pub fn get(&self, branch: Branch) -> Action {
+        self.actions[branch as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table() {
+        let table = DecisionTable::default().set(Branch::Shrank, Action::SkipToEnd);
+        assert_eq!(table.get(Branch::Shrank), Action::SkipToEnd);
+        assert_eq!(table.get(Branch::Grew), Action::Continue);
+        assert_eq!(table.get(Branch::RotatedGrew), Action::FromStart);
+        assert_eq!(Branch::new(true, Ordering::Less), Branch::RotatedShrank);
+    }
+}
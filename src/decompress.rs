@@ -0,0 +1,135 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::io::Write;
+
+use crate::Result;
+
+/// How a followed file is compressed, set with
+/// [`crate::TailedFileBuilder::decompress`] to follow logs written directly
+/// as a compressed stream.
+///
+/// Gzip is available with the `gzip` feature and zstd with the `zstd` feature.
+/// Concatenated gzip members and zstd frames are decoded one after another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decompression {
+    /// A gzip stream
+    #[cfg(feature = "gzip")]
+    Gzip,
+    /// A Zstandard stream
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+/// Decodes a compressed stream handed over in pieces as the file grows,
+/// keeping what was decoded until it is taken
+pub(crate) enum Decoder {
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::write::MultiGzDecoder<Vec<u8>>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Decoder<'static, Vec<u8>>),
+}
+
+// With a single compression feature enabled the matches have one arm
+#[allow(clippy::infallible_destructuring_match)]
+impl Decoder {
+    pub(crate) fn new(kind: Decompression) -> Result<Decoder> {
+        Ok(match kind {
+            #[cfg(feature = "gzip")]
+            Decompression::Gzip => Decoder::Gzip(flate2::write::MultiGzDecoder::new(Vec::new())),
+            #[cfg(feature = "zstd")]
+            Decompression::Zstd => Decoder::Zstd(zstd::stream::write::Decoder::new(Vec::new())?),
+        })
+    }
+
+    /// Decodes `data`, which may end anywhere in the stream
+    pub(crate) fn feed(&mut self, data: &[u8]) -> Result<()> {
+        let writer: &mut dyn Write = match self {
+            #[cfg(feature = "gzip")]
+            Decoder::Gzip(d) => d,
+            #[cfg(feature = "zstd")]
+            Decoder::Zstd(d) => d,
+        };
+        writer.write_all(data)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Returns the number of decoded bytes waiting to be taken
+    pub(crate) fn pending(&self) -> usize {
+        match self {
+            #[cfg(feature = "gzip")]
+            Decoder::Gzip(d) => d.get_ref().len(),
+            #[cfg(feature = "zstd")]
+            Decoder::Zstd(d) => d.get_ref().len(),
+        }
+    }
+
+    /// Moves up to `out.len()` decoded bytes into `out`, returning how many
+    pub(crate) fn take(&mut self, out: &mut [u8]) -> usize {
+        let decoded = match self {
+            #[cfg(feature = "gzip")]
+            Decoder::Gzip(d) => d.get_mut(),
+            #[cfg(feature = "zstd")]
+            Decoder::Zstd(d) => d.get_mut(),
+        };
+        let n = decoded.len().min(out.len());
+        out[..n].copy_from_slice(&decoded[..n]);
+        decoded.drain(..n);
+        n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_in_pieces(kind: Decompression, compressed: &[u8]) -> Vec<u8> {
+        let mut decoder = Decoder::new(kind).unwrap();
+        let mut decoded = Vec::new();
+        let mut out = [0; 7];
+        for piece in compressed.chunks(3) {
+            decoder.feed(piece).unwrap();
+            loop {
+                match decoder.take(&mut out) {
+                    0 => break,
+                    n => decoded.extend_from_slice(&out[..n]),
+                }
+            }
+        }
+        decoded
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip_pieces() {
+        let mut compressed = Vec::new();
+        for member in [&b"first member\n"[..], b"second\n"] {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+            encoder.write_all(member).unwrap();
+            compressed.extend(encoder.finish().unwrap());
+        }
+        assert_eq!(
+            decode_in_pieces(Decompression::Gzip, &compressed),
+            b"first member\nsecond\n"
+        );
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_pieces() {
+        let mut compressed = zstd::encode_all(&b"first frame\n"[..], 3).unwrap();
+        compressed.extend(zstd::encode_all(&b"second\n"[..], 3).unwrap());
+        assert_eq!(
+            decode_in_pieces(Decompression::Zstd, &compressed),
+            b"first frame\nsecond\n"
+        );
+    }
+}
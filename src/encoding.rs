@@ -0,0 +1,200 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::borrow::Cow;
+
+#[cfg(feature = "shift_jis")]
+use crate::cp932::CP932;
+use crate::lines;
+
+/// Characters of Windows-1252 at 0x80..=0x9f, where it differs from Latin-1.
+/// Unassigned bytes map to the C1 control of the same value, as browsers do.
+const WINDOWS_1252: [char; 32] = [
+    '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}', '\u{8f}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}', '\u{17e}', '\u{178}',
+];
+
+/// The character encoding of a followed file, set with
+/// [`crate::TailedFileBuilder::encoding`].
+///
+/// Text returned by the string and line APIs is transcoded to UTF-8, while raw
+/// reads return the bytes as written. A multi-byte character split across
+/// reads is kept back until the rest of it has been written.
+///
+/// Shift_JIS is available with the `shift_jis` feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    /// UTF-8, with invalid sequences replaced by U+FFFD
+    #[default]
+    Utf8,
+    /// ISO-8859-1, mapping each byte to the code point of the same value
+    Latin1,
+    /// Windows-1252, as written by many legacy Windows services
+    Windows1252,
+    /// Shift_JIS, with the extensions of Windows code page 932. Invalid
+    /// sequences are replaced by U+FFFD.
+    #[cfg(feature = "shift_jis")]
+    ShiftJis,
+}
+
+impl Encoding {
+    /// Decodes `bytes` to UTF-8, borrowing them where they are already valid
+    ///
+    /// # Example
+    /// ```
+    /// use staart::Encoding;
+    ///
+    /// assert_eq!(Encoding::Windows1252.decode(b"\x80 5"), "\u{20ac} 5");
+    /// ```
+    pub fn decode<'a>(&self, bytes: &'a [u8]) -> Cow<'a, str> {
+        let table: fn(u8) -> char = match self {
+            Encoding::Utf8 => return String::from_utf8_lossy(bytes),
+            _ if bytes.is_ascii() => return String::from_utf8_lossy(bytes),
+            Encoding::Latin1 => char::from,
+            Encoding::Windows1252 => |b| match b {
+                0x80..=0x9f => WINDOWS_1252[usize::from(b - 0x80)],
+                _ => char::from(b),
+            },
+            #[cfg(feature = "shift_jis")]
+            Encoding::ShiftJis => return Cow::Owned(decode_shift_jis(bytes)),
+        };
+        Cow::Owned(bytes.iter().map(|b| table(*b)).collect())
+    }
+
+    /// Returns the length of `data` without a character split at its end,
+    /// which is kept for the next read
+    pub(crate) fn complete_len(&self, data: &[u8]) -> usize {
+        match self {
+            Encoding::Utf8 => lines::utf8_complete_len(data),
+            Encoding::Latin1 | Encoding::Windows1252 => data.len(),
+            #[cfg(feature = "shift_jis")]
+            Encoding::ShiftJis => shift_jis_complete_len(data),
+        }
+    }
+}
+
+/// Returns whether `b` starts a double-byte Shift_JIS character
+#[cfg(feature = "shift_jis")]
+fn is_shift_jis_lead(b: u8) -> bool {
+    matches!(b, 0x81..=0x9f | 0xe0..=0xfc)
+}
+
+/// Decodes Shift_JIS `bytes`, mapping single bytes as browsers do
+#[cfg(feature = "shift_jis")]
+fn decode_shift_jis(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        i += 1;
+        if !is_shift_jis_lead(b) {
+            out.push(match b {
+                0x00..=0x80 => char::from(b),
+                // Half-width katakana
+                0xa1..=0xdf => char::from_u32(0xff61 + u32::from(b - 0xa1)).unwrap_or('\u{fffd}'),
+                _ => '\u{fffd}',
+            });
+            continue;
+        }
+        let Some(&trail) = bytes.get(i) else {
+            out.push('\u{fffd}');
+            break;
+        };
+        match shift_jis_pair(b, trail) {
+            Some(c) => {
+                out.push(c);
+                i += 1;
+            }
+            None => {
+                out.push('\u{fffd}');
+                // An ASCII byte after a bad lead byte is decoded on its own
+                if trail >= 0x80 {
+                    i += 1;
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Returns the character of the double-byte sequence `lead`, `trail`
+#[cfg(feature = "shift_jis")]
+fn shift_jis_pair(lead: u8, trail: u8) -> Option<char> {
+    let lead_offset = if lead < 0xa0 { 0x81 } else { 0xc1 };
+    let trail_offset = match trail {
+        0x40..=0x7e => 0x40,
+        0x80..=0xfc => 0x41,
+        _ => return None,
+    };
+    let pointer = usize::from(lead - lead_offset) * 188 + usize::from(trail - trail_offset);
+    match CP932.get(pointer) {
+        Some(&c) if c != 0 => char::from_u32(c.into()),
+        _ => None,
+    }
+}
+
+/// Returns the length of `data` without a lead byte ending it
+#[cfg(feature = "shift_jis")]
+fn shift_jis_complete_len(data: &[u8]) -> usize {
+    // Bytes below 0x40 are never trail bytes, so a character starts after the
+    // last of them
+    let mut i = data.iter().rposition(|b| *b < 0x40).map_or(0, |i| i + 1);
+    while i < data.len() {
+        i += if is_shift_jis_lead(data[i]) { 2 } else { 1 };
+    }
+    if i > data.len() {
+        data.len() - 1
+    } else {
+        data.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let data = b"caf\xe9 \x93quoted\x94 \x81";
+        assert_eq!(
+            Encoding::Latin1.decode(data),
+            "caf\u{e9} \u{93}quoted\u{94} \u{81}"
+        );
+        assert_eq!(
+            Encoding::Windows1252.decode(data),
+            "caf\u{e9} \u{201c}quoted\u{201d} \u{81}"
+        );
+        assert_eq!(Encoding::Utf8.decode(data).matches('\u{fffd}').count(), 4);
+        assert!(matches!(
+            Encoding::Latin1.decode(b"ascii"),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[cfg(feature = "shift_jis")]
+    #[test]
+    fn test_shift_jis() {
+        let sjis = Encoding::ShiftJis;
+        assert_eq!(
+            sjis.decode(b"\x82\xa0\x88\x9f \xb1 \x87\x40"),
+            "\u{3042}\u{4e9c} \u{ff71} \u{2460}"
+        );
+        assert_eq!(
+            sjis.decode(b"\x81\x20a\xfd\x82"),
+            "\u{fffd} a\u{fffd}\u{fffd}"
+        );
+
+        assert_eq!(sjis.complete_len(b"a\x82"), 1);
+        assert_eq!(sjis.complete_len(b"a\x82\xa0"), 3);
+        // The second 0x82 is a trail byte, so the third starts a character
+        assert_eq!(sjis.complete_len(b"\n\x82\x82\x82"), 3);
+        assert_eq!(sjis.complete_len(b"\n\x82\x82\xa0"), 4);
+    }
+}
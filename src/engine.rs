@@ -0,0 +1,338 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::batch;
+use crate::glob::{Configure, Pattern};
+use crate::watch;
+use crate::{
+    clock, Checkpoint, GlobEvent, GlobTailer, LineFilter, Pipeline, Result, ShutdownHandle, Sink,
+    StaartError, StartPolicy, TailedFile,
+};
+
+type ErrorHook = Box<dyn FnMut(&Path, &StaartError) + Send>;
+
+/// How often an [`Engine`] saves checkpoints by default
+pub const DEFAULT_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Everything an [`Engine`] needs to know about what to follow and how
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    /// Glob patterns of the files to follow, see [`GlobTailer`]
+    pub patterns: Vec<String>,
+    /// Where files start when there is no checkpoint for them. By default
+    /// files matching at startup start at their end and files matching later
+    /// at their beginning.
+    pub start_policy: Option<StartPolicy>,
+    /// Keeps only the records it matches, see
+    /// [`crate::TailedFileBuilder::filter`]
+    pub filter: Option<LineFilter>,
+    /// Transforms the kept records, see
+    /// [`crate::TailedFileBuilder::pipeline`]
+    pub pipeline: Pipeline,
+    /// Directory holding a checkpoint for each followed file, from which
+    /// files resume after a restart. Checkpoints are not kept if `None`.
+    pub checkpoint_dir: Option<PathBuf>,
+    /// How often checkpoints are saved while running
+    pub checkpoint_interval: Duration,
+    /// How long to wait for changes when nothing was read
+    pub interval: Duration,
+    /// Stops [`Engine::run`] once signaled
+    pub shutdown: ShutdownHandle,
+}
+
+impl Default for EngineConfig {
+    fn default() -> EngineConfig {
+        EngineConfig {
+            patterns: Vec::new(),
+            start_policy: None,
+            filter: None,
+            pipeline: Pipeline::new(),
+            checkpoint_dir: None,
+            checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+            interval: Duration::from_secs(1),
+            shutdown: ShutdownHandle::new(),
+        }
+    }
+}
+
+/// Follows the files matching a set of glob patterns and writes what they
+/// produce to a [`Sink`], filtering, transforming and checkpointing along
+/// the way, as configured by an [`EngineConfig`].
+///
+/// The engine discovers files as they appear and drops them as they go, as
+/// [`GlobTailer`] does, and resumes each file from its checkpoint. Several
+/// sinks can be combined with [`crate::FanOut`]. Errors of individual files
+/// are handled by their error policy and reported to
+/// [`Engine::on_error`], while an error of the sink ends [`Engine::run`].
+/// Between polls the engine waits for changes under any of the patterns, for
+/// up to [`EngineConfig::interval`].
+///
+/// # Example
+/// ```no_run
+/// use staart::{Engine, EngineConfig, LineFilter, Matcher, TcpSink};
+///
+/// let config = EngineConfig {
+///     patterns: vec!["/var/log/app/*.log".to_string()],
+///     filter: Some(LineFilter::new().exclude(Matcher::contains("DEBUG"))),
+///     checkpoint_dir: Some("/var/lib/shipper".into()),
+///     ..EngineConfig::default()
+/// };
+/// let sink = TcpSink::new("logs.example.com:5140").newline(true);
+/// let mut engine = Engine::new(config, sink)
+///     .unwrap()
+///     .on_error(|path, e| eprintln!("{}: {e}", path.display()));
+/// engine.run().unwrap();
+/// ```
+pub struct Engine<S: Sink> {
+    config: EngineConfig,
+    tailers: Vec<GlobTailer>,
+    sink: S,
+    on_error: Option<ErrorHook>,
+    last_checkpoint: Instant,
+}
+
+impl<S: Sink> Engine<S> {
+    /// Creates an engine following the files currently matching the patterns
+    /// of `config` and writing to `sink`
+    ///
+    /// # Propagates Errors
+    /// - If a pattern is invalid
+    /// - If a matching file can not be opened
+    /// - If the checkpoint directory can not be created
+    pub fn new(config: EngineConfig, sink: S) -> Result<Engine<S>> {
+        if let Some(dir) = &config.checkpoint_dir {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tailers = config
+            .patterns
+            .iter()
+            .map(|pattern| GlobTailer::configured(Pattern::new(pattern)?, configure(&config)))
+            .collect::<Result<_>>()?;
+
+        Ok(Engine {
+            config,
+            tailers,
+            sink,
+            on_error: None,
+            last_checkpoint: clock::now(),
+        })
+    }
+
+    /// Calls `f` with the path and error of each file that fails to be read
+    pub fn on_error<F>(mut self, f: F) -> Engine<S>
+    where
+        F: FnMut(&Path, &StaartError) + Send + 'static,
+    {
+        self.on_error = Some(Box::new(f));
+        self
+    }
+
+    /// Reads the complete lines of every file, writing those kept by the
+    /// filter and pipeline to the sink with [`Sink::write_lines`] and flushing
+    /// it, and saves the checkpoints when due. Returns `true` if any lines
+    /// were written.
+    ///
+    /// # Propagates Errors
+    /// - If the sink fails to write or flush
+    /// - If a checkpoint can not be saved
+    pub fn poll(&mut self) -> Result<bool> {
+        let mut read = false;
+        let mut errors = Vec::new();
+        let mut written = Ok(());
+        'tailers: for tailer in &mut self.tailers {
+            for event in tailer.rescan() {
+                if let GlobEvent::Error(path, e) = event {
+                    errors.push((path, e));
+                }
+            }
+            for file in tailer.files_mut().iter_mut() {
+                let mut lines = Vec::new();
+                if let Err(e) = batch::read_lines(file, &mut lines) {
+                    errors.push((file.path().to_path_buf(), e));
+                }
+                if !lines.is_empty() {
+                    written = self.sink.write_lines(file.path(), &lines);
+                    if written.is_err() {
+                        break 'tailers;
+                    }
+                    read = true;
+                }
+            }
+        }
+        // Errors of the files are reported even when the sink failed
+        if let Some(f) = self.on_error.as_mut() {
+            for (path, e) in &errors {
+                f(path, e);
+            }
+        }
+        written?;
+        if read {
+            self.sink.flush()?;
+        }
+        if clock::now().saturating_duration_since(self.last_checkpoint)
+            >= self.config.checkpoint_interval
+        {
+            self.save_checkpoints()?;
+        }
+
+        Ok(read)
+    }
+
+    /// Polls until the [`EngineConfig::shutdown`] handle is signaled, waiting
+    /// for changes whenever nothing was read, then finishes the sink and saves
+    /// the checkpoints
+    ///
+    /// # Propagates Errors
+    /// - If the sink fails to write or flush
+    /// - If a checkpoint can not be saved
+    pub fn run(&mut self) -> Result<()> {
+        while !self.config.shutdown.is_signaled() {
+            if self.poll()? {
+                continue;
+            }
+            let mut watchers: Vec<_> = self.tailers.iter_mut().map(GlobTailer::watcher).collect();
+            watch::wait_any(&mut watchers, self.config.interval)?;
+        }
+        self.sink.finish()?;
+        self.save_checkpoints()
+    }
+
+    /// Saves the checkpoint of every followed file to the checkpoint
+    /// directory, if any
+    ///
+    /// # Propagates Errors
+    /// - If a checkpoint can not be saved
+    pub fn save_checkpoints(&mut self) -> Result<()> {
+        self.last_checkpoint = clock::now();
+        let Some(dir) = &self.config.checkpoint_dir else {
+            return Ok(());
+        };
+        for file in self.files() {
+            file.checkpoint().save(checkpoint_path(dir, file.path()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the files currently followed
+    pub fn files(&self) -> impl Iterator<Item = &TailedFile<PathBuf>> {
+        self.tailers.iter().flat_map(|tailer| tailer.files().iter())
+    }
+
+    /// Returns the configuration
+    pub fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    /// Returns the sink
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    /// Returns the sink mutably
+    pub fn sink_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+}
+
+/// Applies the per-file parts of `config` to the builder of each file
+fn configure(config: &EngineConfig) -> Configure {
+    let start_policy = config.start_policy;
+    let filter = config.filter.clone();
+    let pipeline = config.pipeline.clone();
+    let dir = config.checkpoint_dir.clone();
+    Box::new(move |path, mut builder| {
+        if let Some(policy) = start_policy {
+            builder = builder.start_policy(policy);
+        }
+        if let Some(filter) = &filter {
+            builder = builder.filter(filter.clone());
+        }
+        builder = builder.pipeline(pipeline.clone());
+        let checkpoint = dir
+            .as_ref()
+            .and_then(|dir| Checkpoint::load(checkpoint_path(dir, path)).ok());
+        if let Some(checkpoint) = checkpoint {
+            builder = builder.resume(checkpoint);
+        }
+        builder
+    })
+}
+
+/// Returns where the checkpoint of the file at `path` is kept in `dir`, named
+/// after the whole path with separators escaped
+fn checkpoint_path(dir: &Path, path: &Path) -> PathBuf {
+    let mut name = String::new();
+    for c in path.to_string_lossy().chars() {
+        match c {
+            '%' => name.push_str("%25"),
+            '/' => name.push_str("%2F"),
+            '\\' => name.push_str("%5C"),
+            ':' => name.push_str("%3A"),
+            c => name.push(c),
+        }
+    }
+    name.push_str(".pos");
+    dir.join(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Matcher;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    #[test]
+    fn test_checkpoint_path() {
+        assert_eq!(
+            checkpoint_path(Path::new("state"), Path::new("/var/log/100%.log")),
+            Path::new("state/%2Fvar%2Flog%2F100%25.log.pos")
+        );
+    }
+
+    #[test]
+    fn test_engine() {
+        let dir = tempfile::tempdir().unwrap();
+        let logs = dir.path().join("logs");
+        std::fs::create_dir(&logs).unwrap();
+        let app = logs.join("app.log");
+        std::fs::write(&app, "old\n").unwrap();
+        let config = EngineConfig {
+            patterns: vec![format!("{}/*.log", logs.display())],
+            filter: Some(LineFilter::new().exclude(Matcher::contains("DEBUG"))),
+            pipeline: Pipeline::new().label("> "),
+            checkpoint_dir: Some(dir.path().join("state")),
+            interval: Duration::from_millis(10),
+            ..EngineConfig::default()
+        };
+
+        let mut engine = Engine::new(config.clone(), Vec::new()).unwrap();
+        let mut f = OpenOptions::new().append(true).open(&app).unwrap();
+        f.write_all(b"one\nDEBUG two\n").unwrap();
+        std::fs::write(logs.join("new.log"), "three\n").unwrap();
+        assert!(engine.poll().unwrap());
+        let shutdown = config.shutdown.clone();
+        shutdown.signal();
+        engine.run().unwrap();
+        let mut out = String::from_utf8(engine.sink().clone()).unwrap();
+        let mut lines: Vec<_> = out.lines().collect();
+        lines.sort();
+        assert_eq!(lines, ["> one", "> three"]);
+
+        // A restarted engine resumes from the checkpoints
+        f.write_all(b"four\n").unwrap();
+        let mut engine = Engine::new(config, Vec::new()).unwrap();
+        engine.poll().unwrap();
+        out = String::from_utf8(engine.sink().clone()).unwrap();
+        assert_eq!(out, "> four\n");
+    }
+}
@@ -0,0 +1,557 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::sink::Partials;
+use crate::{Line, LineFilter, Pipeline, Result, Sink, StaartError};
+
+/// A line together with where it was read, as exchanged between chained
+/// staart instances.
+///
+/// An envelope is written as one JSON object per line, with the fields
+/// `path`, `offset` (`null` if unknown) and `line`:
+///
+/// ```text
+/// {"path":"/var/log/syslog","offset":1024,"line":"sshd[1]: accepted"}
+/// ```
+///
+/// Other fields are ignored when parsing, so envelopes written by newer
+/// versions can still be read.
+///
+/// # Example
+/// ```
+/// use staart::Envelope;
+///
+/// let envelope: Envelope = r#"{"path":"/var/log/syslog","offset":null,"line":"a\tb"}"#
+///     .parse()
+///     .unwrap();
+/// assert_eq!(envelope.line, "a\tb");
+/// assert_eq!(envelope.to_string().parse::<Envelope>().unwrap(), envelope);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    /// Path of the file the line was read from
+    pub path: PathBuf,
+    /// Offset of the line in that file, if known
+    pub offset: Option<u64>,
+    /// The line, without its line ending
+    pub line: String,
+}
+
+impl fmt::Display for Envelope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{\"path\":{},", quote(&self.path.to_string_lossy()))?;
+        match self.offset {
+            Some(offset) => write!(f, "\"offset\":{offset},")?,
+            None => write!(f, "\"offset\":null,")?,
+        }
+        write!(f, "\"line\":{}}}", quote(&self.line))
+    }
+}
+
+impl FromStr for Envelope {
+    type Err = StaartError;
+
+    fn from_str(s: &str) -> Result<Envelope> {
+        let invalid = || StaartError::Config(format!("invalid envelope: {s}"));
+        let mut parser = Parser {
+            s: s.trim(),
+            pos: 0,
+        };
+        let mut path = None;
+        let mut offset = None;
+        let mut line = None;
+
+        parser.expect(b'{').ok_or_else(invalid)?;
+        if !parser.eat(b'}') {
+            loop {
+                let key = parser.string().ok_or_else(invalid)?;
+                parser.expect(b':').ok_or_else(invalid)?;
+                let value = parser.value().ok_or_else(invalid)?;
+                match (key.as_str(), value) {
+                    ("path", Value::String(s)) => path = Some(PathBuf::from(s)),
+                    ("offset", Value::Number(n)) => {
+                        offset = Some(n.parse().map_err(|_| invalid())?)
+                    }
+                    ("offset", Value::Null) => offset = None,
+                    ("line", Value::String(s)) => line = Some(s),
+                    ("path" | "offset" | "line", _) => return Err(invalid()),
+                    _ => {}
+                }
+                if parser.eat(b'}') {
+                    break;
+                }
+                parser.expect(b',').ok_or_else(invalid)?;
+            }
+        }
+        parser.skip_whitespace();
+        if parser.pos != parser.s.len() {
+            return Err(invalid());
+        }
+
+        Ok(Envelope {
+            path: path.ok_or_else(invalid)?,
+            offset,
+            line: line.ok_or_else(invalid)?,
+        })
+    }
+}
+
+/// A [`Sink`] writing every line to an inner sink as an [`Envelope`], so that
+/// another staart instance can consume it with an [`EnvelopeReader`], e.g. an
+/// edge instance feeding an aggregator over a [`crate::TcpSink`].
+///
+/// Lines passed to [`Sink::write_lines`] keep their offsets. Chunks passed to
+/// [`Sink::write_chunk`] are split into lines without offsets, keeping an
+/// incomplete last line per path until the rest of it is written.
+///
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+/// use staart::{EnvelopeSink, TailedFile, TcpSink};
+///
+/// let sink = EnvelopeSink::new(TcpSink::new("aggregator:5140"));
+/// let mut f = TailedFile::new("/var/log/syslog").unwrap();
+/// f.follow(sink, Duration::from_secs(1)).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct EnvelopeSink<S: Sink> {
+    inner: S,
+    partials: Partials,
+}
+
+impl<S: Sink> EnvelopeSink<S> {
+    /// Creates a sink writing envelopes to `inner`
+    pub fn new(inner: S) -> EnvelopeSink<S> {
+        EnvelopeSink {
+            inner,
+            partials: Partials::default(),
+        }
+    }
+
+    /// Returns the inner sink
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn send<'l, I>(&mut self, path: &Path, lines: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (Option<u64>, Cow<'l, str>)>,
+    {
+        let mut data = String::new();
+        for (offset, line) in lines {
+            let envelope = Envelope {
+                path: path.to_path_buf(),
+                offset,
+                line: line.into_owned(),
+            };
+            data.push_str(&envelope.to_string());
+            data.push('\n');
+        }
+        if data.is_empty() {
+            return Ok(());
+        }
+        self.inner.write_chunk(path, data.as_bytes())
+    }
+}
+
+impl<S: Sink> Sink for EnvelopeSink<S> {
+    fn write_chunk(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        let data = self.partials.complete(path, data);
+        let lines = data
+            .split_inclusive(|b| *b == b'\n')
+            .map(|line| line.strip_suffix(b"\n").unwrap_or(line))
+            .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+            .map(|line| (None, String::from_utf8_lossy(line)));
+        self.send(path, lines)
+    }
+
+    fn write_lines(&mut self, path: &Path, lines: &[Line]) -> Result<()> {
+        let lines = lines
+            .iter()
+            .map(|line| (Some(line.offset), Cow::Borrowed(line.text.as_str())));
+        self.send(path, lines)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        for (path, line) in self.partials.take_all() {
+            self.write_chunk(&path, &line)?;
+        }
+        self.inner.finish()
+    }
+}
+
+/// Reads [`Envelope`]s written by another staart instance from a file or
+/// socket, applying a [`LineFilter`] and [`Pipeline`] again before handing
+/// the lines to a [`Sink`] under the path they were originally read from.
+///
+/// The source may be anything implementing [`Read`]: a [`crate::TailedFile`]
+/// following a file of envelopes, where reading nothing means that nothing
+/// new was written yet, or a socket, where it means that the sender closed
+/// the connection. Lines that are not valid envelopes are counted in
+/// [`EnvelopeReader::invalid`] and skipped.
+///
+/// # Example
+/// ```no_run
+/// use std::net::TcpListener;
+/// use staart::{EnvelopeReader, LineFilter, Matcher};
+///
+/// let listener = TcpListener::bind("0.0.0.0:5140").unwrap();
+/// let (conn, _) = listener.accept().unwrap();
+/// let mut reader = EnvelopeReader::new(conn)
+///     .filter(LineFilter::new().exclude(Matcher::contains("DEBUG")));
+/// let mut out = std::io::stdout();
+/// while reader.forward(&mut out).unwrap() > 0 {}
+/// ```
+#[derive(Debug)]
+pub struct EnvelopeReader<R: Read> {
+    source: R,
+    filter: LineFilter,
+    pipeline: Pipeline,
+    buf: Vec<u8>,
+    invalid: u64,
+}
+
+impl<R: Read> EnvelopeReader<R> {
+    /// Creates a reader consuming envelopes from `source`
+    pub fn new(source: R) -> EnvelopeReader<R> {
+        EnvelopeReader {
+            source,
+            filter: LineFilter::new(),
+            pipeline: Pipeline::new(),
+            buf: Vec::new(),
+            invalid: 0,
+        }
+    }
+
+    /// Keeps only the lines matching `filter`
+    pub fn filter(mut self, filter: LineFilter) -> EnvelopeReader<R> {
+        self.filter = filter;
+        self
+    }
+
+    /// Runs the kept lines through `pipeline`
+    pub fn pipeline(mut self, pipeline: Pipeline) -> EnvelopeReader<R> {
+        self.pipeline = pipeline;
+        self
+    }
+
+    /// Returns the number of lines skipped for not being valid envelopes
+    pub fn invalid(&self) -> u64 {
+        self.invalid
+    }
+
+    /// Returns the source
+    pub fn into_inner(self) -> R {
+        self.source
+    }
+
+    /// Reads once from the source, returning the kept envelopes in complete
+    /// lines and how many bytes were read
+    ///
+    /// # Propagates Errors
+    /// - If reading from the source fails
+    pub fn read_envelopes(&mut self) -> Result<(Vec<Envelope>, usize)> {
+        let mut chunk = [0; 8192];
+        let n = self.source.read(&mut chunk)?;
+        self.buf.extend_from_slice(&chunk[..n]);
+
+        let complete = match self.buf.iter().rposition(|b| *b == b'\n') {
+            Some(i) => i + 1,
+            None => return Ok((Vec::new(), n)),
+        };
+        let rest = self.buf.split_off(complete);
+        let data = std::mem::replace(&mut self.buf, rest);
+
+        let mut envelopes = Vec::new();
+        for line in data.split(|b| *b == b'\n') {
+            let line = String::from_utf8_lossy(line);
+            if line.trim().is_empty() {
+                continue;
+            }
+            let Ok(mut envelope) = line.parse::<Envelope>() else {
+                self.invalid += 1;
+                continue;
+            };
+            if !self.filter.is_match(envelope.line.as_bytes()) {
+                continue;
+            }
+            match self.pipeline.apply(Cow::Borrowed(&envelope.line)) {
+                Some(Cow::Borrowed(_)) => {}
+                Some(Cow::Owned(line)) => envelope.line = line,
+                None => continue,
+            }
+            envelopes.push(envelope);
+        }
+
+        Ok((envelopes, n))
+    }
+
+    /// Reads once from the source and writes the kept lines to `sink`, each
+    /// run of lines from one path in a single [`Sink::write_lines`] call.
+    /// Lines without an offset are given offset 0. Returns how many bytes
+    /// were read.
+    ///
+    /// # Propagates Errors
+    /// - If reading from the source or writing to the sink fails
+    pub fn forward<S: Sink>(&mut self, mut sink: S) -> Result<usize> {
+        let (envelopes, n) = self.read_envelopes()?;
+        let mut envelopes = envelopes.into_iter().peekable();
+        while let Some(first) = envelopes.next() {
+            let path = first.path;
+            let mut lines = vec![Line {
+                text: first.line,
+                offset: first.offset.unwrap_or(0),
+            }];
+            while let Some(next) = envelopes.next_if(|e| e.path == path) {
+                lines.push(Line {
+                    text: next.line,
+                    offset: next.offset.unwrap_or(0),
+                });
+            }
+            sink.write_lines(&path, &lines)?;
+        }
+
+        Ok(n)
+    }
+}
+
+/// A JSON value as far as envelopes care about it
+enum Value {
+    String(String),
+    Number(String),
+    Null,
+    Other,
+}
+
+/// Parses the flat JSON objects of envelopes by hand, to avoid depending on a
+/// JSON library for a single object
+struct Parser<'a> {
+    s: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.s.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    /// Consumes `c` after any whitespace, returning `true` if it was there
+    fn eat(&mut self, c: u8) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, c: u8) -> Option<()> {
+        self.eat(c).then_some(())
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.expect(b'"')?;
+        let mut out = String::new();
+        let mut chars = self.s[self.pos..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Some(out);
+                }
+                '\\' => match chars.next()?.1 {
+                    'n' => out.push('\n'),
+                    't' => out.push('\t'),
+                    'r' => out.push('\r'),
+                    'b' => out.push('\u{8}'),
+                    'f' => out.push('\u{c}'),
+                    'u' => {
+                        let unit = hex(&mut chars)?;
+                        let code = match unit {
+                            0xd800..=0xdbff => {
+                                let low = match (chars.next()?.1, chars.next()?.1) {
+                                    ('\\', 'u') => hex(&mut chars)?,
+                                    _ => return None,
+                                };
+                                0x10000 + ((unit - 0xd800) << 10) + low.checked_sub(0xdc00)?
+                            }
+                            _ => unit,
+                        };
+                        out.push(char::from_u32(code)?);
+                    }
+                    c @ ('"' | '\\' | '/') => out.push(c),
+                    _ => return None,
+                },
+                c => out.push(c),
+            }
+        }
+        None
+    }
+
+    fn value(&mut self) -> Option<Value> {
+        self.skip_whitespace();
+        match self.peek()? {
+            b'"' => self.string().map(Value::String),
+            b'{' | b'[' => self.skip_nested().map(|_| Value::Other),
+            _ => {
+                let start = self.pos;
+                while matches!(self.peek(), Some(c) if !b",}] \t\r\n".contains(&c)) {
+                    self.pos += 1;
+                }
+                match &self.s[start..self.pos] {
+                    "" => None,
+                    "null" => Some(Value::Null),
+                    "true" | "false" => Some(Value::Other),
+                    n if n.starts_with(|c: char| c == '-' || c.is_ascii_digit()) => {
+                        Some(Value::Number(n.to_string()))
+                    }
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    /// Skips an object or array, along with anything nested in it
+    fn skip_nested(&mut self) -> Option<()> {
+        let mut depth = 0usize;
+        loop {
+            match self.peek()? {
+                b'"' => {
+                    self.string()?;
+                    continue;
+                }
+                b'{' | b'[' => depth += 1,
+                b'}' | b']' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.pos += 1;
+                        return Some(());
+                    }
+                }
+                _ => {}
+            }
+            self.pos += 1;
+        }
+    }
+}
+
+/// Reads the four hex digits of a `\u` escape
+fn hex(chars: &mut std::str::CharIndices<'_>) -> Option<u32> {
+    let digits: String = chars.take(4).map(|(_, c)| c).collect();
+    match digits.len() {
+        4 => u32::from_str_radix(&digits, 16).ok(),
+        _ => None,
+    }
+}
+
+fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Matcher;
+
+    /// Hands out at most seven bytes per read, as a socket might
+    struct Trickle(std::io::Cursor<Vec<u8>>);
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = buf.len().min(7);
+            self.0.read(&mut buf[..n])
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let envelope = Envelope {
+            path: PathBuf::from("/var/log/\"odd\".log"),
+            offset: Some(42),
+            line: "tab\there \u{1} \u{1f600}".to_string(),
+        };
+        assert_eq!(envelope.to_string().parse::<Envelope>().unwrap(), envelope);
+
+        let extra = r#" { "host" : "edge-1", "tags":["a","}"], "line":"😀 é",
+            "meta":{"n":[1,{"x":null}]}, "ok":true, "offset":null, "path":"a.log" } "#;
+        let parsed: Envelope = extra.parse().unwrap();
+        assert_eq!(parsed.line, "\u{1f600} \u{e9}");
+        assert_eq!(parsed.offset, None);
+        assert_eq!(parsed.path, Path::new("a.log"));
+
+        for invalid in [
+            "",
+            "plain text",
+            r#"{"path":"a.log"}"#,
+            r#"{"path":"a.log","line":1}"#,
+            r#"{"path":"a.log","offset":-1,"line":""}"#,
+            r#"{"path":"a.log","line":"x"} trailing"#,
+            r#"{"path":"a.log","line":"x""#,
+        ] {
+            assert!(invalid.parse::<Envelope>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_chain() {
+        let mut edge = EnvelopeSink::new(Vec::new());
+        let a = Path::new("/var/log/a.log");
+        let b = Path::new("/var/log/b.log");
+        let line = |text: &str, offset| Line {
+            text: text.to_string(),
+            offset,
+        };
+        edge.write_lines(a, &[line("INFO one", 0), line("DEBUG two", 9)])
+            .unwrap();
+        edge.write_chunk(b, b"INFO three\nINFO fo").unwrap();
+        edge.write_chunk(b, b"ur\n").unwrap();
+        let mut wire = edge.into_inner();
+        wire.extend_from_slice(b"garbage\n");
+
+        let mut reader = EnvelopeReader::new(Trickle(std::io::Cursor::new(wire)))
+            .filter(LineFilter::new().exclude(Matcher::contains("DEBUG")))
+            .pipeline(Pipeline::new().label("[edge] "));
+        let mut aggregated = Vec::new();
+        while reader.forward(&mut aggregated).unwrap() > 0 {}
+        assert_eq!(
+            String::from_utf8(aggregated).unwrap(),
+            "[edge] INFO one\n[edge] INFO three\n[edge] INFO four\n"
+        );
+        assert_eq!(reader.invalid(), 1);
+    }
+}
@@ -12,6 +12,12 @@ pub enum StaartError {
     IO(std::io::Error),
     Utf8(std::str::Utf8Error),
     IntError(std::num::TryFromIntError),
+    Pattern(String),
+    Config(String),
+    Modified(u64),
+    Ignored(std::time::Duration),
+    Sink(String),
+    Panicked(String),
 }
 
 impl std::fmt::Display for StaartError {
@@ -26,6 +32,31 @@ impl std::fmt::Display for StaartError {
             StaartError::IntError(..) => {
                 write!(f, "encountered integer conversion error")
             }
+            StaartError::Pattern(ref msg) => {
+                write!(f, "encountered invalid pattern: {msg}")
+            }
+            StaartError::Config(ref msg) => {
+                write!(f, "encountered invalid configuration: {msg}")
+            }
+            StaartError::Modified(offset) => {
+                write!(
+                    f,
+                    "encountered modification of append-only file at offset {offset}"
+                )
+            }
+            StaartError::Ignored(age) => {
+                write!(
+                    f,
+                    "encountered file last modified {}s ago, ignoring it",
+                    age.as_secs()
+                )
+            }
+            StaartError::Sink(ref msg) => {
+                write!(f, "encountered sink error: {msg}")
+            }
+            StaartError::Panicked(ref msg) => {
+                write!(f, "encountered panic: {msg}")
+            }
         }
     }
 }
@@ -38,6 +69,18 @@ impl From<std::io::Error> for StaartError {
     }
 }
 
+/// Unwraps I/O errors, and wraps every other error as [`std::io::ErrorKind::Other`]
+impl From<StaartError> for std::io::Error {
+    // `std::io::Error::other` needs Rust 1.74, above the MSRV
+    #[allow(clippy::io_other_error)]
+    fn from(err: StaartError) -> Self {
+        match err {
+            StaartError::IO(e) => e,
+            e => std::io::Error::new(std::io::ErrorKind::Other, e),
+        }
+    }
+}
+
 impl From<std::str::Utf8Error> for StaartError {
     fn from(err: std::str::Utf8Error) -> Self {
         StaartError::Utf8(err)
@@ -0,0 +1,38 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// What happened to a followed file, as returned by
+/// [`crate::TailedFile::poll_event`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TailEvent {
+    /// New data was read from the file
+    Data(Vec<u8>),
+    /// The file at the path was replaced by a different file
+    Rotated {
+        /// Path of the followed file
+        path: PathBuf,
+        /// Where the replaced file was renamed to, if it was found in the same
+        /// directory
+        renamed_to: Option<PathBuf>,
+    },
+    /// The file shrank below the read position
+    Truncated,
+    /// The file was removed from the path
+    Deleted,
+    /// Nothing changed since the last poll
+    NoChange,
+    /// No data was read for the time set by
+    /// [`crate::TailedFileBuilder::idle_after`]
+    Idle {
+        /// When data was last read, or the file was opened if none was
+        since: SystemTime,
+    },
+}
@@ -0,0 +1,214 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::fs::File;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{Result, TailedFile};
+
+/// Number of file handles currently held by all [`TailedFile`]s
+static OPEN: AtomicUsize = AtomicUsize::new(0);
+
+/// A file handle counted towards [`DescriptorStats::open`]
+#[derive(Debug)]
+pub(crate) struct Handle(File);
+
+impl Handle {
+    pub(crate) fn new(file: File) -> Handle {
+        OPEN.fetch_add(1, Ordering::Relaxed);
+        Handle(file)
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        OPEN.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Deref for Handle {
+    type Target = File;
+
+    fn deref(&self) -> &File {
+        &self.0
+    }
+}
+
+impl DerefMut for Handle {
+    fn deref_mut(&mut self) -> &mut File {
+        &mut self.0
+    }
+}
+
+/// File descriptor usage of the process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DescriptorStats {
+    /// Number of file handles held by all [`TailedFile`]s
+    pub open: usize,
+    /// Soft limit on open descriptors, where it can be queried
+    pub soft_limit: Option<u64>,
+    /// Hard limit on open descriptors, where it can be queried
+    pub hard_limit: Option<u64>,
+}
+
+impl DescriptorStats {
+    /// Returns the fraction of the soft limit used by followed files, if known
+    pub fn pressure(&self) -> Option<f64> {
+        self.soft_limit
+            .filter(|limit| *limit > 0)
+            .map(|limit| self.open as f64 / limit as f64)
+    }
+}
+
+/// Returns the current [`DescriptorStats`] of the process
+pub fn descriptor_stats() -> DescriptorStats {
+    let (soft_limit, hard_limit) = match nofile_limit() {
+        Some((soft, hard)) => (Some(soft), Some(hard)),
+        None => (None, None),
+    };
+    DescriptorStats {
+        open: OPEN.load(Ordering::Relaxed),
+        soft_limit,
+        hard_limit,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn nofile_limit() -> Option<(u64, u64)> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    let rc = unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) };
+    (rc == 0).then_some((limit.rlim_cur, limit.rlim_max))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn nofile_limit() -> Option<(u64, u64)> {
+    None
+}
+
+/// Most descriptors the kernel lets a process open when `/proc/sys/fs/nr_open`
+/// can not be read
+#[cfg(target_os = "linux")]
+const DEFAULT_NR_OPEN: u64 = 1 << 20;
+
+/// Raises the soft limit on open descriptors to the hard limit, so that many
+/// files can be followed at once, and returns the new soft limit.
+///
+/// A hard limit that is unlimited, or larger than the kernel allows, is
+/// clamped to `/proc/sys/fs/nr_open`, since the soft limit can not be raised
+/// past it.
+///
+/// Returns `None` on platforms where the limit can not be changed.
+///
+/// # Propagates Errors
+/// - If the limit can not be read or raised
+#[cfg(target_os = "linux")]
+pub fn raise_descriptor_limit() -> Result<Option<u64>> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let nr_open = std::fs::read_to_string("/proc/sys/fs/nr_open")
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(DEFAULT_NR_OPEN);
+    let target = limit.rlim_max.min(nr_open);
+    if limit.rlim_cur < target {
+        limit.rlim_cur = target;
+        if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) } != 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+    }
+
+    Ok(Some(limit.rlim_cur))
+}
+
+/// Raises the soft limit on open descriptors to the hard limit, so that many
+/// files can be followed at once, and returns the new soft limit.
+///
+/// Returns `None` on platforms where the limit can not be changed.
+#[cfg(not(target_os = "linux"))]
+pub fn raise_descriptor_limit() -> Result<Option<u64>> {
+    Ok(None)
+}
+
+/// Closes the handles of the least recently active `files` so that at most
+/// `keep_open` of them remain open, returning how many were closed.
+///
+/// Closed files keep their read position and are reopened by their next
+/// read once they change, see [`crate::TailState::Closed`]. Files following
+/// the original descriptor, see [`crate::RotationCheck::Disabled`], are never
+/// closed.
+pub fn close_least_recent<'a, T, I>(files: I, keep_open: usize) -> Result<usize>
+where
+    T: AsRef<Path> + 'a,
+    I: IntoIterator<Item = &'a mut TailedFile<T>>,
+{
+    let mut open: Vec<_> = files
+        .into_iter()
+        .filter(|f| f.file.is_some() && f.rotation == crate::RotationCheck::FileId)
+        .collect();
+    if open.len() <= keep_open {
+        return Ok(0);
+    }
+
+    open.sort_by_key(|f| std::cmp::Reverse(f.last_data));
+    let mut closed = 0;
+    for f in open.into_iter().skip(keep_open) {
+        f.close()?;
+        closed += 1;
+    }
+
+    Ok(closed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TailState;
+    use std::io::Write;
+
+    #[test]
+    fn test_close_least_recent() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut files = Vec::new();
+        for name in ["a.file", "b.file", "c.file"] {
+            let path = dir.path().join(name);
+            File::create(&path).unwrap();
+            files.push((TailedFile::new(path.clone()).unwrap(), path));
+        }
+
+        for (tailed_file, path) in files.iter_mut().skip(1) {
+            std::thread::sleep(std::time::Duration::from_millis(2));
+            File::create(path).unwrap().write_all(b"data").unwrap();
+            tailed_file.read().unwrap();
+        }
+
+        let tailed: Vec<_> = files.iter_mut().map(|(f, _)| f).collect();
+        assert_eq!(close_least_recent(tailed, 1).unwrap(), 2);
+        assert_eq!(files[0].0.state(), TailState::Closed);
+        assert_eq!(files[1].0.state(), TailState::Closed);
+        assert_eq!(files[2].0.state(), TailState::Following);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_raise_descriptor_limit() {
+        let soft = raise_descriptor_limit().unwrap().unwrap();
+        let stats = descriptor_stats();
+        assert_eq!(stats.soft_limit, Some(soft));
+        assert!(soft <= stats.hard_limit.unwrap());
+    }
+}
@@ -0,0 +1,172 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::profile::Timing;
+use crate::{lines, StageStats, TimeBudget};
+
+type MatchFn = Arc<dyn Fn(&[u8]) -> bool + Send + Sync>;
+
+/// Tests a raw record, before it is decoded, for a [`LineFilter`]
+#[derive(Clone)]
+pub struct Matcher {
+    description: String,
+    matches: MatchFn,
+    timing: Arc<Timing>,
+}
+
+impl Matcher {
+    /// Matches records for which `f` returns `true`.
+    ///
+    /// staart has no regular expression engine of its own, so patterns are
+    /// plugged in this way, e.g. with `regex::bytes::RegexSet`:
+    ///
+    /// ```ignore
+    /// let errors = regex::bytes::RegexSet::new([r"\bERROR\b", r"panicked at"])?;
+    /// let matcher = Matcher::new("errors", move |line| errors.is_match(line));
+    /// ```
+    pub fn new<F>(description: &str, f: F) -> Matcher
+    where
+        F: Fn(&[u8]) -> bool + Send + Sync + 'static,
+    {
+        Matcher {
+            description: description.to_string(),
+            matches: Arc::new(f),
+            timing: Arc::default(),
+        }
+    }
+
+    /// Matches records containing `needle`
+    pub fn contains(needle: &str) -> Matcher {
+        let bytes = needle.as_bytes().to_vec();
+        Matcher::new(&format!("contains {needle:?}"), move |line| {
+            bytes.is_empty() || lines::find(line, &bytes).is_some()
+        })
+    }
+
+    /// Matches records starting with `prefix`
+    pub fn starts_with(prefix: &str) -> Matcher {
+        let bytes = prefix.as_bytes().to_vec();
+        Matcher::new(&format!("starts with {prefix:?}"), move |line| {
+            line.starts_with(&bytes)
+        })
+    }
+
+    /// Returns `true` if `line` matches
+    pub fn is_match(&self, line: &[u8]) -> bool {
+        (self.matches)(line)
+    }
+}
+
+impl fmt::Debug for Matcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Matcher").field(&self.description).finish()
+    }
+}
+
+/// Include and exclude sets deciding which records are delivered, set with
+/// [`crate::TailedFileBuilder::filter`].
+///
+/// A record is kept if it matches any include matcher, or there are none, and
+/// matches no exclude matcher. Records are tested as raw bytes without their
+/// delimiter, so those left out are never decoded or copied.
+///
+/// The time spent in each matcher is measured, see [`LineFilter::stats`].
+///
+/// # Example
+/// ```no_run
+/// use staart::{LineFilter, Matcher, TailedFile};
+///
+/// let filter = LineFilter::new()
+///     .include(Matcher::contains("ERROR"))
+///     .include(Matcher::contains("WARN"))
+///     .exclude(Matcher::contains("healthcheck"));
+/// let mut f = TailedFile::builder("/var/log/app.log")
+///     .filter(filter)
+///     .build()
+///     .unwrap();
+/// for line in f.lines_str().unwrap() {
+///     println!("{line}");
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct LineFilter {
+    include: Vec<Matcher>,
+    exclude: Vec<Matcher>,
+    budget: Option<TimeBudget>,
+}
+
+impl LineFilter {
+    /// Creates a filter keeping every record
+    pub fn new() -> LineFilter {
+        LineFilter::default()
+    }
+
+    /// Keeps records matching `matcher`, or any other include matcher
+    pub fn include(mut self, matcher: Matcher) -> LineFilter {
+        self.include.push(matcher);
+        self
+    }
+
+    /// Leaves out records matching `matcher`, even if they are included
+    pub fn exclude(mut self, matcher: Matcher) -> LineFilter {
+        self.exclude.push(matcher);
+        self
+    }
+
+    /// Counts, and optionally reports, records on which a matcher takes
+    /// longer than `budget`
+    pub fn budget(mut self, budget: TimeBudget) -> LineFilter {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Returns `true` if `line` is kept
+    pub fn is_match(&self, line: &[u8]) -> bool {
+        let timed = |m: &Matcher| {
+            m.timing
+                .time(&m.description, self.budget.as_ref(), || m.is_match(line))
+        };
+        (self.include.is_empty() || self.include.iter().any(timed))
+            && !self.exclude.iter().any(timed)
+    }
+
+    /// Returns the time spent in each include matcher, then each exclude
+    /// matcher, shared with the clones of the filter
+    pub fn stats(&self) -> Vec<StageStats> {
+        self.include
+            .iter()
+            .chain(&self.exclude)
+            .map(|m| m.timing.stats(&m.description))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter() {
+        let filter = LineFilter::new()
+            .include(Matcher::contains("ERROR"))
+            .include(Matcher::starts_with("WARN"))
+            .exclude(Matcher::new("long", |l| l.len() > 8));
+        assert!(filter.is_match(b"an ERROR"));
+        assert!(filter.is_match(b"WARN"));
+        assert!(!filter.is_match(b"an ERROR!"));
+        assert!(!filter.is_match(b"not WARN"));
+        assert!(LineFilter::new().is_match(b"anything"));
+
+        let calls: Vec<_> = filter.stats().iter().map(|s| s.calls).collect();
+        assert_eq!(calls, [4, 2, 3]);
+        assert_eq!(filter.stats()[0].name, "contains \"ERROR\"");
+    }
+}
@@ -0,0 +1,159 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use crate::timestamp::{Preset, TimestampParser};
+
+/// Number of bytes sampled from the start of a file for detection
+pub(crate) const SAMPLE_LEN: usize = 8192;
+
+pub(crate) const PRESETS: [Preset; 5] = [
+    Preset::Rfc3339,
+    Preset::DateTime,
+    Preset::Clf,
+    Preset::Syslog,
+    Preset::Epoch,
+];
+
+/// Record layouts recognized by [`detect`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One JSON object per line
+    Json,
+    /// `key=value` pairs separated by whitespace
+    Logfmt,
+    /// Anything else
+    Plain,
+}
+
+/// The result of sampling a file's content
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Detection {
+    /// The record layout
+    pub format: Format,
+    /// The timestamp layout found in most records, if any
+    pub timestamp: Option<Preset>,
+}
+
+impl Detection {
+    /// Creates a [`Detection`] for a known layout, used to override auto-detection
+    pub fn new(format: Format, timestamp: Option<Preset>) -> Detection {
+        Detection { format, timestamp }
+    }
+
+    /// Returns a parser for the detected timestamp layout
+    pub fn timestamp_parser(&self) -> Option<TimestampParser> {
+        self.timestamp.map(TimestampParser::preset)
+    }
+}
+
+/// Identifies the record and timestamp layout of a sample of file content.
+///
+/// Only complete lines are considered, and a layout is chosen when it matches
+/// at least half of the non-empty lines. Returns `None` if the sample contains
+/// no complete lines.
+///
+/// # Example
+/// ```
+/// use staart::{detect, Format};
+///
+/// let d = detect(b"level=info msg=started\nlevel=warn msg=slow\n").unwrap();
+/// assert_eq!(d.format, Format::Logfmt);
+/// ```
+pub fn detect(sample: &[u8]) -> Option<Detection> {
+    let end = sample.iter().rposition(|b| *b == b'\n')?;
+    let text = String::from_utf8_lossy(&sample[..end]);
+    let lines: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect();
+    if lines.is_empty() {
+        return None;
+    }
+
+    let majority = |n: usize| n * 2 >= lines.len();
+    let format = if majority(lines.iter().filter(|l| is_json(l)).count()) {
+        Format::Json
+    } else if majority(lines.iter().filter(|l| is_logfmt(l)).count()) {
+        Format::Logfmt
+    } else {
+        Format::Plain
+    };
+
+    let timestamp = PRESETS.into_iter().find(|p| {
+        let parser = TimestampParser::preset(*p).assume_year(2000);
+        let plausible = |l: &&&str| match parser.find(l) {
+            Some((_, ts)) => *p != Preset::Epoch || ts.unix_seconds() >= 1_000_000_000,
+            None => false,
+        };
+        majority(lines.iter().filter(plausible).count())
+    });
+
+    Some(Detection { format, timestamp })
+}
+
+/// Returns `true` if `sample` is likely binary rather than text, i.e. if at
+/// least one in a hundred of its first bytes is NUL
+///
+/// # Example
+/// ```
+/// assert!(staart::is_binary(b"\x7fELF\x02\x01\x01\0\0\0\0\0\0\0\0\0"));
+/// assert!(!staart::is_binary(b"plain text\n"));
+/// ```
+pub fn is_binary(sample: &[u8]) -> bool {
+    let sample = &sample[..sample.len().min(SAMPLE_LEN)];
+    let nul = sample.iter().filter(|b| **b == 0).count();
+    nul > 0 && nul * 100 >= sample.len()
+}
+
+fn is_json(line: &str) -> bool {
+    line.starts_with('{') && line.ends_with('}') && line.contains(':')
+}
+
+fn is_logfmt(line: &str) -> bool {
+    let pairs = line
+        .split_whitespace()
+        .filter(|tok| match tok.split_once('=') {
+            Some((key, _)) => {
+                key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                    && key
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "_.-".contains(c))
+            }
+            None => false,
+        })
+        .count();
+    pairs >= 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_json() {
+        let sample = b"{\"ts\":\"2024-06-01T12:00:00Z\",\"msg\":\"a\"}\n{\"ts\":\"2024-06-01T12:00:01Z\",\"msg\":\"b\"}\n";
+        let d = detect(sample).unwrap();
+        assert_eq!(d.format, Format::Json);
+        assert_eq!(d.timestamp, Some(Preset::Rfc3339));
+    }
+
+    #[test]
+    fn test_detect_plain_syslog() {
+        let sample =
+            b"Jun  1 12:00:00 host sshd[1]: accepted\nJun  1 12:00:05 host cron[2]: ran\npartial";
+        let d = detect(sample).unwrap();
+        assert_eq!(d.format, Format::Plain);
+        assert_eq!(d.timestamp, Some(Preset::Syslog));
+    }
+
+    #[test]
+    fn test_detect_incomplete() {
+        assert_eq!(detect(b"no newline yet"), None);
+    }
+}
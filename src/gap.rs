@@ -0,0 +1,56 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::fmt;
+
+/// Why data in a [`Gap`] was never read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GapCause {
+    /// The file was truncated before the data could be read
+    Truncated,
+    /// The [`crate::DecisionTable`] skipped to the end of the file
+    Skipped,
+    /// A read filled its buffer under [`crate::Backpressure::SkipToEnd`]
+    Overflow,
+}
+
+impl fmt::Display for GapCause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GapCause::Truncated => write!(f, "truncated"),
+            GapCause::Skipped => write!(f, "skipped"),
+            GapCause::Overflow => write!(f, "overflow"),
+        }
+    }
+}
+
+/// A range of a followed file known to have been missed, reported when
+/// [`crate::TailedFileBuilder::gap_markers`] is set.
+///
+/// Its [`fmt::Display`] form is the marker record handed out in place of
+/// the missing data, e.g. `[gap: 120 bytes at offset 4096, truncated]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gap {
+    /// Offset of the first missing byte in the file it was missed from
+    pub offset: u64,
+    /// Estimated number of missing bytes. For truncation this is a lower
+    /// bound, see [`crate::TailedFile::lost_bytes`].
+    pub len: u64,
+    /// Why the data was missed
+    pub cause: GapCause,
+}
+
+impl fmt::Display for Gap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[gap: {} bytes at offset {}, {}]",
+            self.len, self.offset, self.cause
+        )
+    }
+}
@@ -0,0 +1,414 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::io::Write;
+use std::net::{TcpStream, UdpSocket};
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+use serde_json::{Map, Value};
+
+use crate::net::{connect_udp, resolve};
+use crate::sink::Partials;
+use crate::{clock, Bandwidth, Line, LinesStr, Result, Sink, StaartError, DEFAULT_NET_TIMEOUT};
+
+/// Largest datagram a [`GelfSink`] sends over UDP by default, chunk header
+/// included
+pub const DEFAULT_GELF_CHUNK_SIZE: usize = 8192;
+
+/// Magic bytes starting every GELF chunk
+const CHUNK_MAGIC: [u8; 2] = [0x1e, 0x0f];
+
+/// Length of the header of a GELF chunk
+const CHUNK_HEADER_LEN: usize = 12;
+
+/// Most chunks a GELF message may be split into
+const MAX_CHUNKS: usize = 128;
+
+/// Additional fields GELF reserves or the sink sets itself
+const RESERVED: [&str; 3] = ["_id", "_file", "_offset"];
+
+#[derive(Debug)]
+enum Transport {
+    Udp(Option<UdpSocket>),
+    Tcp(Option<TcpStream>),
+}
+
+/// A [`Sink`] sending each line as a GELF message to Graylog, enabled by the
+/// `gelf` feature.
+///
+/// The line becomes the `short_message`, and the path and offset it was read
+/// at the additional fields `_file` and `_offset`. Messages are timestamped
+/// when sent.
+///
+/// Over UDP, messages larger than [`GelfSink::chunk_size`] are split into
+/// GELF chunks, up to the 128 the format allows. Larger messages are
+/// reported as an error and not sent. Over TCP, messages are terminated by a
+/// null byte, and the connection is made again on the write following a
+/// failure, as by [`crate::TcpSink`]. Empty lines are skipped, since GELF
+/// requires a message, and a partial line ending a chunk is held back until
+/// the rest of it is written.
+///
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+/// use staart::{GelfSink, TailedFile};
+///
+/// let sink = GelfSink::udp("graylog.example.com:12201")
+///     .level(4)
+///     .field("service", "gateway")
+///     .unwrap();
+/// let mut f = TailedFile::new("/var/log/gateway.log").unwrap();
+/// f.follow(sink, Duration::from_secs(1)).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct GelfSink {
+    addr: String,
+    transport: Transport,
+    host: String,
+    level: u8,
+    fields: Map<String, Value>,
+    chunk_size: usize,
+    timeout: Duration,
+    next_id: u64,
+    bandwidth: Option<Bandwidth>,
+    partials: Partials,
+}
+
+impl GelfSink {
+    /// Creates a sink sending chunked datagrams to the GELF UDP input at
+    /// `addr`, such as `localhost:12201`
+    pub fn udp(addr: &str) -> GelfSink {
+        GelfSink::new(addr, Transport::Udp(None))
+    }
+
+    /// Creates a sink sending null-terminated messages to the GELF TCP input
+    /// at `addr`
+    pub fn tcp(addr: &str) -> GelfSink {
+        GelfSink::new(addr, Transport::Tcp(None))
+    }
+
+    fn new(addr: &str, transport: Transport) -> GelfSink {
+        let seed = clock::system_now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        GelfSink {
+            addr: addr.to_string(),
+            transport,
+            host: local_host(),
+            level: 6,
+            fields: Map::new(),
+            chunk_size: DEFAULT_GELF_CHUNK_SIZE,
+            timeout: DEFAULT_NET_TIMEOUT,
+            next_id: seed ^ (u64::from(std::process::id()) << 32),
+            bandwidth: None,
+            partials: Partials::default(),
+        }
+    }
+
+    /// Sets the `host` of every message, defaults to the host name of the
+    /// system, or `localhost` where it can not be read
+    pub fn host(mut self, host: &str) -> GelfSink {
+        self.host = host.to_string();
+        self
+    }
+
+    /// Sets the syslog severity of every message, from 0 for emergencies to
+    /// 7 for debugging, defaults to 6 for informational messages
+    pub fn level(mut self, level: u8) -> GelfSink {
+        self.level = level.min(7);
+        self
+    }
+
+    /// Adds the additional field `key` with `value` to every message. The
+    /// underscore GELF requires in front of additional fields is added.
+    ///
+    /// # Propagates Errors
+    /// - If `key` is not made of letters, digits, underscores, dashes and
+    ///   dots, or is `id`, which GELF reserves, or a field set by the sink
+    ///   itself
+    pub fn field(mut self, key: &str, value: &str) -> Result<GelfSink> {
+        let name = key.trim_start_matches('_');
+        let key = format!("_{name}");
+        let valid = name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+        if name.is_empty() || !valid || RESERVED.contains(&key.as_str()) {
+            return Err(StaartError::Config(format!("invalid GELF field: {key:?}")));
+        }
+        self.fields.insert(key, Value::String(value.to_string()));
+        Ok(self)
+    }
+
+    /// Sets the largest datagram sent over UDP, defaults to
+    /// [`DEFAULT_GELF_CHUNK_SIZE`]
+    pub fn chunk_size(mut self, bytes: usize) -> GelfSink {
+        self.chunk_size = bytes.max(CHUNK_HEADER_LEN + 1);
+        self
+    }
+
+    /// Gives up on a TCP connection after `timeout`, defaults to
+    /// [`DEFAULT_NET_TIMEOUT`]
+    pub fn timeout(mut self, timeout: Duration) -> GelfSink {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Charges every write to `bandwidth`, blocking while it is exhausted
+    pub fn bandwidth(mut self, bandwidth: Bandwidth) -> GelfSink {
+        self.bandwidth = Some(bandwidth);
+        self
+    }
+
+    /// Returns the GELF message for `line`, read at `offset` of `path`
+    fn message(&self, path: &Path, offset: Option<u64>, line: &str) -> Result<Vec<u8>> {
+        let timestamp = clock::system_now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as f64
+            / 1000.0;
+        let mut message = Map::new();
+        message.insert("version".to_string(), "1.1".into());
+        message.insert("host".to_string(), self.host.as_str().into());
+        message.insert("short_message".to_string(), line.into());
+        message.insert("timestamp".to_string(), timestamp.into());
+        message.insert("level".to_string(), self.level.into());
+        message.insert("_file".to_string(), path.display().to_string().into());
+        if let Some(offset) = offset {
+            message.insert("_offset".to_string(), offset.into());
+        }
+        for (key, value) in &self.fields {
+            message.insert(key.clone(), value.clone());
+        }
+
+        serde_json::to_vec(&message).map_err(|e| StaartError::Sink(e.to_string()))
+    }
+
+    fn send<'l, I>(&mut self, path: &Path, lines: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (Option<u64>, &'l str)>,
+    {
+        for (offset, line) in lines {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let message = self.message(path, offset, line)?;
+            let result = self.deliver(&message);
+            if result.is_err() {
+                self.disconnect();
+            }
+            result?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends `message` over the transport, connecting first if needed
+    fn deliver(&mut self, message: &[u8]) -> Result<()> {
+        match &mut self.transport {
+            Transport::Udp(socket) => {
+                let udp = match socket.take() {
+                    Some(udp) => udp,
+                    None => connect_udp(&self.addr)?,
+                };
+                let udp = socket.insert(udp);
+                let id = self.next_id;
+                self.next_id = self.next_id.wrapping_add(1);
+                send_datagrams(udp, message, self.chunk_size, id)
+            }
+            Transport::Tcp(stream) => {
+                let conn = match stream.take() {
+                    Some(conn) => conn,
+                    None => {
+                        let addr = resolve(&self.addr)?;
+                        let conn = TcpStream::connect_timeout(&addr, self.timeout)?;
+                        conn.set_write_timeout(Some(self.timeout))?;
+                        conn
+                    }
+                };
+                let conn = stream.insert(conn);
+                conn.write_all(message)?;
+                conn.write_all(b"\0")?;
+                Ok(())
+            }
+        }
+    }
+
+    fn disconnect(&mut self) {
+        match &mut self.transport {
+            Transport::Udp(socket) => *socket = None,
+            Transport::Tcp(stream) => *stream = None,
+        }
+    }
+}
+
+/// Sends `message` as one datagram, or split into GELF chunks identified by
+/// `id` if it is larger than `chunk_size`
+fn send_datagrams(socket: &UdpSocket, message: &[u8], chunk_size: usize, id: u64) -> Result<()> {
+    if message.len() <= chunk_size {
+        socket.send(message)?;
+        return Ok(());
+    }
+
+    let payload = chunk_size - CHUNK_HEADER_LEN;
+    let chunks = message.chunks(payload);
+    let count = chunks.len();
+    if count > MAX_CHUNKS {
+        return Err(StaartError::Sink(format!(
+            "GELF message of {} bytes needs more than {MAX_CHUNKS} chunks",
+            message.len()
+        )));
+    }
+    let id = id.to_be_bytes();
+    for (seq, part) in chunks.enumerate() {
+        let mut chunk = Vec::with_capacity(CHUNK_HEADER_LEN + part.len());
+        chunk.extend_from_slice(&CHUNK_MAGIC);
+        chunk.extend_from_slice(&id);
+        chunk.push(seq as u8);
+        chunk.push(count as u8);
+        chunk.extend_from_slice(part);
+        socket.send(&chunk)?;
+    }
+
+    Ok(())
+}
+
+impl Sink for GelfSink {
+    fn write_chunk(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        if let Some(bandwidth) = &self.bandwidth {
+            bandwidth.acquire(path, data.len() as u64);
+        }
+        let data = self.partials.complete(path, data);
+        let lines: Vec<_> = LinesStr::new(&data).collect();
+        self.send(path, lines.iter().map(|line| (None, line.as_ref())))
+    }
+
+    fn write_lines(&mut self, path: &Path, lines: &[Line]) -> Result<()> {
+        if let Some(bandwidth) = &self.bandwidth {
+            let bytes = lines.iter().map(|line| line.text.len() as u64 + 1).sum();
+            bandwidth.acquire(path, bytes);
+        }
+        let lines = lines
+            .iter()
+            .map(|line| (Some(line.offset), line.text.as_str()));
+        self.send(path, lines)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if let Transport::Tcp(Some(conn)) = &mut self.transport {
+            conn.flush()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        for (path, line) in self.partials.take_all() {
+            self.write_chunk(&path, &line)?;
+        }
+        self.flush()
+    }
+}
+
+/// Returns the host name of the system, as far as it can be read without
+/// platform-specific calls
+fn local_host() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_gelf_udp_chunks() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let addr = receiver.local_addr().unwrap().to_string();
+        let mut sink = GelfSink::udp(&addr).host("test").chunk_size(64);
+        let line = Line {
+            text: "x".repeat(100),
+            offset: 7,
+        };
+        sink.write_lines(Path::new("test.log"), &[line]).unwrap();
+
+        let mut message = Vec::new();
+        let mut buf = [0; 64];
+        let mut count = 0;
+        for seq in 0.. {
+            let n = receiver.recv(&mut buf).unwrap();
+            assert_eq!(buf[..2], CHUNK_MAGIC);
+            assert_eq!(buf[10], seq);
+            count = buf[11];
+            message.extend_from_slice(&buf[CHUNK_HEADER_LEN..n]);
+            if seq + 1 == count {
+                break;
+            }
+        }
+        assert!(count > 1);
+        let message: Value = serde_json::from_slice(&message).unwrap();
+        assert_eq!(message["version"], "1.1");
+        assert_eq!(message["host"], "test");
+        assert_eq!(message["short_message"], "x".repeat(100));
+        assert_eq!(message["level"], 6);
+        assert_eq!(message["_file"], "test.log");
+        assert_eq!(message["_offset"], 7);
+    }
+
+    #[test]
+    fn test_gelf_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut received = Vec::new();
+            stream.read_to_end(&mut received).unwrap();
+            received
+        });
+
+        let mut sink = GelfSink::tcp(&addr).level(9).field("_app", "web").unwrap();
+        sink.write_chunk(Path::new("test.log"), b"one\n\ntw")
+            .unwrap();
+        sink.write_chunk(Path::new("test.log"), b"o\n").unwrap();
+        sink.flush().unwrap();
+        drop(sink);
+
+        let received = server.join().unwrap();
+        let messages: Vec<Value> = received
+            .split(|b| *b == 0)
+            .filter(|m| !m.is_empty())
+            .map(|m| serde_json::from_slice(m).unwrap())
+            .collect();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0]["short_message"], "one");
+        assert_eq!(messages[1]["short_message"], "two");
+        assert_eq!(messages[1]["level"], 7);
+        assert_eq!(messages[1]["_app"], "web");
+        assert!(messages[1].get("_offset").is_none());
+    }
+
+    #[test]
+    fn test_reserved_fields() {
+        for key in ["id", "_id", "file", "__offset", "", "app name"] {
+            assert!(GelfSink::udp("localhost:12201").field(key, "x").is_err());
+        }
+        assert!(GelfSink::udp("localhost:12201")
+            .field("app.name-1", "x")
+            .is_ok());
+    }
+}
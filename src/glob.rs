@@ -0,0 +1,453 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+use crate::watch::Watcher;
+use crate::{MultiTailer, Result, StaartError, StartPos, TailedFile, TailedFileBuilder};
+
+/// Adjusts the builder of each file a [`GlobTailer`] starts following
+pub(crate) type Configure =
+    Box<dyn Fn(&Path, TailedFileBuilder<PathBuf>) -> TailedFileBuilder<PathBuf> + Send>;
+
+/// What happened to the files followed by a [`GlobTailer`], as returned by
+/// [`GlobTailer::poll_events`]
+#[derive(Debug)]
+pub enum GlobEvent {
+    /// A file started matching and is now followed
+    Added(PathBuf),
+    /// A file stopped matching, usually because it was deleted, and is no
+    /// longer followed
+    Removed(PathBuf),
+    /// New data was read from a followed file
+    Data(PathBuf, Vec<u8>),
+    /// A file could not be opened or read
+    Error(PathBuf, StaartError),
+}
+
+/// Follows every file matching a glob pattern such as `/var/log/app/*.log`.
+///
+/// The pattern is expanded again on each [`GlobTailer::poll`]: files that
+/// start matching are followed from their beginning, and files that stop
+/// matching are dropped. Files matching when the tailer is created are
+/// followed from their end, as with [`TailedFile::new`].
+///
+/// Within a path component, `*` matches any sequence of characters, `?` any
+/// single character, and `[a-z]` or `[!a-z]` any character in or out of a
+/// set. Matching never crosses a path separator.
+///
+/// [`GlobTailer::directory`] follows every file in a directory, as services
+/// and container runtimes write their logs.
+///
+/// # Example
+/// ```no_run
+/// use std::thread::sleep;
+/// use std::time::Duration;
+/// use staart::GlobTailer;
+///
+/// let mut tailer = GlobTailer::new("/var/log/app/*.log").unwrap();
+/// loop {
+///     for (path, data) in tailer.poll() {
+///         if let Ok(data) = data {
+///             print!("{}: {}", path.display(), String::from_utf8_lossy(&data));
+///         }
+///     }
+///     sleep(Duration::from_millis(100));
+/// }
+/// ```
+pub struct GlobTailer {
+    pattern: Pattern,
+    files: MultiTailer<PathBuf>,
+    watcher: Option<Watcher>,
+    configure: Configure,
+}
+
+impl GlobTailer {
+    /// Creates a tailer following the files currently matching `pattern`
+    ///
+    /// # Propagates Errors
+    /// - If the pattern is invalid
+    /// - If a matching file can not be opened
+    pub fn new(pattern: &str) -> Result<GlobTailer> {
+        GlobTailer::with_pattern(Pattern::new(pattern)?)
+    }
+
+    /// Creates a tailer following every regular file in `dir`, which starts
+    /// following files created in the directory and stops following files
+    /// deleted from it
+    ///
+    /// # Propagates Errors
+    /// - If a file in the directory can not be opened
+    pub fn directory<P: AsRef<Path>>(dir: P) -> Result<GlobTailer> {
+        GlobTailer::with_pattern(Pattern {
+            base: dir.as_ref().to_path_buf(),
+            segments: vec![Segment::Wild(vec![Token::Star])],
+        })
+    }
+
+    fn with_pattern(pattern: Pattern) -> Result<GlobTailer> {
+        GlobTailer::configured(pattern, Box::new(|_, builder| builder))
+    }
+
+    /// Creates a tailer passing the builder of every file through `configure`
+    /// before it is built. Files matching now start at [`StartPos::End`] and
+    /// files matching later at [`StartPos::Beginning`] unless it says
+    /// otherwise.
+    pub(crate) fn configured(pattern: Pattern, configure: Configure) -> Result<GlobTailer> {
+        let mut files = MultiTailer::new();
+        for path in pattern.expand() {
+            files.add(configure(&path, TailedFile::builder(path.clone())).build()?);
+        }
+
+        Ok(GlobTailer {
+            pattern,
+            files,
+            watcher: None,
+            configure,
+        })
+    }
+
+    /// Expands the pattern again, then reads every matching file once. See
+    /// [`MultiTailer::poll`].
+    ///
+    /// A newly matching file that can not be opened is tried again on the
+    /// next poll.
+    pub fn poll(&mut self) -> Vec<(&Path, Result<Vec<u8>>)> {
+        self.rescan();
+        self.files.poll()
+    }
+
+    /// Expands the pattern again, then reads every matching file once,
+    /// returning the files added and removed followed by the data read and the
+    /// errors encountered.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use staart::{GlobEvent, GlobTailer};
+    ///
+    /// let mut tailer = GlobTailer::directory("/var/log/containers").unwrap();
+    /// loop {
+    ///     for event in tailer.poll_events() {
+    ///         match event {
+    ///             GlobEvent::Added(path) => eprintln!("following {}", path.display()),
+    ///             GlobEvent::Removed(path) => eprintln!("dropped {}", path.display()),
+    ///             GlobEvent::Data(_, data) => print!("{}", String::from_utf8_lossy(&data)),
+    ///             GlobEvent::Error(path, e) => eprintln!("{}: {e}", path.display()),
+    ///         }
+    ///     }
+    ///     tailer.wait_for_change(Duration::from_secs(1)).unwrap();
+    /// }
+    /// ```
+    pub fn poll_events(&mut self) -> Vec<GlobEvent> {
+        let mut events = self.rescan();
+        events.extend(self.files.poll().into_iter().map(|(path, data)| {
+            let path = path.to_path_buf();
+            match data {
+                Ok(data) => GlobEvent::Data(path, data),
+                Err(e) => GlobEvent::Error(path, e),
+            }
+        }));
+        events
+    }
+
+    /// Blocks until a file in the leading directory of the pattern may have
+    /// changed, or until `timeout` elapses, see [`TailedFile::wait_for_change`].
+    /// Changes deeper than the leading directory are only seen by polling.
+    pub fn wait_for_change(&mut self, timeout: Duration) -> Result<bool> {
+        self.watcher().wait(timeout)
+    }
+
+    /// Returns the watcher of the leading directory of the pattern, creating
+    /// it on first use
+    pub(crate) fn watcher(&mut self) -> &mut Watcher {
+        let dir = self.pattern.watch_dir();
+        self.watcher.get_or_insert_with(|| Watcher::new(dir))
+    }
+
+    /// Follows newly matching files and drops those no longer matching,
+    /// returning what changed
+    pub(crate) fn rescan(&mut self) -> Vec<GlobEvent> {
+        let mut events = Vec::new();
+        let matched: HashSet<PathBuf> = self.pattern.expand().into_iter().collect();
+        let gone: Vec<PathBuf> = self
+            .files
+            .iter()
+            .map(|f| f.path().to_path_buf())
+            .filter(|path| !matched.contains(path))
+            .collect();
+        for path in gone {
+            self.files.remove(&path);
+            events.push(GlobEvent::Removed(path));
+        }
+
+        let mut added: Vec<PathBuf> = matched
+            .into_iter()
+            .filter(|path| self.files.get(path).is_none())
+            .collect();
+        added.sort();
+        for path in added {
+            let builder = TailedFile::builder(path.clone()).start(StartPos::Beginning);
+            match (self.configure)(&path, builder).build() {
+                Ok(file) => {
+                    self.files.add(file);
+                    events.push(GlobEvent::Added(path));
+                }
+                Err(StaartError::IO(e)) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => events.push(GlobEvent::Error(path, e)),
+            }
+        }
+
+        events
+    }
+
+    /// Returns the files currently followed
+    pub fn files(&self) -> &MultiTailer<PathBuf> {
+        &self.files
+    }
+
+    /// Returns the files currently followed mutably
+    pub fn files_mut(&mut self) -> &mut MultiTailer<PathBuf> {
+        &mut self.files
+    }
+}
+
+/// A glob pattern split into path components
+#[derive(Debug, Clone)]
+pub(crate) struct Pattern {
+    base: PathBuf,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Literal(OsString),
+    Wild(Vec<Token>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    Any,
+    Star,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Pattern {
+    pub(crate) fn new(pattern: &str) -> Result<Pattern> {
+        let mut base = PathBuf::new();
+        let mut segments = Vec::new();
+        for component in Path::new(pattern).components() {
+            let Component::Normal(name) = component else {
+                if segments.is_empty() {
+                    base.push(component);
+                    continue;
+                }
+                segments.push(Segment::Literal(component.as_os_str().to_os_string()));
+                continue;
+            };
+            match name.to_str() {
+                Some(s) if s.contains(['*', '?', '[']) => {
+                    segments.push(Segment::Wild(tokenize(s, pattern)?));
+                }
+                _ if segments.is_empty() => base.push(name),
+                _ => segments.push(Segment::Literal(name.to_os_string())),
+            }
+        }
+
+        Ok(Pattern { base, segments })
+    }
+
+    /// Returns the leading directory of the pattern, which holds no wildcards
+    pub(crate) fn watch_dir(&self) -> &Path {
+        if self.base.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            self.base.as_path()
+        }
+    }
+
+    /// Returns the regular files matching the pattern, sorted by path
+    pub(crate) fn expand(&self) -> Vec<PathBuf> {
+        let mut paths = vec![self.base.clone()];
+        for segment in &self.segments {
+            let mut next = Vec::new();
+            for dir in &paths {
+                match segment {
+                    Segment::Literal(name) => next.push(dir.join(name)),
+                    Segment::Wild(tokens) => {
+                        let listing = if dir.as_os_str().is_empty() {
+                            Path::new(".")
+                        } else {
+                            dir.as_path()
+                        };
+                        let Ok(entries) = std::fs::read_dir(listing) else {
+                            continue;
+                        };
+                        for entry in entries.flatten() {
+                            let name = entry.file_name();
+                            if matches!(name.to_str(), Some(s) if matches(tokens, s)) {
+                                next.push(dir.join(name));
+                            }
+                        }
+                    }
+                }
+            }
+            paths = next;
+        }
+
+        paths.retain(|path| path.is_file());
+        paths.sort();
+        paths
+    }
+}
+
+fn tokenize(s: &str, pattern: &str) -> Result<Vec<Token>> {
+    let invalid = || StaartError::Pattern(format!("unclosed character class in {pattern}"));
+    let mut tokens = Vec::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '*' => Token::Star,
+            '?' => Token::Any,
+            '[' => {
+                let mut negated = false;
+                let mut ranges = Vec::new();
+                let mut first = true;
+                loop {
+                    let c = chars.next().ok_or_else(invalid)?;
+                    match c {
+                        '!' if first => negated = true,
+                        ']' if !ranges.is_empty() => break,
+                        _ => {
+                            let mut lookahead = chars.clone();
+                            match (lookahead.next(), lookahead.next()) {
+                                (Some('-'), Some(end)) if end != ']' => {
+                                    chars = lookahead;
+                                    ranges.push((c, end));
+                                }
+                                _ => ranges.push((c, c)),
+                            }
+                        }
+                    }
+                    first = false;
+                }
+                Token::Class { negated, ranges }
+            }
+            c => Token::Char(c),
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+fn matches(tokens: &[Token], s: &str) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+        return s.is_empty();
+    };
+    if *token == Token::Star {
+        return s
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(std::iter::once(s.len()))
+            .any(|i| matches(rest, &s[i..]));
+    }
+
+    let mut chars = s.chars();
+    let Some(c) = chars.next() else {
+        return false;
+    };
+    let matched = match token {
+        Token::Char(t) => *t == c,
+        Token::Any => true,
+        Token::Class { negated, ranges } => {
+            ranges.iter().any(|(lo, hi)| (*lo..=*hi).contains(&c)) != *negated
+        }
+        Token::Star => unreachable!(),
+    };
+
+    matched && matches(rest, chars.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+
+    #[test]
+    fn test_matches() {
+        let tokens = tokenize("[a-c]?*.log", "").unwrap();
+        assert!(matches(&tokens, "ab.log"));
+        assert!(matches(&tokens, "cxyz.log"));
+        assert!(!matches(&tokens, "d1.log"));
+        assert!(!matches(&tokens, "a.log"));
+
+        let tokens = tokenize("[!.]*", "").unwrap();
+        assert!(matches(&tokens, "app.log"));
+        assert!(!matches(&tokens, ".hidden"));
+        assert!(tokenize("app[.log", "").is_err());
+    }
+
+    #[test]
+    fn test_glob_tailer() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.log");
+        let mut file_a = File::create(&a).unwrap();
+        File::create(dir.path().join("b.txt")).unwrap();
+
+        let pattern = dir.path().join("*.log");
+        let mut tailer = GlobTailer::new(pattern.to_str().unwrap()).unwrap();
+        assert_eq!(tailer.files().len(), 1);
+
+        file_a.write_all(b"from a").unwrap();
+        let c = dir.path().join("c.log");
+        File::create(&c).unwrap().write_all(b"from c").unwrap();
+        let polled = tailer.poll();
+        assert_eq!(polled.len(), 2);
+        assert_eq!(polled[0].0, a);
+        assert_eq!(polled[0].1.as_ref().unwrap(), b"from a");
+        assert_eq!(polled[1].0, c);
+        assert_eq!(polled[1].1.as_ref().unwrap(), b"from c");
+
+        std::fs::remove_file(&a).unwrap();
+        assert!(tailer.poll().is_empty());
+        assert_eq!(tailer.files().len(), 1);
+        assert!(tailer.files().get(&c).is_some());
+    }
+
+    #[test]
+    fn test_directory_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.log");
+        File::create(&a).unwrap();
+
+        let mut tailer = GlobTailer::directory(dir.path()).unwrap();
+        assert!(tailer.poll_events().is_empty());
+        tailer.wait_for_change(Duration::from_millis(1)).unwrap();
+
+        let b = dir.path().join("b.log");
+        File::create(&b).unwrap().write_all(b"from b").unwrap();
+        std::fs::remove_file(&a).unwrap();
+        assert!(tailer.wait_for_change(Duration::from_secs(1)).unwrap());
+        let events = tailer.poll_events();
+        assert_eq!(events.len(), 3);
+        assert!(matches!(&events[0], GlobEvent::Removed(path) if *path == a));
+        assert!(matches!(&events[1], GlobEvent::Added(path) if *path == b));
+        assert!(
+            matches!(&events[2], GlobEvent::Data(path, data) if *path == b && data == b"from b")
+        );
+    }
+}
@@ -0,0 +1,250 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+use crate::{Checkpoint, Result, StaartError, TailedFile, TailedFileBuilder};
+
+/// A file handed over by another process with [`hand_over`], as received by
+/// [`take_over`]
+#[derive(Debug)]
+pub struct InheritedFile {
+    /// Path the file was followed at
+    pub path: PathBuf,
+    /// Where the previous owner left off
+    pub checkpoint: Checkpoint,
+    /// The open file, or `None` if the previous owner had closed it, in which
+    /// case it is opened again at the path
+    pub file: Option<File>,
+}
+
+impl InheritedFile {
+    /// Returns a [`TailedFileBuilder`] continuing where the previous owner
+    /// left off, to be configured further as that owner configured it
+    pub fn builder(self) -> TailedFileBuilder<PathBuf> {
+        let builder = TailedFile::builder(self.path).resume(self.checkpoint);
+        match self.file {
+            Some(file) => builder.inherit(file),
+            None => builder,
+        }
+    }
+}
+
+/// Hands the open descriptors and read positions of `files` over to another
+/// process through `conn`, so that a new staart binary takes over following
+/// without losing data, even from files rotated away during the upgrade. The
+/// receiving process calls [`take_over`] on the other end of the connection.
+///
+/// Descriptors are passed as `SCM_RIGHTS` messages, so `conn` must connect
+/// two processes on the same host. The files must no longer be read once
+/// handed over, or data is read twice. Returns the number of files handed
+/// over.
+///
+/// # Example
+/// ```no_run
+/// use std::os::unix::net::UnixStream;
+/// use staart::TailedFile;
+///
+/// let f = TailedFile::new("/var/log/syslog").unwrap();
+/// // ... on upgrade, once the new process listens for the handoff
+/// let mut conn = UnixStream::connect("/run/staart/handoff.sock").unwrap();
+/// staart::hand_over(&mut conn, [&f]).unwrap();
+/// ```
+///
+/// # Propagates Errors
+/// - If a file can not be sent
+pub fn hand_over<'a, T, I>(conn: &mut UnixStream, files: I) -> Result<usize>
+where
+    T: AsRef<Path> + 'a,
+    I: IntoIterator<Item = &'a TailedFile<T>>,
+{
+    let mut count = 0;
+    for file in files {
+        let mut payload = format!("{}\n", file.checkpoint()).into_bytes();
+        payload.extend_from_slice(file.path().as_os_str().as_bytes());
+        let mut frame = u32::try_from(payload.len())?.to_be_bytes().to_vec();
+        frame.extend_from_slice(&payload);
+        let fd = file.file.as_ref().map(|handle| handle.as_raw_fd());
+        send_frame(conn, &frame, fd)?;
+        count += 1;
+    }
+    conn.flush()?;
+
+    Ok(count)
+}
+
+/// Receives the files handed over by another process with [`hand_over`] until
+/// it closes `conn`
+///
+/// # Example
+/// ```no_run
+/// use std::os::unix::net::UnixListener;
+///
+/// let listener = UnixListener::bind("/run/staart/handoff.sock").unwrap();
+/// let (mut conn, _) = listener.accept().unwrap();
+/// let files = staart::take_over(&mut conn)
+///     .unwrap()
+///     .into_iter()
+///     .map(|inherited| inherited.builder().build())
+///     .collect::<Result<Vec<_>, _>>()
+///     .unwrap();
+/// ```
+///
+/// # Propagates Errors
+/// - If a file can not be received, or was not sent by [`hand_over`]
+pub fn take_over(conn: &mut UnixStream) -> Result<Vec<InheritedFile>> {
+    let mut files = Vec::new();
+    loop {
+        let mut header = [0; 4];
+        let (n, file) = recv_header(conn, &mut header)?;
+        if n == 0 {
+            return Ok(files);
+        }
+        conn.read_exact(&mut header[n..])?;
+        let mut payload = vec![0; u32::from_be_bytes(header) as usize];
+        conn.read_exact(&mut payload)?;
+
+        let invalid = || StaartError::Config("invalid handoff message".to_string());
+        let split = payload
+            .iter()
+            .position(|b| *b == b'\n')
+            .ok_or_else(invalid)?;
+        let checkpoint = std::str::from_utf8(&payload[..split])?.parse()?;
+        let path = PathBuf::from(OsStr::from_bytes(&payload[split + 1..]));
+        files.push(InheritedFile {
+            path,
+            checkpoint,
+            file,
+        });
+    }
+}
+
+/// Sends `frame`, with `fd` attached to its first byte
+fn send_frame(conn: &mut UnixStream, frame: &[u8], fd: Option<RawFd>) -> Result<()> {
+    let mut iov = libc::iovec {
+        iov_base: frame.as_ptr() as *mut libc::c_void,
+        iov_len: frame.len(),
+    };
+    let mut control = [0u64; 8];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    if let Some(fd) = fd {
+        let len = std::mem::size_of::<RawFd>() as u32;
+        msg.msg_control = control.as_mut_ptr().cast();
+        msg.msg_controllen = unsafe { libc::CMSG_SPACE(len) } as _;
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(len) as _;
+            std::ptr::write_unaligned(libc::CMSG_DATA(cmsg).cast::<RawFd>(), fd);
+        }
+    }
+    let sent = unsafe { libc::sendmsg(conn.as_raw_fd(), &msg, libc::MSG_NOSIGNAL) };
+    if sent < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    conn.write_all(&frame[sent as usize..])?;
+
+    Ok(())
+}
+
+/// Receives the start of a frame into `header`, returning how many bytes were
+/// received, zero once the sender closed the connection, and the file
+/// attached to the frame if any
+fn recv_header(conn: &mut UnixStream, header: &mut [u8]) -> Result<(usize, Option<File>)> {
+    let mut iov = libc::iovec {
+        iov_base: header.as_mut_ptr().cast(),
+        iov_len: header.len(),
+    };
+    let mut control = [0u64; 8];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = std::mem::size_of_val(&control) as _;
+    let n = loop {
+        let n = unsafe { libc::recvmsg(conn.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC) };
+        if n >= 0 {
+            break n as usize;
+        }
+        let e = std::io::Error::last_os_error();
+        if e.kind() != std::io::ErrorKind::Interrupted {
+            return Err(e.into());
+        }
+    };
+
+    let mut file = None;
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+    while !cmsg.is_null() {
+        let (level, kind) = unsafe { ((*cmsg).cmsg_level, (*cmsg).cmsg_type) };
+        let len: usize = unsafe { (*cmsg).cmsg_len } as _;
+        if level == libc::SOL_SOCKET && kind == libc::SCM_RIGHTS {
+            let data = unsafe { libc::CMSG_DATA(cmsg) };
+            let count =
+                (len - unsafe { libc::CMSG_LEN(0) } as usize) / std::mem::size_of::<RawFd>();
+            for i in 0..count {
+                let fd = unsafe { std::ptr::read_unaligned(data.cast::<RawFd>().add(i)) };
+                // Any descriptors past the first are closed as they are dropped
+                let received = unsafe { File::from_raw_fd(fd) };
+                file.get_or_insert(received);
+            }
+        }
+        cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+    }
+
+    Ok((n, file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+
+    #[test]
+    fn test_handoff() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.log");
+        let mut writer = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .unwrap();
+        writer.write_all(b"one\n").unwrap();
+        let mut old = TailedFile::builder(path.clone())
+            .start(crate::StartPos::Beginning)
+            .build()
+            .unwrap();
+        assert_eq!(old.read().unwrap(), b"one\n");
+
+        // The file is rotated away while the upgrade is under way
+        writer.write_all(b"two\n").unwrap();
+        std::fs::rename(&path, dir.path().join("test.log.1")).unwrap();
+        std::fs::write(&path, b"three\n").unwrap();
+        let (mut sender, mut receiver) = UnixStream::pair().unwrap();
+        assert_eq!(hand_over(&mut sender, [&old]).unwrap(), 1);
+        drop(sender);
+        drop(old);
+
+        let mut inherited = take_over(&mut receiver).unwrap();
+        assert_eq!(inherited.len(), 1);
+        let inherited = inherited.remove(0);
+        assert_eq!(inherited.path, path);
+        assert!(inherited.file.is_some());
+        let mut new = inherited.builder().build().unwrap();
+        assert_eq!(new.read().unwrap(), b"two\n");
+        assert_eq!(new.read().unwrap(), b"three\n");
+    }
+}
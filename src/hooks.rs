@@ -0,0 +1,53 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::path::Path;
+
+use crate::{Action, Branch};
+
+type RotateHook = Box<dyn FnMut(&Path, Branch, Action) + Send>;
+type TruncateHook = Box<dyn FnMut(&Path, Action) + Send>;
+type ReopenHook = Box<dyn FnMut(&Path) + Send>;
+type HeaderHook = Box<dyn FnMut(&Path, &[u8]) + Send>;
+
+/// Callbacks registered on a [`crate::TailedFile`] for rotation, truncation,
+/// reopening and header capture
+#[derive(Default)]
+pub(crate) struct Hooks {
+    pub(crate) rotate: Option<RotateHook>,
+    pub(crate) truncate: Option<TruncateHook>,
+    pub(crate) reopen: Option<ReopenHook>,
+    pub(crate) header: Option<HeaderHook>,
+}
+
+impl Hooks {
+    /// Calls the hook matching `branch`, if it is a rotation or truncation
+    pub(crate) fn changed(&mut self, path: &Path, branch: Branch, action: Action) {
+        if branch.is_rotation() {
+            if let Some(hook) = self.rotate.as_mut() {
+                hook(path, branch, action);
+            }
+        } else if branch == Branch::Shrank {
+            if let Some(hook) = self.truncate.as_mut() {
+                hook(path, action);
+            }
+        }
+    }
+
+    pub(crate) fn reopened(&mut self, path: &Path) {
+        if let Some(hook) = self.reopen.as_mut() {
+            hook(path);
+        }
+    }
+
+    pub(crate) fn captured(&mut self, path: &Path, header: &[u8]) {
+        if let Some(hook) = self.header.as_mut() {
+            hook(path, header);
+        }
+    }
+}
@@ -0,0 +1,129 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use crate::net::resolve;
+use crate::Result;
+
+/// The server of a plain `http://` URL, and the path requests go under
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Endpoint {
+    host: String,
+    base: String,
+}
+
+impl Endpoint {
+    /// Parses `url`, such as `http://localhost:8123/base`, using
+    /// `default_port` if it names none. Returns `None` for any other scheme.
+    pub(crate) fn parse(url: &str, default_port: u16) -> Option<Endpoint> {
+        let rest = url.strip_prefix("http://")?;
+        let (host, base) = match rest.find('/') {
+            Some(i) => (&rest[..i], rest[i..].trim_end_matches('/')),
+            None => (rest, ""),
+        };
+        if host.is_empty() {
+            return None;
+        }
+        let host = if host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{host}:{default_port}")
+        };
+
+        Some(Endpoint {
+            host,
+            base: base.to_string(),
+        })
+    }
+
+    /// Posts `body` to `path` under the endpoint with the extra `headers`, on
+    /// a connection closed once the response is read
+    pub(crate) fn post(
+        &self,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+        timeout: Duration,
+    ) -> Result<Response> {
+        let mut request = format!(
+            "POST {}{path} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+            self.base,
+            self.host,
+            body.len()
+        );
+        for (name, value) in headers {
+            request.push_str(&format!("{name}: {value}\r\n"));
+        }
+        request.push_str("\r\n");
+
+        let mut stream = TcpStream::connect_timeout(&resolve(&self.host)?, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        stream.write_all(request.as_bytes())?;
+        stream.write_all(body)?;
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response)?;
+
+        let response = String::from_utf8_lossy(&response);
+        let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+        Ok(Response {
+            head: head.to_string(),
+            body: body.to_string(),
+        })
+    }
+}
+
+/// The response to [`Endpoint::post`]
+#[derive(Debug)]
+pub(crate) struct Response {
+    head: String,
+    body: String,
+}
+
+impl Response {
+    /// Returns the status code, such as `200`
+    pub(crate) fn status(&self) -> &str {
+        self.head.split_whitespace().nth(1).unwrap_or_default()
+    }
+
+    /// Returns the value of the header `name`, if present
+    #[cfg(feature = "loki")]
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.head
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.trim())
+    }
+
+    /// Returns the body, trimmed
+    pub(crate) fn body(&self) -> &str {
+        self.body.trim()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let endpoint = Endpoint::parse("http://db.local/ch/", 8123).unwrap();
+        assert_eq!(endpoint.host, "db.local:8123");
+        assert_eq!(endpoint.base, "/ch");
+        let endpoint = Endpoint::parse("http://127.0.0.1:3100", 80).unwrap();
+        assert_eq!(endpoint.host, "127.0.0.1:3100");
+        assert_eq!(endpoint.base, "");
+
+        assert!(Endpoint::parse("https://db.local", 443).is_none());
+        assert!(Endpoint::parse("http:///path", 80).is_none());
+    }
+}
@@ -0,0 +1,133 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::fs::{File, Metadata};
+use std::io;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// Identifies a file within the system, as a device and a file number on
+/// that device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct FileId {
+    pub(crate) device: u64,
+    pub(crate) index: u64,
+}
+
+/// The metadata of a file together with its [`FileId`]
+#[derive(Debug, Clone)]
+pub(crate) struct Stat {
+    meta: Metadata,
+    pub(crate) id: FileId,
+}
+
+impl Stat {
+    /// Returns the [`Stat`] of an open file
+    pub(crate) fn of(file: &File) -> io::Result<Stat> {
+        let meta = file.metadata()?;
+        let id = file_id(file, &meta)?;
+        Ok(Stat { meta, id })
+    }
+
+    /// Returns the [`Stat`] of the file at `path`
+    #[cfg(unix)]
+    pub(crate) fn path(path: &Path) -> io::Result<Stat> {
+        let meta = std::fs::metadata(path)?;
+        let id = meta_id(&meta);
+        Ok(Stat { meta, id })
+    }
+
+    /// Returns the [`Stat`] of the file at `path`, which is opened since the
+    /// file index is only available from a handle on Windows
+    #[cfg(windows)]
+    pub(crate) fn path(path: &Path) -> io::Result<Stat> {
+        Stat::of(&File::open(path)?)
+    }
+
+    /// Returns `true` if both describe the same file
+    pub(crate) fn same_file(&self, other: &Stat) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Deref for Stat {
+    type Target = Metadata;
+
+    fn deref(&self) -> &Metadata {
+        &self.meta
+    }
+}
+
+/// Returns the path of the regular file identified by `id` in `dir`, found by
+/// scanning the directory
+pub(crate) fn find_in_dir(dir: &Path, id: FileId) -> Option<PathBuf> {
+    std::fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .filter(|entry| matches!(entry.file_type(), Ok(t) if t.is_file()))
+        .map(|entry| entry.path())
+        .find(|path| matches!(Stat::path(path), Ok(stat) if stat.id == id))
+}
+
+#[cfg(unix)]
+fn file_id(_file: &File, meta: &Metadata) -> io::Result<FileId> {
+    Ok(meta_id(meta))
+}
+
+/// Identifies a file by device and inode in Unix systems
+#[cfg(unix)]
+fn meta_id(meta: &Metadata) -> FileId {
+    use std::os::unix::fs::MetadataExt;
+    FileId {
+        device: meta.dev(),
+        index: meta.ino(),
+    }
+}
+
+/// Identifies a file by volume serial number and file index in Windows
+/// systems. Creation times are not used, since filesystem tunneling gives a
+/// file recreated under the same name the creation time of the old one.
+#[cfg(windows)]
+fn file_id(file: &File, _meta: &Metadata) -> io::Result<FileId> {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Storage::FileSystem::{
+        GetFileInformationByHandle, BY_HANDLE_FILE_INFORMATION,
+    };
+
+    let mut info: BY_HANDLE_FILE_INFORMATION = unsafe { std::mem::zeroed() };
+    if unsafe { GetFileInformationByHandle(file.as_raw_handle() as _, &mut info) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(FileId {
+        device: u64::from(info.dwVolumeSerialNumber),
+        index: u64::from(info.nFileIndexHigh) << 32 | u64::from(info.nFileIndexLow),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.file");
+        let b = dir.path().join("b.file");
+        std::fs::write(&a, b"a").unwrap();
+        std::fs::write(&b, b"b").unwrap();
+
+        let stat_a = Stat::path(&a).unwrap();
+        assert!(stat_a.same_file(&Stat::of(&File::open(&a).unwrap()).unwrap()));
+        assert!(!stat_a.same_file(&Stat::path(&b).unwrap()));
+        assert_eq!(stat_a.id.device, Stat::path(&b).unwrap().id.device);
+
+        let c = dir.path().join("c.file");
+        std::fs::rename(&a, &c).unwrap();
+        assert_eq!(find_in_dir(dir.path(), stat_a.id), Some(c));
+    }
+}
@@ -0,0 +1,269 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::{Result, StaartError, StartPos, TailedFileBuilder};
+
+/// A single observation recorded in a read journal. Journals never contain file content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JournalEntry {
+    /// The file was opened with the given identity, length, read buffer size, and start position
+    Open {
+        id: u64,
+        len: u64,
+        buffer: usize,
+        pos: u64,
+    },
+    /// The file at the path had the given identity and length before a read
+    Stat { id: u64, len: u64 },
+    /// The path could not be opened
+    Missing,
+    /// `len` bytes were read starting at `offset`
+    Read { offset: u64, len: u64 },
+}
+
+impl fmt::Display for JournalEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JournalEntry::Open {
+                id,
+                len,
+                buffer,
+                pos,
+            } => {
+                write!(f, "open id={id} len={len} buffer={buffer} pos={pos}")
+            }
+            JournalEntry::Stat { id, len } => write!(f, "stat id={id} len={len}"),
+            JournalEntry::Missing => write!(f, "missing"),
+            JournalEntry::Read { offset, len } => write!(f, "read offset={offset} len={len}"),
+        }
+    }
+}
+
+impl FromStr for JournalEntry {
+    type Err = StaartError;
+
+    fn from_str(s: &str) -> Result<JournalEntry> {
+        let invalid = || StaartError::Config(format!("invalid journal entry: {s}"));
+        let mut words = s.split_whitespace();
+        let kind = words.next().ok_or_else(invalid)?;
+        let mut field = |name: &str| -> Result<u64> {
+            words
+                .next()
+                .and_then(|w| w.strip_prefix(name))
+                .and_then(|w| w.strip_prefix('='))
+                .and_then(|v| v.parse().ok())
+                .ok_or_else(invalid)
+        };
+
+        match kind {
+            "open" => Ok(JournalEntry::Open {
+                id: field("id")?,
+                len: field("len")?,
+                buffer: field("buffer")?.try_into()?,
+                pos: field("pos")?,
+            }),
+            "stat" => Ok(JournalEntry::Stat {
+                id: field("id")?,
+                len: field("len")?,
+            }),
+            "missing" => Ok(JournalEntry::Missing),
+            "read" => Ok(JournalEntry::Read {
+                offset: field("offset")?,
+                len: field("len")?,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// A point where replaying a journal produced a different read than was recorded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Zero-based index of the entry in the journal
+    pub index: usize,
+    /// The recorded entry
+    pub expected: JournalEntry,
+    /// What the replay produced instead, or `None` if the read failed
+    pub actual: Option<JournalEntry>,
+}
+
+/// Appends journal entries to a file, one per line
+pub(crate) struct Journal {
+    out: BufWriter<File>,
+}
+
+impl Journal {
+    pub(crate) fn create(path: &Path) -> Result<Journal> {
+        let f = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Journal {
+            out: BufWriter::new(f),
+        })
+    }
+
+    pub(crate) fn record(&mut self, entry: JournalEntry) -> Result<()> {
+        writeln!(self.out, "{entry}")?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Reads the entries of a journal written with [`TailedFileBuilder::journal`]
+///
+/// # Propagates Errors
+/// - If the journal can not be read
+/// - If a line of the journal is not a valid entry
+pub fn read_journal<P: AsRef<Path>>(path: P) -> Result<Vec<JournalEntry>> {
+    let f = File::open(path)?;
+    BufReader::new(f)
+        .lines()
+        .filter(|l| !matches!(l, Ok(l) if l.trim().is_empty()))
+        .map(|l| l?.parse())
+        .collect()
+}
+
+/// Replays a journal against a scratch file in `dir`, re-creating the recorded
+/// sequence of file identities and lengths with placeholder content, and
+/// returns every read whose offset or length differs from the recording.
+///
+/// An empty result means the current rotation and truncation handling
+/// reproduces the recorded behavior.
+///
+/// # Propagates Errors
+/// - If the journal can not be read or parsed
+/// - If the scratch file can not be created or modified
+pub fn replay_journal<P: AsRef<Path>, D: AsRef<Path>>(
+    journal: P,
+    dir: D,
+) -> Result<Vec<Divergence>> {
+    let entries = read_journal(journal)?;
+    let path = dir.as_ref().join("replay.log");
+    let path = path.as_path();
+
+    let mut divergences = Vec::new();
+    let mut tailed = None;
+    let mut current: Option<(u64, u64)> = None;
+    let mut rotations = 0;
+    for (index, entry) in entries.iter().enumerate() {
+        match *entry {
+            JournalEntry::Open {
+                id,
+                len,
+                buffer,
+                pos,
+            } => {
+                File::create(path)?.set_len(len)?;
+                current = Some((id, len));
+                tailed = Some(
+                    TailedFileBuilder::new(path)
+                        .start(StartPos::Offset(pos))
+                        .buffer_size(buffer)
+                        .build()?,
+                );
+            }
+            JournalEntry::Stat { id, len } => {
+                match current {
+                    Some((cur, _)) if cur != id => {
+                        rotations += 1;
+                        std::fs::rename(
+                            path,
+                            dir.as_ref().join(format!("replay.log.{rotations}")),
+                        )?;
+                        File::create(path)?.set_len(len)?;
+                    }
+                    None => File::create(path)?.set_len(len)?,
+                    Some(_) => OpenOptions::new().write(true).open(path)?.set_len(len)?,
+                }
+                current = Some((id, len));
+            }
+            JournalEntry::Missing => {
+                if current.take().is_some() {
+                    std::fs::remove_file(path)?;
+                }
+            }
+            JournalEntry::Read { .. } => {
+                let tailed = match tailed.as_mut() {
+                    Some(t) => t,
+                    None => continue,
+                };
+                let actual = tailed.read().ok().map(|data| JournalEntry::Read {
+                    offset: tailed.pos - data.len() as u64,
+                    len: data.len() as u64,
+                });
+                if actual != Some(*entry) {
+                    divergences.push(Divergence {
+                        index,
+                        expected: *entry,
+                        actual,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(divergences)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn test_entry_roundtrip() {
+        let entries = [
+            JournalEntry::Open {
+                id: 7,
+                len: 10,
+                buffer: 65536,
+                pos: 10,
+            },
+            JournalEntry::Stat { id: 8, len: 3 },
+            JournalEntry::Missing,
+            JournalEntry::Read { offset: 0, len: 3 },
+        ];
+        for entry in entries {
+            assert_eq!(entry.to_string().parse::<JournalEntry>().unwrap(), entry);
+        }
+        assert!("read offset=1".parse::<JournalEntry>().is_err());
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+        let journal = dir.path().join("journal.txt");
+
+        let mut f = File::create(path).unwrap();
+        f.write_all(b"Some data").unwrap();
+        let mut tailed_file = TailedFileBuilder::new(&path)
+            .journal(&journal)
+            .build()
+            .unwrap();
+
+        f.write_all(b"more").unwrap();
+        tailed_file.read().unwrap();
+        std::fs::rename(path, dir.path().join("test.file.1")).unwrap();
+        let mut f = File::create(path).unwrap();
+        f.write_all(b"fun").unwrap();
+        tailed_file.read().unwrap();
+
+        let entries = read_journal(&journal).unwrap();
+        assert_eq!(entries.len(), 5);
+        assert_eq!(entries[4], JournalEntry::Read { offset: 0, len: 3 });
+
+        let scratch = tempfile::tempdir().unwrap();
+        assert!(replay_journal(&journal, scratch.path()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,253 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::time::{Duration, Instant};
+
+use crate::{clock, DEFAULT_MULTILINE_IDLE};
+
+/// How deeply a [`JsonAssembler`] lets documents nest by default
+pub const DEFAULT_JSON_MAX_DEPTH: usize = 64;
+
+/// How many bytes a [`JsonAssembler`] lets a document grow to by default
+pub const DEFAULT_JSON_MAX_SIZE: usize = 1 << 20;
+
+/// Assembles JSON documents pretty-printed over several lines into single
+/// records, by tracking the nesting of braces and brackets outside strings.
+///
+/// A document starts at a line beginning with `{` or `[`, and ends at the end
+/// of the line where its braces balance. Other lines seen between documents,
+/// such as single-line messages, form records of their own. The document is
+/// not otherwise validated.
+///
+/// A document nesting deeper than [`JsonAssembler::max_depth`] or growing
+/// past [`JsonAssembler::max_size`] is released as it stands, so that a
+/// malformed document does not hold back the rest of the log. The lines
+/// following it then form records of their own until the next document.
+///
+/// # Example
+/// ```no_run
+/// use staart::{JsonAssembler, TailedFile};
+///
+/// let mut file = TailedFile::new("/var/log/app.json")?;
+/// let mut documents = JsonAssembler::new().compact(true);
+/// loop {
+///     for line in file.lines_str()? {
+///         if let Some(document) = documents.push(&line) {
+///             println!("{document}");
+///         }
+///     }
+///     if let Some(document) = documents.flush_idle() {
+///         println!("{document}");
+///     }
+/// #   break;
+/// }
+/// # Ok::<(), staart::StaartError>(())
+/// ```
+#[derive(Debug)]
+pub struct JsonAssembler {
+    pending: Option<String>,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    compact: bool,
+    max_depth: usize,
+    max_size: usize,
+    overflows: u64,
+    idle: Duration,
+    last: Instant,
+}
+
+impl Default for JsonAssembler {
+    fn default() -> JsonAssembler {
+        JsonAssembler::new()
+    }
+}
+
+impl JsonAssembler {
+    /// Creates an assembler keeping the lines of each document joined by `\n`
+    pub fn new() -> JsonAssembler {
+        JsonAssembler {
+            pending: None,
+            depth: 0,
+            in_string: false,
+            escaped: false,
+            compact: false,
+            max_depth: DEFAULT_JSON_MAX_DEPTH,
+            max_size: DEFAULT_JSON_MAX_SIZE,
+            overflows: 0,
+            idle: DEFAULT_MULTILINE_IDLE,
+            last: clock::now(),
+        }
+    }
+
+    /// Drops whitespace outside strings, so each document is a single line,
+    /// defaults to `false`
+    pub fn compact(mut self, compact: bool) -> JsonAssembler {
+        self.compact = compact;
+        self
+    }
+
+    /// Releases a document nesting deeper than `depth`, defaults to
+    /// [`DEFAULT_JSON_MAX_DEPTH`]
+    pub fn max_depth(mut self, depth: usize) -> JsonAssembler {
+        self.max_depth = depth.max(1);
+        self
+    }
+
+    /// Releases a document growing past `bytes`, defaults to
+    /// [`DEFAULT_JSON_MAX_SIZE`]
+    pub fn max_size(mut self, bytes: usize) -> JsonAssembler {
+        self.max_size = bytes;
+        self
+    }
+
+    /// Sets how long [`JsonAssembler::flush_idle`] waits without a new line
+    /// before releasing an incomplete document, defaults to
+    /// [`DEFAULT_MULTILINE_IDLE`]
+    pub fn idle_timeout(mut self, idle: Duration) -> JsonAssembler {
+        self.idle = idle;
+        self
+    }
+
+    /// Adds a line, returning the record it completes, if any
+    pub fn push(&mut self, line: &str) -> Option<String> {
+        self.last = clock::now();
+        let record = match self.pending.as_mut() {
+            Some(record) => {
+                if !self.compact {
+                    record.push('\n');
+                }
+                record
+            }
+            None if line.trim_start().starts_with(['{', '[']) => {
+                self.pending.insert(String::with_capacity(line.len()))
+            }
+            None => return Some(line.to_string()),
+        };
+
+        let mut deepest = self.depth;
+        for c in line.chars() {
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if c == '\\' {
+                    self.escaped = true;
+                } else if c == '"' {
+                    self.in_string = false;
+                }
+            } else {
+                match c {
+                    '"' => self.in_string = true,
+                    '{' | '[' => {
+                        self.depth += 1;
+                        deepest = deepest.max(self.depth);
+                    }
+                    '}' | ']' => self.depth = self.depth.saturating_sub(1),
+                    c if self.compact && c.is_whitespace() => continue,
+                    _ => {}
+                }
+            }
+            record.push(c);
+        }
+
+        if deepest > self.max_depth || record.len() > self.max_size {
+            self.overflows += 1;
+            return self.flush();
+        }
+        if self.depth == 0 && !self.in_string {
+            return self.flush();
+        }
+
+        None
+    }
+
+    /// Returns an incomplete document if no line was added within the idle
+    /// timeout, since the rest of it may never be written
+    pub fn flush_idle(&mut self) -> Option<String> {
+        if clock::now().saturating_duration_since(self.last) >= self.idle {
+            self.flush()
+        } else {
+            None
+        }
+    }
+
+    /// Returns the pending document, complete or not, e.g. at the end of input
+    pub fn flush(&mut self) -> Option<String> {
+        self.depth = 0;
+        self.in_string = false;
+        self.escaped = false;
+        self.pending.take()
+    }
+
+    /// Returns `true` if a document is waiting for more lines
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Returns the number of documents released for exceeding the maximum
+    /// depth or size
+    pub fn overflows(&self) -> u64 {
+        self.overflows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assemble(mut assembler: JsonAssembler, lines: &[&str]) -> Vec<String> {
+        let mut records: Vec<_> = lines.iter().filter_map(|l| assembler.push(l)).collect();
+        records.extend(assembler.flush());
+        records
+    }
+
+    #[test]
+    fn test_pretty_printed() {
+        let lines = [
+            "starting up",
+            "{",
+            r#"  "msg": "a } in \" a string {","#,
+            r#"  "tags": ["x", "y"],"#,
+            r#"  "ctx": {"id": 1}"#,
+            "}",
+            r#"{"single": true}"#,
+        ];
+        assert_eq!(
+            assemble(JsonAssembler::new().compact(true), &lines),
+            [
+                "starting up",
+                r#"{"msg":"a } in \" a string {","tags":["x","y"],"ctx":{"id":1}}"#,
+                r#"{"single":true}"#,
+            ]
+        );
+        assert_eq!(
+            assemble(JsonAssembler::new(), &lines[1..3]),
+            ["{\n  \"msg\": \"a } in \\\" a string {\","]
+        );
+    }
+
+    #[test]
+    fn test_guards() {
+        let mut assembler = JsonAssembler::new().max_depth(2);
+        assert_eq!(assembler.push("{"), None);
+        assert_eq!(
+            assembler.push(r#"  "a": {"b": ["#).as_deref(),
+            Some("{\n  \"a\": {\"b\": [")
+        );
+        assert_eq!(assembler.push("  ]}").as_deref(), Some("  ]}"));
+
+        let mut assembler = JsonAssembler::new().max_size(8);
+        assert_eq!(assembler.push("["), None);
+        assert_eq!(
+            assembler.push("  1, 2, 3,").as_deref(),
+            Some("[\n  1, 2, 3,")
+        );
+        assert_eq!(assembler.overflows(), 1);
+        assert!(!assembler.is_pending());
+    }
+}
@@ -0,0 +1,195 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::glob::Pattern;
+use crate::watch::Watcher;
+use crate::{Result, StaartError, StartPos, TailedFile};
+
+/// Follows whichever file matching a glob pattern was modified last, such as
+/// the current one of a set of daily files like `app-2024-06-01.log`.
+///
+/// When a file other than the followed one becomes the most recently
+/// modified, the followed file is drained and then dropped, and the newer one
+/// is followed from its beginning. A dropped file is never followed again, so
+/// a late write to yesterday's file does not switch back to it.
+///
+/// The file matching when the tailer is created is followed from its end, as
+/// with [`TailedFile::new`]. See [`crate::GlobTailer`] for the pattern syntax.
+///
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+/// use staart::LatestTailer;
+///
+/// let mut tailer = LatestTailer::new("/var/log/app/app-*.log").unwrap();
+/// loop {
+///     let data = tailer.read().unwrap();
+///     print!("{}", String::from_utf8_lossy(&data));
+///     tailer.wait_for_change(Duration::from_secs(1)).unwrap();
+/// }
+/// ```
+pub struct LatestTailer {
+    pattern: Pattern,
+    current: Option<TailedFile<PathBuf>>,
+    retired: HashSet<PathBuf>,
+    watcher: Option<Watcher>,
+}
+
+impl LatestTailer {
+    /// Creates a tailer following the newest file currently matching
+    /// `pattern`, if any
+    ///
+    /// # Propagates Errors
+    /// - If the pattern is invalid
+    /// - If the newest matching file can not be opened
+    pub fn new(pattern: &str) -> Result<LatestTailer> {
+        let pattern = Pattern::new(pattern)?;
+        let current = match newest(&pattern.expand()) {
+            Some((path, _)) => Some(TailedFile::new(path)?),
+            None => None,
+        };
+
+        Ok(LatestTailer {
+            pattern,
+            current,
+            retired: HashSet::new(),
+            watcher: None,
+        })
+    }
+
+    /// Switches to a newer matching file if one appeared, then returns the
+    /// data written since the last read.
+    ///
+    /// On a switch, the rest of the previous file comes before the start of
+    /// the new one.
+    ///
+    /// # Propagates Errors
+    /// - If the followed file can not be read
+    /// - If the previous file could not be drained, after switching
+    pub fn read(&mut self) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        let drained = self.switch(&mut data);
+        if let Some(file) = self.current.as_mut() {
+            file.read_into(&mut data)?;
+        }
+        drained?;
+
+        Ok(data)
+    }
+
+    /// Follows the newest matching file if it is not the followed one,
+    /// draining the followed one into `data` first
+    fn switch(&mut self, data: &mut Vec<u8>) -> Result<()> {
+        let candidates: Vec<PathBuf> = self
+            .pattern
+            .expand()
+            .into_iter()
+            .filter(|path| !self.retired.contains(path))
+            .collect();
+        let Some((path, modified)) = newest(&candidates) else {
+            return Ok(());
+        };
+        if let Some(current) = &self.current {
+            let current_modified = modified_time(current.path());
+            if current.path() == path || matches!(current_modified, Some(t) if t >= modified) {
+                return Ok(());
+            }
+        }
+
+        let next = match TailedFile::new_with_start(path, StartPos::Beginning) {
+            Ok(next) => next,
+            Err(StaartError::IO(e)) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let Some(mut previous) = self.current.replace(next) else {
+            return Ok(());
+        };
+        self.retired.insert(previous.path().to_path_buf());
+        loop {
+            let len = data.len();
+            previous.read_into(data)?;
+            if data.len() == len {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Returns the path of the followed file, if any matched
+    pub fn path(&self) -> Option<&Path> {
+        self.current.as_ref().map(|f| f.path())
+    }
+
+    /// Returns the followed file, if any matched
+    pub fn file(&self) -> Option<&TailedFile<PathBuf>> {
+        self.current.as_ref()
+    }
+
+    /// Blocks until a file in the leading directory of the pattern may have
+    /// changed, or until `timeout` elapses, see [`TailedFile::wait_for_change`]
+    pub fn wait_for_change(&mut self, timeout: Duration) -> Result<bool> {
+        let dir = self.pattern.watch_dir();
+        self.watcher
+            .get_or_insert_with(|| Watcher::new(dir))
+            .wait(timeout)
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Returns the most recently modified of `paths`, the last by path on a tie
+fn newest(paths: &[PathBuf]) -> Option<(PathBuf, SystemTime)> {
+    paths
+        .iter()
+        .filter_map(|path| Some((modified_time(path)?, path)))
+        .max()
+        .map(|(modified, path)| (path.clone(), modified))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+
+    fn write(file: &mut File, data: &[u8]) {
+        // Keeps modification times apart on coarse clocks
+        std::thread::sleep(Duration::from_millis(20));
+        file.write_all(data).unwrap();
+    }
+
+    #[test]
+    fn test_latest() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = dir.path().join("app-01.log");
+        let mut f1 = File::create(&first).unwrap();
+        write(&mut f1, b"before\n");
+
+        let pattern = dir.path().join("app-*.log");
+        let mut tailer = LatestTailer::new(pattern.to_str().unwrap()).unwrap();
+        assert_eq!(tailer.path(), Some(first.as_path()));
+
+        write(&mut f1, b"one\n");
+        let second = dir.path().join("app-02.log");
+        let mut f2 = File::create(&second).unwrap();
+        write(&mut f2, b"two\n");
+        assert_eq!(tailer.read().unwrap(), b"one\ntwo\n");
+        assert_eq!(tailer.path(), Some(second.as_path()));
+
+        // A late write to the retired file does not switch back
+        write(&mut f2, b"three\n");
+        write(&mut f1, b"late\n");
+        assert_eq!(tailer.read().unwrap(), b"three\n");
+        assert_eq!(tailer.path(), Some(second.as_path()));
+    }
+}
@@ -24,197 +24,2828 @@
 //!     let path = &args[1].as_str();
 //!     let mut f = TailedFile::new(path)?;
 //!     loop {
-//!        f.read_and(|d| print!("{}", std::str::from_utf8(d).unwrap()))?;
+//!        print!("{}", f.read_string_lossy()?);
 //!        sleep(delay);
 //!     }
 //! }
 //! ```
+//!
+//! # Panics
+//!
+//! Nothing read from a followed file can make the library panic: malformed
+//! UTF-8, binary data, oversized records, timestamps out of range and files
+//! changing between reads all surface as data, a [`Lifecycle`] entry or a
+//! [`StaartError`]. Panics are left to callers' closures, and a
+//! [`MultiTailer`] confines those to the file being processed.
+//!
+//! This does not extend to [`TailedFile::read_mapped`] where another process
+//! truncates the file while it is mapped, which the OS may signal with
+//! `SIGBUS`.
+
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufRead, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+mod append;
+mod backoff;
+mod bandwidth;
+mod batch;
+mod breaker;
+mod budget;
+mod builder;
+mod catchup;
+mod checkpoint;
+#[cfg(feature = "clickhouse")]
+mod clickhouse;
+mod clock;
+#[cfg(any(
+    feature = "clickhouse",
+    feature = "loki",
+    feature = "nats",
+    feature = "mqtt"
+))]
+mod compression;
+#[cfg(feature = "shift_jis")]
+mod cp932;
+mod dated;
+mod decision;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+mod decompress;
+mod encoding;
+mod engine;
+mod envelope;
+mod errors;
+mod event;
+mod fdlimit;
+mod filter;
+mod format;
+mod gap;
+#[cfg(feature = "gelf")]
+mod gelf;
+mod glob;
+#[cfg(target_os = "linux")]
+mod handoff;
+mod hooks;
+#[cfg(any(feature = "clickhouse", feature = "loki"))]
+mod http;
+mod identity;
+mod journal;
+mod jsondoc;
+mod latest;
+mod lines;
+#[cfg(feature = "loki")]
+mod loki;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod multi;
+mod multiline;
+#[cfg(feature = "nats")]
+mod nats;
+mod net;
+mod pause;
+mod pipeline;
+mod profile;
+mod progress;
+mod ratelimit;
+mod region;
+#[cfg(feature = "filebeat")]
+mod registry;
+mod rotate;
+mod shared;
+mod shutdown;
+mod siem;
+mod sink;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod state;
+mod stats;
+mod symlink;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod timeline;
+mod timestamp;
+mod watch;
+
+pub use backoff::{Backoff, RetryPolicy};
+pub use bandwidth::Bandwidth;
+pub use batch::{Line, LineBatches};
+pub use breaker::{Circuit, CircuitBreaker, SinkHealth, DEFAULT_BREAKER_BUFFER};
+pub use budget::ErrorBudget;
+pub use builder::{
+    Backpressure, BinaryPolicy, ErrorPolicy, OversizePolicy, RotationCheck, StartPolicy, StartPos,
+    TailedFileBuilder, DEFAULT_BUFFER_SIZE, TRUNCATION_MARKER,
+};
+pub use catchup::{CatchUp, Decompressors, DEFAULT_CATCH_UP_FILES};
+pub use checkpoint::Checkpoint;
+#[cfg(feature = "clickhouse")]
+pub use clickhouse::{
+    ClickHouseSink, RecordField, DEFAULT_CLICKHOUSE_BATCH, DEFAULT_CLICKHOUSE_MAX_PENDING,
+};
+#[cfg(any(
+    feature = "clickhouse",
+    feature = "loki",
+    feature = "nats",
+    feature = "mqtt"
+))]
+pub use compression::Compression;
+pub use dated::{DatedTailer, DEFAULT_PREOPEN};
+pub use decision::{Action, Branch, DecisionTable};
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+pub use decompress::Decompression;
+pub use encoding::Encoding;
+pub use engine::{Engine, EngineConfig, DEFAULT_CHECKPOINT_INTERVAL};
+pub use envelope::{Envelope, EnvelopeReader, EnvelopeSink};
+pub use errors::StaartError;
+pub use event::TailEvent;
+pub use fdlimit::{close_least_recent, descriptor_stats, raise_descriptor_limit, DescriptorStats};
+pub use filter::{LineFilter, Matcher};
+pub use format::{detect, is_binary, Detection, Format};
+pub use gap::{Gap, GapCause};
+#[cfg(feature = "gelf")]
+pub use gelf::{GelfSink, DEFAULT_GELF_CHUNK_SIZE};
+pub use glob::{GlobEvent, GlobTailer};
+#[cfg(target_os = "linux")]
+pub use handoff::{hand_over, take_over, InheritedFile};
+pub use journal::{read_journal, replay_journal, Divergence, JournalEntry};
+pub use jsondoc::{JsonAssembler, DEFAULT_JSON_MAX_DEPTH, DEFAULT_JSON_MAX_SIZE};
+pub use latest::LatestTailer;
+pub use lines::LinesStr;
+#[cfg(feature = "loki")]
+pub use loki::{LokiSink, DEFAULT_LOKI_BATCH, DEFAULT_LOKI_MAX_PENDING, DEFAULT_LOKI_RETRIES};
+#[cfg(feature = "mqtt")]
+pub use mqtt::{MqttSink, QoS};
+pub use multi::MultiTailer;
+pub use multiline::{Multiline, DEFAULT_MULTILINE_IDLE};
+#[cfg(feature = "nats")]
+pub use nats::NatsSink;
+#[cfg(unix)]
+pub use net::UnixSink;
+pub use net::{TcpSink, UdpSink, DEFAULT_MAX_DATAGRAM, DEFAULT_NET_TIMEOUT};
+pub use pause::PauseHandle;
+pub use pipeline::{Pipeline, REDACTED};
+pub use profile::{StageStats, TimeBudget};
+pub use progress::Progress;
+pub use ratelimit::{OverflowPolicy, RateLimit, RateLimitedSink};
+#[cfg(feature = "filebeat")]
+pub use registry::Registry;
+pub use rotate::RotatingFile;
+pub use shared::SharedTailedFile;
+pub use shutdown::ShutdownHandle;
+pub use siem::{SiemFormat, SiemSink};
+pub use sink::{FanOut, FanOutPolicy, Sink};
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteSink;
+pub use state::TailState;
+pub use stats::TailStats;
+pub use symlink::SymlinkCache;
+pub use timeline::{Lifecycle, TimelineEntry, DEFAULT_TIMELINE_CAPACITY};
+pub use timestamp::{Preset, Timestamp, TimestampParser};
+
+use append::AppendGuard;
+use budget::BudgetTracker;
+use fdlimit::Handle;
+use hooks::Hooks;
+use identity::{find_in_dir, FileId, Stat};
+use journal::Journal;
+use lines::RecordLimit;
+use progress::ProgressTracker;
+use region::Region;
+use timeline::Timeline;
+use watch::Watcher;
+
+type Result<T> = std::result::Result<T, StaartError>;
+
+/// [`TailedFile`] tracks the state of a file being followed. It offers
+/// methods for updating this state, and printing data to `stdout`.
+pub struct TailedFile<T> {
+    path: T,
+    file: Option<Handle>,
+    pos: u64,
+    meta: Stat,
+    buffer_size: usize,
+    read_buf: Vec<u8>,
+    drain_limit: Option<u64>,
+    backpressure: Backpressure,
+    align_records: bool,
+    flush_partial: Option<Duration>,
+    held: Option<(usize, Instant)>,
+    delimiter: Vec<u8>,
+    record_limit: Option<RecordLimit>,
+    oversized: u64,
+    binary_policy: BinaryPolicy,
+    binary: Option<bool>,
+    encoding: Encoding,
+    filter: Option<LineFilter>,
+    gap_markers: bool,
+    gaps: Vec<Gap>,
+    header_limit: Option<usize>,
+    header: Option<Vec<u8>>,
+    pipeline: Pipeline,
+    catch_up: Option<catchup::Predecessors>,
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    decoder: Option<(Decompression, Option<decompress::Decoder>)>,
+    rotation: RotationCheck,
+    errors: ErrorPolicy,
+    retry: Option<RetryPolicy>,
+    decisions: DecisionTable,
+    format: Option<Detection>,
+    state: TailState,
+    timeline: Timeline,
+    journal: Option<Journal>,
+    append_guard: Option<AppendGuard>,
+    budget: Option<BudgetTracker>,
+    change: Option<Branch>,
+    replaced: Option<FileId>,
+    pending: Option<TailEvent>,
+    close_inactive: Option<Duration>,
+    last_data: Instant,
+    idle_after: Option<Duration>,
+    quiet_since: SystemTime,
+    idle_reported: bool,
+    seen_len: u64,
+    lost: u64,
+    line_buf: Vec<u8>,
+    line_consumed: usize,
+    watcher: Option<Watcher>,
+    hooks: Hooks,
+    progress: Option<ProgressTracker>,
+    shutdown: Option<ShutdownHandle>,
+    shutdown_checkpoint: Option<PathBuf>,
+    paused: PauseHandle,
+    stats: TailStats,
+}
+
+impl<T> TailedFile<T>
+where
+    T: AsRef<Path>,
+{
+    /// Creates an instance of `std::io::Result<staart::TailedFile>`
+    ///
+    /// # Example
+    /// ```no_run
+    /// let mut f = staart::TailedFile::new("/var/log/syslog");
+    /// ```
+    ///
+    /// The record format of the file is detected from its first bytes, see
+    /// [`TailedFile::format`].
+    ///
+    /// # Propagates Errors
+    /// - If the path provided does not exist, or is not readable by the current user
+    /// - If file metadata can not be read
+    pub fn new(path: T) -> Result<TailedFile<T>> {
+        TailedFileBuilder::new(path).build()
+    }
+
+    /// Creates a [`TailedFile`] which starts reading at the given [`StartPos`]
+    ///
+    /// # Example
+    /// ```no_run
+    /// use staart::{StartPos, TailedFile};
+    ///
+    /// let mut f = TailedFile::new_with_start("/var/log/syslog", StartPos::Beginning);
+    /// ```
+    ///
+    /// # Propagates Errors
+    /// - If the path provided does not exist, or is not readable by the current user
+    /// - If file metadata can not be read
+    pub fn new_with_start(path: T, start: StartPos) -> Result<TailedFile<T>> {
+        TailedFileBuilder::new(path).start(start).build()
+    }
+
+    /// Creates a [`TailedFile`] which continues from a [`Checkpoint`] taken by
+    /// [`TailedFile::checkpoint`], typically before a restart. If the file at
+    /// `path` is no longer the checkpointed one, or is shorter than the
+    /// checkpointed offset, it is read from the beginning.
+    ///
+    /// # Propagates Errors
+    /// - If the path provided does not exist, or is not readable by the current user
+    /// - If file metadata can not be read
+    pub fn resume(path: T, checkpoint: Checkpoint) -> Result<TailedFile<T>> {
+        TailedFileBuilder::new(path).resume(checkpoint).build()
+    }
+
+    /// Returns a [`TailedFileBuilder`] to configure how `path` is followed
+    pub fn builder(path: T) -> TailedFileBuilder<T> {
+        TailedFileBuilder::new(path)
+    }
+
+    /// Returns a [`Checkpoint`] of the followed file and the read position,
+    /// from which [`TailedFile::resume`] continues without repeating or
+    /// skipping data. An incomplete line held back by [`TailedFile::lines_str`],
+    /// or data buffered by [`BufRead`] and not yet consumed, is not counted as
+    /// read.
+    ///
+    /// Data decoded from a compressed file can not be mapped back to an offset
+    /// in it, so while any of it is held back the checkpoint points at the
+    /// start of the file, see [`TailedFileBuilder::decompress`].
+    pub fn checkpoint(&self) -> Checkpoint {
+        let held = (self.line_buf.len() - self.line_consumed) as u64;
+        Checkpoint {
+            device: self.meta.id.device,
+            inode: self.meta.id.index,
+            offset: self.delivered_offset(held),
+        }
+    }
+
+    /// Returns the offset up to which data was delivered, with `held` bytes
+    /// read but not yet consumed
+    fn delivered_offset(&self, held: u64) -> u64 {
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        if let Some((_, decoder)) = &self.decoder {
+            let pending = decoder.as_ref().map_or(0, |d| d.pending() as u64);
+            return if held + pending == 0 { self.pos } else { 0 };
+        }
+        self.pos.saturating_sub(held)
+    }
+
+    /// Returns the path of the file being followed
+    pub fn path(&self) -> &Path {
+        self.path.as_ref()
+    }
+
+    /// Returns the detected record and timestamp format of the file.
+    ///
+    /// Detection happens when the file is opened, or on the first read that
+    /// returns complete lines if the file was empty. Returns `None` until then.
+    pub fn format(&self) -> Option<&Detection> {
+        self.format.as_ref()
+    }
+
+    /// Returns the current [`TailState`] of the file
+    pub fn state(&self) -> TailState {
+        match self.state {
+            TailState::Stopped | TailState::Disabled => self.state,
+            _ if self.paused.is_paused() => TailState::Paused,
+            state => state,
+        }
+    }
+
+    /// Pauses deliveries: reads return no data, and waiting for changes
+    /// sleeps out its timeout, until [`TailedFile::unpause`]. The read
+    /// position is kept, and so is the handle of the file, so data written to
+    /// it while paused is read once unpaused, even if the file was rotated in
+    /// the meantime. A file rotated more than once while paused loses the
+    /// files in between, as they are never opened.
+    pub fn pause(&mut self) {
+        self.paused.pause();
+    }
+
+    /// Continues deliveries from the position reached when paused
+    pub fn unpause(&mut self) {
+        self.paused.unpause();
+    }
+
+    /// Returns a [`PauseHandle`] pausing and unpausing the file from another
+    /// thread, such as after it was moved into [`TailedFile::spawn`]
+    pub fn pause_handle(&self) -> PauseHandle {
+        self.paused.clone()
+    }
+
+    /// Stops following the file. Subsequent reads return no data.
+    pub fn stop(&mut self) {
+        self.state = TailState::Stopped;
+        self.timeline.record(Lifecycle::Stopped);
+    }
+
+    /// Resumes following a file in [`TailState::Disabled`] with a refilled
+    /// [`ErrorBudget`]. Has no effect in any other state.
+    pub fn enable(&mut self) {
+        if self.state != TailState::Disabled {
+            return;
+        }
+        if let Some(budget) = self.budget.as_mut() {
+            budget.reset();
+        }
+        self.state = TailState::Following;
+        self.timeline.record(Lifecycle::Enabled);
+    }
+
+    /// Disables the file after processing it panicked with `msg`
+    pub(crate) fn disable_panicked(&mut self, msg: String) {
+        self.state = TailState::Disabled;
+        self.timeline.record(Lifecycle::Panicked(msg));
+        self.timeline.record(Lifecycle::Disabled);
+    }
+
+    /// Returns the number of records longer than the limit set with
+    /// [`TailedFileBuilder::max_record_size`], whether they were truncated,
+    /// dropped or passed through
+    pub fn oversized_records(&self) -> u64 {
+        self.oversized
+    }
+
+    /// Returns the number of bytes known to have been lost because the file was
+    /// truncated before they were read.
+    ///
+    /// This is a lower bound: only data seen by an earlier read is counted, so
+    /// bytes written and truncated between two reads go unnoticed.
+    pub fn lost_bytes(&self) -> u64 {
+        self.lost
+    }
+
+    /// Returns the [`TailStats`] of the file since it was built
+    pub fn stats(&self) -> TailStats {
+        self.stats
+    }
+
+    /// Returns the [`Gap`]s found since the last call, when
+    /// [`TailedFileBuilder::gap_markers`] is set. Gaps are otherwise handed
+    /// out as marker records by [`TailedFile::lines_str`] and
+    /// [`TailedFile::batches`], so this is for callers of the raw reads.
+    pub fn take_gaps(&mut self) -> Vec<Gap> {
+        std::mem::take(&mut self.gaps)
+    }
+
+    /// Counts `n` bytes returned by a read in the [`TailStats`]
+    fn record_data(&mut self, n: usize) {
+        if n > 0 {
+            self.stats.bytes_read += n as u64;
+            let now = clock::system_now();
+            self.stats.last_data = Some(now);
+            self.quiet_since = now;
+            self.idle_reported = false;
+            #[cfg(feature = "log")]
+            log::trace!("{}: read {n} bytes", self.path.as_ref().display());
+        }
+    }
+
+    fn record_gap(&mut self, offset: u64, len: u64, cause: GapCause) {
+        if self.gap_markers && len > 0 {
+            self.gaps.push(Gap { offset, len, cause });
+        }
+    }
+
+    /// Returns the number of bytes in the followed file past the read
+    /// position, which is how far reading lags behind the writer.
+    ///
+    /// # Propagates Errors
+    /// - If the file can not be inspected
+    pub fn lag(&self) -> Result<u64> {
+        let len = match self.file.as_ref() {
+            Some(file) => file.metadata()?.len(),
+            None => Stat::path(self.path.as_ref())?.len(),
+        };
+
+        Ok(len.saturating_sub(self.pos))
+    }
+
+    /// Drops the handle of the followed file, so that the next read opens the
+    /// file at the path afresh, keeping the read position unless the file
+    /// there was replaced.
+    ///
+    /// # Propagates Errors
+    /// - If the file can not be inspected
+    pub fn request_reopen(&mut self) -> Result<()> {
+        if let Some(file) = self.file.take() {
+            self.meta = Stat::of(&file)?;
+        }
+        if self.state == TailState::Closed {
+            self.state = TailState::Following;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the most recent lifecycle events of the file, oldest first.
+    ///
+    /// # Example
+    /// ```no_run
+    /// let f = staart::TailedFile::new("/var/log/syslog").unwrap();
+    /// for entry in f.timeline() {
+    ///     eprintln!("{entry}");
+    /// }
+    /// ```
+    pub fn timeline(&self) -> impl Iterator<Item = &TimelineEntry> {
+        self.timeline.iter()
+    }
+
+    /// Calls `f` whenever the file at the path is replaced by a different
+    /// file, with the path, the [`Branch`] that fired and the [`Action`] taken.
+    /// Replaces any callback registered before.
+    ///
+    /// Callbacks run during reads, so they should return quickly.
+    ///
+    /// # Example
+    /// ```no_run
+    /// let mut f = staart::TailedFile::new("/var/log/syslog").unwrap();
+    /// f.on_rotate(|path, branch, action| {
+    ///     eprintln!("{}: {branch}, {action}", path.display());
+    /// });
+    /// ```
+    pub fn on_rotate<F>(&mut self, f: F)
+    where
+        F: FnMut(&Path, Branch, Action) + Send + 'static,
+    {
+        self.hooks.rotate = Some(Box::new(f));
+    }
+
+    /// Calls `f` whenever the file shrinks below the read position, with the
+    /// path and the [`Action`] taken. Replaces any callback registered before.
+    pub fn on_truncate<F>(&mut self, f: F)
+    where
+        F: FnMut(&Path, Action) + Send + 'static,
+    {
+        self.hooks.truncate = Some(Box::new(f));
+    }
+
+    /// Calls `f` whenever the file is reopened, after being missing from its
+    /// path or closed while inactive. Replaces any callback registered before.
+    pub fn on_reopen<F>(&mut self, f: F)
+    where
+        F: FnMut(&Path) + Send + 'static,
+    {
+        self.hooks.reopen = Some(Box::new(f));
+    }
+
+    /// Calls `f` with the header of each file attached at the path, once it
+    /// has been captured, see [`TailedFileBuilder::capture_header`]. Replaces
+    /// any callback registered before.
+    pub fn on_header<F>(&mut self, f: F)
+    where
+        F: FnMut(&Path, &[u8]) + Send + 'static,
+    {
+        self.hooks.header = Some(Box::new(f));
+    }
+
+    /// Calls `f` with the [`Progress`] through the backlog at most every
+    /// `interval` while reading lags behind the file, and once more when it
+    /// catches up, e.g. to tell whether a backfill takes minutes or days.
+    /// Replaces any callback registered before, and restarts the counts.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// let mut f = staart::TailedFile::builder("/var/log/syslog")
+    ///     .start(staart::StartPos::Beginning)
+    ///     .build()
+    ///     .unwrap();
+    /// f.on_progress(Duration::from_secs(10), |path, progress| {
+    ///     eprintln!("{}: {progress}", path.display());
+    /// });
+    /// ```
+    pub fn on_progress<F>(&mut self, interval: Duration, f: F)
+    where
+        F: FnMut(&Path, &Progress) + Send + 'static,
+    {
+        self.progress = Some(ProgressTracker::new(interval, Box::new(f)));
+    }
+
+    /// Returns the [`Progress`] last reported to the callback registered with
+    /// [`TailedFile::on_progress`], for exporting as metrics
+    pub fn progress(&self) -> Option<Progress> {
+        self.progress.as_ref().and_then(|p| p.last())
+    }
+
+    /// Returns the first record of the file, without its delimiter, or its
+    /// first bytes if the record is longer than the limit set with
+    /// [`TailedFileBuilder::capture_header`]. Returns `None` until a read finds
+    /// the first record complete, and again once the file is rotated or
+    /// truncated, until the header of the new content is captured.
+    ///
+    /// # Example
+    /// ```no_run
+    /// let mut f = staart::TailedFile::builder("/var/data/export.csv")
+    ///     .capture_header(4096)
+    ///     .build()
+    ///     .unwrap();
+    /// f.read().unwrap();
+    /// if let Some(columns) = f.header() {
+    ///     println!("columns: {}", String::from_utf8_lossy(columns));
+    /// }
+    /// ```
+    pub fn header(&self) -> Option<&[u8]> {
+        self.header.as_deref()
+    }
+
+    /// Reads the header from the start of the file, leaving it uncaptured if
+    /// the first record is still incomplete
+    fn capture_header(&mut self, limit: usize) -> Result<()> {
+        let Some(file) = self.file.as_mut() else {
+            return Ok(());
+        };
+        let mut buf = vec![0; limit];
+        file.seek(SeekFrom::Start(0))?;
+        let mut n = 0;
+        while n < limit {
+            match file.read(&mut buf[n..])? {
+                0 => break,
+                read => n += read,
+            }
+        }
+        buf.truncate(n);
+        match lines::find(&buf, &self.delimiter) {
+            Some(end) => buf.truncate(end),
+            None if n < limit => return Ok(()),
+            None => {}
+        }
+        self.hooks.captured(self.path.as_ref(), &buf);
+        self.header = Some(buf);
+
+        Ok(())
+    }
+
+    /// Returns whether the file was detected as binary, or `None` until data
+    /// has been read from it. See [`TailedFileBuilder::binary_policy`].
+    pub fn is_binary(&self) -> Option<bool> {
+        self.binary
+    }
+
+    /// Overrides the detected format of the file
+    pub fn set_format(&mut self, detection: Detection) {
+        self.format = Some(detection);
+    }
+
+    /// Reads new data for an instance of `staart::TailedFile` and returns
+    /// `Result<Vec<u8>>`
+    ///
+    /// Prior to reading the file, it is checked for rotation and/or truncation.
+    ///
+    /// Once [`TailedFile::stop`] has been called no further data is read.
+    pub fn read(&mut self) -> Result<Vec<u8>> {
+        // Read through a buffer kept between calls, so that a poll finding no
+        // new data allocates nothing
+        let mut buf = std::mem::take(&mut self.read_buf);
+        buf.clear();
+        let result = self.read_into(&mut buf);
+        let data = buf.to_vec();
+        self.read_buf = buf;
+        result?;
+
+        Ok(data)
+    }
+
+    /// Appends new data to `buf`, returning the number of bytes appended.
+    ///
+    /// This behaves like [`TailedFile::read`], but lets callers reuse one
+    /// buffer across polls. `buf` is only reallocated if its spare capacity is
+    /// smaller than the configured buffer size, which is zeroed past the end
+    /// of `buf` before each read.
+    ///
+    /// # Example
+    /// ```no_run
+    /// let mut f = staart::TailedFile::new("/var/log/syslog").unwrap();
+    /// let mut buf = Vec::with_capacity(staart::DEFAULT_BUFFER_SIZE);
+    /// loop {
+    ///     buf.clear();
+    ///     f.read_into(&mut buf).unwrap();
+    ///     print!("{}", String::from_utf8_lossy(&buf));
+    /// }
+    /// ```
+    pub fn read_into(&mut self, buf: &mut Vec<u8>) -> Result<usize> {
+        let start = buf.len();
+        buf.resize(start + self.buffer_size, 0);
+        let result = self.read_slice(&mut buf[start..]);
+        buf.truncate(start + *result.as_ref().unwrap_or(&0));
+
+        result
+    }
+
+    /// Reads new data into `out`, reporting progress when due, and returns
+    /// the number of bytes read
+    fn read_slice(&mut self, out: &mut [u8]) -> Result<usize> {
+        let n = self.read_source(out)?;
+        self.record_data(n);
+        let due = self.progress.as_mut().map(|p| p.record(n));
+        if due == Some(true) {
+            let remaining = self.lag()?;
+            if let Some(progress) = self.progress.as_mut() {
+                progress.report(self.path.as_ref(), remaining);
+            }
+        }
+
+        Ok(n)
+    }
+
+    /// Reads new data into `out` from the predecessors, the decoder or the
+    /// file itself
+    fn read_source(&mut self, out: &mut [u8]) -> Result<usize> {
+        if let Some(catch_up) = self.catch_up.as_mut() {
+            let n = catch_up.read(out)?;
+            if n > 0 {
+                // Keep draining until the live file has been reached
+                self.state = TailState::Draining;
+                return Ok(n);
+            }
+            self.catch_up = None;
+        }
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        if self.decoder.is_some() {
+            return self.read_decoded(out);
+        }
+        self.read_retrying(out)
+    }
+
+    /// Reads new data from a compressed file and decodes it into `out`
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    fn read_decoded(&mut self, out: &mut [u8]) -> Result<usize> {
+        let mut raw = vec![0; out.len()];
+        loop {
+            if let Some((_, Some(decoder))) = self.decoder.as_mut() {
+                let n = decoder.take(out);
+                if n > 0 {
+                    return Ok(n);
+                }
+            }
+            let n = self.read_retrying(&mut raw)?;
+            if n == 0 {
+                return Ok(0);
+            }
+            if let Some((kind, decoder)) = self.decoder.as_mut() {
+                let decoder = match decoder {
+                    Some(decoder) => decoder,
+                    None => decoder.insert(decompress::Decoder::new(*kind)?),
+                };
+                decoder.feed(&raw[..n])?;
+            }
+        }
+    }
+
+    /// Starts decoding a compressed file afresh, once it was truncated or
+    /// replaced
+    fn reset_decoder(&mut self) {
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        if let Some((_, decoder)) = self.decoder.as_mut() {
+            *decoder = None;
+        }
+    }
+
+    /// Reads new data into `out` as stored in the file, retrying transient
+    /// errors under the [`RetryPolicy`]
+    fn read_retrying(&mut self, out: &mut [u8]) -> Result<usize> {
+        let mut attempt = 0;
+        let result = loop {
+            let result = self.read_inner(out);
+            match (&result, self.retry) {
+                (Err(e), Some(retry)) if retry.retries(e, attempt) => {
+                    std::thread::sleep(retry.delay(attempt));
+                    attempt += 1;
+                }
+                _ => break result,
+            }
+        };
+        self.record_result(&result);
+
+        result
+    }
+
+    /// Passes everything written since the last read, up to the drain limit,
+    /// to `f` as a single slice and returns its length.
+    ///
+    /// With the `mmap` feature enabled the file is mapped into memory, so that
+    /// catching up on a large backlog does not copy it through a buffer. Without
+    /// the feature, or where the file cannot be mapped, the data is read into a
+    /// temporary buffer instead.
+    ///
+    /// A mapped file that is truncated while `f` runs may cause the process to
+    /// receive `SIGBUS`, so this is best suited to append-only files. Failed
+    /// reads are not retried by the [`RetryPolicy`], since `f` is only called
+    /// once.
+    ///
+    /// # Example
+    /// ```no_run
+    /// let mut f = staart::TailedFile::builder("/var/log/syslog")
+    ///     .start(staart::StartPos::Beginning)
+    ///     .build()
+    ///     .unwrap();
+    /// f.read_mapped(|data| print!("{}", String::from_utf8_lossy(data)))
+    ///     .unwrap();
+    /// ```
+    pub fn read_mapped<F: FnOnce(&[u8])>(&mut self, f: F) -> Result<u64> {
+        let result = self.read_mapped_inner(f);
+        self.record_result(&result);
+
+        result
+    }
+
+    fn read_mapped_inner<F: FnOnce(&[u8])>(&mut self, f: F) -> Result<u64> {
+        if !self.prepare()? {
+            return Ok(0);
+        }
+        let Some(file) = self.file.as_mut() else {
+            return Ok(0);
+        };
+        let remaining = file.metadata()?.len().saturating_sub(self.pos);
+        let len = match self.drain_limit {
+            Some(limit) => remaining.min(limit),
+            None => remaining,
+        };
+        let full = len < remaining;
+        let region = Region::new(file, self.pos, len)?;
+        let data = &region[..self.aligned_len(&region, full)];
+        if self.skip_binary(data) {
+            return Ok(0);
+        }
+        f(data);
+        self.record_data(data.len());
+        self.advance(data, full)?;
+
+        Ok(data.len() as u64)
+    }
+
+    /// Records errors other than a missing file in the timeline, and disables
+    /// the file once its [`ErrorBudget`] is exhausted
+    fn record_result<R>(&mut self, result: &Result<R>) {
+        match result {
+            Err(StaartError::IO(e)) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => self.timeline.record_error(e),
+            Ok(_) => {}
+        }
+        let Some(budget) = self.budget.as_mut() else {
+            return;
+        };
+        if result.is_ok() {
+            budget.success();
+        } else if budget.error(clock::now()) {
+            self.state = TailState::Disabled;
+            self.timeline.record(Lifecycle::Disabled);
+        }
+    }
+
+    fn read_inner(&mut self, out: &mut [u8]) -> Result<usize> {
+        if !self.prepare()? {
+            return Ok(0);
+        }
+        let Some(file) = self.file.as_mut() else {
+            return Ok(0);
+        };
+        file.seek(SeekFrom::Start(self.pos))?;
+        let n = file.read(out)?;
+        let full = n == out.len();
+        let n = self.aligned_len(&out[..n], full);
+        if self.skip_binary(&out[..n]) {
+            return Ok(0);
+        }
+        self.advance(&out[..n], full)?;
+        if full {
+            self.relieve()?;
+        }
+
+        Ok(n)
+    }
+
+    /// Skips the data left past a read that filled its buffer, under
+    /// [`Backpressure::SkipToEnd`]
+    fn relieve(&mut self) -> Result<()> {
+        if self.backpressure == Backpressure::CarryOver {
+            return Ok(());
+        }
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        if self.decoder.is_some() {
+            return Ok(());
+        }
+        let Some(file) = self.file.as_ref() else {
+            return Ok(());
+        };
+        let len = file.metadata()?.len();
+        if len > self.pos {
+            #[cfg(feature = "log")]
+            log::warn!(
+                "{}: skipped {} bytes past a full read buffer",
+                self.path.as_ref().display(),
+                len - self.pos
+            );
+            self.record_gap(self.pos, len - self.pos, GapCause::Overflow);
+            self.pos = len;
+        }
+        self.state = TailState::Following;
+
+        Ok(())
+    }
+
+    /// Detects binary content in the first data read from the file, returning
+    /// `true` if the data is to be skipped under [`BinaryPolicy::Skip`]
+    fn skip_binary(&mut self, data: &[u8]) -> bool {
+        #[cfg(any(feature = "gzip", feature = "zstd"))]
+        if self.decoder.is_some() {
+            return false;
+        }
+        if self.binary.is_none() && !data.is_empty() {
+            let binary = !self.delimiter.contains(&0) && is_binary(data);
+            if binary {
+                self.timeline.record(Lifecycle::Binary);
+            }
+            self.binary = Some(binary);
+        }
+        if self.binary == Some(true) && self.binary_policy == BinaryPolicy::Skip {
+            self.state = TailState::Disabled;
+            self.timeline.record(Lifecycle::Disabled);
+            return true;
+        }
+
+        false
+    }
+
+    /// Checks the file for rotation, truncation and modification ahead of a
+    /// read, returning `false` if nothing should be read.
+    fn prepare(&mut self) -> Result<bool> {
+        if matches!(self.state, TailState::Stopped | TailState::Disabled) || self.paused.is_paused()
+        {
+            return Ok(false);
+        }
+        if self.file.is_none() && !self.reopen()? {
+            return Ok(false);
+        }
+
+        if self.rotation == RotationCheck::FileId {
+            let path_meta = match Stat::path(self.path.as_ref()) {
+                Ok(meta) => meta,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return self.missing(e),
+                Err(e) => return Err(e.into()),
+            };
+            if self.state == TailState::WaitingForFile {
+                self.timeline.record(Lifecycle::Reopened);
+                self.hooks.reopened(self.path.as_ref());
+            }
+            if let Some(journal) = self.journal.as_mut() {
+                journal.record(JournalEntry::Stat {
+                    id: path_meta.id.index,
+                    len: path_meta.len(),
+                })?;
+            }
+            // Finish reading the rotated file before switching to its replacement
+            let held_len = match self.file.as_ref() {
+                Some(file) => file.metadata()?.len(),
+                None => 0,
+            };
+            if !path_meta.same_file(&self.meta) && held_len <= self.pos {
+                let mut fd = self.open_path()?;
+                self.check_rotate(&fd)?;
+                if let Some(guard) = self.append_guard.as_mut() {
+                    guard.rebase(&mut fd, self.pos)?;
+                }
+                self.file = Some(Handle::new(fd));
+            }
+        }
+
+        let Some(file) = self.file.as_ref() else {
+            return Ok(false);
+        };
+        let meta = Stat::of(file)?;
+        let len = meta.len();
+        let shrank = meta.same_file(&self.meta) && len < self.pos;
+        // Data seen by an earlier stat but not yet read is gone once the file
+        // is truncated in place, as with logrotate's copytruncate
+        if shrank && self.seen_len > self.pos {
+            let lost = self.seen_len - self.pos;
+            self.lost += lost;
+            self.timeline.record(Lifecycle::Lost(lost));
+            self.record_gap(self.pos, lost, GapCause::Truncated);
+        }
+        self.seen_len = len;
+        if shrank {
+            self.header = None;
+            self.reset_decoder();
+        }
+        self.check_truncate(meta);
+        if let (Some(guard), Some(file)) = (self.append_guard.as_mut(), self.file.as_mut()) {
+            let modified = if shrank {
+                Some(len)
+            } else if guard.due() {
+                guard.verify(file)?
+            } else {
+                None
+            };
+            if let Some(offset) = modified {
+                guard.rebase(file, self.pos.min(len))?;
+                self.timeline.record(Lifecycle::Modified(offset));
+                return Err(StaartError::Modified(offset));
+            }
+        }
+        if let (Some(limit), None) = (self.header_limit, self.header.as_ref()) {
+            if len > 0 {
+                self.capture_header(limit)?;
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Opens the file again at its path, counting a failure in the
+    /// [`TailStats`]
+    fn open_path(&mut self) -> Result<File> {
+        File::open(self.path.as_ref()).map_err(|e| {
+            self.stats.reopen_failures += 1;
+            e.into()
+        })
+    }
+
+    /// Records that the file is missing from its path, and applies the
+    /// [`ErrorPolicy`] to `e`
+    fn missing(&mut self, e: std::io::Error) -> Result<bool> {
+        self.stats.reopen_failures += 1;
+        if self.state != TailState::WaitingForFile {
+            self.timeline.record(Lifecycle::Missing);
+        }
+        if let Some(journal) = self.journal.as_mut() {
+            journal.record(JournalEntry::Missing)?;
+        }
+        self.state = TailState::WaitingForFile;
+        match self.errors {
+            ErrorPolicy::Propagate => Err(e.into()),
+            ErrorPolicy::IgnoreMissing => Ok(false),
+        }
+    }
+
+    /// Closes the handle of a file that has been inactive, keeping the read
+    /// position so that it can be reopened once the file changes
+    fn close(&mut self) -> Result<()> {
+        if let Some(file) = self.file.take() {
+            self.meta = Stat::of(&file)?;
+        }
+        self.state = TailState::Closed;
+        self.timeline.record(Lifecycle::Inactive);
+
+        Ok(())
+    }
+
+    /// Reopens a file closed by [`TailedFile::close`] if it was replaced or has
+    /// changed since, returning `false` while it remains unchanged
+    fn reopen(&mut self) -> Result<bool> {
+        let path_meta = match Stat::path(self.path.as_ref()) {
+            Ok(meta) => meta,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return self.missing(e),
+            Err(e) => return Err(e.into()),
+        };
+        let unchanged = path_meta.same_file(&self.meta)
+            && path_meta.len() == self.meta.len()
+            && path_meta.modified().ok() == self.meta.modified().ok();
+        if unchanged && self.state == TailState::Closed {
+            return Ok(false);
+        }
+
+        let mut fd = self.open_path()?;
+        let rotated = !path_meta.same_file(&self.meta);
+        self.check_rotate(&fd)?;
+        if let (true, Some(guard)) = (rotated, self.append_guard.as_mut()) {
+            guard.rebase(&mut fd, self.pos)?;
+        }
+        self.file = Some(Handle::new(fd));
+        self.last_data = clock::now();
+        self.state = TailState::Following;
+        self.timeline.record(Lifecycle::Reopened);
+        self.hooks.reopened(self.path.as_ref());
+
+        Ok(true)
+    }
+
+    /// Looks for the file identified by `id` next to the followed path, where
+    /// rotation usually renames it
+    fn renamed_to(&self, id: FileId) -> Option<PathBuf> {
+        let dir = match self.path.as_ref().parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        find_in_dir(dir, id)
+    }
+
+    /// Returns how much of `data` may be handed out, holding back a trailing
+    /// partial record when reads are aligned to records. A partial record that
+    /// stopped growing for the flush timeout is handed out whole.
+    fn aligned_len(&mut self, data: &[u8], full: bool) -> usize {
+        if !self.align_records {
+            return data.len();
+        }
+        let n = match lines::complete_len(data, &self.delimiter) {
+            0 if full => data.len(),
+            n => n,
+        };
+        if n > 0 || data.is_empty() {
+            self.held = None;
+            return n;
+        }
+
+        let Some(timeout) = self.flush_partial else {
+            return 0;
+        };
+        let now = clock::now();
+        let since = match self.held {
+            Some((len, since)) if len == data.len() => since,
+            _ => now,
+        };
+        if now.duration_since(since) >= timeout {
+            self.held = None;
+            data.len()
+        } else {
+            self.held = Some((data.len(), since));
+            0
+        }
+    }
+
+    /// Records `data`, read at the current position, as consumed.
+    fn advance(&mut self, data: &[u8], full: bool) -> Result<()> {
+        let n = data.len();
+        if let Some(journal) = self.journal.as_mut() {
+            journal.record(JournalEntry::Read {
+                offset: self.pos,
+                len: n.try_into()?,
+            })?;
+        }
+        self.pos += u64::try_from(n)?;
+        self.state = if full {
+            TailState::Draining
+        } else {
+            TailState::Following
+        };
+        if n > 0 {
+            self.last_data = clock::now();
+        } else if self.rotation == RotationCheck::FileId
+            && matches!(self.close_inactive, Some(idle) if clock::now().duration_since(self.last_data) > idle)
+        {
+            self.close()?;
+        }
+
+        if let Some(guard) = self.append_guard.as_mut() {
+            guard.observe(self.pos - n as u64, data);
+        }
+        if self.format.is_none() {
+            self.format = detect(data);
+        }
+
+        Ok(())
+    }
+
+    /// Reads new data like [`TailedFile::read`], and reports what happened to
+    /// the file as a [`TailEvent`].
+    ///
+    /// When the file was rotated or truncated and data was also read, the
+    /// change is returned first and the data by the next call. A missing file
+    /// is reported as [`TailEvent::Deleted`] once, and as
+    /// [`TailEvent::NoChange`] until it reappears. With
+    /// [`TailedFileBuilder::idle_after`] set, [`TailEvent::Idle`] is returned
+    /// in place of one [`TailEvent::NoChange`] once the file has been quiet
+    /// for that long.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use staart::{TailEvent, TailedFile};
+    ///
+    /// let mut f = TailedFile::new("/var/log/syslog").unwrap();
+    /// loop {
+    ///     match f.poll_event().unwrap() {
+    ///         TailEvent::Data(d) => print!("{}", String::from_utf8_lossy(&d)),
+    ///         TailEvent::NoChange => std::thread::sleep(std::time::Duration::from_secs(1)),
+    ///         event => eprintln!("{event:?}"),
+    ///     }
+    /// }
+    /// ```
+    pub fn poll_event(&mut self) -> Result<TailEvent> {
+        if let Some(event) = self.pending.take() {
+            return Ok(event);
+        }
+
+        let waiting = self.state == TailState::WaitingForFile;
+        self.change = None;
+        let mut data = Vec::new();
+        match self.read_into(&mut data) {
+            Err(StaartError::IO(e)) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+            Ok(_) => {}
+        }
+        if self.state == TailState::WaitingForFile {
+            return Ok(if waiting {
+                self.no_change()
+            } else {
+                TailEvent::Deleted
+            });
+        }
+
+        let data = if data.is_empty() {
+            TailEvent::NoChange
+        } else {
+            TailEvent::Data(data)
+        };
+        let change = match self.change.take() {
+            Some(branch) if branch.is_rotation() => TailEvent::Rotated {
+                path: self.path.as_ref().to_path_buf(),
+                renamed_to: self.replaced.and_then(|id| self.renamed_to(id)),
+            },
+            Some(_) => TailEvent::Truncated,
+            None if data == TailEvent::NoChange => return Ok(self.no_change()),
+            None => return Ok(data),
+        };
+        if data != TailEvent::NoChange {
+            self.pending = Some(data);
+        }
+
+        Ok(change)
+    }
+
+    /// Returns [`TailEvent::Idle`] if the file has been quiet for longer than
+    /// [`TailedFileBuilder::idle_after`] and this was not reported yet, or
+    /// [`TailEvent::NoChange`]
+    fn no_change(&mut self) -> TailEvent {
+        let quiet = clock::system_now()
+            .duration_since(self.quiet_since)
+            .unwrap_or_default();
+        match self.idle_after {
+            Some(after) if !self.idle_reported && quiet >= after => {
+                self.idle_reported = true;
+                TailEvent::Idle {
+                    since: self.quiet_since,
+                }
+            }
+            _ => TailEvent::NoChange,
+        }
+    }
+
+    /// Reads all data written since the last read, looping until the end of the
+    /// file so that bursts larger than the buffer are consumed in one call.
+    ///
+    /// At most the drain limit configured with [`TailedFileBuilder::drain_limit`]
+    /// is returned, in which case the file remains in [`TailState::Draining`].
+    pub fn read_all_new(&mut self) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.drain_into(&mut data)?;
+
+        Ok(data)
+    }
+
+    /// Appends data to `buf` until the end of the file or the drain limit
+    fn drain_into(&mut self, buf: &mut Vec<u8>) -> Result<u64> {
+        let mut total = self.read_into(buf)? as u64;
+        while self.drain_more(total) {
+            total += self.read_into(buf)? as u64;
+        }
+
+        Ok(total)
+    }
+
+    /// Passes `&Vec<u8>` read from the tailed file to a user-defined function returning the unit type ()`.
+    ///
+    /// The function is called once per buffer until the end of the file, or the
+    /// drain limit, is reached.
+    pub fn read_and<F: FnMut(&[u8])>(&mut self, mut f: F) -> Result<()> {
+        self.try_read_and(|data| {
+            f(data);
+            Ok::<(), StaartError>(())
+        })
+    }
+
+    /// Like [`TailedFile::read_and`], but stops at the first error returned by
+    /// `f` and propagates it. Data already passed to `f` is not read again.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::io::Write;
+    /// use staart::StaartError;
+    ///
+    /// let mut f = staart::TailedFile::new("/var/log/syslog").unwrap();
+    /// let mut out = std::io::stdout().lock();
+    /// f.try_read_and(|d| out.write_all(d).map_err(StaartError::from))
+    ///     .unwrap();
+    /// ```
+    pub fn try_read_and<F, E>(&mut self, mut f: F) -> std::result::Result<(), E>
+    where
+        F: FnMut(&[u8]) -> std::result::Result<(), E>,
+        E: From<StaartError>,
+    {
+        let mut data = Vec::new();
+        let mut total = self.read_into(&mut data)? as u64;
+        f(&data)?;
+        while self.drain_more(total) {
+            data.clear();
+            total += self.read_into(&mut data)? as u64;
+            f(&data)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads all new data and returns an iterator over the complete lines it
+    /// contains. An incomplete trailing line is kept and returned by a later
+    /// call once it has been terminated by the record delimiter, see
+    /// [`TailedFileBuilder::delimiter`].
+    ///
+    /// Lines that are valid UTF-8 are borrowed without copying, see [`LinesStr`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// let mut f = staart::TailedFile::new("/var/log/syslog").unwrap();
+    /// for line in f.lines_str().unwrap() {
+    ///     println!("{line}");
+    /// }
+    /// ```
+    pub fn lines_str(&mut self) -> Result<LinesStr<'_>> {
+        self.complete_lines()?;
+        let gaps = std::mem::take(&mut self.gaps);
+        Ok(self
+            .records(&self.line_buf[..self.line_consumed])
+            .gaps(gaps))
+    }
+
+    /// Reads all new data and returns the complete lines it contains as owned
+    /// strings, transcoded as by [`TailedFile::read_string_lossy`]. Lines are
+    /// split as by [`TailedFile::lines_str`].
+    pub fn read_lines_lossy(&mut self) -> Result<Vec<String>> {
+        Ok(self.lines_str()?.map(Cow::into_owned).collect())
+    }
+
+    /// Reads all new data as a string, transcoded from the
+    /// [`TailedFileBuilder::encoding`] of the file, with invalid UTF-8 replaced
+    /// by U+FFFD.
+    ///
+    /// A character split across reads is kept back until the rest of it has
+    /// been written, rather than being replaced.
+    ///
+    /// # Example
+    /// ```no_run
+    /// let mut f = staart::TailedFile::new("/var/log/syslog").unwrap();
+    /// print!("{}", f.read_string_lossy().unwrap());
+    /// ```
+    pub fn read_string_lossy(&mut self) -> Result<String> {
+        self.fill_line_buf()?;
+        let n = self.encoding.complete_len(&self.line_buf);
+        let s = self.encoding.decode(&self.line_buf[..n]).into_owned();
+        self.line_buf.drain(..n);
+
+        Ok(s)
+    }
+
+    /// Appends all new data to the line buffer, after dropping what earlier
+    /// calls consumed from it
+    fn fill_line_buf(&mut self) -> Result<()> {
+        let mut buf = std::mem::take(&mut self.line_buf);
+        buf.drain(..self.line_consumed);
+        self.line_consumed = 0;
+
+        let result = self.drain_into(&mut buf);
+        self.line_buf = buf;
+        result.map(|_| ())
+    }
+
+    /// Reads new data into the line buffer, returning the complete lines it
+    /// holds and keeping a trailing partial line for the next call
+    fn complete_lines(&mut self) -> Result<&[u8]> {
+        self.fill_line_buf()?;
+
+        self.line_consumed = lines::complete_len(&self.line_buf, &self.delimiter);
+        self.stats.records += lines::count(&self.line_buf[..self.line_consumed], &self.delimiter);
+        if let Some(limit) = self.record_limit {
+            let complete = &self.line_buf[..self.line_consumed];
+            self.oversized += limit.count_oversized(complete, &self.delimiter);
+        }
+
+        Ok(&self.line_buf[..self.line_consumed])
+    }
+
+    /// Splits `complete` into records with the configured delimiter and
+    /// record size limit
+    fn records<'a>(&'a self, complete: &'a [u8]) -> LinesStr<'a> {
+        let hex = self.binary == Some(true) && self.binary_policy == BinaryPolicy::HexEncode;
+        LinesStr::with_delimiter(complete, &self.delimiter)
+            .limited(self.record_limit)
+            .hex(hex)
+            .encoding(self.encoding)
+            .filtered(self.filter.as_ref())
+            .pipeline(&self.pipeline)
+    }
+
+    /// Returns an iterator over batches of at most `max_lines` complete lines,
+    /// each yielded no later than `max_latency` after its oldest line was read.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// let mut f = staart::TailedFile::new("/var/log/syslog").unwrap();
+    /// for batch in f.batches(500, Duration::from_secs(1)) {
+    ///     for line in batch.unwrap() {
+    ///         println!("{}: {}", line.offset, line.text);
+    ///     }
+    /// }
+    /// ```
+    pub fn batches(&mut self, max_lines: usize, max_latency: Duration) -> LineBatches<'_, T> {
+        LineBatches::new(self, max_lines, max_latency)
+    }
+
+    /// Returns `true` if more data is available and the drain limit allows reading it
+    fn drain_more(&self, drained: u64) -> bool {
+        self.state() == TailState::Draining
+            && !matches!(self.drain_limit, Some(max) if drained >= max)
+    }
+
+    /// Blocks until the tailed file may have changed, or until `timeout` elapses.
+    /// Returns `false` if the timeout elapsed without any change being observed.
+    ///
+    /// On Linux the first call registers an inotify watch on the file and its
+    /// parent directory, so that modifications, rotations, and re-creation wake
+    /// the caller immediately. On Windows and MacOS the same is achieved through
+    /// the `notify` crate when the `notify` feature is enabled. Otherwise, and on
+    /// filesystems where inotify does not observe remote changes (NFS, SMB,
+    /// FUSE), this sleeps for `timeout` and always returns `true`. While the
+    /// file is paused, this sleeps for `timeout` and returns `false`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// let mut f = staart::TailedFile::new("/var/log/syslog").unwrap();
+    /// loop {
+    ///     f.read_and(|d| print!("{}", String::from_utf8_lossy(d))).unwrap();
+    ///     f.wait_for_change(Duration::from_secs(1)).unwrap();
+    /// }
+    /// ```
+    pub fn wait_for_change(&mut self, timeout: Duration) -> Result<bool> {
+        if self.paused.is_paused() {
+            std::thread::sleep(timeout);
+            return Ok(false);
+        }
+        self.watcher
+            .get_or_insert_with(|| Watcher::new(self.path.as_ref()))
+            .wait(timeout)
+    }
+
+    /// Follows the file, writing everything read to `sink` and flushing it
+    /// after each read, and waiting up to `interval` for changes between
+    /// reads. Returns once the file is stopped or disabled, or shut down
+    /// through [`TailedFileBuilder::shutdown`], which hands whatever the sink
+    /// held back to [`Sink::finish`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// let mut f = staart::TailedFile::new("/var/log/syslog").unwrap();
+    /// f.follow(std::io::stdout(), Duration::from_secs(1)).unwrap();
+    /// ```
+    ///
+    /// # Propagates Errors
+    /// - If the file can not be read
+    /// - If `sink` fails to write or flush
+    pub fn follow<S: Sink>(&mut self, mut sink: S, interval: Duration) -> Result<()> {
+        let mut buf = Vec::with_capacity(self.buffer_size);
+        loop {
+            if self.shutdown_requested() {
+                let rest = self.release_partial()?;
+                if !rest.is_empty() {
+                    sink.write_chunk(self.path.as_ref(), &rest)?;
+                }
+                sink.finish()?;
+                return self.shut_down();
+            }
+            buf.clear();
+            self.read_into(&mut buf)?;
+            if !buf.is_empty() {
+                sink.write_chunk(self.path.as_ref(), &buf)?;
+                sink.flush()?;
+            }
+            match self.state() {
+                TailState::Stopped | TailState::Disabled => return Ok(()),
+                TailState::Draining => {}
+                _ => {
+                    self.wait_for_change(interval)?;
+                }
+            }
+        }
+    }
+
+    fn shutdown_requested(&self) -> bool {
+        matches!(&self.shutdown, Some(handle) if handle.is_signaled())
+    }
+
+    /// Reads once more, handing out a trailing partial record that aligned
+    /// reads would hold back
+    fn release_partial(&mut self) -> Result<Vec<u8>> {
+        let mut rest = Vec::new();
+        if !self.align_records || matches!(self.state, TailState::Stopped | TailState::Disabled) {
+            return Ok(rest);
+        }
+        let flush_partial = self.flush_partial.replace(Duration::ZERO);
+        let result = self.read_into(&mut rest);
+        self.flush_partial = flush_partial;
+        result?;
+
+        Ok(rest)
+    }
+
+    /// Saves the shutdown checkpoint, if any, and stops the file
+    fn shut_down(&mut self) -> Result<()> {
+        if let Some(path) = &self.shutdown_checkpoint {
+            self.checkpoint().save(path)?;
+        }
+        self.stop();
+
+        Ok(())
+    }
+
+    /// Applies the [`DecisionTable`] to the [`Branch`] described by whether the
+    /// file was rotated and how its length compares to the read position
+    fn decide(&mut self, rotated: bool, meta: Stat) {
+        let len = meta.len();
+        let branch = Branch::new(rotated, len.cmp(&self.pos));
+        let action = self.decisions.get(branch);
+        if branch.is_rotation() {
+            self.stats.rotations += 1;
+        } else if branch == Branch::Shrank {
+            self.stats.truncations += 1;
+        }
+        match action {
+            Action::Continue => {}
+            Action::FromStart => self.pos = 0,
+            Action::SkipToEnd => {
+                let skipped = len.saturating_sub(self.pos);
+                self.record_gap(self.pos, skipped, GapCause::Skipped);
+                self.pos = len;
+            }
+        }
+        if rotated {
+            self.replaced = Some(self.meta.id);
+            self.meta = meta;
+            self.binary = None;
+            self.header = None;
+            self.reset_decoder();
+        }
+        if branch.is_notable() {
+            self.timeline.record(Lifecycle::Changed(branch, action));
+            self.hooks.changed(self.path.as_ref(), branch, action);
+            self.change = Some(branch);
+        }
+    }
+
+    /// Checks for file rotation by comparing the identity of `fd` to the
+    /// followed file, by device and inode on Unix systems and by volume serial
+    /// number and file index on Windows
+    fn check_rotate(&mut self, fd: &File) -> Result<()> {
+        let meta = Stat::of(fd)?;
+        if !meta.same_file(&self.meta) {
+            self.decide(true, meta);
+        }
+
+        Ok(())
+    }
+
+    /// Checks for file truncation by length comparision to the previous read position
+    fn check_truncate(&mut self, meta: Stat) {
+        if meta.same_file(&self.meta) {
+            self.decide(false, meta);
+        }
+    }
+}
+
+impl<T> TailedFile<T>
+where
+    T: AsRef<Path> + Send + 'static,
+{
+    /// Follows the file on a dedicated thread, delivering what
+    /// [`TailedFile::poll_event`] returns over a channel and waiting up to
+    /// `interval` for changes between polls.
+    ///
+    /// While the file is idle, [`TailEvent::NoChange`] is sent once per
+    /// `interval`. The thread ends once the receiver is dropped, the file is
+    /// disabled, or it is shut down through [`TailedFileBuilder::shutdown`]
+    /// after sending any partial record held back, and hands the file back
+    /// through the [`JoinHandle`], e.g.
+    /// to take a [`TailedFile::checkpoint`]. It ends early with the error if
+    /// the file can not be read.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use staart::{TailEvent, TailedFile};
+    ///
+    /// let f = TailedFile::new("/var/log/syslog").unwrap();
+    /// let (handle, events) = f.spawn(Duration::from_secs(1));
+    /// for event in events.iter().take(100) {
+    ///     if let TailEvent::Data(d) = event {
+    ///         print!("{}", String::from_utf8_lossy(&d));
+    ///     }
+    /// }
+    /// drop(events);
+    /// let f = handle.join().unwrap().unwrap();
+    /// ```
+    pub fn spawn(
+        mut self,
+        interval: Duration,
+    ) -> (JoinHandle<Result<TailedFile<T>>>, Receiver<TailEvent>) {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let handle = std::thread::spawn(move || loop {
+            if self.shutdown_requested() {
+                let rest = self.release_partial()?;
+                if !rest.is_empty() {
+                    // The receiver may be gone already, which changes nothing
+                    let _ = sender.send(TailEvent::Data(rest));
+                }
+                self.shut_down()?;
+                return Ok(self);
+            }
+            let event = self.poll_event()?;
+            let idle = matches!(event, TailEvent::NoChange | TailEvent::Idle { .. });
+            if sender.send(event).is_err() {
+                return Ok(self);
+            }
+            match self.state() {
+                TailState::Stopped | TailState::Disabled => return Ok(self),
+                TailState::Draining => {}
+                _ if idle => {
+                    self.wait_for_change(interval)?;
+                }
+                _ => {}
+            }
+        });
+
+        (handle, receiver)
+    }
+}
+
+/// Reads new data like [`TailedFile::read_into`], so a followed file can be
+/// passed to anything consuming a reader, such as a decompressor or
+/// [`std::io::copy`]. Data buffered by [`BufRead`] is returned first.
+///
+/// A read returning `Ok(0)` only means that no new data is available yet:
+/// unlike a regular file, later reads return whatever was written since.
+/// Rotation and truncation are handled before each read as usual. The trait
+/// method is shadowed by [`TailedFile::read`], so call it as
+/// `Read::read(&mut f, buf)` or through generic code.
+///
+/// # Example
+/// ```no_run
+/// let mut f = staart::TailedFile::new("/var/log/syslog").unwrap();
+/// std::io::copy(&mut f, &mut std::io::stdout()).unwrap();
+/// ```
+impl<T: AsRef<Path>> Read for TailedFile<T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.line_consumed < self.line_buf.len() {
+            let buffered = self.fill_buf()?;
+            let n = buffered.len().min(buf.len());
+            buf[..n].copy_from_slice(&buffered[..n]);
+            BufRead::consume(self, n);
+            return Ok(n);
+        }
+        Ok(self.read_slice(buf)?)
+    }
+}
+
+/// Buffers new data so that [`BufRead::read_line`], [`BufRead::lines`] and
+/// [`BufRead::split`] work on a followed file.
+///
+/// As with [`Read`], reaching the end of the available data only means that
+/// nothing new was written yet, so a line returned by `read_line` may be
+/// incomplete; check for its terminator. Buffered data outlives rotation, so
+/// the rest of a line is appended from the replacement file.
+///
+/// # Example
+/// ```no_run
+/// use std::io::BufRead;
+///
+/// let mut f = staart::TailedFile::new("/var/log/syslog").unwrap();
+/// let mut line = String::new();
+/// loop {
+///     if f.read_line(&mut line).unwrap() == 0 {
+///         std::thread::sleep(std::time::Duration::from_millis(100));
+///     } else if line.ends_with('\n') {
+///         print!("{line}");
+///         line.clear();
+///     }
+/// }
+/// ```
+impl<T: AsRef<Path>> BufRead for TailedFile<T> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if self.line_consumed == self.line_buf.len() {
+            self.line_buf.clear();
+            self.line_consumed = 0;
+            let mut buf = std::mem::take(&mut self.line_buf);
+            let result = self.read_into(&mut buf);
+            self.line_buf = buf;
+            result?;
+        }
+
+        Ok(&self.line_buf[self.line_consumed..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.line_consumed = (self.line_consumed + amt).min(self.line_buf.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn tailed_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+        let _f = File::create(path).unwrap();
+        let tailed_file = TailedFile::new(&path);
+        assert!(tailed_file.is_ok())
+    }
+
+    #[test]
+    fn test_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+        let test_data = b"Some data";
+
+        let mut f = File::create(path).unwrap();
+        let mut tailed_file = TailedFile::new(&path).unwrap();
+
+        f.write_all(test_data).unwrap();
+
+        let data = tailed_file.read().unwrap();
+        assert_eq!(data.len(), test_data.len());
+        assert_eq!(tailed_file.pos, 9);
+    }
+
+    #[test]
+    fn test_owned_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.file");
+
+        let mut f = File::create(&path).unwrap();
+        let mut tailed_file = TailedFile::new(path.clone()).unwrap();
+        assert_eq!(tailed_file.path(), path);
+
+        f.write_all(b"Some data").unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b"Some data");
+
+        let tailed_file = TailedFile::new(path.to_str().unwrap().to_string()).unwrap();
+        assert_eq!(tailed_file.path(), path);
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+        let path2 = &dir.path().join("test2.file");
+
+        let mut f = File::create(path).unwrap();
+        let mut tailed_file = TailedFile::new(&path).unwrap();
+        f.write_all(b"Some data").unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b"Some data");
+        let checkpoint = tailed_file.checkpoint();
+        drop(tailed_file);
+
+        f.write_all(b", more").unwrap();
+        let mut tailed_file = TailedFile::resume(&path, checkpoint).unwrap();
+        assert_eq!(tailed_file.lines_str().unwrap().count(), 0);
+        let checkpoint = tailed_file.checkpoint();
+        drop(tailed_file);
+
+        let mut tailed_file = TailedFile::resume(&path, checkpoint).unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b", more");
+        let checkpoint = tailed_file.checkpoint();
+        drop(tailed_file);
+
+        std::fs::rename(path, path2).unwrap();
+        File::create(path).unwrap().write_all(b"fun").unwrap();
+        let mut tailed_file = TailedFile::resume(&path, checkpoint).unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b"fun");
+    }
+
+    #[test]
+    fn test_read_into() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+
+        let mut f = File::create(path).unwrap();
+        let mut tailed_file = TailedFile::builder(&path).buffer_size(16).build().unwrap();
+        let mut buf = b"prefix:".to_vec();
+
+        f.write_all(b"Some data").unwrap();
+        assert_eq!(tailed_file.read_into(&mut buf).unwrap(), 9);
+        assert_eq!(buf, b"prefix:Some data");
+
+        buf.clear();
+        let capacity = buf.capacity();
+        f.write_all(b"fun").unwrap();
+        assert_eq!(tailed_file.read_into(&mut buf).unwrap(), 3);
+        assert_eq!(buf, b"fun");
+        assert_eq!(buf.capacity(), capacity);
+    }
+
+    #[test]
+    fn test_read_mapped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+
+        let mut f = File::create(path).unwrap();
+        let mut tailed_file = TailedFile::builder(&path)
+            .buffer_size(4)
+            .drain_limit(12)
+            .build()
+            .unwrap();
+
+        f.write_all(b"a longer burst of data").unwrap();
+        let mut seen: Vec<u8> = Vec::new();
+        assert_eq!(tailed_file.read_mapped(|d| seen.extend(d)).unwrap(), 12);
+        assert_eq!(tailed_file.state(), TailState::Draining);
+        assert_eq!(tailed_file.read_mapped(|d| seen.extend(d)).unwrap(), 10);
+        assert_eq!(tailed_file.state(), TailState::Following);
+        assert_eq!(seen, b"a longer burst of data");
+
+        assert_eq!(
+            tailed_file.read_mapped(|d| assert!(d.is_empty())).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_align_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+
+        let mut f = File::create(path).unwrap();
+        let mut tailed_file = TailedFile::builder(&path)
+            .buffer_size(8)
+            .align_to_records(true)
+            .build()
+            .unwrap();
+
+        f.write_all(b"one\ntw").unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b"one\n");
+        assert!(tailed_file.read().unwrap().is_empty());
+
+        f.write_all(b"o\nthree-is-long\n").unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b"two\n");
+        assert_eq!(tailed_file.read().unwrap(), b"three-is");
+        assert_eq!(tailed_file.read().unwrap(), b"-long\n");
+    }
+
+    #[test]
+    fn test_untrusted_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+
+        // xorshift, biased towards delimiters and UTF-8 lead bytes
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut garbage = Vec::new();
+        for _ in 0..64 * 1024 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            garbage.push(match state % 8 {
+                0 => b'\n',
+                1 => b'|',
+                2 => 0xe2,
+                _ => (state >> 32) as u8,
+            });
+        }
+
+        let mut f = File::create(path).unwrap();
+        let builders = [
+            TailedFile::builder(path).buffer_size(7),
+            TailedFile::builder(path)
+                .delimiter(b"||")
+                .max_record_size(3, OversizePolicy::Truncate),
+            TailedFile::builder(path).binary_policy(BinaryPolicy::HexEncode),
+        ];
+        let mut files: Vec<_> = builders.into_iter().map(|b| b.build().unwrap()).collect();
+        let parsers: Vec<_> = format::PRESETS
+            .into_iter()
+            .map(TimestampParser::preset)
+            .collect();
+        let mut multiline = Multiline::indented().max_lines(3);
+
+        for chunk in garbage.chunks(997) {
+            f.write_all(chunk).unwrap();
+            assert!(files[0].read_string_lossy().is_ok());
+            for file in &mut files[1..] {
+                for line in file.lines_str().unwrap() {
+                    for parser in &parsers {
+                        parser.find(&line);
+                    }
+                    multiline.push(&line);
+                }
+            }
+            detect(chunk);
+        }
+    }
+
+    #[test]
+    fn test_read_lossy() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+
+        let mut f = File::create(path).unwrap();
+        let mut tailed_file = TailedFile::new(path).unwrap();
+
+        let euro = "\u{20ac}".as_bytes();
+        f.write_all(&[b"bad \xff ", &euro[..1]].concat()).unwrap();
+        assert_eq!(tailed_file.read_string_lossy().unwrap(), "bad \u{fffd} ");
+        f.write_all(&[&euro[1..], b"\none\ntw"].concat()).unwrap();
+        assert_eq!(
+            tailed_file.read_string_lossy().unwrap(),
+            "\u{20ac}\none\ntw"
+        );
+
+        f.write_all(b"o \xfe\nthr").unwrap();
+        assert_eq!(tailed_file.read_lines_lossy().unwrap(), ["o \u{fffd}"]);
+        f.write_all(b"ee\n").unwrap();
+        assert_eq!(tailed_file.read_lines_lossy().unwrap(), ["three"]);
+    }
+
+    #[test]
+    fn test_encoding() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+
+        let mut f = File::create(path).unwrap();
+        let mut tailed_file = TailedFile::builder(path)
+            .encoding(Encoding::Windows1252)
+            .build()
+            .unwrap();
+
+        f.write_all(b"\x80 5\nna\xefve\n").unwrap();
+        let lines: Vec<_> = tailed_file.lines_str().unwrap().collect();
+        assert_eq!(lines, ["\u{20ac} 5", "na\u{ef}ve"]);
+        f.write_all(b"\x93end\x94").unwrap();
+        assert_eq!(
+            tailed_file.read_string_lossy().unwrap(),
+            "\u{201c}end\u{201d}"
+        );
+    }
+
+    #[cfg(feature = "shift_jis")]
+    #[test]
+    fn test_shift_jis() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+
+        let mut f = File::create(path).unwrap();
+        let mut tailed_file = TailedFile::builder(path)
+            .encoding(Encoding::ShiftJis)
+            .build()
+            .unwrap();
+
+        f.write_all(b"\x82\xa0\n\x88").unwrap();
+        assert_eq!(tailed_file.read_string_lossy().unwrap(), "\u{3042}\n");
+        f.write_all(b"\x9f\n").unwrap();
+        assert_eq!(tailed_file.read_string_lossy().unwrap(), "\u{4e9c}\n");
+        f.write_all(b"\x82\xa0\x88").unwrap();
+        assert!(tailed_file.lines_str().unwrap().next().is_none());
+        f.write_all(b"\x9f\n").unwrap();
+        let lines: Vec<_> = tailed_file.lines_str().unwrap().collect();
+        assert_eq!(lines, ["\u{3042}\u{4e9c}"]);
+    }
+
+    #[test]
+    fn test_follow() {
+        struct Until(Vec<u8>);
+
+        impl Sink for Until {
+            fn write_chunk(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+                self.0.write_chunk(path, data)
+            }
+
+            fn flush(&mut self) -> Result<()> {
+                match self.0.ends_with(b"two\n") {
+                    true => Err(StaartError::Sink("done".to_string())),
+                    false => Ok(()),
+                }
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+
+        let mut f = File::create(path).unwrap();
+        f.write_all(b"one\ntwo\n").unwrap();
+        let mut tailed_file = TailedFile::new_with_start(path, StartPos::Beginning).unwrap();
+        let mut sink = Until(Vec::new());
+        let result = tailed_file.follow(&mut sink, Duration::from_millis(10));
+        assert!(matches!(result, Err(StaartError::Sink(_))));
+        assert_eq!(sink.0, b"one\ntwo\n");
+
+        tailed_file.stop();
+        assert!(tailed_file.follow(Vec::new(), Duration::ZERO).is_ok());
+    }
+
+    #[test]
+    fn test_pause() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+
+        let mut f = File::create(path).unwrap();
+        let mut tailed_file = TailedFile::new(path).unwrap();
+        let pause = tailed_file.pause_handle();
+        pause.pause();
+        assert_eq!(tailed_file.state(), TailState::Paused);
+        f.write_all(b"one\n").unwrap();
+        assert!(tailed_file.read().unwrap().is_empty());
+        assert!(!tailed_file.wait_for_change(Duration::ZERO).unwrap());
+
+        // Rotated while paused, the rest of the old file comes first
+        std::fs::rename(path, dir.path().join("test.file.1")).unwrap();
+        f.write_all(b"two\n").unwrap();
+        std::fs::write(path, b"three\n").unwrap();
+        assert!(tailed_file.read().unwrap().is_empty());
+        tailed_file.unpause();
+        assert!(!pause.is_paused());
+        assert_eq!(tailed_file.read().unwrap(), b"one\ntwo\n");
+        assert_eq!(tailed_file.read().unwrap(), b"three\n");
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+        let pos = dir.path().join("test.pos");
+
+        let mut f = File::create(path).unwrap();
+        f.write_all(b"one\ntw").unwrap();
+        let shutdown = ShutdownHandle::new();
+        let mut tailed_file = TailedFile::builder(path)
+            .start(StartPos::Beginning)
+            .align_to_records(true)
+            .shutdown(shutdown.clone())
+            .checkpoint_on_shutdown(&pos)
+            .build()
+            .unwrap();
+        let signal = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            shutdown.signal();
+        });
+        let mut sink = Vec::new();
+        tailed_file
+            .follow(&mut sink, Duration::from_millis(10))
+            .unwrap();
+        signal.join().unwrap();
+
+        // The partial line held back by aligned reads is handed out
+        assert_eq!(sink, b"one\ntw");
+        assert_eq!(tailed_file.state(), TailState::Stopped);
+        assert_eq!(Checkpoint::load(&pos).unwrap(), tailed_file.checkpoint());
+        assert_eq!(tailed_file.checkpoint().offset, 6);
+    }
+
+    #[test]
+    fn test_progress() {
+        use std::sync::{Arc, Mutex};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+
+        let mut f = File::create(path).unwrap();
+        f.write_all(&[b'x'; 100]).unwrap();
+        let mut tailed_file = TailedFile::builder(path)
+            .start(StartPos::Beginning)
+            .buffer_size(40)
+            .build()
+            .unwrap();
+        let remaining = Arc::new(Mutex::new(Vec::new()));
+        tailed_file.on_progress(Duration::ZERO, {
+            let remaining = Arc::clone(&remaining);
+            move |_, p| remaining.lock().unwrap().push(p.remaining)
+        });
+
+        tailed_file.read_all_new().unwrap();
+        assert_eq!(*remaining.lock().unwrap(), [60, 20, 0]);
+        assert_eq!(tailed_file.progress().unwrap().read, 100);
+    }
+
+    #[test]
+    fn test_spawn() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.file");
+
+        let mut f = File::create(&path).unwrap();
+        let tailed_file = TailedFile::new(path).unwrap();
+        let (handle, events) = tailed_file.spawn(Duration::from_millis(10));
+
+        f.write_all(b"from the thread\n").unwrap();
+        let data = events
+            .iter()
+            .find(|event| *event != TailEvent::NoChange)
+            .unwrap();
+        assert_eq!(data, TailEvent::Data(b"from the thread\n".to_vec()));
+
+        drop(events);
+        let tailed_file = handle.join().unwrap().unwrap();
+        assert_eq!(tailed_file.pos, 16);
+    }
+
+    #[test]
+    fn test_flush_partial() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+
+        let mut f = File::create(path).unwrap();
+        let mut tailed_file = TailedFile::builder(&path)
+            .flush_partial_after(Duration::from_millis(50))
+            .build()
+            .unwrap();
+
+        f.write_all(b"one\nfinal").unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b"one\n");
+        assert!(tailed_file.read().unwrap().is_empty());
+        std::thread::sleep(Duration::from_millis(30));
+        // Growing restarts the timeout
+        f.write_all(b" line").unwrap();
+        assert!(tailed_file.read().unwrap().is_empty());
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(tailed_file.read().unwrap().is_empty());
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(tailed_file.read().unwrap(), b"final line");
+    }
+
+    #[test]
+    fn test_try_read_and() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.file");
+        let mut f = File::create(&path).unwrap();
+        let mut tailed_file = TailedFileBuilder::new(&path)
+            .buffer_size(4)
+            .build()
+            .unwrap();
+
+        f.write_all(b"abcdefghij").unwrap();
+        let mut chunks = 0;
+        tailed_file.read_and(|_| chunks += 1).unwrap();
+        assert_eq!(chunks, 3);
+
+        f.write_all(b"klmnop").unwrap();
+        let mut seen = Vec::new();
+        let result = tailed_file.try_read_and(|d| {
+            seen.extend_from_slice(d);
+            Err(StaartError::Sink("full".to_string()))
+        });
+        assert!(matches!(result, Err(StaartError::Sink(_))));
+        assert_eq!(seen, b"klmn");
+        assert_eq!(tailed_file.read().unwrap(), b"op");
+    }
+
+    #[test]
+    fn test_io_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.file");
+        let mut f = File::create(&path).unwrap();
+        let mut tailed_file = TailedFile::new(&path).unwrap();
+
+        let mut out = Vec::new();
+        assert_eq!(std::io::copy(&mut tailed_file, &mut out).unwrap(), 0);
+
+        f.write_all(b"Some data").unwrap();
+        let mut buf = [0; 4];
+        assert_eq!(Read::read(&mut tailed_file, &mut buf).unwrap(), 4);
+        assert_eq!(&buf, b"Some");
+        assert_eq!(tailed_file.state(), TailState::Draining);
+        std::io::copy(&mut tailed_file, &mut out).unwrap();
+        assert_eq!(out, b" data");
+
+        f.set_len(0).unwrap();
+        File::create(&path).unwrap().write_all(b"new").unwrap();
+        out.clear();
+        std::io::copy(&mut tailed_file, &mut out).unwrap();
+        assert_eq!(out, b"new");
+    }
+
+    #[test]
+    fn test_buf_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.file");
+        let path2 = dir.path().join("test2.file");
+        let mut f = File::create(&path).unwrap();
+        let mut tailed_file = TailedFileBuilder::new(&path)
+            .buffer_size(4)
+            .build()
+            .unwrap();
+
+        f.write_all(b"one\ntwo\nthr").unwrap();
+        let mut line = String::new();
+        tailed_file.read_line(&mut line).unwrap();
+        assert_eq!(line, "one\n");
+        assert_eq!(tailed_file.checkpoint().offset, 4);
+        let mut rest = Vec::new();
+        tailed_file.read_until(b'\n', &mut rest).unwrap();
+        assert_eq!(rest, b"two\n");
+
+        // The start of a line survives rotation
+        line.clear();
+        tailed_file.read_line(&mut line).unwrap();
+        assert_eq!(line, "thr");
+        std::fs::rename(&path, &path2).unwrap();
+        File::create(&path)
+            .unwrap()
+            .write_all(b"ee\nfour\n")
+            .unwrap();
+        tailed_file.read_line(&mut line).unwrap();
+        assert_eq!(line, "three\n");
+
+        let lines: Vec<_> = (&mut tailed_file).lines().map(|l| l.unwrap()).collect();
+        assert_eq!(lines, ["four"]);
+    }
+
+    #[test]
+    fn test_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+
+        let mut f = File::create(path).unwrap();
+        let filter = LineFilter::new()
+            .include(Matcher::contains("ERROR"))
+            .exclude(Matcher::starts_with("#"));
+        let mut tailed_file = TailedFile::builder(path).filter(filter).build().unwrap();
+
+        f.write_all(b"INFO up\nERROR \xff down\n# ERROR ignored\nERROR again\n")
+            .unwrap();
+        let lines: Vec<_> = tailed_file.lines_str().unwrap().collect();
+        assert_eq!(lines, ["ERROR \u{fffd} down", "ERROR again"]);
+
+        f.write_all(b"INFO quiet\nERROR last\n").unwrap();
+        let batch = tailed_file
+            .batches(10, Duration::from_millis(10))
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].text, "ERROR last");
+        assert_eq!(batch[0].offset, 60);
+    }
+
+    #[test]
+    fn test_delimiter() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.file");
+        let mut f = File::create(&path).unwrap();
+        let mut tailed_file = TailedFileBuilder::new(&path)
+            .delimiter(*b"\0")
+            .build()
+            .unwrap();
+
+        f.write_all(b"one\ntwo\0three\0fo").unwrap();
+        let lines: Vec<_> = tailed_file.lines_str().unwrap().collect();
+        assert_eq!(lines, ["one\ntwo", "three"]);
+        f.write_all(b"ur\0").unwrap();
+        let lines: Vec<_> = tailed_file.lines_str().unwrap().collect();
+        assert_eq!(lines, ["four"]);
+
+        let mut tailed_file = TailedFileBuilder::new(&path)
+            .start(StartPos::Beginning)
+            .delimiter("\n--\n")
+            .align_to_records(true)
+            .build()
+            .unwrap();
+        f.write_all(b"a\n--\nb\n-").unwrap();
+        assert_eq!(
+            tailed_file.read().unwrap(),
+            b"one\ntwo\0three\0four\0a\n--\n"
+        );
+
+        let empty = TailedFileBuilder::new(&path).delimiter(Vec::new()).build();
+        assert!(matches!(empty, Err(StaartError::Config(_))));
+    }
+
+    #[test]
+    fn test_max_record_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.file");
+        let mut f = File::create(&path).unwrap();
+        let mut tailed_file = TailedFileBuilder::new(&path)
+            .max_record_size(5, OversizePolicy::Drop)
+            .build()
+            .unwrap();
+
+        f.write_all(b"one\nhuge record\ntwo\n").unwrap();
+        let lines: Vec<_> = tailed_file.lines_str().unwrap().collect();
+        assert_eq!(lines, ["one", "two"]);
+        assert_eq!(tailed_file.oversized_records(), 1);
+
+        f.write_all(b"another huge one\nthree\n").unwrap();
+        let batch = tailed_file
+            .batches(10, Duration::ZERO)
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].text, "three");
+        assert_eq!(batch[0].offset, 37);
+        assert_eq!(tailed_file.oversized_records(), 2);
+    }
+
+    #[test]
+    fn test_binary_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.file");
+        let mut f = File::create(&path).unwrap();
+        let build = |policy| {
+            TailedFileBuilder::new(&path)
+                .start(StartPos::Beginning)
+                .binary_policy(policy)
+                .build()
+                .unwrap()
+        };
 
-use std::fs::{File, Metadata};
-use std::io::{BufReader, Read, Seek, SeekFrom};
-use std::path::Path;
+        f.write_all(b"\x01\0\0\n\xff\0\n").unwrap();
+        let mut skipped = build(BinaryPolicy::Skip);
+        assert!(skipped.read().unwrap().is_empty());
+        assert_eq!(skipped.is_binary(), Some(true));
+        assert_eq!(skipped.state(), TailState::Disabled);
+        assert!(skipped.timeline().any(|e| e.event == Lifecycle::Binary));
 
-mod errors;
+        let mut hex = build(BinaryPolicy::HexEncode);
+        let lines: Vec<_> = hex.lines_str().unwrap().collect();
+        assert_eq!(lines, ["010000", "ff00"]);
 
-pub use errors::StaartError;
+        let mut raw = build(BinaryPolicy::Raw);
+        assert_eq!(raw.read().unwrap(), b"\x01\0\0\n\xff\0\n");
+        assert_eq!(raw.is_binary(), Some(true));
+    }
 
-type Result<T> = std::result::Result<T, StaartError>;
+    #[test]
+    fn test_lines_str() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
 
-/// [`TailedFile`] tracks the state of a file being followed. It offers
-/// methods for updating this state, and printing data to `stdout`.
-pub struct TailedFile<T> {
-    path: T,
-    pos: u64,
-    meta: Metadata,
-}
+        let mut f = File::create(path).unwrap();
+        let mut tailed_file = TailedFile::new(&path).unwrap();
 
-impl<T> TailedFile<T>
-where
-    T: AsRef<Path> + Copy,
-{
-    /// Creates an instance of `std::io::Result<staart::TailedFile>`
-    ///
-    /// # Example
-    /// ```no_run
-    /// let mut f = staart::TailedFile::new("/var/log/syslog");
-    /// ```
-    ///
-    /// # Propagates Errors
-    /// - If the path provided does not exist, or is not readable by the current user
-    /// - If file metadata can not be read
-    pub fn new(path: T) -> Result<TailedFile<T>> {
-        let f = File::open(path)?;
-        let meta = f.metadata()?;
-        let pos = meta.len();
+        f.write_all(b"one\ntwo\nthr").unwrap();
+        let lines: Vec<String> = tailed_file
+            .lines_str()
+            .unwrap()
+            .map(|l| l.into_owned())
+            .collect();
+        assert_eq!(lines, vec!["one", "two"]);
 
-        Ok(TailedFile { path, pos, meta })
+        f.write_all(b"ee\n").unwrap();
+        let lines: Vec<String> = tailed_file
+            .lines_str()
+            .unwrap()
+            .map(|l| l.into_owned())
+            .collect();
+        assert_eq!(lines, vec!["three"]);
     }
 
-    /// Reads new data for an instance of `staart::TailedFile` and returns
-    /// `Result<Vec<u8>>`
-    ///
-    /// Prior to reading the file, it is checked for rotation and/or truncation.
-    pub fn read(&mut self) -> Result<Vec<u8>> {
-	let fd = File::open(self.path)?;
-	self.check_rotate(&fd)?;
-	self.check_truncate(&fd)?;
-        let mut reader = BufReader::with_capacity(65536, &fd);
-        let mut data: [u8; 65536] = [0u8; 65536];
-        reader.seek(SeekFrom::Start(self.pos))?;
-        let n: u64 = reader.read(&mut data)?.try_into()?;
+    #[test]
+    fn test_read_all_new() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
 
-        self.pos += n;
+        let mut f = File::create(path).unwrap();
+        let mut tailed_file = TailedFile::new(&path).unwrap();
+        let mut limited = TailedFile::builder(&path)
+            .buffer_size(1000)
+            .drain_limit(2500)
+            .build()
+            .unwrap();
 
-        let data: Vec<u8> = data.into_iter().take(n.try_into()?).collect();
+        f.write_all(&[b'a'; 200000]).unwrap();
+        assert_eq!(tailed_file.read_all_new().unwrap().len(), 200000);
+        assert_eq!(tailed_file.state(), TailState::Following);
 
-        Ok(data)
+        assert_eq!(limited.read_all_new().unwrap().len(), 3000);
+        assert_eq!(limited.state(), TailState::Draining);
     }
 
-    /// Passes `&Vec<u8>` read from the tailed file to a user-defined function returning the unit type ()`.
-    pub fn read_and<F: Fn(&[u8])>(&mut self, f: F) -> Result<()> {
-	let data = self.read()?;
+    #[test]
+    fn test_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+
+        let mut f = File::create(path).unwrap();
+        let mut tailed_file = TailedFile::new(&path).unwrap();
+        assert_eq!(tailed_file.state(), TailState::Opening);
+
+        f.write_all(&[b'a'; 70000]).unwrap();
+        tailed_file.read().unwrap();
+        assert_eq!(tailed_file.state(), TailState::Draining);
+        tailed_file.read().unwrap();
+        assert_eq!(tailed_file.state(), TailState::Following);
 
-	f(&data);
-	
-	Ok(())
+        std::fs::remove_file(path).unwrap();
+        assert!(tailed_file.read().is_err());
+        assert_eq!(tailed_file.state(), TailState::WaitingForFile);
+
+        tailed_file.stop();
+        assert!(tailed_file.read().unwrap().is_empty());
+        assert_eq!(tailed_file.state(), TailState::Stopped);
     }
 
-    /// Checks for file rotation by inode comparision in Linux-like systems
-    #[cfg(target_os = "linux")]
-    fn check_rotate(&mut self, fd: &File) -> Result<()> {
-        use std::os::linux::fs::MetadataExt;
-        let meta = fd.metadata()?;
-        let inode = meta.st_ino();
-        if inode != self.meta.st_ino() {
-            self.pos = 0;
-            self.meta = meta;
-        }
+    #[test]
+    fn test_drain_rotated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+        let path2 = &dir.path().join("test2.file");
 
-        Ok(())
+        let mut f = File::create(path).unwrap();
+        let mut tailed_file = TailedFile::builder(&path).buffer_size(4).build().unwrap();
+
+        f.write_all(b"abcdefghij").unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b"abcd");
+
+        std::fs::rename(path, path2).unwrap();
+        File::create(path).unwrap().write_all(b"new").unwrap();
+        f.write_all(b"kl").unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b"efgh");
+        assert_eq!(tailed_file.read().unwrap(), b"ijkl");
+        assert_eq!(tailed_file.read().unwrap(), b"new");
     }
 
-    /// Checks for file rotation by creation time comparision in Windows systems
-    #[cfg(target_os = "windows")]
-    fn check_rotate(&mut self, fd: &File) -> Result<()> {
-        use std::os::windows::fs::MetadataExt;
+    #[test]
+    fn test_poll_event() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+        let path2 = &dir.path().join("test2.file");
 
-        let meta = fd.metadata()?;
-        let created_at = meta.creation_time();
-        if created_at != self.meta.creation_time() {
-            self.pos = 0;
-            self.meta = meta;
-        }
+        let mut f = File::create(path).unwrap();
+        let mut tailed_file = TailedFile::new(&path).unwrap();
+        assert_eq!(tailed_file.poll_event().unwrap(), TailEvent::NoChange);
 
-        Ok(())
+        f.write_all(b"Some data").unwrap();
+        assert_eq!(
+            tailed_file.poll_event().unwrap(),
+            TailEvent::Data(b"Some data".to_vec())
+        );
+
+        f.set_len(0).unwrap();
+        assert_eq!(tailed_file.poll_event().unwrap(), TailEvent::Truncated);
+
+        std::fs::rename(path, path2).unwrap();
+        assert_eq!(tailed_file.poll_event().unwrap(), TailEvent::Deleted);
+        assert_eq!(tailed_file.poll_event().unwrap(), TailEvent::NoChange);
+
+        let mut f = File::create(path).unwrap();
+        f.write_all(b"fun").unwrap();
+        assert_eq!(
+            tailed_file.poll_event().unwrap(),
+            TailEvent::Rotated {
+                path: path.to_path_buf(),
+                renamed_to: Some(path2.to_path_buf()),
+            }
+        );
+        assert_eq!(
+            tailed_file.poll_event().unwrap(),
+            TailEvent::Data(b"fun".to_vec())
+        );
+        assert_eq!(tailed_file.poll_event().unwrap(), TailEvent::NoChange);
     }
 
+    #[test]
+    fn test_hooks() {
+        use std::sync::{Arc, Mutex};
 
-    /// Checks for file rotation by inode comparision in MacOS systems
-    #[cfg(target_os = "macos")]
-    fn check_rotate(&mut self, fd: &File) -> Result<()> {
-        use std::os::unix::fs::MetadataExt;
-        let meta = fd.metadata()?;
-        let inode = meta.ino();
-        if inode != self.meta.ino() {
-            self.pos = 0;
-            self.meta = meta;
-        }
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+        let path2 = &dir.path().join("test2.file");
 
-        Ok(())
+        let mut f = File::create(path).unwrap();
+        let mut tailed_file = TailedFile::new(&path).unwrap();
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let c = calls.clone();
+        tailed_file.on_rotate(move |_, branch, action| {
+            c.lock().unwrap().push(format!("rotate {branch} {action}"))
+        });
+        let c = calls.clone();
+        tailed_file
+            .on_truncate(move |_, action| c.lock().unwrap().push(format!("truncate {action}")));
+        let c = calls.clone();
+        tailed_file.on_reopen(move |p| c.lock().unwrap().push(format!("reopen {}", p.display())));
+
+        f.write_all(b"Some data").unwrap();
+        tailed_file.poll_event().unwrap();
+        f.set_len(0).unwrap();
+        tailed_file.poll_event().unwrap();
+        std::fs::rename(path, path2).unwrap();
+        tailed_file.poll_event().unwrap();
+        File::create(path).unwrap().write_all(b"fun").unwrap();
+        tailed_file.poll_event().unwrap();
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                "truncate from-start".to_string(),
+                format!("reopen {}", path.display()),
+                "rotate rotated-grew from-start".to_string(),
+            ]
+        );
     }
 
-    /// Checks for file truncation by length comparision to the previous read position
-    #[cfg(target_os = "linux")]
-    fn check_truncate(&mut self, fd: &File) -> Result<()> {
-        use std::os::linux::fs::MetadataExt;
-        let meta = fd.metadata()?;
-        let inode = meta.st_ino();
-        let len = meta.len();
-        if inode == self.meta.st_ino() && len < self.pos {
-            self.pos = 0;
-        }
+    #[test]
+    fn test_close_inactive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
 
-        Ok(())
+        let mut f = File::create(path).unwrap();
+        let mut tailed_file = TailedFile::builder(&path)
+            .close_inactive(Duration::from_millis(50))
+            .build()
+            .unwrap();
+
+        f.write_all(b"Some data").unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b"Some data");
+        assert!(tailed_file.read().unwrap().is_empty());
+        assert_eq!(tailed_file.state(), TailState::Following);
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(tailed_file.read().unwrap().is_empty());
+        assert_eq!(tailed_file.state(), TailState::Closed);
+        assert!(tailed_file.file.is_none());
+        assert!(tailed_file.read().unwrap().is_empty());
+        assert_eq!(tailed_file.state(), TailState::Closed);
+
+        f.write_all(b", more").unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b", more");
+        assert_eq!(tailed_file.state(), TailState::Following);
+        let events: Vec<_> = tailed_file.timeline().map(|e| e.event.clone()).collect();
+        assert!(events.ends_with(&[Lifecycle::Inactive, Lifecycle::Reopened]));
     }
 
-    /// Checks for file truncation by length comparision to the previous read position
-    #[cfg(target_os = "windows")]
-    fn check_truncate(&mut self, fd: &File) -> Result<()> {
-        use std::os::windows::fs::MetadataExt;
-        let meta = fd.metadata()?;
-        let created_at = meta.creation_time();
-        let len = meta.len();
-        if created_at == self.meta.creation_time() && len < self.pos {
-            self.pos = 0;
-        }
+    #[test]
+    fn test_idle_after() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
 
-        Ok(())
+        let mut f = File::create(path).unwrap();
+        let mut tailed_file = TailedFile::builder(&path)
+            .idle_after(Duration::from_millis(50))
+            .build()
+            .unwrap();
+        assert_eq!(tailed_file.poll_event().unwrap(), TailEvent::NoChange);
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(matches!(
+            tailed_file.poll_event().unwrap(),
+            TailEvent::Idle { .. }
+        ));
+        assert_eq!(tailed_file.poll_event().unwrap(), TailEvent::NoChange);
+
+        f.write_all(b"Some data").unwrap();
+        assert_eq!(
+            tailed_file.poll_event().unwrap(),
+            TailEvent::Data(b"Some data".to_vec())
+        );
+        std::thread::sleep(Duration::from_millis(60));
+        let last_data = tailed_file.stats().last_data.unwrap();
+        assert_eq!(
+            tailed_file.poll_event().unwrap(),
+            TailEvent::Idle { since: last_data }
+        );
     }
 
-    /// Checks for file truncation by length comparision to the previous read position
-    #[cfg(target_os = "macos")]
-    fn check_truncate(&mut self, fd: &File) -> Result<()> {
-        use std::os::unix::fs::MetadataExt;
-        let meta = fd.metadata()?;
-        let inode = meta.ino();
-        let len = meta.len();
-        if inode == self.meta.ino() && len < self.pos {
-            self.pos = 0;
-        }
+    #[test]
+    fn test_lost_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
 
-        Ok(())
+        let mut f = File::create(path).unwrap();
+        let mut tailed_file = TailedFile::builder(&path).buffer_size(4).build().unwrap();
+
+        f.write_all(b"abcdefghij").unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b"abcd");
+        assert_eq!(tailed_file.lost_bytes(), 0);
+
+        f.set_len(0).unwrap();
+        assert!(tailed_file.read().unwrap().is_empty());
+        assert_eq!(tailed_file.lost_bytes(), 6);
+        assert!(tailed_file
+            .timeline()
+            .any(|e| e.event == Lifecycle::Lost(6)));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::io::Write;
+    #[test]
+    fn test_pipeline() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
 
-    use super::*;
+        let mut f = File::create(path).unwrap();
+        let pipeline = Pipeline::new()
+            .strip_ansi()
+            .then(|line| (!line.contains("noise")).then_some(line))
+            .label("app: ");
+        let mut tailed_file = TailedFile::builder(path)
+            .pipeline(pipeline)
+            .build()
+            .unwrap();
+
+        f.write_all(b"\x1b[32mok\x1b[0m\nnoise\nplain\n").unwrap();
+        let batch = tailed_file
+            .batches(10, Duration::from_millis(10))
+            .next()
+            .unwrap()
+            .unwrap();
+        let lines: Vec<_> = batch.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(lines, ["app: ok", "app: plain"]);
+        assert_eq!(batch[1].offset, 18);
+    }
 
+    #[cfg(feature = "gzip")]
     #[test]
-    fn tailed_file() {
+    fn test_catch_up() {
         let dir = tempfile::tempdir().unwrap();
         let path = &dir.path().join("test.file");
-        let _f = File::create(path).unwrap();
-        let tailed_file = TailedFile::new(&path);
-        assert!(tailed_file.is_ok())
+
+        let gz = File::create(dir.path().join("test.file.1.gz")).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(gz, flate2::Compression::fast());
+        encoder.write_all(b"rotated\n").unwrap();
+        encoder.finish().unwrap();
+        let mut f = File::create(path).unwrap();
+        f.write_all(b"live\n").unwrap();
+
+        let pattern = format!("{}.{{n}}.gz", path.display());
+        let mut tailed_file = TailedFile::builder(path)
+            .catch_up(CatchUp::new(&pattern))
+            .start(StartPos::Beginning)
+            .build()
+            .unwrap();
+        let lines: Vec<_> = tailed_file.lines_str().unwrap().collect();
+        assert_eq!(lines, ["rotated", "live"]);
+        assert_eq!(tailed_file.pos, 5);
     }
 
+    #[cfg(feature = "gzip")]
     #[test]
-    fn test_read() {
+    fn test_decompress() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.log.gz");
+
+        let f = File::create(path).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(f, flate2::Compression::fast());
+        let mut tailed_file = TailedFile::builder(path)
+            .decompress(Decompression::Gzip)
+            .build()
+            .unwrap();
+
+        encoder.write_all(b"first\nsec").unwrap();
+        encoder.flush().unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b"first\nsec");
+        encoder.write_all(b"ond\n").unwrap();
+        encoder.finish().unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b"ond\n");
+        assert_eq!(tailed_file.pos, std::fs::metadata(path).unwrap().len());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_decompress_checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.log.gz");
+
+        let f = File::create(path).unwrap();
+        let mut encoder = flate2::write::GzEncoder::new(f, flate2::Compression::fast());
+        encoder.write_all(b"first\nsec").unwrap();
+        encoder.flush().unwrap();
+        let mut tailed_file = TailedFile::builder(path)
+            .decompress(Decompression::Gzip)
+            .build()
+            .unwrap();
+
+        // The held "sec" has no offset in the compressed file
+        let lines: Vec<_> = tailed_file.lines_str().unwrap().collect();
+        assert_eq!(lines, ["first"]);
+        assert_eq!(tailed_file.checkpoint().offset, 0);
+
+        encoder.write_all(b"ond\n").unwrap();
+        encoder.finish().unwrap();
+        let lines: Vec<_> = tailed_file.lines_str().unwrap().collect();
+        assert_eq!(lines, ["second"]);
+        let len = std::fs::metadata(path).unwrap().len();
+        assert_eq!(tailed_file.checkpoint().offset, len);
+    }
+
+    #[test]
+    fn test_capture_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.csv");
+
+        let mut f = File::create(path).unwrap();
+        f.write_all(b"a,b\n1,2\n").unwrap();
+        let mut tailed_file = TailedFile::builder(&path)
+            .capture_header(64)
+            .build()
+            .unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        tailed_file.on_header(move |_, header| tx.send(header.to_vec()).unwrap());
+        assert_eq!(tailed_file.header(), None);
+
+        assert!(tailed_file.read().unwrap().is_empty());
+        assert_eq!(tailed_file.header(), Some(&b"a,b"[..]));
+        f.write_all(b"3,4\n").unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b"3,4\n");
+
+        std::fs::rename(path, dir.path().join("test.csv.1")).unwrap();
+        let mut f = File::create(path).unwrap();
+        f.write_all(b"c,d").unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b"c,d");
+        assert_eq!(tailed_file.header(), None);
+        f.write_all(b"\n").unwrap();
+        tailed_file.read().unwrap();
+        assert_eq!(tailed_file.header(), Some(&b"c,d"[..]));
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [b"a,b", b"c,d"]);
+    }
+
+    #[test]
+    fn test_gap_markers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+
+        let mut f = File::create(path).unwrap();
+        let mut tailed_file = TailedFile::builder(&path)
+            .buffer_size(4)
+            .gap_markers(true)
+            .build()
+            .unwrap();
+
+        f.write_all(b"abcdefghij").unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b"abcd");
+        let mut f = File::create(path).unwrap();
+        f.write_all(b"x\n").unwrap();
+        let lines: Vec<_> = tailed_file.lines_str().unwrap().collect();
+        assert_eq!(lines, ["[gap: 6 bytes at offset 4, truncated]", "x"]);
+        assert!(tailed_file.take_gaps().is_empty());
+    }
+
+    #[test]
+    fn test_backpressure() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+
+        let mut f = File::create(path).unwrap();
+        let mut tailed_file = TailedFile::builder(&path)
+            .buffer_size(4)
+            .backpressure(Backpressure::SkipToEnd)
+            .gap_markers(true)
+            .build()
+            .unwrap();
+
+        f.write_all(b"abcdefghij").unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b"abcd");
+        assert_eq!(tailed_file.state(), TailState::Following);
+        f.write_all(b"kl").unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b"kl");
+        assert_eq!(
+            tailed_file.take_gaps(),
+            [Gap {
+                offset: 4,
+                len: 6,
+                cause: GapCause::Overflow
+            }]
+        );
+    }
+
+    #[test]
+    fn test_retry() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.file");
+        std::fs::write(&path, b"").unwrap();
+        let mut f = TailedFile::builder(path.clone())
+            .retry(RetryPolicy::new(50).backoff(Backoff::Fixed(Duration::from_millis(10))))
+            .build()
+            .unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            std::fs::write(path, b"replaced").unwrap();
+        });
+        assert_eq!(f.read().unwrap(), b"replaced");
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn test_error_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+
+        File::create(path).unwrap();
+        let mut tailed_file = TailedFile::builder(&path)
+            .error_budget(ErrorBudget::new(1))
+            .build()
+            .unwrap();
+
+        std::fs::remove_file(path).unwrap();
+        assert!(tailed_file.read().is_err());
+        assert_eq!(tailed_file.state(), TailState::WaitingForFile);
+        assert!(tailed_file.read().is_err());
+        assert_eq!(tailed_file.state(), TailState::Disabled);
+
+        let mut f = File::create(path).unwrap();
+        f.write_all(b"Some data").unwrap();
+        assert!(tailed_file.read().unwrap().is_empty());
+
+        tailed_file.enable();
+        assert_eq!(tailed_file.read().unwrap(), b"Some data");
+        assert_eq!(tailed_file.state(), TailState::Following);
+        let events: Vec<_> = tailed_file.timeline().map(|e| e.event.clone()).collect();
+        let disabled = events.iter().position(|e| *e == Lifecycle::Disabled);
+        assert_eq!(events.get(disabled.unwrap() + 1), Some(&Lifecycle::Enabled));
+    }
+
+    #[test]
+    fn test_format() {
         let dir = tempfile::tempdir().unwrap();
         let path = &dir.path().join("test.file");
-        let test_data = b"Some data";
 
         let mut f = File::create(path).unwrap();
         let mut tailed_file = TailedFile::new(&path).unwrap();
+        assert!(tailed_file.format().is_none());
 
-        f.write_all(test_data).unwrap();
+        f.write_all(b"{\"level\":\"info\"}\n{\"level\":\"warn\"}\n")
+            .unwrap();
+        tailed_file.read().unwrap();
+        assert_eq!(tailed_file.format().unwrap().format, Format::Json);
 
-        let data = tailed_file.read().unwrap();
-        assert_eq!(data.len(), test_data.len());
-        assert_eq!(tailed_file.pos, 9);
+        tailed_file.set_format(Detection::new(Format::Plain, None));
+        assert_eq!(tailed_file.format().unwrap().format, Format::Plain);
     }
 
     #[test]
@@ -251,11 +2882,7 @@ mod tests {
 
         #[cfg(target_os = "windows")]
         {
-            use std::os::windows::fs::MetadataExt;
-            assert_eq!(
-                tailed_file.meta.creation_time(),
-                f.metadata().unwrap().creation_time()
-            )
+            assert_eq!(tailed_file.meta.id, Stat::of(&f).unwrap().id)
         }
     }
 
@@ -274,7 +2901,150 @@ mod tests {
         let mut f = File::create(path).unwrap();
         f.write_all(more_test_data).unwrap();
 
-        tailed_file.check_truncate(&f).unwrap();
+        tailed_file.check_truncate(Stat::of(&f).unwrap());
         assert_eq!(tailed_file.pos, 0)
     }
+
+    #[test]
+    fn test_decisions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+        let path2 = &dir.path().join("test2.file");
+
+        let mut f = File::create(path).unwrap();
+        f.write_all(b"Some data").unwrap();
+        let table = DecisionTable::default()
+            .set(Branch::RotatedGrew, Action::SkipToEnd)
+            .set(Branch::Shrank, Action::Continue);
+        let mut tailed_file = TailedFile::builder(&path).decisions(table).build().unwrap();
+
+        std::fs::rename(path, path2).unwrap();
+        let mut f = File::create(path).unwrap();
+        f.write_all(b"Some data, copied").unwrap();
+        assert!(tailed_file.read().unwrap().is_empty());
+        assert_eq!(tailed_file.pos, 17);
+
+        f.set_len(4).unwrap();
+        assert!(tailed_file.read().unwrap().is_empty());
+        assert_eq!(tailed_file.pos, 17);
+    }
+
+    #[test]
+    fn test_retained_handle() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+        let path2 = &dir.path().join("test2.file");
+
+        let mut f = File::create(path).unwrap();
+        let mut tailed_file = TailedFile::new(&path).unwrap();
+        let mut descriptor = TailedFile::builder(&path)
+            .rotation_check(RotationCheck::Disabled)
+            .build()
+            .unwrap();
+
+        f.write_all(b"Some data").unwrap();
+        std::fs::rename(path, path2).unwrap();
+        f.write_all(b", more").unwrap();
+        File::create(path).unwrap().write_all(b"fun").unwrap();
+
+        assert_eq!(tailed_file.read().unwrap(), b"Some data, more");
+        assert_eq!(tailed_file.read().unwrap(), b"fun");
+        assert_eq!(descriptor.read().unwrap(), b"Some data, more");
+
+        File::create(path2).unwrap().write_all(b"!").unwrap();
+        assert_eq!(descriptor.read().unwrap(), b"!");
+    }
+
+    #[test]
+    fn test_append_only() {
+        use std::io::{Seek, SeekFrom};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+
+        let mut f = File::create(path).unwrap();
+        f.write_all(b"audit record 1\n").unwrap();
+        let mut tailed_file = TailedFile::builder(&path)
+            .verify_append_only(1)
+            .build()
+            .unwrap();
+
+        f.write_all(b"audit record 2\n").unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b"audit record 2\n");
+
+        let mut w = std::fs::OpenOptions::new().write(true).open(path).unwrap();
+        w.seek(SeekFrom::Start(13)).unwrap();
+        w.write_all(b"X").unwrap();
+        f.write_all(b"audit record 3\n").unwrap();
+        assert!(matches!(tailed_file.read(), Err(StaartError::Modified(0))));
+        assert_eq!(tailed_file.read().unwrap(), b"audit record 3\n");
+
+        f.set_len(10).unwrap();
+        assert!(matches!(tailed_file.read(), Err(StaartError::Modified(10))));
+    }
+
+    #[test]
+    fn test_timeline() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+
+        let mut f = File::create(path).unwrap();
+        f.write_all(b"Some data").unwrap();
+        let mut tailed_file = TailedFile::new(&path).unwrap();
+
+        std::fs::remove_file(path).unwrap();
+        assert!(tailed_file.read().is_err());
+        assert!(tailed_file.read().is_err());
+        File::create(path).unwrap();
+        tailed_file.read().unwrap();
+
+        let events: Vec<_> = tailed_file.timeline().map(|e| e.event.clone()).collect();
+        assert_eq!(
+            events,
+            vec![
+                Lifecycle::Opened,
+                Lifecycle::Missing,
+                Lifecycle::Reopened,
+                Lifecycle::Changed(Branch::RotatedShrank, Action::FromStart)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = &dir.path().join("test.file");
+
+        let mut f = File::create(path).unwrap();
+        f.write_all(b"one\ntwo\n").unwrap();
+        let mut tailed_file = TailedFile::builder(&path)
+            .start(StartPos::Beginning)
+            .build()
+            .unwrap();
+        assert_eq!(tailed_file.stats(), TailStats::default());
+        assert_eq!(tailed_file.lines_str().unwrap().count(), 2);
+
+        let mut f = File::create(path).unwrap();
+        f.write_all(b"x\n").unwrap();
+        tailed_file.lines_str().unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert!(tailed_file.read().is_err());
+        assert!(tailed_file.read().is_err());
+        std::fs::write(path, b"replaced").unwrap();
+        assert_eq!(tailed_file.read().unwrap(), b"replaced");
+
+        let stats = tailed_file.stats();
+        assert!(stats.last_data.is_some());
+        assert_eq!(
+            stats,
+            TailStats {
+                bytes_read: 18,
+                records: 3,
+                rotations: 1,
+                truncations: 1,
+                reopen_failures: 2,
+                last_data: stats.last_data,
+            }
+        );
+    }
 }
@@ -0,0 +1,299 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::borrow::Cow;
+
+use crate::{Encoding, Gap, LineFilter, OversizePolicy, Pipeline, TRUNCATION_MARKER};
+
+/// Complete lines read by [`crate::TailedFile::lines_str`], without their
+/// line endings.
+///
+/// Lines end with the record delimiter set by
+/// [`crate::TailedFileBuilder::delimiter`], a newline by default, in which case
+/// a carriage return before it is also removed.
+///
+/// Each line borrows from the file's internal buffer when it is valid UTF-8,
+/// and is an owned copy with invalid sequences replaced by U+FFFD otherwise,
+/// or transcoded from the [`crate::Encoding`] of the file.
+#[derive(Debug, Clone)]
+pub struct LinesStr<'a> {
+    rest: &'a [u8],
+    delimiter: &'a [u8],
+    limit: Option<RecordLimit>,
+    hex: bool,
+    encoding: Encoding,
+    filter: Option<&'a LineFilter>,
+    gaps: std::vec::IntoIter<Gap>,
+    pipeline: Option<&'a Pipeline>,
+}
+
+impl<'a> LinesStr<'a> {
+    /// Splits `complete` into newline-terminated lines, e.g. to process the
+    /// chunks handed to a [`crate::Sink`]
+    pub fn new(complete: &'a [u8]) -> LinesStr<'a> {
+        LinesStr::with_delimiter(complete, b"\n")
+    }
+
+    /// Splits `complete` into records ending with `delimiter`
+    pub fn with_delimiter(complete: &'a [u8], delimiter: &'a [u8]) -> LinesStr<'a> {
+        LinesStr {
+            rest: complete,
+            delimiter,
+            limit: None,
+            hex: false,
+            encoding: Encoding::Utf8,
+            filter: None,
+            gaps: Vec::new().into_iter(),
+            pipeline: None,
+        }
+    }
+
+    pub(crate) fn limited(mut self, limit: Option<RecordLimit>) -> LinesStr<'a> {
+        self.limit = limit;
+        self
+    }
+
+    pub(crate) fn hex(mut self, hex: bool) -> LinesStr<'a> {
+        self.hex = hex;
+        self
+    }
+
+    pub(crate) fn encoding(mut self, encoding: Encoding) -> LinesStr<'a> {
+        self.encoding = encoding;
+        self
+    }
+
+    pub(crate) fn filtered(mut self, filter: Option<&'a LineFilter>) -> LinesStr<'a> {
+        self.filter = filter;
+        self
+    }
+
+    pub(crate) fn pipeline(mut self, pipeline: &'a Pipeline) -> LinesStr<'a> {
+        self.pipeline = Some(pipeline).filter(|p| !p.is_empty());
+        self
+    }
+
+    /// Yields the markers of `gaps` ahead of the records
+    pub(crate) fn gaps(mut self, gaps: Vec<Gap>) -> LinesStr<'a> {
+        self.gaps = gaps.into_iter();
+        self
+    }
+
+    /// Returns the next record without its delimiter, before any limit
+    fn next_raw(&mut self) -> Option<&'a [u8]> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let mut line = match find(self.rest, self.delimiter) {
+            Some(end) => {
+                let line = &self.rest[..end];
+                self.rest = &self.rest[end + self.delimiter.len()..];
+                line
+            }
+            None => std::mem::take(&mut self.rest),
+        };
+        if self.delimiter == b"\n" {
+            if let Some(stripped) = line.strip_suffix(b"\r") {
+                line = stripped;
+            }
+        }
+
+        Some(line)
+    }
+}
+
+/// The longest record handed out, and what happens to longer ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RecordLimit {
+    pub(crate) max: usize,
+    pub(crate) policy: OversizePolicy,
+}
+
+impl RecordLimit {
+    /// Applies the policy to `record`, returning `None` if it is dropped
+    fn apply<'a>(&self, record: &'a [u8]) -> Option<Cow<'a, [u8]>> {
+        if record.len() <= self.max {
+            return Some(Cow::Borrowed(record));
+        }
+        match self.policy {
+            OversizePolicy::PassThrough => Some(Cow::Borrowed(record)),
+            OversizePolicy::Drop => None,
+            OversizePolicy::Truncate => {
+                // Cut before a UTF-8 continuation byte rather than through a
+                // character
+                let mut end = self.max;
+                while end > 0 && self.max - end < 3 && record[end] & 0xc0 == 0x80 {
+                    end -= 1;
+                }
+                let mut truncated = record[..end].to_vec();
+                truncated.extend_from_slice(TRUNCATION_MARKER.as_bytes());
+                Some(Cow::Owned(truncated))
+            }
+        }
+    }
+
+    /// Counts the records of `complete` longer than the limit
+    pub(crate) fn count_oversized(&self, complete: &[u8], delimiter: &[u8]) -> u64 {
+        let mut records = LinesStr::with_delimiter(complete, delimiter);
+        let mut oversized = 0;
+        while let Some(record) = records.next_raw() {
+            if record.len() > self.max {
+                oversized += 1;
+            }
+        }
+        oversized
+    }
+}
+
+/// Returns the number of records in `complete`, which ends in `delimiter`
+pub(crate) fn count(complete: &[u8], delimiter: &[u8]) -> u64 {
+    if let [byte] = delimiter {
+        return complete.iter().filter(|b| *b == byte).count() as u64;
+    }
+    let mut records = LinesStr::with_delimiter(complete, delimiter);
+    let mut n = 0;
+    while records.next_raw().is_some() {
+        n += 1;
+    }
+    n
+}
+
+/// Returns the position of the first `delimiter` in `data`
+pub(crate) fn find(data: &[u8], delimiter: &[u8]) -> Option<usize> {
+    match delimiter {
+        [byte] => data.iter().position(|b| b == byte),
+        _ => data
+            .windows(delimiter.len())
+            .position(|window| window == delimiter),
+    }
+}
+
+/// Returns the length of `data` without an incomplete UTF-8 sequence at its end
+pub(crate) fn utf8_complete_len(data: &[u8]) -> usize {
+    let tail = data.len().saturating_sub(3);
+    let Some(lead) = data[tail..].iter().rposition(|b| b & 0xc0 != 0x80) else {
+        return data.len();
+    };
+    let lead = tail + lead;
+    let width = match data[lead] {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 1,
+    };
+    if data.len() - lead < width {
+        lead
+    } else {
+        data.len()
+    }
+}
+
+/// Returns the length of the complete records at the start of `data`, i.e.
+/// the end of its last `delimiter`
+pub(crate) fn complete_len(data: &[u8], delimiter: &[u8]) -> usize {
+    if let [byte] = delimiter {
+        return data.iter().rposition(|b| b == byte).map_or(0, |i| i + 1);
+    }
+    let mut end = 0;
+    while let Some(i) = find(&data[end..], delimiter) {
+        end += i + delimiter.len();
+    }
+    end
+}
+
+impl<'a> Iterator for LinesStr<'a> {
+    type Item = Cow<'a, str>;
+
+    fn next(&mut self) -> Option<Cow<'a, str>> {
+        if let Some(gap) = self.gaps.next() {
+            return Some(Cow::Owned(gap.to_string()));
+        }
+        loop {
+            let line = self.next_raw()?;
+            if matches!(self.filter, Some(filter) if !filter.is_match(line)) {
+                continue;
+            }
+            let line = match self.limit {
+                Some(limit) => match limit.apply(line) {
+                    Some(line) => line,
+                    None => continue,
+                },
+                None => Cow::Borrowed(line),
+            };
+
+            let text = if self.hex {
+                Cow::Owned(line.iter().map(|b| format!("{b:02x}")).collect())
+            } else {
+                match line {
+                    Cow::Borrowed(line) => self.encoding.decode(line),
+                    Cow::Owned(line) => Cow::Owned(self.encoding.decode(&line).into_owned()),
+                }
+            };
+            // A record dropped by the pipeline yields nothing
+            let text = match self.pipeline {
+                Some(pipeline) => pipeline.apply(text),
+                None => Some(text),
+            };
+            if text.is_some() {
+                return text;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lines_str() {
+        let data = b"clean\r\nbad \xff byte\n\n";
+        let lines: Vec<_> = LinesStr::new(data).collect();
+        assert_eq!(lines, vec!["clean", "bad \u{fffd} byte", ""]);
+        assert!(matches!(lines[0], Cow::Borrowed(_)));
+        assert!(matches!(lines[1], Cow::Owned(_)));
+    }
+
+    #[test]
+    fn test_delimiter() {
+        let data = b"one\r\0two<EOR>three<EOR>fou";
+        let lines: Vec<_> = LinesStr::with_delimiter(data, b"<EOR>").collect();
+        assert_eq!(lines, vec!["one\r\0two", "three", "fou"]);
+        assert_eq!(complete_len(data, b"<EOR>"), 23);
+        assert_eq!(complete_len(data, b"\0"), 5);
+        assert_eq!(complete_len(b"aaa", b"aa"), 2);
+    }
+
+    #[test]
+    fn test_record_limit() {
+        let data = "short\nlong line\nn\u{e9}e\n".as_bytes();
+        let limit = |policy| RecordLimit { max: 2, policy };
+        let lines =
+            |policy| -> Vec<_> { LinesStr::new(data).limited(Some(limit(policy))).collect() };
+        assert_eq!(lines(OversizePolicy::Drop), Vec::<String>::new());
+        assert_eq!(
+            lines(OversizePolicy::Truncate),
+            ["sh[truncated]", "lo[truncated]", "n[truncated]"]
+        );
+        assert_eq!(
+            lines(OversizePolicy::PassThrough),
+            ["short", "long line", "n\u{e9}e"]
+        );
+        assert_eq!(limit(OversizePolicy::Drop).count_oversized(data, b"\n"), 3);
+    }
+
+    #[test]
+    fn test_utf8_complete_len() {
+        let euro = "\u{20ac}".as_bytes();
+        assert_eq!(utf8_complete_len(b"plain"), 5);
+        assert_eq!(utf8_complete_len(&[b"a", &euro[..2]].concat()), 1);
+        assert_eq!(utf8_complete_len(&[b"a", euro].concat()), 4);
+        assert_eq!(utf8_complete_len(b"a\xff"), 2);
+        assert_eq!(utf8_complete_len(b""), 0);
+    }
+}
@@ -0,0 +1,450 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+use serde_json::{json, Map, Value};
+
+use crate::http::Endpoint;
+use crate::sink::Partials;
+use crate::{clock, Backoff, Bandwidth, Compression, Line, LinesStr, Result, Sink, StaartError};
+
+/// Default number of lines sent in one push
+pub const DEFAULT_LOKI_BATCH: usize = 1000;
+
+/// Default number of times a push is retried when Loki asks to back off
+pub const DEFAULT_LOKI_RETRIES: u32 = 5;
+
+/// Default number of lines held while Loki can not be reached
+pub const DEFAULT_LOKI_MAX_PENDING: usize = 100 * DEFAULT_LOKI_BATCH;
+
+/// The lines buffered for one set of labels
+#[derive(Debug)]
+struct Stream {
+    labels: Map<String, Value>,
+    values: Vec<Value>,
+}
+
+/// A [`Sink`] pushing lines to Grafana Loki through its push API, enabled by
+/// the `loki` feature.
+///
+/// Every line is sent to the stream identified by its labels, whose values
+/// are templates rendered for the path the line was read from. `{path}` is
+/// replaced by the full path, `{filename}` by the file name and `{dir}` by
+/// the directory holding the file, as in `app-{filename}`. Other text is
+/// kept as is. By default lines carry the single label `filename` with the
+/// full path, as promtail sets it. Lines are timestamped when buffered.
+///
+/// Lines are sent once `batch_size` are buffered, and on every
+/// [`Sink::flush`]. When Loki answers with `429 Too Many Requests` or a
+/// server error, the push is retried after the delay named by its
+/// `Retry-After` header, or given by the [`Backoff`] otherwise. The lines are
+/// kept for the next attempt once the retries are exhausted, made after
+/// another `batch_size` lines or on the next flush. The lines of a write are
+/// always kept, even when it fails, but once [`LokiSink::max_pending`] lines
+/// are held every write returns an error, so that a caller such as
+/// [`TailedFile::follow`](crate::TailedFile::follow) stops reading until Loki
+/// catches up.
+///
+/// Only complete lines are sent: a partial line ending a chunk is held back
+/// until the rest of it is written.
+///
+/// Only plain `http://` endpoints are supported.
+///
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+/// use staart::{LokiSink, TailedFile};
+///
+/// let sink = LokiSink::new("http://localhost:3100")
+///     .unwrap()
+///     .label("job", "nginx")
+///     .label("file", "{filename}")
+///     .tenant("team-a")
+///     .unwrap();
+/// let mut f = TailedFile::new("/var/log/nginx/access.log").unwrap();
+/// f.follow(sink, Duration::from_secs(1)).unwrap();
+/// ```
+pub struct LokiSink {
+    endpoint: Endpoint,
+    labels: Vec<(String, String)>,
+    tenant: Option<String>,
+    batch_size: usize,
+    timeout: Duration,
+    backoff: Backoff,
+    retries: u32,
+    compression: Compression,
+    max_pending: usize,
+    bandwidth: Option<Bandwidth>,
+    partials: Partials,
+    streams: BTreeMap<String, Stream>,
+    lines: usize,
+    send_at: usize,
+}
+
+impl LokiSink {
+    /// Creates a sink pushing to the Loki server at `url`, such as
+    /// `http://localhost:3100`
+    ///
+    /// # Propagates Errors
+    /// - If `url` is not a plain `http://` URL
+    pub fn new(url: &str) -> Result<LokiSink> {
+        let endpoint = Endpoint::parse(url, 3100)
+            .ok_or_else(|| StaartError::Config(format!("unsupported Loki URL: {url}")))?;
+
+        Ok(LokiSink {
+            endpoint,
+            labels: vec![("filename".to_string(), "{path}".to_string())],
+            tenant: None,
+            batch_size: DEFAULT_LOKI_BATCH,
+            timeout: Duration::from_secs(10),
+            backoff: Backoff::default(),
+            retries: DEFAULT_LOKI_RETRIES,
+            compression: Compression::None,
+            max_pending: DEFAULT_LOKI_MAX_PENDING,
+            bandwidth: None,
+            partials: Partials::default(),
+            streams: BTreeMap::new(),
+            lines: 0,
+            send_at: DEFAULT_LOKI_BATCH,
+        })
+    }
+
+    /// Sets the label `name` to `template`, replacing the default `filename`
+    /// label or an earlier template for the same name
+    pub fn label(mut self, name: &str, template: &str) -> LokiSink {
+        self.labels.retain(|(label, _)| label != name);
+        self.labels.push((name.to_string(), template.to_string()));
+        self
+    }
+
+    /// Removes the label `name`, such as the default `filename`
+    pub fn without_label(mut self, name: &str) -> LokiSink {
+        self.labels.retain(|(label, _)| label != name);
+        self
+    }
+
+    /// Pushes to the tenant `id` of a multi-tenant Loki, through the
+    /// `X-Scope-OrgID` header
+    ///
+    /// # Propagates Errors
+    /// - If `id` contains control characters, which would corrupt the header
+    pub fn tenant(mut self, id: &str) -> Result<LokiSink> {
+        if id.chars().any(char::is_control) {
+            return Err(StaartError::Config(format!("invalid Loki tenant: {id:?}")));
+        }
+        self.tenant = Some(id.to_string());
+        Ok(self)
+    }
+
+    /// Sends a push once `lines` lines are buffered, defaults to
+    /// [`DEFAULT_LOKI_BATCH`]
+    pub fn batch_size(mut self, lines: usize) -> LokiSink {
+        self.batch_size = lines.max(1);
+        self.send_at = self.batch_size;
+        self
+    }
+
+    /// Gives up on a request to Loki after `timeout`
+    pub fn timeout(mut self, timeout: Duration) -> LokiSink {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets how long to wait before retrying a push Loki rejected, when it
+    /// names no delay itself, defaults to [`Backoff::default`]
+    pub fn backoff(mut self, backoff: Backoff) -> LokiSink {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets how many times a rejected push is retried before the error is
+    /// returned, defaults to [`DEFAULT_LOKI_RETRIES`]
+    pub fn retries(mut self, retries: u32) -> LokiSink {
+        self.retries = retries;
+        self
+    }
+
+    /// Compresses each push, sent with the matching `Content-Encoding`
+    pub fn compression(mut self, compression: Compression) -> LokiSink {
+        self.compression = compression;
+        self
+    }
+
+    /// Fails every write once `lines` are waiting to be sent, defaults to
+    /// [`DEFAULT_LOKI_MAX_PENDING`]
+    pub fn max_pending(mut self, lines: usize) -> LokiSink {
+        self.max_pending = lines.max(1);
+        self
+    }
+
+    /// Charges every write to `bandwidth`, blocking while it is exhausted
+    pub fn bandwidth(mut self, bandwidth: Bandwidth) -> LokiSink {
+        self.bandwidth = Some(bandwidth);
+        self
+    }
+
+    /// Returns the number of lines buffered and not yet sent
+    pub fn pending(&self) -> usize {
+        self.lines
+    }
+
+    fn push(&mut self, path: &Path, line: &str) {
+        let labels: Map<String, Value> = self
+            .labels
+            .iter()
+            .map(|(name, template)| (name.clone(), render(template, path).into()))
+            .collect();
+        let key = Value::Object(labels.clone()).to_string();
+        let timestamp = clock::system_now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos());
+        self.streams
+            .entry(key)
+            .or_insert_with(|| Stream {
+                labels,
+                values: Vec::new(),
+            })
+            .values
+            .push(json!([timestamp.to_string(), line]));
+        self.lines += 1;
+    }
+
+    /// Sends the buffered lines once a batch is complete, and fails if too
+    /// many are waiting. Called after all the lines of a write are buffered,
+    /// so that none of them are lost when sending fails.
+    fn settle(&mut self) -> Result<()> {
+        // Lines kept after a failed push are only sent again once another
+        // batch is complete, rather than with every write
+        if self.lines >= self.send_at {
+            self.send_at = self.lines + self.batch_size;
+            self.send()?;
+        }
+        if self.lines >= self.max_pending {
+            return Err(StaartError::Sink(format!(
+                "{} lines are waiting for Loki",
+                self.lines
+            )));
+        }
+        Ok(())
+    }
+
+    /// Sends the buffered lines in a single push, retrying while Loki asks to
+    /// back off. The lines are kept for the next attempt if the push fails.
+    fn send(&mut self) -> Result<()> {
+        if self.lines == 0 {
+            return Ok(());
+        }
+
+        let streams: Vec<Value> = self
+            .streams
+            .values()
+            .map(|stream| json!({ "stream": stream.labels, "values": stream.values }))
+            .collect();
+        let body = json!({ "streams": streams }).to_string();
+        let body = self.compression.compress(body.as_bytes())?;
+
+        let mut headers = vec![("Content-Type", "application/json")];
+        if let Some(encoding) = self.compression.encoding() {
+            headers.push(("Content-Encoding", encoding));
+        }
+        if let Some(tenant) = &self.tenant {
+            headers.push(("X-Scope-OrgID", tenant));
+        }
+
+        let mut attempt = 0;
+        loop {
+            let response =
+                self.endpoint
+                    .post("/loki/api/v1/push", &headers, &body, self.timeout)?;
+            let status = response.status();
+            if status.starts_with('2') {
+                break;
+            }
+            let retryable = status == "429" || status.starts_with('5');
+            if !retryable || attempt >= self.retries {
+                return Err(StaartError::Sink(format!(
+                    "Loki push failed with status {status}: {}",
+                    response.body()
+                )));
+            }
+            let retry_after = response
+                .header("Retry-After")
+                .and_then(|value| value.parse().ok())
+                .map(Duration::from_secs);
+            std::thread::sleep(retry_after.unwrap_or_else(|| self.backoff.delay(attempt)));
+            attempt += 1;
+        }
+
+        self.streams.clear();
+        self.lines = 0;
+        self.send_at = self.batch_size;
+        Ok(())
+    }
+}
+
+/// Renders a label `template` for `path`
+fn render(template: &str, path: &Path) -> String {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let dir = path.parent().unwrap_or(Path::new("")).to_string_lossy();
+    template
+        .replace("{path}", &path.to_string_lossy())
+        .replace("{filename}", &file_name)
+        .replace("{dir}", &dir)
+}
+
+impl Sink for LokiSink {
+    fn write_chunk(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        if let Some(bandwidth) = &self.bandwidth {
+            bandwidth.acquire(path, data.len() as u64);
+        }
+        let data = self.partials.complete(path, data);
+        for line in LinesStr::new(&data) {
+            self.push(path, &line);
+        }
+        self.settle()
+    }
+
+    fn write_lines(&mut self, path: &Path, lines: &[Line]) -> Result<()> {
+        if let Some(bandwidth) = &self.bandwidth {
+            let bytes = lines.iter().map(|line| line.text.len() as u64 + 1).sum();
+            bandwidth.acquire(path, bytes);
+        }
+        for line in lines {
+            self.push(path, &line.text);
+        }
+        self.settle()
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.send()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        for (path, line) in self.partials.take_all() {
+            self.write_chunk(&path, &line)?;
+        }
+        self.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    /// Answers one request per response in `responses`, returning the bodies
+    fn serve(
+        listener: TcpListener,
+        responses: &'static [&'static str],
+    ) -> std::thread::JoinHandle<Vec<String>> {
+        std::thread::spawn(move || {
+            let mut bodies = Vec::new();
+            for response in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut len = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(v) = line.strip_prefix("Content-Length: ") {
+                        len = v.trim().parse().unwrap();
+                    }
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                let mut body = vec![0; len];
+                reader.read_exact(&mut body).unwrap();
+                let response = format!("HTTP/1.1 {response}\r\nContent-Length: 0\r\n\r\n");
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+                bodies.push(String::from_utf8(body).unwrap());
+            }
+            bodies
+        })
+    }
+
+    #[test]
+    fn test_loki_push() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = serve(listener, &["204 No Content"]);
+
+        let mut sink = LokiSink::new(&url)
+            .unwrap()
+            .without_label("filename")
+            .label("job", "app-{filename}")
+            .label("dir", "{dir}");
+        sink.write_chunk(Path::new("/var/log/a.log"), b"one\ntw")
+            .unwrap();
+        assert_eq!(sink.pending(), 1);
+        sink.write_chunk(Path::new("/var/log/a.log"), b"o\n")
+            .unwrap();
+        sink.write_chunk(Path::new("/var/log/b.log"), b"three\n")
+            .unwrap();
+        assert_eq!(sink.pending(), 3);
+        sink.flush().unwrap();
+        assert_eq!(sink.pending(), 0);
+
+        let bodies = server.join().unwrap();
+        let push: Value = serde_json::from_str(&bodies[0]).unwrap();
+        let streams = push["streams"].as_array().unwrap();
+        assert_eq!(streams.len(), 2);
+        assert_eq!(
+            streams[0]["stream"],
+            json!({"dir": "/var/log", "job": "app-a.log"})
+        );
+        assert_eq!(streams[0]["values"][1][1], "two");
+        assert_eq!(streams[1]["stream"]["job"], "app-b.log");
+        assert_eq!(streams[1]["values"][0][1], "three");
+    }
+
+    #[test]
+    fn test_loki_backoff() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = serve(
+            listener,
+            &[
+                "429 Too Many Requests",
+                "429 Too Many Requests",
+                "400 Bad Request",
+                "204 No Content",
+            ],
+        );
+
+        let mut sink = LokiSink::new(&url)
+            .unwrap()
+            .backoff(Backoff::Fixed(Duration::ZERO))
+            .retries(1)
+            .max_pending(2);
+        sink.write_chunk(Path::new("a.log"), b"data\n").unwrap();
+        assert!(sink.flush().is_err());
+        assert_eq!(sink.pending(), 1);
+        // Lines past the limit fail the write but are kept
+        assert!(sink.write_chunk(Path::new("a.log"), b"more\n").is_err());
+        assert_eq!(sink.pending(), 2);
+        assert!(sink.flush().is_err());
+        sink.flush().unwrap();
+        assert_eq!(sink.pending(), 0);
+
+        let bodies = server.join().unwrap();
+        assert_eq!(bodies.len(), 4);
+        let push: Value = serde_json::from_str(&bodies[3]).unwrap();
+        let values = &push["streams"][0]["values"];
+        assert_eq!(values[0][1], "data");
+        assert_eq!(values[1][1], "more");
+
+        assert!(LokiSink::new("https://localhost").is_err());
+        let sink = LokiSink::new(&url).unwrap();
+        assert!(sink.tenant("a\r\nX-Injected: 1").is_err());
+    }
+}
@@ -0,0 +1,446 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::net::resolve;
+use crate::sink::Partials;
+use crate::{clock, Bandwidth, Compression, Line, LinesStr, Result, Sink, StaartError};
+
+/// Largest remaining length an MQTT packet can encode
+const MAX_PACKET: usize = 268_435_455;
+
+/// Largest packet accepted from the broker, which only ever sends
+/// acknowledgements to a publisher
+const MAX_INCOMING: usize = 1024;
+
+/// Delivery guarantee requested for each message published by an [`MqttSink`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QoS {
+    /// Messages are sent without waiting for the broker
+    AtMostOnce,
+    /// Every message waits for the broker's acknowledgement, and a write only
+    /// succeeds once all its messages were acknowledged
+    AtLeastOnce,
+}
+
+/// A [`Sink`] publishing each line as a message to an MQTT topic, enabled by
+/// the `mqtt` feature.
+///
+/// The sink speaks MQTT 3.1.1 to the broker. The connection is made on the
+/// first write, and made again on the write following a failure. With
+/// [`QoS::AtLeastOnce`] a write blocks until the broker acknowledged its
+/// messages, so a slow or unreachable broker holds back reading.
+///
+/// With [`MqttSink::compression`] set, each write is published as a single
+/// compressed message of newline-terminated lines. A partial line ending a
+/// chunk is held back until the rest of it is written.
+///
+/// The broker drops connections idle for longer than the keep alive
+/// interval, so a connection idle for that long is checked with a ping before
+/// it is used, and made again if the broker does not answer.
+///
+/// Only plain TCP connections are supported.
+///
+/// # Example
+/// ```no_run
+/// use staart::{MqttSink, QoS, Sink};
+///
+/// let mut sink = MqttSink::new("localhost:1883", "devices/gateway/logs")
+///     .unwrap()
+///     .client_id("gateway-1")
+///     .qos(QoS::AtLeastOnce);
+/// sink.write_chunk(std::path::Path::new("/var/log/syslog"), b"hello\n").unwrap();
+/// ```
+pub struct MqttSink {
+    addr: String,
+    topic: String,
+    client_id: String,
+    credentials: Option<(String, String)>,
+    qos: QoS,
+    keep_alive: u16,
+    timeout: Duration,
+    compression: Compression,
+    bandwidth: Option<Bandwidth>,
+    partials: Partials,
+    conn: Option<TcpStream>,
+    last_packet: Instant,
+    packet_id: u16,
+}
+
+impl MqttSink {
+    /// Creates a sink publishing to `topic` on the broker at `addr`, such as
+    /// `localhost:1883`
+    ///
+    /// # Propagates Errors
+    /// - If `topic` is empty, contains the `+` or `#` wildcards or a null
+    ///   character, or is longer than an MQTT string can be
+    pub fn new(addr: &str, topic: &str) -> Result<MqttSink> {
+        if topic.is_empty() || topic.contains(['+', '#', '\0']) {
+            return Err(StaartError::Config(format!(
+                "invalid MQTT topic: {topic:?}"
+            )));
+        }
+        push_str(&mut Vec::new(), topic)?;
+        Ok(MqttSink {
+            addr: addr.to_string(),
+            topic: topic.to_string(),
+            client_id: format!("staart-{}", std::process::id()),
+            credentials: None,
+            qos: QoS::AtMostOnce,
+            keep_alive: 60,
+            timeout: Duration::from_secs(10),
+            compression: Compression::None,
+            bandwidth: None,
+            partials: Partials::default(),
+            conn: None,
+            last_packet: clock::now(),
+            packet_id: 0,
+        })
+    }
+
+    /// Identifies to the broker as `id`, defaults to `staart-` followed by the
+    /// process ID
+    pub fn client_id(mut self, id: &str) -> MqttSink {
+        self.client_id = id.to_string();
+        self
+    }
+
+    /// Authenticates as `user` with `password`
+    pub fn credentials(mut self, user: &str, password: &str) -> MqttSink {
+        self.credentials = Some((user.to_string(), password.to_string()));
+        self
+    }
+
+    /// Sets the delivery guarantee, defaults to [`QoS::AtMostOnce`]
+    pub fn qos(mut self, qos: QoS) -> MqttSink {
+        self.qos = qos;
+        self
+    }
+
+    /// Sets the keep alive interval announced to the broker, in seconds,
+    /// defaults to 60. Zero turns keep alive off.
+    pub fn keep_alive(mut self, seconds: u16) -> MqttSink {
+        self.keep_alive = seconds;
+        self
+    }
+
+    /// Gives up on the broker after `timeout`
+    pub fn timeout(mut self, timeout: Duration) -> MqttSink {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Publishes each write as one compressed message
+    pub fn compression(mut self, compression: Compression) -> MqttSink {
+        self.compression = compression;
+        self
+    }
+
+    /// Charges every write to `bandwidth`, blocking while it is exhausted
+    pub fn bandwidth(mut self, bandwidth: Bandwidth) -> MqttSink {
+        self.bandwidth = Some(bandwidth);
+        self
+    }
+
+    fn connect(&mut self) -> Result<&mut TcpStream> {
+        let keep_alive = Duration::from_secs(self.keep_alive.into());
+        let idle = clock::now().saturating_duration_since(self.last_packet);
+        let conn = match self.conn.take() {
+            Some(mut conn) if self.keep_alive > 0 && idle >= keep_alive => match ping(&mut conn) {
+                Ok(()) => conn,
+                Err(_) => self.open()?,
+            },
+            Some(conn) => conn,
+            None => self.open()?,
+        };
+        self.last_packet = clock::now();
+
+        Ok(self.conn.insert(conn))
+    }
+
+    fn open(&self) -> Result<TcpStream> {
+        let mut stream = TcpStream::connect_timeout(&resolve(&self.addr)?, self.timeout)?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+
+        // Protocol name, level 4 (3.1.1), flags and keep alive
+        let mut body = Vec::new();
+        push_str(&mut body, "MQTT")?;
+        body.push(4);
+        let mut flags = 0x02; // clean session
+        if self.credentials.is_some() {
+            flags |= 0x80 | 0x40;
+        }
+        body.push(flags);
+        body.extend_from_slice(&self.keep_alive.to_be_bytes());
+        push_str(&mut body, &self.client_id)?;
+        if let Some((user, password)) = &self.credentials {
+            push_str(&mut body, user)?;
+            push_str(&mut body, password)?;
+        }
+        stream.write_all(&packet(0x10, &body)?)?;
+
+        let (kind, body) = read_packet(&mut stream)?;
+        if kind != 0x20 || body.len() != 2 {
+            return Err(protocol_error(kind));
+        }
+        if body[1] != 0 {
+            return Err(StaartError::Sink(format!(
+                "MQTT broker refused the connection with code {}",
+                body[1]
+            )));
+        }
+
+        Ok(stream)
+    }
+
+    fn publish<'l, I: IntoIterator<Item = &'l str>>(&mut self, lines: I) -> Result<()> {
+        let result = self.try_publish(lines);
+        if result.is_err() {
+            self.conn = None;
+        }
+        result
+    }
+
+    fn try_publish<'l, I: IntoIterator<Item = &'l str>>(&mut self, lines: I) -> Result<()> {
+        let mut out = Vec::new();
+        let mut awaiting = Vec::new();
+        for payload in self.compression.frames(lines)? {
+            let mut body = Vec::new();
+            push_str(&mut body, &self.topic)?;
+            let kind = match self.qos {
+                QoS::AtMostOnce => 0x30,
+                QoS::AtLeastOnce => {
+                    // Packet identifiers must not be zero
+                    self.packet_id = self.packet_id.checked_add(1).unwrap_or(1);
+                    body.extend_from_slice(&self.packet_id.to_be_bytes());
+                    awaiting.push(self.packet_id);
+                    0x32
+                }
+            };
+            body.extend_from_slice(&payload);
+            out.extend_from_slice(&packet(kind, &body)?);
+        }
+
+        let conn = self.connect()?;
+        conn.write_all(&out)?;
+        for id in awaiting {
+            let (kind, body) = read_packet(conn)?;
+            if kind != 0x40 || body != id.to_be_bytes() {
+                return Err(protocol_error(kind));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Appends `s` prefixed by its length, as MQTT encodes strings
+fn push_str(buf: &mut Vec<u8>, s: &str) -> Result<()> {
+    let len = u16::try_from(s.len()).map_err(|_| {
+        StaartError::Config(format!(
+            "MQTT string of {} bytes is longer than {} bytes",
+            s.len(),
+            u16::MAX
+        ))
+    })?;
+    buf.extend_from_slice(&len.to_be_bytes());
+    buf.extend_from_slice(s.as_bytes());
+    Ok(())
+}
+
+/// Checks that the broker still answers on `conn`
+fn ping(conn: &mut TcpStream) -> Result<()> {
+    conn.write_all(&[0xc0, 0x00])?;
+    let (kind, _) = read_packet(conn)?;
+    if kind != 0xd0 {
+        return Err(protocol_error(kind));
+    }
+    Ok(())
+}
+
+/// Builds a packet of type `kind` holding `body`
+fn packet(kind: u8, body: &[u8]) -> Result<Vec<u8>> {
+    if body.len() > MAX_PACKET {
+        return Err(StaartError::Sink(format!(
+            "MQTT packet of {} bytes is longer than {MAX_PACKET} bytes",
+            body.len()
+        )));
+    }
+    let mut packet = vec![kind];
+    let mut len = body.len();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    Ok(packet)
+}
+
+/// Reads a packet, returning its type and body
+fn read_packet<R: Read>(stream: &mut R) -> Result<(u8, Vec<u8>)> {
+    let mut byte = [0; 1];
+    stream.read_exact(&mut byte)?;
+    let kind = byte[0];
+    let mut len = 0;
+    let mut shift = 0;
+    loop {
+        stream.read_exact(&mut byte)?;
+        len |= ((byte[0] & 0x7f) as usize) << shift;
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+        if shift == 28 {
+            return Err(StaartError::Sink(
+                "malformed packet length from MQTT broker".to_string(),
+            ));
+        }
+    }
+    if len > MAX_INCOMING {
+        return Err(StaartError::Sink(format!(
+            "MQTT broker sent a packet of {len} bytes, more than {MAX_INCOMING}"
+        )));
+    }
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body)?;
+    Ok((kind, body))
+}
+
+fn protocol_error(kind: u8) -> StaartError {
+    StaartError::Sink(format!("unexpected packet from MQTT broker: {kind:#04x}"))
+}
+
+impl Sink for MqttSink {
+    fn write_chunk(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        if let Some(bandwidth) = &self.bandwidth {
+            bandwidth.acquire(path, data.len() as u64);
+        }
+        let data = self.partials.complete(path, data);
+        if data.is_empty() {
+            return Ok(());
+        }
+        let lines: Vec<_> = LinesStr::new(&data).collect();
+        self.publish(lines.iter().map(|line| line.as_ref()))
+    }
+
+    fn write_lines(&mut self, path: &Path, lines: &[Line]) -> Result<()> {
+        if let Some(bandwidth) = &self.bandwidth {
+            let bytes = lines.iter().map(|line| line.text.len() as u64 + 1).sum();
+            bandwidth.acquire(path, bytes);
+        }
+        self.publish(lines.iter().map(|line| line.text.as_str()))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if let Some(conn) = self.conn.as_mut() {
+            conn.flush()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        for (path, line) in self.partials.take_all() {
+            self.write_chunk(&path, &line)?;
+        }
+        self.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_mqtt_publish() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let (kind, connect) = read_packet(&mut stream).unwrap();
+            assert_eq!(kind, 0x10);
+            stream.write_all(&[0x20, 0x02, 0x00, 0x00]).unwrap();
+
+            let mut published = vec![connect];
+            for _ in 0..2 {
+                let (kind, body) = read_packet(&mut stream).unwrap();
+                assert_eq!(kind, 0x32);
+                let id = &body[body.len() - 3 - 2..body.len() - 3];
+                stream.write_all(&[0x40, 0x02, id[0], id[1]]).unwrap();
+                published.push(body);
+            }
+
+            // An idle connection is pinged before it is used again
+            let (kind, _) = read_packet(&mut stream).unwrap();
+            assert_eq!(kind, 0xc0);
+            stream.write_all(&[0xd0, 0x00]).unwrap();
+            let (kind, body) = read_packet(&mut stream).unwrap();
+            assert_eq!(kind, 0x32);
+            let id = &body[body.len() - 5 - 2..body.len() - 5];
+            stream.write_all(&[0x40, 0x02, id[0], id[1]]).unwrap();
+            published.push(body);
+            published
+        });
+
+        let mut sink = MqttSink::new(&addr, "logs")
+            .unwrap()
+            .client_id("test")
+            .credentials("user", "secret")
+            .qos(QoS::AtLeastOnce);
+        sink.write_chunk(Path::new("app.log"), b"one\ntw").unwrap();
+        sink.write_chunk(Path::new("app.log"), b"o\nthr").unwrap();
+        sink.last_packet = clock::now().checked_sub(Duration::from_secs(61)).unwrap();
+        sink.write_chunk(Path::new("app.log"), b"ee\n").unwrap();
+
+        let published = server.join().unwrap();
+        assert_eq!(
+            published[0],
+            b"\x00\x04MQTT\x04\xc2\x00\x3c\x00\x04test\x00\x04user\x00\x06secret"
+        );
+        assert_eq!(published[1], b"\x00\x04logs\x00\x01one");
+        assert_eq!(published[2], b"\x00\x04logs\x00\x02two");
+        assert_eq!(published[3], b"\x00\x04logs\x00\x03three");
+    }
+
+    #[test]
+    fn test_invalid_topic() {
+        let topic = "t".repeat(usize::from(u16::MAX) + 1);
+        for topic in ["", "logs/+", "logs/#", "logs\0", &topic] {
+            assert!(matches!(
+                MqttSink::new("localhost:1883", topic),
+                Err(StaartError::Config(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_packet_length() {
+        let body = vec![0; 321];
+        let encoded = packet(0x30, &body).unwrap();
+        assert_eq!(&encoded[..3], [0x30, 0xc1, 0x02]);
+        assert_eq!(encoded.len(), 3 + 321);
+        assert!(packet(0x30, &vec![0; MAX_PACKET + 1]).is_err());
+
+        let (kind, body) = read_packet(&mut &[0x40, 0x02, 0x00, 0x01][..]).unwrap();
+        assert_eq!((kind, body), (0x40, vec![0x00, 0x01]));
+        // Longer than the broker sends, and longer than four length bytes
+        assert!(read_packet(&mut &[0x30, 0x80, 0x80, 0x01][..]).is_err());
+        assert!(read_packet(&mut &[0x30, 0xff, 0xff, 0xff, 0xff, 0x01][..]).is_err());
+    }
+}
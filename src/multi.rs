@@ -0,0 +1,334 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+
+use crate::{Result, StaartError, TailStats, TailedFile};
+
+/// Follows a set of [`TailedFile`]s from a single object.
+///
+/// Each call to [`MultiTailer::poll`] reads every file once and returns the
+/// files that produced data or an error, so that a single loop can follow
+/// any number of logs. An error from one file does not stop the others from
+/// being read, and neither does a panic: a file whose read, callbacks or
+/// processing panic is moved to [`crate::TailState::Disabled`] with a
+/// [`crate::Lifecycle::Panicked`] entry in its timeline, and reported with
+/// [`StaartError::Panicked`].
+///
+/// In [`MultiTailer::deterministic`] mode the output of each poll depends only
+/// on the content of the files, not on the order they were added in or on
+/// where a writer happened to be when they were read, which makes pipelines
+/// built on it testable against golden files.
+///
+/// # Example
+/// ```no_run
+/// use std::thread::sleep;
+/// use std::time::Duration;
+/// use staart::{MultiTailer, TailedFile};
+///
+/// let mut tailer = MultiTailer::new();
+/// tailer.add(TailedFile::new("/var/log/syslog").unwrap());
+/// tailer.add(TailedFile::new("/var/log/auth.log").unwrap());
+/// loop {
+///     for (path, data) in tailer.poll() {
+///         match data {
+///             Ok(data) => print!("{}: {}", path.display(), String::from_utf8_lossy(&data)),
+///             Err(e) => eprintln!("{}: {e}", path.display()),
+///         }
+///     }
+///     sleep(Duration::from_millis(100));
+/// }
+/// ```
+pub struct MultiTailer<T: AsRef<Path>> {
+    files: Vec<TailedFile<T>>,
+    deterministic: bool,
+}
+
+impl<T: AsRef<Path>> MultiTailer<T> {
+    /// Creates a tailer following no files
+    pub fn new() -> MultiTailer<T> {
+        MultiTailer {
+            files: Vec::new(),
+            deterministic: false,
+        }
+    }
+
+    /// Creates a tailer with a stable output for the same inputs: files are
+    /// polled in the order of their paths rather than the order they were
+    /// added in, and reads are cut at the end of the last complete record as
+    /// with [`crate::TailedFileBuilder::align_to_records`], so a record being
+    /// written during a poll is returned whole by a later one.
+    pub fn deterministic() -> MultiTailer<T> {
+        MultiTailer {
+            files: Vec::new(),
+            deterministic: true,
+        }
+    }
+
+    /// Starts following `file`, replacing any file already followed at the
+    /// same path
+    pub fn add(&mut self, mut file: TailedFile<T>) {
+        self.remove(file.path());
+        if self.deterministic {
+            file.align_records = true;
+            let index = self.files.partition_point(|f| f.path() < file.path());
+            self.files.insert(index, file);
+        } else {
+            self.files.push(file);
+        }
+    }
+
+    /// Stops following the file at `path`, returning it
+    pub fn remove<P: AsRef<Path>>(&mut self, path: P) -> Option<TailedFile<T>> {
+        let index = self.files.iter().position(|f| f.path() == path.as_ref())?;
+        Some(self.files.remove(index))
+    }
+
+    /// Returns the file followed at `path`
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<&TailedFile<T>> {
+        self.files.iter().find(|f| f.path() == path.as_ref())
+    }
+
+    /// Returns the totals of the [`TailStats`] of the files currently followed
+    pub fn stats(&self) -> TailStats {
+        self.files
+            .iter()
+            .fold(TailStats::default(), |total, f| total.merge(f.stats()))
+    }
+
+    /// Returns the file followed at `path` mutably
+    pub fn get_mut<P: AsRef<Path>>(&mut self, path: P) -> Option<&mut TailedFile<T>> {
+        self.files.iter_mut().find(|f| f.path() == path.as_ref())
+    }
+
+    /// Reads every followed file once, returning the path and result of each
+    /// read that produced data or an error, in the order the files were added,
+    /// or of their paths in [`MultiTailer::deterministic`] mode
+    pub fn poll(&mut self) -> Vec<(&Path, Result<Vec<u8>>)> {
+        self.files
+            .iter_mut()
+            .map(|f| {
+                let data = isolate(f, |f| f.read());
+                (f.path(), data)
+            })
+            .filter(|(_, data)| !matches!(data, Ok(d) if d.is_empty()))
+            .collect()
+    }
+
+    /// Reads every followed file once, passing new data to `f` along with the
+    /// path it came from, and returns the path and error of each file that
+    /// failed.
+    ///
+    /// A panic in `f` disables only the file being processed, so a parser
+    /// choking on one log does not stop the others.
+    pub fn poll_and<F>(&mut self, mut f: F) -> Vec<(&Path, StaartError)>
+    where
+        F: FnMut(&Path, &[u8]),
+    {
+        self.files
+            .iter_mut()
+            .filter_map(|file| {
+                let result = isolate(file, |file| {
+                    let path = file.path().to_path_buf();
+                    file.read_and(|data| f(&path, data))
+                });
+                result.err().map(|e| (file.path(), e))
+            })
+            .collect()
+    }
+
+    /// Iterates over the followed files
+    pub fn iter(&self) -> impl Iterator<Item = &TailedFile<T>> {
+        self.files.iter()
+    }
+
+    /// Iterates mutably over the followed files, e.g. for
+    /// [`crate::close_least_recent`]
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut TailedFile<T>> {
+        self.files.iter_mut()
+    }
+
+    /// Returns the number of followed files
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Returns `true` if no files are followed
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+impl<T: AsRef<Path>> Default for MultiTailer<T> {
+    fn default() -> MultiTailer<T> {
+        MultiTailer::new()
+    }
+}
+
+impl<T: AsRef<Path>> FromIterator<TailedFile<T>> for MultiTailer<T> {
+    fn from_iter<I: IntoIterator<Item = TailedFile<T>>>(iter: I) -> MultiTailer<T> {
+        let mut tailer = MultiTailer::new();
+        for file in iter {
+            tailer.add(file);
+        }
+        tailer
+    }
+}
+
+/// Runs `f` on `file`, disabling the file if it panics
+fn isolate<T, R, F>(file: &mut TailedFile<T>, f: F) -> Result<R>
+where
+    T: AsRef<Path>,
+    F: FnOnce(&mut TailedFile<T>) -> Result<R>,
+{
+    // The file is disabled rather than reused after a panic, so state left
+    // half-updated is never observed through it
+    match panic::catch_unwind(AssertUnwindSafe(|| f(file))) {
+        Ok(result) => result,
+        Err(payload) => {
+            let msg = panic_message(payload.as_ref());
+            file.disable_panicked(msg.clone());
+            Err(StaartError::Panicked(msg))
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ErrorPolicy, Lifecycle, TailState, TailedFile};
+    use std::fs::File;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_multi_poll() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.file");
+        let b = dir.path().join("b.file");
+        let mut file_a = File::create(&a).unwrap();
+        File::create(&b).unwrap();
+
+        let mut tailer: MultiTailer<_> = [&a, &b]
+            .into_iter()
+            .map(|path| {
+                TailedFile::builder(path.clone())
+                    .error_policy(ErrorPolicy::Propagate)
+                    .build()
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(tailer.len(), 2);
+        assert!(tailer.poll().is_empty());
+
+        file_a.write_all(b"from a").unwrap();
+        std::fs::remove_file(&b).unwrap();
+        let polled = tailer.poll();
+        assert_eq!(polled.len(), 2);
+        assert_eq!(polled[0].0, a);
+        assert_eq!(polled[0].1.as_ref().unwrap(), b"from a");
+        assert_eq!(polled[1].0, b);
+        assert!(polled[1].1.is_err());
+        drop(polled);
+        let stats = tailer.stats();
+        assert_eq!((stats.bytes_read, stats.reopen_failures), (6, 1));
+
+        assert!(tailer.remove(&b).is_some());
+        assert!(tailer.get(&b).is_none());
+        assert!(tailer.poll().is_empty());
+    }
+
+    #[test]
+    fn test_panic_isolation() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.file");
+        let b = dir.path().join("b.file");
+        let mut file_a = File::create(&a).unwrap();
+        let mut file_b = File::create(&b).unwrap();
+        let mut tailer: MultiTailer<_> = [&a, &b]
+            .into_iter()
+            .map(|path| TailedFile::new(path.clone()).unwrap())
+            .collect();
+
+        file_a.write_all(b"boom\n").unwrap();
+        file_b.write_all(b"fine\n").unwrap();
+        let mut seen = Vec::new();
+        let failed = tailer.poll_and(|path, data| {
+            assert!(data != b"boom\n", "cannot parse {}", path.display());
+            seen.push(data.to_vec());
+        });
+        assert_eq!(seen, [b"fine\n"]);
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].0, a);
+        assert!(
+            matches!(&failed[0].1, StaartError::Panicked(msg) if msg.starts_with("cannot parse"))
+        );
+
+        let disabled = tailer.get(&a).unwrap();
+        assert_eq!(disabled.state(), TailState::Disabled);
+        assert!(disabled
+            .timeline()
+            .any(|e| matches!(e.event, Lifecycle::Panicked(_))));
+
+        file_b.write_all(b"still\n").unwrap();
+        let polled = tailer.poll();
+        assert_eq!(polled.len(), 1);
+        assert_eq!(polled[0].1.as_ref().unwrap(), b"still\n");
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths: Vec<_> = ["c.file", "a.file", "b.file"]
+            .iter()
+            .map(|name| dir.path().join(name))
+            .collect();
+        let mut files: Vec<_> = paths.iter().map(|p| File::create(p).unwrap()).collect();
+        let open = |order: &[usize]| -> MultiTailer<_> {
+            let mut tailer = MultiTailer::deterministic();
+            for i in order {
+                tailer.add(TailedFile::new(paths[*i].clone()).unwrap());
+            }
+            tailer
+        };
+        let mut first = open(&[0, 1, 2]);
+        let mut second = open(&[2, 1, 0]);
+
+        for (file, data) in files.iter_mut().zip([&b"c1\nc2"[..], b"a1\n", b"b"]) {
+            file.write_all(data).unwrap();
+        }
+        let output = |tailer: &mut MultiTailer<_>| -> Vec<(PathBuf, Vec<u8>)> {
+            tailer
+                .poll()
+                .into_iter()
+                .map(|(path, data)| (path.to_path_buf(), data.unwrap()))
+                .collect()
+        };
+        let expected = vec![
+            (paths[1].clone(), b"a1\n".to_vec()),
+            (paths[0].clone(), b"c1\n".to_vec()),
+        ];
+        assert_eq!(output(&mut first), expected);
+        assert_eq!(output(&mut second), expected);
+
+        files[2].write_all(b"1\n").unwrap();
+        assert_eq!(output(&mut first), [(paths[2].clone(), b"b1\n".to_vec())]);
+    }
+}
@@ -0,0 +1,199 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::clock;
+use crate::TimestampParser;
+
+type StartFn = Box<dyn Fn(&str) -> bool + Send>;
+
+/// Groups physical lines into logical records, folding continuation lines
+/// such as Java stack traces or wrapped messages into the record before them.
+///
+/// A record begins with each line accepted by the start predicate, and every
+/// following line which is not is appended to it, joined by `\n`. Lines seen
+/// before the first start line form records of their own.
+///
+/// staart has no regular expression engine of its own: a predicate backed by
+/// the `regex` crate can be passed to [`Multiline::new`], and the common case
+/// of records beginning with a timestamp is covered by [`Multiline::timestamp`].
+///
+/// # Example
+/// ```no_run
+/// use staart::{Multiline, Preset, TailedFile, TimestampParser};
+///
+/// let mut file = TailedFile::new("/var/log/app.log")?;
+/// let mut records = Multiline::timestamp(TimestampParser::preset(Preset::Rfc3339));
+/// loop {
+///     for line in file.lines_str()? {
+///         if let Some(record) = records.push(&line) {
+///             println!("{record}");
+///         }
+///     }
+///     if let Some(record) = records.flush_idle() {
+///         println!("{record}");
+///     }
+/// #   break;
+/// }
+/// # Ok::<(), staart::StaartError>(())
+/// ```
+pub struct Multiline {
+    start: StartFn,
+    pending: Option<String>,
+    lines: usize,
+    max_lines: Option<usize>,
+    idle: Duration,
+    last: Instant,
+}
+
+/// How long [`Multiline::flush_idle`] waits for continuation lines by default
+pub const DEFAULT_MULTILINE_IDLE: Duration = Duration::from_secs(5);
+
+impl Multiline {
+    /// Starts a record at each line for which `start` returns `true`
+    pub fn new<F>(start: F) -> Multiline
+    where
+        F: Fn(&str) -> bool + Send + 'static,
+    {
+        Multiline {
+            start: Box::new(start),
+            pending: None,
+            lines: 0,
+            max_lines: None,
+            idle: DEFAULT_MULTILINE_IDLE,
+            last: clock::now(),
+        }
+    }
+
+    /// Starts a record at each line beginning with a timestamp `parser` accepts
+    pub fn timestamp(parser: TimestampParser) -> Multiline {
+        Multiline::new(move |line| parser.parse_prefix(line).is_some())
+    }
+
+    /// Starts a record at each line not beginning with whitespace, so indented
+    /// lines continue the record before them
+    pub fn indented() -> Multiline {
+        Multiline::new(|line| !line.starts_with(char::is_whitespace))
+    }
+
+    /// Ends a record once it holds `max_lines` lines, starting a new one with
+    /// the next continuation line
+    pub fn max_lines(mut self, max_lines: usize) -> Multiline {
+        self.max_lines = Some(max_lines.max(1));
+        self
+    }
+
+    /// Sets how long [`Multiline::flush_idle`] waits without a new line
+    /// before releasing the pending record
+    pub fn idle_timeout(mut self, idle: Duration) -> Multiline {
+        self.idle = idle;
+        self
+    }
+
+    /// Adds a line, returning the previous record if the line ends it
+    pub fn push(&mut self, line: &str) -> Option<String> {
+        self.last = clock::now();
+        let full = matches!(self.max_lines, Some(max) if self.lines >= max);
+        match self.pending.as_mut() {
+            Some(record) if !full && !(self.start)(line) => {
+                record.push('\n');
+                record.push_str(line);
+                self.lines += 1;
+                None
+            }
+            _ => {
+                self.lines = 1;
+                self.pending.replace(line.to_string())
+            }
+        }
+    }
+
+    /// Returns the pending record if no line was added within the idle
+    /// timeout, since the next start line may be a long time coming
+    pub fn flush_idle(&mut self) -> Option<String> {
+        if clock::now().saturating_duration_since(self.last) >= self.idle {
+            self.flush()
+        } else {
+            None
+        }
+    }
+
+    /// Returns the pending record, e.g. at the end of input
+    pub fn flush(&mut self) -> Option<String> {
+        self.lines = 0;
+        self.pending.take()
+    }
+
+    /// Returns `true` if a record is waiting for continuation lines
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+}
+
+impl fmt::Debug for Multiline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Multiline")
+            .field("pending", &self.pending)
+            .field("lines", &self.lines)
+            .field("max_lines", &self.max_lines)
+            .field("idle", &self.idle)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Preset;
+
+    fn assemble(mut multiline: Multiline, lines: &[&str]) -> Vec<String> {
+        let mut records: Vec<_> = lines.iter().filter_map(|l| multiline.push(l)).collect();
+        records.extend(multiline.flush());
+        records
+    }
+
+    #[test]
+    fn test_stack_trace() {
+        let lines = [
+            "  orphan",
+            "2024-01-02T03:04:05Z ERROR boom",
+            "java.lang.IllegalStateException: bad",
+            "\tat com.example.Main.run(Main.java:10)",
+            "2024-01-02T03:04:06Z INFO ok",
+        ];
+        let parser = TimestampParser::preset(Preset::Rfc3339);
+        assert_eq!(
+            assemble(Multiline::timestamp(parser), &lines),
+            [
+                "  orphan",
+                "2024-01-02T03:04:05Z ERROR boom\njava.lang.IllegalStateException: bad\n\tat com.example.Main.run(Main.java:10)",
+                "2024-01-02T03:04:06Z INFO ok",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_max_lines() {
+        let lines = ["a", " 1", " 2", " 3", "b"];
+        assert_eq!(
+            assemble(Multiline::indented().max_lines(2), &lines),
+            ["a\n 1", " 2\n 3", "b"]
+        );
+    }
+
+    #[test]
+    fn test_flush_idle() {
+        let mut multiline = Multiline::indented().idle_timeout(Duration::ZERO);
+        assert_eq!(multiline.push("a"), None);
+        assert_eq!(multiline.flush_idle().as_deref(), Some("a"));
+        assert!(!multiline.is_pending());
+        assert_eq!(multiline.flush_idle(), None);
+    }
+}
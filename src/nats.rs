@@ -0,0 +1,318 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::net::resolve;
+use crate::sink::Partials;
+use crate::{Bandwidth, Compression, Line, LinesStr, Result, Sink, StaartError};
+
+/// A [`Sink`] publishing each line as a message to a NATS subject, enabled by
+/// the `nats` feature.
+///
+/// The connection is made on the first write, and made again on the write
+/// following a failure. With acknowledgements enabled every write waits for
+/// the server to confirm it processed the messages, so a slow or unreachable
+/// server holds back reading instead of messages piling up in the socket.
+/// Otherwise the server is only waited for on [`Sink::flush`].
+///
+/// With [`NatsSink::compression`] set, each write is published as a single
+/// compressed message of newline-terminated lines. A partial line ending a
+/// chunk is held back until the rest of it is written.
+///
+/// Only plain TCP connections are supported.
+///
+/// # Example
+/// ```no_run
+/// use staart::{NatsSink, Sink};
+///
+/// let mut sink = NatsSink::new("localhost:4222", "logs.syslog")
+///     .unwrap()
+///     .acknowledge(true);
+/// sink.write_chunk(std::path::Path::new("/var/log/syslog"), b"hello\n").unwrap();
+/// ```
+pub struct NatsSink {
+    addr: String,
+    subject: String,
+    credentials: Option<(String, String)>,
+    acknowledge: bool,
+    timeout: Duration,
+    compression: Compression,
+    bandwidth: Option<Bandwidth>,
+    partials: Partials,
+    conn: Option<BufReader<TcpStream>>,
+}
+
+impl NatsSink {
+    /// Creates a sink publishing to `subject` on the server at `addr`, such as
+    /// `localhost:4222`
+    ///
+    /// # Propagates Errors
+    /// - If `subject` is empty or contains whitespace or control characters,
+    ///   which would corrupt the protocol
+    pub fn new(addr: &str, subject: &str) -> Result<NatsSink> {
+        if subject.is_empty() || subject.chars().any(|c| c.is_whitespace() || c.is_control()) {
+            return Err(StaartError::Config(format!(
+                "invalid NATS subject: {subject:?}"
+            )));
+        }
+        Ok(NatsSink {
+            addr: addr.to_string(),
+            subject: subject.to_string(),
+            credentials: None,
+            acknowledge: false,
+            timeout: Duration::from_secs(10),
+            compression: Compression::None,
+            bandwidth: None,
+            partials: Partials::default(),
+            conn: None,
+        })
+    }
+
+    /// Authenticates as `user` with `password`
+    pub fn credentials(mut self, user: &str, password: &str) -> NatsSink {
+        self.credentials = Some((user.to_string(), password.to_string()));
+        self
+    }
+
+    /// Waits for the server to acknowledge every write, defaults to `false`
+    pub fn acknowledge(mut self, acknowledge: bool) -> NatsSink {
+        self.acknowledge = acknowledge;
+        self
+    }
+
+    /// Gives up on the server after `timeout`
+    pub fn timeout(mut self, timeout: Duration) -> NatsSink {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Publishes each write as one compressed message
+    pub fn compression(mut self, compression: Compression) -> NatsSink {
+        self.compression = compression;
+        self
+    }
+
+    /// Charges every write to `bandwidth`, blocking while it is exhausted
+    pub fn bandwidth(mut self, bandwidth: Bandwidth) -> NatsSink {
+        self.bandwidth = Some(bandwidth);
+        self
+    }
+
+    fn connect(&mut self) -> Result<&mut BufReader<TcpStream>> {
+        let conn = match self.conn.take() {
+            Some(conn) => conn,
+            None => {
+                let stream = TcpStream::connect_timeout(&resolve(&self.addr)?, self.timeout)?;
+                stream.set_read_timeout(Some(self.timeout))?;
+                stream.set_write_timeout(Some(self.timeout))?;
+                let mut conn = BufReader::new(stream);
+
+                let info = read_line(&mut conn)?;
+                if !info.starts_with("INFO ") {
+                    return Err(protocol_error(&info));
+                }
+                let mut options = connect_options(&self.credentials);
+                options.insert_str(0, "CONNECT ");
+                options.push_str("\r\n");
+                conn.get_mut().write_all(options.as_bytes())?;
+                conn
+            }
+        };
+
+        Ok(self.conn.insert(conn))
+    }
+
+    fn publish<'l, I: IntoIterator<Item = &'l str>>(&mut self, lines: I) -> Result<()> {
+        let result = self.try_publish(lines);
+        if result.is_err() {
+            self.conn = None;
+        }
+        result
+    }
+
+    fn try_publish<'l, I: IntoIterator<Item = &'l str>>(&mut self, lines: I) -> Result<()> {
+        let mut out = Vec::new();
+        for payload in self.compression.frames(lines)? {
+            let header = format!("PUB {} {}\r\n", self.subject, payload.len());
+            out.extend_from_slice(header.as_bytes());
+            out.extend_from_slice(&payload);
+            out.extend_from_slice(b"\r\n");
+        }
+        let acknowledge = self.acknowledge;
+        let conn = self.connect()?;
+        conn.get_mut().write_all(&out)?;
+
+        if acknowledge {
+            ping(conn)?;
+        }
+        Ok(())
+    }
+}
+
+/// Builds the options of the `CONNECT` message, quoting credentials by hand
+/// to avoid depending on a JSON library for a single object
+fn connect_options(credentials: &Option<(String, String)>) -> String {
+    let quote = |s: &str| {
+        let mut quoted = String::from('"');
+        for c in s.chars() {
+            match c {
+                '"' => quoted.push_str("\\\""),
+                '\\' => quoted.push_str("\\\\"),
+                c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+                c => quoted.push(c),
+            }
+        }
+        quoted.push('"');
+        quoted
+    };
+    let mut options = format!(
+        "{{\"verbose\":false,\"pedantic\":false,\"name\":\"staart\",\"lang\":\"rust\",\"version\":\"{}\"",
+        env!("CARGO_PKG_VERSION")
+    );
+    if let Some((user, password)) = credentials {
+        options.push_str(&format!(
+            ",\"user\":{},\"pass\":{}",
+            quote(user),
+            quote(password)
+        ));
+    }
+    options.push('}');
+    options
+}
+
+/// Sends `PING` and waits for the `PONG` confirming that everything sent
+/// before was processed
+fn ping(conn: &mut BufReader<TcpStream>) -> Result<()> {
+    conn.get_mut().write_all(b"PING\r\n")?;
+    loop {
+        let line = read_line(conn)?;
+        match line.as_str() {
+            "PONG" => return Ok(()),
+            "PING" => conn.get_mut().write_all(b"PONG\r\n")?,
+            "+OK" => {}
+            _ if line.starts_with("INFO ") => {}
+            _ => return Err(protocol_error(&line)),
+        }
+    }
+}
+
+fn read_line(conn: &mut BufReader<TcpStream>) -> Result<String> {
+    let mut line = String::new();
+    if conn.read_line(&mut line)? == 0 {
+        return Err(StaartError::Sink(
+            "NATS server closed the connection".to_string(),
+        ));
+    }
+    Ok(line.trim_end().to_string())
+}
+
+fn protocol_error(line: &str) -> StaartError {
+    StaartError::Sink(format!("unexpected reply from NATS server: {line}"))
+}
+
+impl Sink for NatsSink {
+    fn write_chunk(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        if let Some(bandwidth) = &self.bandwidth {
+            bandwidth.acquire(path, data.len() as u64);
+        }
+        let data = self.partials.complete(path, data);
+        if data.is_empty() {
+            return Ok(());
+        }
+        let lines: Vec<_> = LinesStr::new(&data).collect();
+        self.publish(lines.iter().map(|line| line.as_ref()))
+    }
+
+    fn write_lines(&mut self, path: &Path, lines: &[Line]) -> Result<()> {
+        if let Some(bandwidth) = &self.bandwidth {
+            let bytes = lines.iter().map(|line| line.text.len() as u64 + 1).sum();
+            bandwidth.acquire(path, bytes);
+        }
+        self.publish(lines.iter().map(|line| line.text.as_str()))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let Some(conn) = self.conn.as_mut() else {
+            return Ok(());
+        };
+        let result = ping(conn);
+        if result.is_err() {
+            self.conn = None;
+        }
+        result
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        for (path, line) in self.partials.take_all() {
+            self.write_chunk(&path, &line)?;
+        }
+        self.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_nats_publish() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream
+                .write_all(b"INFO {\"server_id\":\"test\"}\r\n")
+                .unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut received = Vec::new();
+            let mut pings = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "PING\r\n" {
+                    stream.write_all(b"PONG\r\n").unwrap();
+                    pings += 1;
+                    if pings == 2 {
+                        return received;
+                    }
+                    continue;
+                }
+                received.push(line);
+            }
+        });
+
+        let mut sink = NatsSink::new(&addr, "logs.app")
+            .unwrap()
+            .credentials("user", "p\"ss")
+            .acknowledge(true);
+        sink.write_chunk(Path::new("app.log"), b"one\ntw").unwrap();
+        sink.write_chunk(Path::new("app.log"), b"o\n").unwrap();
+
+        let received = server.join().unwrap();
+        assert!(received[0].starts_with("CONNECT {"));
+        assert!(received[0].contains(r#""user":"user","pass":"p\"ss""#));
+        assert_eq!(
+            received[1..],
+            [
+                "PUB logs.app 3\r\n",
+                "one\r\n",
+                "PUB logs.app 3\r\n",
+                "two\r\n"
+            ]
+        );
+
+        for subject in ["", "logs app", "logs\r\nPUB x 1"] {
+            assert!(NatsSink::new(&addr, subject).is_err());
+        }
+    }
+}
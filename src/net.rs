@@ -0,0 +1,464 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::borrow::Cow;
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::sink::Partials;
+use crate::{Bandwidth, Result, Sink, StaartError};
+
+/// How long a network sink waits for its endpoint by default
+pub const DEFAULT_NET_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest datagram a [`UdpSink`] sends by default, the most a UDP datagram
+/// carries over IPv4
+pub const DEFAULT_MAX_DATAGRAM: usize = 65_507;
+
+/// A [`Sink`] forwarding data to a TCP endpoint.
+///
+/// The connection is made on the first write, and made again on the write
+/// following a failure, so a restarted receiver is picked up without
+/// restarting staart. Data in the failed write is reported in the error and
+/// not resent.
+///
+/// With [`TcpSink::newline`] set, only complete lines are sent, so that the
+/// receiver can split records even when a line is read while half written.
+///
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+/// use staart::{TailedFile, TcpSink};
+///
+/// let sink = TcpSink::new("logs.example.com:5140").newline(true);
+/// let mut f = TailedFile::new("/var/log/syslog").unwrap();
+/// f.follow(sink, Duration::from_secs(1)).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct TcpSink {
+    addr: String,
+    timeout: Duration,
+    newline: bool,
+    bandwidth: Option<Bandwidth>,
+    partials: Partials,
+    conn: Option<TcpStream>,
+}
+
+impl TcpSink {
+    /// Creates a sink sending to `addr`, such as `localhost:5140`
+    pub fn new(addr: &str) -> TcpSink {
+        TcpSink {
+            addr: addr.to_string(),
+            timeout: DEFAULT_NET_TIMEOUT,
+            newline: false,
+            bandwidth: None,
+            partials: Partials::default(),
+            conn: None,
+        }
+    }
+
+    /// Gives up on connecting or writing after `timeout`, defaults to
+    /// [`DEFAULT_NET_TIMEOUT`]
+    pub fn timeout(mut self, timeout: Duration) -> TcpSink {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sends only complete lines, holding back a partial line ending a chunk
+    /// until the rest of it is written, defaults to `false`
+    pub fn newline(mut self, newline: bool) -> TcpSink {
+        self.newline = newline;
+        self
+    }
+
+    /// Charges every write to `bandwidth`, blocking while it is exhausted
+    pub fn bandwidth(mut self, bandwidth: Bandwidth) -> TcpSink {
+        self.bandwidth = Some(bandwidth);
+        self
+    }
+
+    fn connect(&mut self) -> Result<&mut TcpStream> {
+        let conn = match self.conn.take() {
+            Some(conn) => conn,
+            None => {
+                let addr = resolve(&self.addr)?;
+                let conn = TcpStream::connect_timeout(&addr, self.timeout)?;
+                conn.set_write_timeout(Some(self.timeout))?;
+                conn.set_nodelay(true)?;
+                conn
+            }
+        };
+
+        Ok(self.conn.insert(conn))
+    }
+}
+
+impl Sink for TcpSink {
+    fn write_chunk(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        if let Some(bandwidth) = &self.bandwidth {
+            bandwidth.acquire(path, data.len() as u64);
+        }
+        let data = if self.newline {
+            self.partials.complete(path, data)
+        } else {
+            Cow::Borrowed(data)
+        };
+        if data.is_empty() {
+            return Ok(());
+        }
+        let result = self.connect().and_then(|conn| Ok(conn.write_all(&data)?));
+        if result.is_err() {
+            self.conn = None;
+        }
+        result
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match self.conn.as_mut() {
+            Some(conn) => Ok(conn.flush()?),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        for (path, line) in self.partials.take_all() {
+            self.write_chunk(&path, &line)?;
+        }
+        self.flush()
+    }
+}
+
+/// A [`Sink`] sending data to a UDP endpoint, such as a syslog receiver.
+///
+/// Each chunk is sent as one datagram, or with [`UdpSink::newline`] set each
+/// complete line is sent as its own datagram ending in a newline. Chunks and
+/// lines larger than [`UdpSink::max_datagram`] are split over several
+/// datagrams, after the last newline that fits where there is one.
+/// Datagrams are not acknowledged, so data lost on the way is not reported.
+///
+/// # Example
+/// ```no_run
+/// use staart::{Sink, UdpSink};
+///
+/// let mut sink = UdpSink::new("127.0.0.1:514").newline(true);
+/// sink.write_chunk(std::path::Path::new("/var/log/syslog"), b"hello\n").unwrap();
+/// ```
+#[derive(Debug)]
+pub struct UdpSink {
+    addr: String,
+    newline: bool,
+    max_datagram: usize,
+    bandwidth: Option<Bandwidth>,
+    partials: Partials,
+    socket: Option<UdpSocket>,
+}
+
+impl UdpSink {
+    /// Creates a sink sending to `addr`, such as `localhost:514`
+    pub fn new(addr: &str) -> UdpSink {
+        UdpSink {
+            addr: addr.to_string(),
+            newline: false,
+            max_datagram: DEFAULT_MAX_DATAGRAM,
+            bandwidth: None,
+            partials: Partials::default(),
+            socket: None,
+        }
+    }
+
+    /// Sends each complete line as its own datagram, holding back a partial
+    /// line ending a chunk until the rest of it is written, defaults to
+    /// `false`
+    pub fn newline(mut self, newline: bool) -> UdpSink {
+        self.newline = newline;
+        self
+    }
+
+    /// Sets the largest datagram sent, defaults to [`DEFAULT_MAX_DATAGRAM`]
+    pub fn max_datagram(mut self, bytes: usize) -> UdpSink {
+        self.max_datagram = bytes.max(1);
+        self
+    }
+
+    /// Charges every write to `bandwidth`, blocking while it is exhausted
+    pub fn bandwidth(mut self, bandwidth: Bandwidth) -> UdpSink {
+        self.bandwidth = Some(bandwidth);
+        self
+    }
+
+    fn socket(&mut self) -> Result<&UdpSocket> {
+        let socket = match self.socket.take() {
+            Some(socket) => socket,
+            None => connect_udp(&self.addr)?,
+        };
+
+        Ok(self.socket.insert(socket))
+    }
+}
+
+impl Sink for UdpSink {
+    fn write_chunk(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        if let Some(bandwidth) = &self.bandwidth {
+            bandwidth.acquire(path, data.len() as u64);
+        }
+        let max = self.max_datagram;
+        if !self.newline {
+            let socket = self.socket()?;
+            let mut rest = data;
+            while !rest.is_empty() {
+                let end = if rest.len() <= max {
+                    rest.len()
+                } else {
+                    rest[..max]
+                        .iter()
+                        .rposition(|b| *b == b'\n')
+                        .map_or(max, |i| i + 1)
+                };
+                socket.send(&rest[..end])?;
+                rest = &rest[end..];
+            }
+            return Ok(());
+        }
+
+        let data = self.partials.complete(path, data);
+        if data.is_empty() {
+            return Ok(());
+        }
+        let socket = self.socket()?;
+        for line in data.split_inclusive(|b| *b == b'\n') {
+            for datagram in line.chunks(max) {
+                socket.send(datagram)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        for (path, line) in self.partials.take_all() {
+            self.write_chunk(&path, &line)?;
+        }
+        self.flush()
+    }
+}
+
+/// A [`Sink`] forwarding data to a Unix domain stream socket, such as one
+/// opened by a local log collector. Connections are handled as by
+/// [`TcpSink`].
+///
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+/// use staart::{TailedFile, UnixSink};
+///
+/// let sink = UnixSink::new("/run/collector.sock").newline(true);
+/// let mut f = TailedFile::new("/var/log/syslog").unwrap();
+/// f.follow(sink, Duration::from_secs(1)).unwrap();
+/// ```
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct UnixSink {
+    path: PathBuf,
+    timeout: Duration,
+    newline: bool,
+    bandwidth: Option<Bandwidth>,
+    partials: Partials,
+    conn: Option<UnixStream>,
+}
+
+#[cfg(unix)]
+impl UnixSink {
+    /// Creates a sink sending to the socket at `path`
+    pub fn new<P: Into<PathBuf>>(path: P) -> UnixSink {
+        UnixSink {
+            path: path.into(),
+            timeout: DEFAULT_NET_TIMEOUT,
+            newline: false,
+            bandwidth: None,
+            partials: Partials::default(),
+            conn: None,
+        }
+    }
+
+    /// Gives up on writing after `timeout`, defaults to
+    /// [`DEFAULT_NET_TIMEOUT`]
+    pub fn timeout(mut self, timeout: Duration) -> UnixSink {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sends only complete lines, as by [`TcpSink::newline`], defaults to
+    /// `false`
+    pub fn newline(mut self, newline: bool) -> UnixSink {
+        self.newline = newline;
+        self
+    }
+
+    /// Charges every write to `bandwidth`, blocking while it is exhausted
+    pub fn bandwidth(mut self, bandwidth: Bandwidth) -> UnixSink {
+        self.bandwidth = Some(bandwidth);
+        self
+    }
+
+    fn connect(&mut self) -> Result<&mut UnixStream> {
+        let conn = match self.conn.take() {
+            Some(conn) => conn,
+            None => {
+                let conn = UnixStream::connect(&self.path)?;
+                conn.set_write_timeout(Some(self.timeout))?;
+                conn
+            }
+        };
+
+        Ok(self.conn.insert(conn))
+    }
+}
+
+#[cfg(unix)]
+impl Sink for UnixSink {
+    fn write_chunk(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        if let Some(bandwidth) = &self.bandwidth {
+            bandwidth.acquire(path, data.len() as u64);
+        }
+        let data = if self.newline {
+            self.partials.complete(path, data)
+        } else {
+            Cow::Borrowed(data)
+        };
+        if data.is_empty() {
+            return Ok(());
+        }
+        let result = self.connect().and_then(|conn| Ok(conn.write_all(&data)?));
+        if result.is_err() {
+            self.conn = None;
+        }
+        result
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match self.conn.as_mut() {
+            Some(conn) => Ok(conn.flush()?),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        for (path, line) in self.partials.take_all() {
+            self.write_chunk(&path, &line)?;
+        }
+        self.flush()
+    }
+}
+
+/// Returns the first address `addr` resolves to
+pub(crate) fn resolve(addr: &str) -> Result<std::net::SocketAddr> {
+    addr.to_socket_addrs()?
+        .next()
+        .ok_or_else(|| StaartError::Sink(format!("{addr} did not resolve to an address")))
+}
+
+/// Returns a UDP socket bound to any local port and connected to `addr`
+pub(crate) fn connect_udp(addr: &str) -> Result<UdpSocket> {
+    let addr = resolve(addr)?;
+    let local = if addr.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(addr)?;
+    Ok(socket)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_tcp_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let mut sink = TcpSink::new(&addr).newline(true);
+        let path = Path::new("test.log");
+
+        sink.write_chunk(path, b"on").unwrap();
+        sink.write_chunk(path, b"e\n").unwrap();
+        sink.flush().unwrap();
+        let (mut conn, _) = listener.accept().unwrap();
+        let mut buf = [0; 4];
+        conn.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"one\n");
+
+        // A failed write drops the connection, the next one reconnects
+        sink.conn = None;
+        sink.write_chunk(path, b"two\n").unwrap();
+        let (mut conn, _) = listener.accept().unwrap();
+        conn.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"two\n");
+    }
+
+    #[test]
+    fn test_udp_newline() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let addr = receiver.local_addr().unwrap().to_string();
+        let mut sink = UdpSink::new(&addr).newline(true);
+
+        sink.write_chunk(Path::new("test.log"), b"one\ntwo")
+            .unwrap();
+        let mut buf = [0; 16];
+        let n = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"one\n");
+
+        // The partial line is held back until its newline is written
+        sink.write_chunk(Path::new("test.log"), b" and a half\n")
+            .unwrap();
+        let n = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"two and a half\n");
+
+        // Finishing sends the last line even though it has no newline
+        sink.write_chunk(Path::new("test.log"), b"last").unwrap();
+        sink.finish().unwrap();
+        let n = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"last\n");
+
+        let mut sink = UdpSink::new(&addr).max_datagram(8);
+        sink.write_chunk(Path::new("test.log"), b"one\ntwo\nthree\n")
+            .unwrap();
+        for expected in [&b"one\ntwo\n"[..], b"three\n"] {
+            let n = receiver.recv(&mut buf).unwrap();
+            assert_eq!(&buf[..n], expected);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("collector.sock");
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        let mut sink = UnixSink::new(&path);
+
+        sink.write_chunk(Path::new("test.log"), b"raw").unwrap();
+        drop(sink);
+        let (mut conn, _) = listener.accept().unwrap();
+        let mut data = Vec::new();
+        conn.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"raw");
+    }
+}
@@ -0,0 +1,53 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Pauses and unpauses a [`crate::TailedFile`] from another thread, e.g. one
+/// moved into [`crate::TailedFile::spawn`], as returned by
+/// [`crate::TailedFile::pause_handle`].
+///
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+/// use staart::TailedFile;
+///
+/// let f = TailedFile::new("/var/log/syslog").unwrap();
+/// let pause = f.pause_handle();
+/// let (handle, events) = f.spawn(Duration::from_secs(1));
+/// // The consumer is under memory pressure, stop deliveries for a while
+/// pause.pause();
+/// std::thread::sleep(Duration::from_secs(10));
+/// pause.unpause();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PauseHandle {
+    paused: Arc<AtomicBool>,
+}
+
+impl PauseHandle {
+    pub(crate) fn new() -> PauseHandle {
+        PauseHandle::default()
+    }
+
+    /// Stops deliveries from the file until [`PauseHandle::unpause`]
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Continues deliveries from the position reached when paused
+    pub fn unpause(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    /// Returns `true` while the file is paused
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,220 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::profile::Timing;
+use crate::{StageStats, TimeBudget};
+
+type StageFn = Arc<dyn for<'r> Fn(Cow<'r, str>) -> Option<Cow<'r, str>> + Send + Sync>;
+
+#[derive(Clone)]
+struct Stage {
+    name: String,
+    f: StageFn,
+    timing: Arc<Timing>,
+}
+
+/// Text substituted for values removed by [`Pipeline::redact`]
+pub const REDACTED: &str = "[redacted]";
+
+/// Transformations applied in order to each record before it is delivered by
+/// [`crate::TailedFile::lines_str`] or [`crate::TailedFile::batches`], set
+/// with [`crate::TailedFileBuilder::pipeline`].
+///
+/// Each stage takes the record and returns it, changed or not, or `None` to
+/// drop it. Records borrowed from the read buffer are only copied by the
+/// first stage that changes them. The time spent in each stage is measured,
+/// see [`Pipeline::stats`].
+///
+/// # Example
+/// ```
+/// use std::borrow::Cow;
+/// use staart::Pipeline;
+///
+/// let pipeline = Pipeline::new()
+///     .strip_ansi()
+///     .redact("password=")
+///     .then(|record| (!record.is_empty()).then_some(record))
+///     .label("[web] ");
+/// let record = pipeline.apply(Cow::Borrowed("\x1b[1mlogin\x1b[0m password=hunter2"));
+/// assert_eq!(record.unwrap(), "[web] login password=[redacted]");
+/// ```
+#[derive(Clone, Default)]
+pub struct Pipeline {
+    stages: Vec<Stage>,
+    budget: Option<TimeBudget>,
+}
+
+impl Pipeline {
+    /// Creates a pipeline passing records through unchanged
+    pub fn new() -> Pipeline {
+        Pipeline::default()
+    }
+
+    /// Appends a stage, named `stage <n>` after its position in
+    /// [`Pipeline::stats`]
+    pub fn then<F>(self, stage: F) -> Pipeline
+    where
+        F: for<'r> Fn(Cow<'r, str>) -> Option<Cow<'r, str>> + Send + Sync + 'static,
+    {
+        let name = format!("stage {}", self.stages.len());
+        self.then_named(&name, stage)
+    }
+
+    /// Appends a stage named `name` in [`Pipeline::stats`]
+    pub fn then_named<F>(mut self, name: &str, stage: F) -> Pipeline
+    where
+        F: for<'r> Fn(Cow<'r, str>) -> Option<Cow<'r, str>> + Send + Sync + 'static,
+    {
+        self.stages.push(Stage {
+            name: name.to_string(),
+            f: Arc::new(stage),
+            timing: Arc::default(),
+        });
+        self
+    }
+
+    /// Appends a stage removing ANSI escape sequences, e.g. terminal colours
+    pub fn strip_ansi(self) -> Pipeline {
+        self.then_named("strip_ansi", |record| {
+            if !record.contains('\x1b') {
+                return Some(record);
+            }
+            Some(Cow::Owned(strip_ansi(&record)))
+        })
+    }
+
+    /// Appends a stage replacing the value following each occurrence of
+    /// `key`, up to the next whitespace, with [`REDACTED`]
+    pub fn redact(self, key: &str) -> Pipeline {
+        let name = format!("redact {key:?}");
+        let key = key.to_string();
+        self.then_named(&name, move |record| {
+            if key.is_empty() || !record.contains(key.as_str()) {
+                return Some(record);
+            }
+            Some(Cow::Owned(redact(&record, &key)))
+        })
+    }
+
+    /// Appends a stage prefixing each record with `label`
+    pub fn label(self, label: &str) -> Pipeline {
+        let label = label.to_string();
+        self.then_named("label", move |record| {
+            Some(Cow::Owned(format!("{label}{record}")))
+        })
+    }
+
+    /// Counts, and optionally reports, records on which a stage takes longer
+    /// than `budget`
+    pub fn budget(mut self, budget: TimeBudget) -> Pipeline {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Runs `record` through every stage, returning `None` if one dropped it
+    pub fn apply<'r>(&self, record: Cow<'r, str>) -> Option<Cow<'r, str>> {
+        self.stages.iter().try_fold(record, |record, stage| {
+            stage
+                .timing
+                .time(&stage.name, self.budget.as_ref(), || (stage.f)(record))
+        })
+    }
+
+    /// Returns the time spent in each stage, shared with the clones of the
+    /// pipeline
+    pub fn stats(&self) -> Vec<StageStats> {
+        self.stages
+            .iter()
+            .map(|stage| stage.timing.stats(&stage.name))
+            .collect()
+    }
+
+    /// Returns `true` if the pipeline has no stages
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+}
+
+impl fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<_> = self.stages.iter().map(|stage| &stage.name).collect();
+        f.debug_struct("Pipeline")
+            .field("stages", &names)
+            .field("budget", &self.budget)
+            .finish()
+    }
+}
+
+/// Removes CSI sequences (`ESC [` parameters and a final byte) and two-byte
+/// escapes from `s`
+fn strip_ansi(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push(c);
+            continue;
+        }
+        if chars.next() == Some('[') {
+            for c in chars.by_ref() {
+                if ('\x40'..='\x7e').contains(&c) {
+                    break;
+                }
+            }
+        }
+    }
+    out
+}
+
+fn redact(s: &str, key: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find(key) {
+        let value = i + key.len();
+        out.push_str(&rest[..value]);
+        rest = &rest[value..];
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        if end > 0 {
+            out.push_str(REDACTED);
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stages() {
+        let pipeline = Pipeline::new()
+            .strip_ansi()
+            .redact("token=")
+            .then(|r| (!r.starts_with("DEBUG")).then_some(r));
+        let apply = |s| pipeline.apply(Cow::Borrowed(s));
+
+        assert!(matches!(apply("plain"), Some(Cow::Borrowed("plain"))));
+        assert_eq!(apply("\x1b[31;1mred\x1b[0m\x1bc").unwrap(), "red");
+        assert_eq!(
+            apply("token=abc token= id=1 token=xyz").unwrap(),
+            "token=[redacted] token= id=1 token=[redacted]"
+        );
+        assert_eq!(apply("DEBUG token=abc"), None);
+
+        let stats = pipeline.stats();
+        let names: Vec<_> = stats.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["strip_ansi", "redact \"token=\"", "stage 2"]);
+        assert!(stats.iter().all(|s| s.calls == 4));
+    }
+}
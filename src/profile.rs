@@ -0,0 +1,168 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+type WarnFn = Arc<dyn Fn(&str, Duration) + Send + Sync>;
+
+/// Time spent in one [`crate::Matcher`] or [`crate::Pipeline`] stage, as
+/// returned by [`crate::LineFilter::stats`] and [`crate::Pipeline::stats`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StageStats {
+    /// Description of the matcher or name of the stage
+    pub name: String,
+    /// Number of records it processed
+    pub calls: u64,
+    /// Total time spent in it
+    pub total: Duration,
+    /// Longest time spent on a single record
+    pub max: Duration,
+    /// Number of records on which it exceeded the [`TimeBudget`]
+    pub over_budget: u64,
+}
+
+impl StageStats {
+    /// Returns the mean time spent per record
+    pub fn mean(&self) -> Duration {
+        match self.calls {
+            0 => Duration::ZERO,
+            calls => Duration::from_nanos((self.total.as_nanos() / u128::from(calls)) as u64),
+        }
+    }
+}
+
+/// Time a matcher or stage may spend on one record before it is counted in
+/// [`StageStats::over_budget`], and optionally reported, to find the one
+/// making a shipper fall behind.
+///
+/// # Example
+/// ```
+/// use std::time::Duration;
+/// use staart::{LineFilter, Matcher, TimeBudget};
+///
+/// let budget = TimeBudget::new(Duration::from_micros(50))
+///     .warn(|name, took| eprintln!("filter {name} took {took:?}"));
+/// let filter = LineFilter::new()
+///     .include(Matcher::contains("ERROR"))
+///     .budget(budget);
+/// filter.is_match(b"an ERROR");
+/// assert_eq!(filter.stats()[0].calls, 1);
+/// ```
+#[derive(Clone)]
+pub struct TimeBudget {
+    limit: Duration,
+    warn: Option<WarnFn>,
+}
+
+impl TimeBudget {
+    /// Allows `limit` per record
+    pub fn new(limit: Duration) -> TimeBudget {
+        TimeBudget { limit, warn: None }
+    }
+
+    /// Calls `f` with the name of the matcher or stage and the time it took
+    /// each time it exceeds the budget
+    pub fn warn<F>(mut self, f: F) -> TimeBudget
+    where
+        F: Fn(&str, Duration) + Send + Sync + 'static,
+    {
+        self.warn = Some(Arc::new(f));
+        self
+    }
+
+    /// Returns the time allowed per record
+    pub fn limit(&self) -> Duration {
+        self.limit
+    }
+}
+
+impl fmt::Debug for TimeBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimeBudget")
+            .field("limit", &self.limit)
+            .field("warn", &self.warn.is_some())
+            .finish()
+    }
+}
+
+/// Running totals of the time spent in a matcher or stage, shared by its
+/// clones
+#[derive(Debug, Default)]
+pub(crate) struct Timing {
+    calls: AtomicU64,
+    total_nanos: AtomicU64,
+    max_nanos: AtomicU64,
+    over_budget: AtomicU64,
+}
+
+impl Timing {
+    /// Runs `f`, adding the time it took to the totals
+    pub(crate) fn time<R>(
+        &self,
+        name: &str,
+        budget: Option<&TimeBudget>,
+        f: impl FnOnce() -> R,
+    ) -> R {
+        let started = Instant::now();
+        let result = f();
+        let elapsed = started.elapsed();
+
+        let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.total_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+        if let Some(budget) = budget.filter(|b| elapsed > b.limit) {
+            self.over_budget.fetch_add(1, Ordering::Relaxed);
+            if let Some(warn) = &budget.warn {
+                warn(name, elapsed);
+            }
+        }
+
+        result
+    }
+
+    pub(crate) fn stats(&self, name: &str) -> StageStats {
+        StageStats {
+            name: name.to_string(),
+            calls: self.calls.load(Ordering::Relaxed),
+            total: Duration::from_nanos(self.total_nanos.load(Ordering::Relaxed)),
+            max: Duration::from_nanos(self.max_nanos.load(Ordering::Relaxed)),
+            over_budget: self.over_budget.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_timing() {
+        let warned = Arc::new(Mutex::new(Vec::new()));
+        let budget = TimeBudget::new(Duration::from_millis(5)).warn({
+            let warned = Arc::clone(&warned);
+            move |name, _| warned.lock().unwrap().push(name.to_string())
+        });
+        let timing = Timing::default();
+        assert_eq!(timing.time("fast", Some(&budget), || 1), 1);
+        timing.time("slow", Some(&budget), || {
+            std::thread::sleep(Duration::from_millis(10))
+        });
+
+        let stats = timing.stats("stage");
+        assert_eq!(stats.calls, 2);
+        assert_eq!(stats.over_budget, 1);
+        assert!(stats.max >= Duration::from_millis(10));
+        assert!(stats.mean() >= Duration::from_millis(5));
+        assert_eq!(*warned.lock().unwrap(), ["slow"]);
+    }
+}
@@ -0,0 +1,192 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::fmt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::clock;
+
+type ProgressHook = Box<dyn FnMut(&Path, &Progress) + Send>;
+
+/// How far a [`crate::TailedFile`] is through a backlog, as reported by
+/// [`crate::TailedFile::on_progress`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// Bytes read since reporting started
+    pub read: u64,
+    /// Bytes in the file past the read position, see
+    /// [`crate::TailedFile::lag`]
+    pub remaining: u64,
+    /// Bytes read per second since the last report
+    pub rate: f64,
+    /// Time left to catch up, from how fast the remaining bytes shrank since
+    /// the last report, or from the rate on the first report. `None` if they
+    /// did not shrink, such as when the writer is as fast as the reader.
+    pub eta: Option<Duration>,
+}
+
+impl Progress {
+    /// Returns `true` once nothing remains to be read
+    pub fn caught_up(&self) -> bool {
+        self.remaining == 0
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} read, {} remaining at {}/s",
+            Bytes(self.read as f64),
+            Bytes(self.remaining as f64),
+            Bytes(self.rate)
+        )?;
+        match self.eta {
+            _ if self.caught_up() => write!(f, ", caught up"),
+            Some(eta) => write!(f, ", ETA {}s", eta.as_secs()),
+            None => write!(f, ", not catching up"),
+        }
+    }
+}
+
+/// A byte count in binary units
+struct Bytes(f64);
+
+impl fmt::Display for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+        let mut value = self.0;
+        let mut unit = 0;
+        while value >= 1024.0 && unit < UNITS.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
+        match unit {
+            0 => write!(f, "{value:.0} {}", UNITS[unit]),
+            _ => write!(f, "{value:.1} {}", UNITS[unit]),
+        }
+    }
+}
+
+/// Counts the bytes read by a file and reports its [`Progress`] at an
+/// interval while it is behind, and once more when it catches up
+pub(crate) struct ProgressTracker {
+    interval: Duration,
+    hook: ProgressHook,
+    read: u64,
+    last_at: Instant,
+    last_read: u64,
+    last_remaining: Option<u64>,
+    last: Option<Progress>,
+}
+
+impl ProgressTracker {
+    pub(crate) fn new(interval: Duration, hook: ProgressHook) -> ProgressTracker {
+        ProgressTracker {
+            interval,
+            hook,
+            read: 0,
+            last_at: clock::now(),
+            last_read: 0,
+            last_remaining: None,
+            last: None,
+        }
+    }
+
+    /// Counts `n` bytes read, returning `true` if a report is due
+    pub(crate) fn record(&mut self, n: usize) -> bool {
+        self.read += n as u64;
+        clock::now().saturating_duration_since(self.last_at) >= self.interval
+    }
+
+    /// Reports the progress with `remaining` bytes left, unless the file was
+    /// and still is caught up
+    pub(crate) fn report(&mut self, path: &Path, remaining: u64) {
+        let now = clock::now();
+        let elapsed = now.saturating_duration_since(self.last_at).as_secs_f64();
+        let rate = match elapsed {
+            e if e > 0.0 => (self.read - self.last_read) as f64 / e,
+            _ => 0.0,
+        };
+        let eta = match self.last_remaining {
+            Some(last) if last > remaining && elapsed > 0.0 => {
+                let shrink = (last - remaining) as f64 / elapsed;
+                Some(Duration::from_secs_f64(remaining as f64 / shrink))
+            }
+            None if rate > 0.0 => Some(Duration::from_secs_f64(remaining as f64 / rate)),
+            _ => None,
+        };
+        let was_behind = !matches!(self.last, Some(p) if p.caught_up());
+        let progress = Progress {
+            read: self.read,
+            remaining,
+            rate,
+            eta,
+        };
+
+        self.last_at = now;
+        self.last_read = self.read;
+        self.last_remaining = Some(remaining);
+        self.last = Some(progress);
+        if remaining > 0 || was_behind {
+            (self.hook)(path, &progress);
+        }
+    }
+
+    pub(crate) fn last(&self) -> Option<Progress> {
+        self.last
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_reports() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let hook = {
+            let reports = Arc::clone(&reports);
+            Box::new(move |_: &Path, p: &Progress| reports.lock().unwrap().push(*p))
+        };
+        let mut tracker = ProgressTracker::new(Duration::ZERO, hook);
+        let path = Path::new("test.log");
+
+        assert!(tracker.record(100));
+        tracker.report(path, 300);
+        std::thread::sleep(Duration::from_millis(10));
+        tracker.record(200);
+        tracker.report(path, 100);
+        tracker.report(path, 0);
+        tracker.report(path, 0);
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 3);
+        assert!(reports[0].eta.is_some());
+        assert!(reports[1].eta.is_some() && reports[1].rate > 0.0);
+        assert_eq!(reports[1].read, 300);
+        assert!(reports[2].caught_up());
+        assert!(reports[2].to_string().ends_with("caught up"));
+    }
+
+    #[test]
+    fn test_display() {
+        let progress = Progress {
+            read: 1536,
+            remaining: 3 << 30,
+            rate: 2.0 * 1024.0 * 1024.0,
+            eta: Some(Duration::from_secs(1536)),
+        };
+        assert_eq!(
+            progress.to_string(),
+            "1.5 KiB read, 3.0 GiB remaining at 2.0 MiB/s, ETA 1536s"
+        );
+    }
+}
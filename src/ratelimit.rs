@@ -0,0 +1,261 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::{clock, Line, Result, Sink};
+
+/// How fast a [`RateLimitedSink`] lets data through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimit {
+    /// Lines per second
+    Lines(u64),
+    /// Bytes per second, counting the line ending of each line
+    Bytes(u64),
+}
+
+/// What a [`RateLimitedSink`] does with data over its [`RateLimit`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Block until the limit allows the data through
+    #[default]
+    Delay,
+    /// Drop the data, and report how many lines were dropped with a marker
+    /// line such as `[suppressed: 120 lines]` once data from the same file
+    /// is let through again, or on [`Sink::flush`]
+    Suppress,
+}
+
+/// A [`Sink`] keeping a runaway log from overwhelming the sink it wraps, by
+/// letting at most a [`RateLimit`] through and delaying or suppressing the
+/// rest according to an [`OverflowPolicy`].
+///
+/// The limit is a token bucket holding up to one second of the rate, so
+/// short bursts pass unhindered. Lines passed to [`Sink::write_lines`] are
+/// limited one by one, while chunks passed to [`Sink::write_chunk`] are
+/// limited as a whole, counting their newlines as lines. A line or chunk
+/// larger than the bucket is let through once the bucket is full.
+///
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+/// use staart::{OverflowPolicy, RateLimit, RateLimitedSink, TailedFile, TcpSink};
+///
+/// let sink = RateLimitedSink::new(TcpSink::new("logs.example.com:5140"), RateLimit::Lines(1000))
+///     .policy(OverflowPolicy::Suppress);
+/// let mut f = TailedFile::new("/var/log/syslog").unwrap();
+/// f.follow(sink, Duration::from_secs(1)).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct RateLimitedSink<S: Sink> {
+    inner: S,
+    limit: RateLimit,
+    policy: OverflowPolicy,
+    tokens: f64,
+    last: Instant,
+    pending: BTreeMap<PathBuf, Suppressed>,
+    suppressed: u64,
+}
+
+/// Lines suppressed from one file and not yet reported
+#[derive(Debug, Clone, Copy)]
+struct Suppressed {
+    lines: u64,
+    offset: u64,
+}
+
+impl<S: Sink> RateLimitedSink<S> {
+    /// Creates a sink letting `limit` through to `inner`, delaying the rest
+    pub fn new(inner: S, limit: RateLimit) -> RateLimitedSink<S> {
+        let mut sink = RateLimitedSink {
+            inner,
+            limit,
+            policy: OverflowPolicy::default(),
+            tokens: 0.0,
+            last: clock::now(),
+            pending: BTreeMap::new(),
+            suppressed: 0,
+        };
+        sink.tokens = sink.rate();
+        sink
+    }
+
+    /// Sets what happens to data over the limit, defaults to
+    /// [`OverflowPolicy::Delay`]
+    pub fn policy(mut self, policy: OverflowPolicy) -> RateLimitedSink<S> {
+        self.policy = policy;
+        self
+    }
+
+    /// Returns the number of lines suppressed so far
+    pub fn suppressed(&self) -> u64 {
+        self.suppressed
+    }
+
+    /// Returns the inner sink
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn rate(&self) -> f64 {
+        match self.limit {
+            RateLimit::Lines(n) | RateLimit::Bytes(n) => n.max(1) as f64,
+        }
+    }
+
+    fn cost(&self, lines: u64, bytes: usize) -> f64 {
+        match self.limit {
+            RateLimit::Lines(_) => lines as f64,
+            RateLimit::Bytes(_) => bytes as f64,
+        }
+    }
+
+    /// Takes `cost` tokens, waiting for them under [`OverflowPolicy::Delay`].
+    /// Returns `false` if they are not available under
+    /// [`OverflowPolicy::Suppress`].
+    fn admit(&mut self, cost: f64) -> bool {
+        let rate = self.rate();
+        loop {
+            let now = clock::now();
+            let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+            self.last = now;
+            self.tokens = (self.tokens + elapsed * rate).min(rate);
+
+            let needed = cost.min(rate);
+            if self.tokens >= needed {
+                self.tokens -= cost;
+                return true;
+            }
+            if self.policy == OverflowPolicy::Suppress {
+                return false;
+            }
+            std::thread::sleep(Duration::from_secs_f64((needed - self.tokens) / rate));
+        }
+    }
+
+    fn suppress(&mut self, path: &Path, lines: u64, offset: u64) {
+        self.suppressed += lines;
+        self.pending
+            .entry(path.to_path_buf())
+            .or_insert(Suppressed { lines: 0, offset })
+            .lines += lines;
+    }
+
+    /// Returns the marker for the lines suppressed from `path`, if any
+    fn marker(&mut self, path: &Path) -> Option<Line> {
+        self.pending.remove(path).map(|s| Line {
+            text: format!("[suppressed: {} lines]", s.lines),
+            offset: s.offset,
+        })
+    }
+
+    /// Writes a marker for every path lines were suppressed from
+    fn write_summaries(&mut self) -> Result<()> {
+        for (path, suppressed) in std::mem::take(&mut self.pending) {
+            let marker = Line {
+                text: format!("[suppressed: {} lines]", suppressed.lines),
+                offset: suppressed.offset,
+            };
+            self.inner.write_lines(&path, &[marker])?;
+        }
+        Ok(())
+    }
+}
+
+impl<S: Sink> Sink for RateLimitedSink<S> {
+    fn write_chunk(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        let lines = data.iter().filter(|b| **b == b'\n').count() as u64;
+        if !self.admit(self.cost(lines, data.len())) {
+            self.suppress(path, lines.max(1), 0);
+            return Ok(());
+        }
+        if let Some(marker) = self.marker(path) {
+            self.inner
+                .write_chunk(path, format!("{}\n", marker.text).as_bytes())?;
+        }
+        self.inner.write_chunk(path, data)
+    }
+
+    fn write_lines(&mut self, path: &Path, lines: &[Line]) -> Result<()> {
+        let mut passed = Vec::with_capacity(lines.len());
+        for line in lines {
+            if !self.admit(self.cost(1, line.text.len() + 1)) {
+                self.suppress(path, 1, line.offset);
+                continue;
+            }
+            passed.extend(self.marker(path));
+            passed.push(line.clone());
+        }
+        if passed.is_empty() {
+            return Ok(());
+        }
+        self.inner.write_lines(path, &passed)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.write_summaries()?;
+        self.inner.flush()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.write_summaries()?;
+        self.inner.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(n: u64) -> Vec<Line> {
+        (0..n)
+            .map(|i| Line {
+                text: format!("line {i}"),
+                offset: i * 7,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_suppress() {
+        let path = Path::new("test.log");
+        let mut sink =
+            RateLimitedSink::new(Vec::new(), RateLimit::Lines(3)).policy(OverflowPolicy::Suppress);
+        sink.write_lines(path, &lines(5)).unwrap();
+        sink.flush().unwrap();
+        assert_eq!(sink.suppressed(), 2);
+        assert_eq!(
+            String::from_utf8(sink.into_inner()).unwrap(),
+            "line 0\nline 1\nline 2\n[suppressed: 2 lines]\n"
+        );
+
+        // The marker precedes the next line let through
+        let mut sink =
+            RateLimitedSink::new(Vec::new(), RateLimit::Bytes(10)).policy(OverflowPolicy::Suppress);
+        sink.write_chunk(path, b"0123456789").unwrap();
+        sink.write_chunk(path, b"a\nb\n").unwrap();
+        std::thread::sleep(Duration::from_millis(500));
+        sink.write_chunk(path, b"c\n").unwrap();
+        assert_eq!(
+            String::from_utf8(sink.into_inner()).unwrap(),
+            "0123456789[suppressed: 2 lines]\nc\n"
+        );
+    }
+
+    #[test]
+    fn test_delay() {
+        let mut sink = RateLimitedSink::new(Vec::new(), RateLimit::Lines(20));
+        let started = Instant::now();
+        sink.write_lines(Path::new("test.log"), &lines(25)).unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert_eq!(sink.suppressed(), 0);
+        assert_eq!(sink.into_inner().split(|b| *b == b'\n').count(), 26);
+    }
+}
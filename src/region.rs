@@ -0,0 +1,81 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Deref;
+
+use crate::Result;
+
+/// A contiguous range of a file, either mapped into memory or read into a
+/// buffer when mapping is disabled or unavailable.
+pub(crate) enum Region {
+    #[cfg(feature = "mmap")]
+    Mapped(memmap2::Mmap),
+    Read(Vec<u8>),
+}
+
+impl Region {
+    /// Returns `len` bytes of `file` starting at `offset`, which must not
+    /// extend past the end of the file.
+    pub(crate) fn new(file: &mut File, offset: u64, len: u64) -> Result<Self> {
+        #[cfg(feature = "mmap")]
+        if let Some(map) = map(file, offset, len) {
+            return Ok(Region::Mapped(map));
+        }
+
+        let mut data = Vec::new();
+        file.seek(SeekFrom::Start(offset))?;
+        file.take(len).read_to_end(&mut data)?;
+
+        Ok(Region::Read(data))
+    }
+}
+
+impl Deref for Region {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            #[cfg(feature = "mmap")]
+            Region::Mapped(map) => map,
+            Region::Read(data) => data,
+        }
+    }
+}
+
+#[cfg(feature = "mmap")]
+fn map(file: &File, offset: u64, len: u64) -> Option<memmap2::Mmap> {
+    let len = usize::try_from(len).ok().filter(|len| *len > 0)?;
+    // SAFETY: the mapping is read-only and dropped before the next read. A
+    // concurrent truncation of the file while it is borrowed is not guarded
+    // against, see `TailedFile::read_mapped`.
+    unsafe {
+        memmap2::MmapOptions::new()
+            .offset(offset)
+            .len(len)
+            .map(file)
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_region() {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(b"0123456789").unwrap();
+
+        assert_eq!(&*Region::new(&mut file, 2, 5).unwrap(), b"23456");
+        assert_eq!(&*Region::new(&mut file, 8, 2).unwrap(), b"89");
+        assert!(Region::new(&mut file, 4, 0).unwrap().is_empty());
+    }
+}
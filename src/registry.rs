@@ -0,0 +1,216 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Checkpoint, Result, StaartError, Timestamp};
+
+/// The [`Checkpoint`]s of a set of followed files, keyed by path, which can
+/// be read from and written to a Filebeat registry.
+///
+/// The registry format is the JSON array of file states written by Filebeat
+/// to `data/registry/filebeat/data.json` (or `data/registry` before 7.0), so
+/// that a shipper built on staart can take over from Filebeat without
+/// replaying or skipping logs, and the other way around.
+///
+/// # Example
+/// ```no_run
+/// use staart::{Registry, TailedFile};
+///
+/// let mut registry = Registry::load_filebeat("/var/lib/filebeat/registry/filebeat/data.json").unwrap();
+/// let mut f = match registry.get("/var/log/syslog") {
+///     Some(checkpoint) => TailedFile::resume("/var/log/syslog", checkpoint).unwrap(),
+///     None => TailedFile::new("/var/log/syslog").unwrap(),
+/// };
+/// f.read().unwrap();
+/// registry.insert("/var/log/syslog", f.checkpoint());
+/// registry.save_filebeat("/var/lib/app/registry.json").unwrap();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Registry {
+    files: BTreeMap<PathBuf, Checkpoint>,
+}
+
+/// A file state as written by Filebeat. Fields staart has no use for are
+/// ignored when reading, and written with Filebeat's defaults.
+#[derive(Serialize, Deserialize)]
+struct FileState {
+    source: PathBuf,
+    offset: u64,
+    #[serde(default)]
+    timestamp: String,
+    #[serde(default = "default_ttl")]
+    ttl: i64,
+    #[serde(rename = "type", default = "default_type")]
+    kind: String,
+    #[serde(rename = "FileStateOS")]
+    os: FileStateOs,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FileStateOs {
+    inode: u64,
+    device: u64,
+}
+
+fn default_ttl() -> i64 {
+    -1
+}
+
+fn default_type() -> String {
+    String::from("log")
+}
+
+impl Registry {
+    /// Creates an empty registry
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    /// Reads a Filebeat registry from `path`
+    ///
+    /// # Propagates Errors
+    /// - If the registry can not be read or is invalid
+    pub fn load_filebeat<P: AsRef<Path>>(path: P) -> Result<Registry> {
+        let data = std::fs::read(path)?;
+        let states: Vec<FileState> = serde_json::from_slice(&data)
+            .map_err(|e| StaartError::Config(format!("invalid registry: {e}")))?;
+
+        Ok(states
+            .into_iter()
+            .map(|s| {
+                let checkpoint = Checkpoint {
+                    device: s.os.device,
+                    inode: s.os.inode,
+                    offset: s.offset,
+                };
+                (s.source, checkpoint)
+            })
+            .collect())
+    }
+
+    /// Writes the registry to `path` in Filebeat's format. The file is
+    /// replaced atomically, as with [`Checkpoint::save`].
+    ///
+    /// # Propagates Errors
+    /// - If the registry can not be written
+    pub fn save_filebeat<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let timestamp = Timestamp::from(SystemTime::now()).to_rfc3339();
+        let states: Vec<FileState> = self
+            .files
+            .iter()
+            .map(|(path, checkpoint)| FileState {
+                source: path.clone(),
+                offset: checkpoint.offset,
+                timestamp: timestamp.clone(),
+                ttl: default_ttl(),
+                kind: default_type(),
+                os: FileStateOs {
+                    inode: checkpoint.inode,
+                    device: checkpoint.device,
+                },
+            })
+            .collect();
+        let data = serde_json::to_vec(&states)
+            .map_err(|e| StaartError::Config(format!("invalid registry: {e}")))?;
+
+        let path = path.as_ref();
+        let mut tmp = path.as_os_str().to_os_string();
+        tmp.push(".tmp");
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, path)?;
+
+        Ok(())
+    }
+
+    /// Returns the checkpoint recorded for `path`
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<Checkpoint> {
+        self.files.get(path.as_ref()).copied()
+    }
+
+    /// Records `checkpoint` for `path`, returning the one it replaces
+    pub fn insert<P: Into<PathBuf>>(
+        &mut self,
+        path: P,
+        checkpoint: Checkpoint,
+    ) -> Option<Checkpoint> {
+        self.files.insert(path.into(), checkpoint)
+    }
+
+    /// Forgets the checkpoint recorded for `path`, returning it
+    pub fn remove<P: AsRef<Path>>(&mut self, path: P) -> Option<Checkpoint> {
+        self.files.remove(path.as_ref())
+    }
+
+    /// Iterates over the recorded paths and checkpoints in path order
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, Checkpoint)> {
+        self.files.iter().map(|(path, c)| (path.as_path(), *c))
+    }
+
+    /// Returns the number of recorded files
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Returns `true` if no files are recorded
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+impl FromIterator<(PathBuf, Checkpoint)> for Registry {
+    fn from_iter<I: IntoIterator<Item = (PathBuf, Checkpoint)>>(iter: I) -> Registry {
+        Registry {
+            files: iter.into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filebeat_registry() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.json");
+        std::fs::write(
+            &path,
+            r#"[{"source":"/var/log/syslog","offset":5678,"timestamp":"2024-06-01T12:00:00.123456789Z","ttl":-1,"type":"log","meta":null,"FileStateOS":{"inode":1234,"device":2049}}]"#,
+        )
+        .unwrap();
+
+        let mut registry = Registry::load_filebeat(&path).unwrap();
+        let syslog = Checkpoint {
+            device: 2049,
+            inode: 1234,
+            offset: 5678,
+        };
+        assert_eq!(registry.get("/var/log/syslog"), Some(syslog));
+
+        let auth = Checkpoint {
+            device: 2049,
+            inode: 4321,
+            offset: 10,
+        };
+        registry.insert("/var/log/auth.log", auth);
+        registry.save_filebeat(&path).unwrap();
+
+        let registry = Registry::load_filebeat(&path).unwrap();
+        assert_eq!(registry.len(), 2);
+        assert_eq!(registry.get("/var/log/auth.log"), Some(auth));
+        assert_eq!(registry.get("/var/log/syslog"), Some(syslog));
+
+        std::fs::write(&path, "{}").unwrap();
+        assert!(Registry::load_filebeat(&path).is_err());
+    }
+}
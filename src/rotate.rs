@@ -0,0 +1,261 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::{clock, Result, Sink};
+
+/// An output file rotated by age or size, keeping a bounded number of
+/// archives, so that staart can archive logs without an external logrotate.
+///
+/// On rotation the file is renamed to `<path>.<unix seconds>`, with a `-<n>`
+/// suffix if that name is taken, and a new file is created at the path.
+/// Archives beyond [`RotatingFile::keep`], or older than
+/// [`RotatingFile::keep_for`], are then removed.
+///
+/// # Example
+/// ```no_run
+/// use std::io::Write;
+/// use std::time::Duration;
+/// use staart::RotatingFile;
+///
+/// let mut out = RotatingFile::create("/var/archive/syslog")
+///     .unwrap()
+///     .rotate_every(Duration::from_secs(24 * 3600))
+///     .keep(7);
+/// out.write_all(b"archived line\n").unwrap();
+/// ```
+#[derive(Debug)]
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    opened: Instant,
+    every: Option<Duration>,
+    max_size: Option<u64>,
+    keep: Option<usize>,
+    keep_for: Option<Duration>,
+}
+
+impl RotatingFile {
+    /// Opens or creates the file at `path` for appending
+    ///
+    /// # Propagates Errors
+    /// - If the file can not be opened
+    pub fn create<P: Into<PathBuf>>(path: P) -> Result<RotatingFile> {
+        let path = path.into();
+        let file = open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(RotatingFile {
+            path,
+            file,
+            size,
+            opened: clock::now(),
+            every: None,
+            max_size: None,
+            keep: None,
+            keep_for: None,
+        })
+    }
+
+    /// Rotates the file once it has been written to for `interval`
+    pub fn rotate_every(mut self, interval: Duration) -> RotatingFile {
+        self.every = Some(interval);
+        self
+    }
+
+    /// Rotates the file before a write would take it past `bytes`
+    pub fn max_size(mut self, bytes: u64) -> RotatingFile {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Keeps at most `archives` rotated files, removing the oldest
+    pub fn keep(mut self, archives: usize) -> RotatingFile {
+        self.keep = Some(archives);
+        self
+    }
+
+    /// Removes rotated files last written more than `age` ago
+    pub fn keep_for(mut self, age: Duration) -> RotatingFile {
+        self.keep_for = Some(age);
+        self
+    }
+
+    /// Returns the path of the file being written
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Renames the current file to an archive, starts a new one and removes
+    /// archives beyond the retention
+    ///
+    /// # Propagates Errors
+    /// - If the file can not be renamed or created, or an archive removed
+    pub fn rotate(&mut self) -> Result<()> {
+        Write::flush(&mut self.file)?;
+        let secs = clock::system_now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let mut archive = self.archive_path(&secs.to_string());
+        let mut n = 0;
+        while archive.exists() {
+            n += 1;
+            archive = self.archive_path(&format!("{secs}-{n}"));
+        }
+        std::fs::rename(&self.path, &archive)?;
+        self.file = open(&self.path)?;
+        self.size = 0;
+        self.opened = clock::now();
+
+        self.prune()
+    }
+
+    fn archive_path(&self, suffix: &str) -> PathBuf {
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(".");
+        name.push(suffix);
+        self.path.with_file_name(name)
+    }
+
+    /// Removes archives beyond the retention
+    fn prune(&self) -> Result<()> {
+        if self.keep.is_none() && self.keep_for.is_none() {
+            return Ok(());
+        }
+        let mut archives = self.archives()?;
+        // Newest first
+        archives.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| b.1.cmp(&a.1)));
+        let now = clock::system_now();
+        for (i, (modified, path)) in archives.iter().enumerate() {
+            let surplus = matches!(self.keep, Some(keep) if i >= keep);
+            let expired = matches!(
+                (self.keep_for, now.duration_since(*modified)),
+                (Some(age), Ok(elapsed)) if elapsed > age
+            );
+            if surplus || expired {
+                std::fs::remove_file(path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Lists the archives of the file with the time they were last written
+    fn archives(&self) -> Result<Vec<(SystemTime, PathBuf)>> {
+        let Some(name) = self.path.file_name().and_then(|n| n.to_str()) else {
+            return Ok(Vec::new());
+        };
+        let prefix = format!("{name}.");
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut archives = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let is_archive = matches!(
+                name.to_str().and_then(|n| n.strip_prefix(&prefix)),
+                Some(suffix) if suffix.starts_with(|c: char| c.is_ascii_digit())
+            );
+            if is_archive {
+                archives.push((entry.metadata()?.modified()?, entry.path()));
+            }
+        }
+
+        Ok(archives)
+    }
+
+    fn due(&self, len: u64) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+        let full = matches!(self.max_size, Some(max) if self.size + len > max);
+        let old = matches!(
+            self.every,
+            Some(every) if clock::now().saturating_duration_since(self.opened) >= every
+        );
+        full || old
+    }
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.due(buf.len() as u64) {
+            self.rotate()?;
+        }
+        let n = self.file.write(buf)?;
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Write::flush(&mut self.file)
+    }
+}
+
+impl Sink for RotatingFile {
+    fn write_chunk(&mut self, _path: &Path, data: &[u8]) -> Result<()> {
+        Ok(self.write_all(data)?)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(Write::flush(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_max_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.log");
+        let mut out = RotatingFile::create(&path).unwrap().max_size(8).keep(2);
+
+        for line in [
+            b"one\n", b"two\n", b"thr\n", b"fou\n", b"fiv\n", b"six\n", b"sev\n",
+        ] {
+            out.write_all(line).unwrap();
+        }
+        assert_eq!(std::fs::read(&path).unwrap(), b"sev\n");
+        let mut archives: Vec<_> = out
+            .archives()
+            .unwrap()
+            .into_iter()
+            .map(|(_, p)| std::fs::read(p).unwrap())
+            .collect();
+        archives.sort();
+        assert_eq!(archives, [b"fiv\nsix\n", b"thr\nfou\n"]);
+    }
+
+    #[test]
+    fn test_rotate_every() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.log");
+        let mut out = RotatingFile::create(&path)
+            .unwrap()
+            .rotate_every(Duration::ZERO)
+            .keep_for(Duration::from_secs(3600));
+
+        out.write_all(b"first\n").unwrap();
+        out.write_all(b"second\n").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"second\n");
+        assert_eq!(out.archives().unwrap().len(), 1);
+    }
+}
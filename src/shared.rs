@@ -0,0 +1,149 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::{Checkpoint, Result, TailEvent, TailState, TailedFile};
+
+/// A [`TailedFile`] that can be shared between threads.
+///
+/// Clones refer to the same file, and every call locks it for its duration,
+/// so one thread can read while others query the lag or request a reopen.
+/// Calls that are not covered here go through [`SharedTailedFile::with`].
+///
+/// # Example
+/// ```no_run
+/// use staart::{SharedTailedFile, TailedFile};
+///
+/// let shared = SharedTailedFile::new(TailedFile::new("/var/log/syslog").unwrap());
+/// let monitor = shared.clone();
+/// std::thread::spawn(move || {
+///     eprintln!("{} bytes behind", monitor.lag().unwrap());
+/// });
+/// let data = shared.read().unwrap();
+/// ```
+pub struct SharedTailedFile<T: AsRef<Path>> {
+    inner: Arc<Mutex<TailedFile<T>>>,
+}
+
+impl<T: AsRef<Path>> SharedTailedFile<T> {
+    /// Wraps `file` for use from several threads
+    pub fn new(file: TailedFile<T>) -> SharedTailedFile<T> {
+        SharedTailedFile {
+            inner: Arc::new(Mutex::new(file)),
+        }
+    }
+
+    /// Locks the file. A panic in another thread while it held the lock does
+    /// not leave the file in an inconsistent state, so the poison is ignored.
+    fn lock(&self) -> MutexGuard<'_, TailedFile<T>> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Calls `f` with exclusive access to the file
+    pub fn with<R, F: FnOnce(&mut TailedFile<T>) -> R>(&self, f: F) -> R {
+        f(&mut self.lock())
+    }
+
+    /// See [`TailedFile::read`]
+    pub fn read(&self) -> Result<Vec<u8>> {
+        self.lock().read()
+    }
+
+    /// See [`TailedFile::read_into`]
+    pub fn read_into(&self, buf: &mut Vec<u8>) -> Result<usize> {
+        self.lock().read_into(buf)
+    }
+
+    /// See [`TailedFile::poll_event`]
+    pub fn poll_event(&self) -> Result<TailEvent> {
+        self.lock().poll_event()
+    }
+
+    /// See [`TailedFile::lag`]
+    pub fn lag(&self) -> Result<u64> {
+        self.lock().lag()
+    }
+
+    /// See [`TailedFile::request_reopen`]
+    pub fn request_reopen(&self) -> Result<()> {
+        self.lock().request_reopen()
+    }
+
+    /// See [`TailedFile::checkpoint`]
+    pub fn checkpoint(&self) -> Checkpoint {
+        self.lock().checkpoint()
+    }
+
+    /// See [`TailedFile::state`]
+    pub fn state(&self) -> TailState {
+        self.lock().state()
+    }
+
+    /// See [`TailedFile::lost_bytes`]
+    pub fn lost_bytes(&self) -> u64 {
+        self.lock().lost_bytes()
+    }
+
+    /// See [`TailedFile::stop`]
+    pub fn stop(&self) {
+        self.lock().stop()
+    }
+
+    /// See [`TailedFile::enable`]
+    pub fn enable(&self) {
+        self.lock().enable()
+    }
+}
+
+impl<T: AsRef<Path>> Clone for SharedTailedFile<T> {
+    fn clone(&self) -> SharedTailedFile<T> {
+        SharedTailedFile {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<T: AsRef<Path>> From<TailedFile<T>> for SharedTailedFile<T> {
+    fn from(file: TailedFile<T>) -> SharedTailedFile<T> {
+        SharedTailedFile::new(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_shared_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.file");
+        let mut file = std::fs::File::create(&path).unwrap();
+        let shared = SharedTailedFile::new(TailedFile::new(path.clone()).unwrap());
+
+        file.write_all(b"0123456789").unwrap();
+        assert_eq!(shared.lag().unwrap(), 10);
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let shared = shared.clone();
+                std::thread::spawn(move || shared.read().unwrap())
+            })
+            .collect();
+        let total: usize = readers.into_iter().map(|r| r.join().unwrap().len()).sum();
+        assert_eq!(total, 10);
+        assert_eq!(shared.lag().unwrap(), 0);
+
+        shared.request_reopen().unwrap();
+        file.write_all(b"more").unwrap();
+        assert_eq!(shared.read().unwrap(), b"more");
+        assert_eq!(shared.checkpoint().offset, 14);
+    }
+}
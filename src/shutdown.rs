@@ -0,0 +1,73 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A token asking long-running loops such as [`crate::TailedFile::follow`]
+/// and [`crate::TailedFile::spawn`] to stop cleanly, set with
+/// [`crate::TailedFileBuilder::shutdown`].
+///
+/// Clones share the same token, so one handle kept by the application, e.g.
+/// in a signal handling thread, stops every file built with a clone of it.
+/// A loop notices the signal after its current read or wait, so within the
+/// interval it waits for changes.
+///
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+/// use staart::{ShutdownHandle, TailedFile};
+///
+/// let shutdown = ShutdownHandle::new();
+/// let mut f = TailedFile::builder("/var/log/syslog")
+///     .shutdown(shutdown.clone())
+///     .checkpoint_on_shutdown("/var/lib/app/syslog.pos")
+///     .build()
+///     .unwrap();
+/// std::thread::spawn(move || {
+///     std::thread::sleep(Duration::from_secs(60));
+///     shutdown.signal();
+/// });
+/// f.follow(std::io::stdout(), Duration::from_secs(1)).unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownHandle {
+    signaled: Arc<AtomicBool>,
+}
+
+impl ShutdownHandle {
+    /// Creates a handle that has not been signaled
+    pub fn new() -> ShutdownHandle {
+        ShutdownHandle::default()
+    }
+
+    /// Asks every loop holding a clone of the handle to stop
+    pub fn signal(&self) {
+        self.signaled.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns `true` once [`ShutdownHandle::signal`] was called on any clone
+    pub fn is_signaled(&self) -> bool {
+        self.signaled.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signal_clones() {
+        let handle = ShutdownHandle::new();
+        let clone = handle.clone();
+        assert!(!clone.is_signaled());
+        handle.signal();
+        assert!(clone.is_signaled());
+        assert!(!ShutdownHandle::new().is_signaled());
+    }
+}
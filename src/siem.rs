@@ -0,0 +1,424 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::path::Path;
+
+use crate::sink::Partials;
+use crate::{Line, Result, Sink, StaartError};
+
+/// Attributes set by the sink itself, which extracted pairs can not replace
+const RESERVED: [&str; 6] = ["filePath", "cn1", "cn1Label", "offset", "sev", "msg"];
+
+/// Security event formats written by a [`SiemSink`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SiemFormat {
+    /// ArcSight Common Event Format, version 0
+    #[default]
+    Cef,
+    /// IBM QRadar Log Event Extended Format, version 2.0, with tab-separated
+    /// attributes
+    Leef,
+}
+
+/// A [`Sink`] writing each line as a CEF or LEEF event, for SIEMs that only
+/// accept those formats.
+///
+/// The header names the vendor, product and version given to
+/// [`SiemSink::new`], and the event set by [`SiemSink::event`]. The line
+/// itself becomes the `msg` attribute and the path it was read from
+/// `filePath`. With CEF, the offset of the line, when known, is sent as the
+/// custom number `cn1` labelled `offset`, and with LEEF as `offset`.
+///
+/// Fields given to [`SiemSink::field`] are added to every event and, with
+/// [`SiemSink::extract`], so are the `key=value` pairs found in the line,
+/// such as those of logfmt records, with double quoted values read whole.
+/// Values are escaped as the format
+/// requires, so a line can not forge attributes of its own, and extracted
+/// pairs whose key is already set, by a field or by the sink itself, are
+/// dropped.
+///
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+/// use staart::{SiemFormat, SiemSink, TailedFile, UdpSink};
+///
+/// let sink = SiemSink::new(UdpSink::new("siem.example.com:514"), "Acme", "gateway", "2.1")
+///     .format(SiemFormat::Leef)
+///     .event("access", "Access log record")
+///     .severity(3)
+///     .field("dvchost", "gw01")
+///     .unwrap()
+///     .extract(true);
+/// let mut f = TailedFile::new("/var/log/gateway/access.log").unwrap();
+/// f.follow(sink, Duration::from_secs(1)).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct SiemSink<S: Sink> {
+    inner: S,
+    format: SiemFormat,
+    vendor: String,
+    product: String,
+    version: String,
+    event_id: String,
+    event_name: String,
+    severity: u8,
+    fields: Vec<(String, String)>,
+    extract: bool,
+    partials: Partials,
+}
+
+impl<S: Sink> SiemSink<S> {
+    /// Creates a sink writing CEF events from `product` by `vendor`, at
+    /// `version`, to `inner`
+    pub fn new(inner: S, vendor: &str, product: &str, version: &str) -> SiemSink<S> {
+        SiemSink {
+            inner,
+            format: SiemFormat::default(),
+            vendor: vendor.to_string(),
+            product: product.to_string(),
+            version: version.to_string(),
+            event_id: "record".to_string(),
+            event_name: "Log record".to_string(),
+            severity: 5,
+            fields: Vec::new(),
+            extract: false,
+            partials: Partials::default(),
+        }
+    }
+
+    /// Sets the event format, defaults to [`SiemFormat::Cef`]
+    pub fn format(mut self, format: SiemFormat) -> SiemSink<S> {
+        self.format = format;
+        self
+    }
+
+    /// Sets the event class `id`, and the `name` CEF gives it, defaults to
+    /// `record` and `Log record`
+    pub fn event(mut self, id: &str, name: &str) -> SiemSink<S> {
+        self.event_id = id.to_string();
+        self.event_name = name.to_string();
+        self
+    }
+
+    /// Sets the severity from 0 to 10, defaults to 5
+    pub fn severity(mut self, severity: u8) -> SiemSink<S> {
+        self.severity = severity.min(10);
+        self
+    }
+
+    /// Adds the attribute `key` with `value` to every event
+    ///
+    /// # Propagates Errors
+    /// - If `key` is not made of ASCII letters, digits and underscores
+    ///   starting with a letter, or is an attribute set by the sink itself
+    pub fn field(mut self, key: &str, value: &str) -> Result<SiemSink<S>> {
+        if !valid_key(key) || RESERVED.contains(&key) {
+            return Err(StaartError::Config(format!("invalid SIEM field: {key:?}")));
+        }
+        self.fields.push((key.to_string(), value.to_string()));
+        Ok(self)
+    }
+
+    /// Adds the `key=value` pairs found in each line to its event, defaults
+    /// to `false`
+    pub fn extract(mut self, extract: bool) -> SiemSink<S> {
+        self.extract = extract;
+        self
+    }
+
+    /// Returns the inner sink
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Formats the event for `line`, read at `offset` of `path`
+    fn event_for(&self, path: &Path, offset: Option<u64>, line: &str) -> String {
+        let mut attrs: Vec<(&str, String)> = self
+            .fields
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.clone()))
+            .collect();
+        if self.extract {
+            for (key, value) in pairs(line) {
+                if !RESERVED.contains(&key) && !attrs.iter().any(|(k, _)| *k == key) {
+                    attrs.push((key, value));
+                }
+            }
+        }
+        attrs.push(("filePath", path.display().to_string()));
+        match (self.format, offset) {
+            (SiemFormat::Cef, Some(offset)) => {
+                attrs.push(("cn1", offset.to_string()));
+                attrs.push(("cn1Label", "offset".to_string()));
+            }
+            (SiemFormat::Leef, Some(offset)) => attrs.push(("offset", offset.to_string())),
+            (_, None) => {}
+        }
+        if self.format == SiemFormat::Leef {
+            attrs.push(("sev", self.severity.to_string()));
+        }
+        attrs.push(("msg", line.to_string()));
+
+        match self.format {
+            SiemFormat::Cef => {
+                let header = [
+                    &self.vendor,
+                    &self.product,
+                    &self.version,
+                    &self.event_id,
+                    &self.event_name,
+                ]
+                .map(|field| cef_header(field))
+                .join("|");
+                let extension = attrs
+                    .iter()
+                    .map(|(key, value)| format!("{key}={}", cef_value(value)))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                format!("CEF:0|{header}|{}|{extension}", self.severity)
+            }
+            SiemFormat::Leef => {
+                let header = [&self.vendor, &self.product, &self.version, &self.event_id]
+                    .map(|field| leef_value(&field.replace('|', " ")))
+                    .join("|");
+                let attributes = attrs
+                    .iter()
+                    .map(|(key, value)| format!("{key}={}", leef_value(value)))
+                    .collect::<Vec<_>>()
+                    .join("\t");
+                format!("LEEF:2.0|{header}|x09|{attributes}")
+            }
+        }
+    }
+
+    fn send<'l, I>(&mut self, path: &Path, lines: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (Option<u64>, &'l str)>,
+    {
+        let mut data = String::new();
+        for (offset, line) in lines {
+            data.push_str(&self.event_for(path, offset, line));
+            data.push('\n');
+        }
+        if data.is_empty() {
+            return Ok(());
+        }
+        self.inner.write_chunk(path, data.as_bytes())
+    }
+}
+
+impl<S: Sink> Sink for SiemSink<S> {
+    fn write_chunk(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        let data = self.partials.complete(path, data);
+        let text = String::from_utf8_lossy(&data);
+        let lines = text
+            .lines()
+            .map(|line| line.strip_suffix('\r').unwrap_or(line))
+            .map(|line| (None, line));
+        self.send(path, lines)
+    }
+
+    fn write_lines(&mut self, path: &Path, lines: &[Line]) -> Result<()> {
+        let lines = lines
+            .iter()
+            .map(|line| (Some(line.offset), line.text.as_str()));
+        self.send(path, lines)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        for (path, line) in self.partials.take_all() {
+            self.write_chunk(&path, &line)?;
+        }
+        self.inner.finish()
+    }
+}
+
+/// Returns the `key=value` pairs of `line`. A value in double quotes may
+/// hold spaces and `\"` escapes. Pairs whose key is not [`valid_key`] are
+/// skipped.
+fn pairs(line: &str) -> Vec<(&str, String)> {
+    let mut pairs = Vec::new();
+    let mut rest = line.trim_start();
+    while !rest.is_empty() {
+        let end = rest
+            .find(|c: char| c.is_whitespace() || c == '=')
+            .unwrap_or(rest.len());
+        let key = &rest[..end];
+        let Some(after) = rest[end..].strip_prefix('=') else {
+            rest = rest[end..].trim_start();
+            continue;
+        };
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => unquote(quoted),
+            None => {
+                let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                (after[..end].to_string(), &after[end..])
+            }
+        };
+        if valid_key(key) {
+            pairs.push((key, value));
+        }
+        rest = after.trim_start();
+    }
+    pairs
+}
+
+/// Reads a double quoted value up to its closing quote, undoing `\"` and
+/// `\\` escapes, and returns it with the rest of the line
+fn unquote(s: &str) -> (String, &str) {
+    let mut value = String::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return (value, &s[i + 1..]),
+            '\\' => match chars.peek() {
+                Some(&(_, escaped @ ('"' | '\\'))) => {
+                    value.push(escaped);
+                    chars.next();
+                }
+                _ => value.push(c),
+            },
+            c => value.push(c),
+        }
+    }
+    (value, "")
+}
+
+/// Returns whether `key` is limited to the characters both formats accept
+/// in attribute names
+fn valid_key(key: &str) -> bool {
+    key.starts_with(|c: char| c.is_ascii_alphabetic())
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Escapes a CEF header field
+fn cef_header(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' | '|' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\r' | '\n' => out.push(' '),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Escapes a CEF extension value
+fn cef_value(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' | '=' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Replaces the tabs and line breaks delimiting LEEF attributes and events,
+/// which the format has no escape for
+fn leef_value(s: &str) -> String {
+    s.replace(['\t', '\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cef() {
+        let mut sink = SiemSink::new(Vec::new(), "Acme|Corp", "gateway", "2.1")
+            .severity(12)
+            .field("dvchost", "gw01")
+            .unwrap()
+            .extract(true);
+        let lines = [Line {
+            text: "user=bob action=\"login\" note=a\\b c=d=e".to_string(),
+            offset: 42,
+        }];
+        sink.write_lines(Path::new("/var/log/gw.log"), &lines)
+            .unwrap();
+        sink.write_chunk(Path::new("/var/log/gw.log"), b"plain\nrest")
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(sink.into_inner()).unwrap(),
+            "CEF:0|Acme\\|Corp|gateway|2.1|record|Log record|10|dvchost=gw01 user=bob \
+             action=login note=a\\\\b c=d\\=e filePath=/var/log/gw.log cn1=42 \
+             cn1Label=offset msg=user\\=bob action\\=\"login\" note\\=a\\\\b c\\=d\\=e\n\
+             CEF:0|Acme\\|Corp|gateway|2.1|record|Log record|10|dvchost=gw01 \
+             filePath=/var/log/gw.log msg=plain\n"
+        );
+    }
+
+    #[test]
+    fn test_leef() {
+        let mut sink = SiemSink::new(Vec::new(), "Acme", "gateway", "2.1")
+            .format(SiemFormat::Leef)
+            .event("access", "ignored");
+        let lines = [Line {
+            text: "GET /\tindex".to_string(),
+            offset: 7,
+        }];
+        sink.write_lines(Path::new("access.log"), &lines).unwrap();
+        assert_eq!(
+            String::from_utf8(sink.into_inner()).unwrap(),
+            "LEEF:2.0|Acme|gateway|2.1|access|x09|filePath=access.log\toffset=7\tsev=5\t\
+             msg=GET / index\n"
+        );
+    }
+
+    #[test]
+    fn test_reserved_keys() {
+        let mut sink = SiemSink::new(Vec::new(), "Acme", "gateway", "2.1")
+            .field("dvchost", "gw01")
+            .unwrap()
+            .extract(true);
+        sink.write_chunk(
+            Path::new("gw.log"),
+            b"msg=forged filePath=/etc cn1=0 dvchost=evil user=bob user=eve\n",
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(sink.into_inner()).unwrap(),
+            "CEF:0|Acme|gateway|2.1|record|Log record|5|dvchost=gw01 user=bob filePath=gw.log \
+             msg=msg\\=forged filePath\\=/etc cn1\\=0 dvchost\\=evil user\\=bob user\\=eve\n"
+        );
+
+        let sink = || SiemSink::new(Vec::new(), "Acme", "gateway", "2.1");
+        for key in ["msg", "filePath", "a b", "a=b", "1a", ""] {
+            assert!(sink().field(key, "x").is_err());
+        }
+    }
+
+    #[test]
+    fn test_quoted_values() {
+        assert_eq!(
+            pairs(r#"text="a b" who="x \"y\"" path=C:\dir bare ok=1 tail="open"#),
+            [
+                ("text", "a b".to_string()),
+                ("who", "x \"y\"".to_string()),
+                ("path", "C:\\dir".to_string()),
+                ("ok", "1".to_string()),
+                ("tail", "open".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,350 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Stdout, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{Line, Result};
+
+/// A destination for data read from followed files
+pub trait Sink {
+    /// Writes `data`, read from the file at `path`
+    fn write_chunk(&mut self, path: &Path, data: &[u8]) -> Result<()>;
+
+    /// Writes complete `lines`, read from the file at `path`. Sinks storing
+    /// records override this to keep the offset of each line, by default each
+    /// line is written as a chunk ending in a newline.
+    fn write_lines(&mut self, path: &Path, lines: &[Line]) -> Result<()> {
+        for line in lines {
+            let mut data = Vec::with_capacity(line.text.len() + 1);
+            data.extend_from_slice(line.text.as_bytes());
+            data.push(b'\n');
+            self.write_chunk(path, &data)?;
+        }
+
+        Ok(())
+    }
+
+    /// Flushes any data buffered by the sink
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Writes out anything held back, such as a partial last line, then
+    /// flushes the sink. Called once when following stops, by default only
+    /// flushes.
+    fn finish(&mut self) -> Result<()> {
+        self.flush()
+    }
+}
+
+impl<S: Sink + ?Sized> Sink for &mut S {
+    fn write_chunk(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        (**self).write_chunk(path, data)
+    }
+
+    fn write_lines(&mut self, path: &Path, lines: &[Line]) -> Result<()> {
+        (**self).write_lines(path, lines)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        (**self).flush()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        (**self).finish()
+    }
+}
+
+impl<S: Sink + ?Sized> Sink for Box<S> {
+    fn write_chunk(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        (**self).write_chunk(path, data)
+    }
+
+    fn write_lines(&mut self, path: &Path, lines: &[Line]) -> Result<()> {
+        (**self).write_lines(path, lines)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        (**self).flush()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        (**self).finish()
+    }
+}
+
+/// Collects every chunk in memory
+impl Sink for Vec<u8> {
+    fn write_chunk(&mut self, _path: &Path, data: &[u8]) -> Result<()> {
+        self.extend_from_slice(data);
+        Ok(())
+    }
+}
+
+/// Writes every chunk to standard output
+impl Sink for Stdout {
+    fn write_chunk(&mut self, _path: &Path, data: &[u8]) -> Result<()> {
+        Ok(self.lock().write_all(data)?)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(Write::flush(self)?)
+    }
+}
+
+/// Writes every chunk to the file, at its cursor or end if opened for
+/// appending
+impl Sink for File {
+    fn write_chunk(&mut self, _path: &Path, data: &[u8]) -> Result<()> {
+        Ok(self.write_all(data)?)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(Write::flush(self)?)
+    }
+}
+
+/// Longest partial line held back for a path, a longer one is passed on as a
+/// line of its own
+pub(crate) const MAX_PARTIAL: usize = 1024 * 1024;
+
+/// The partial line ending the data last written for each path to a sink
+/// passing on whole lines, held back until the rest of the line arrives
+#[derive(Debug, Default)]
+pub(crate) struct Partials {
+    held: HashMap<PathBuf, Vec<u8>>,
+}
+
+impl Partials {
+    /// Returns the complete lines of what was held back for `path` followed
+    /// by `data`, holding back the partial line at the end
+    pub(crate) fn complete<'d>(&mut self, path: &Path, data: &'d [u8]) -> Cow<'d, [u8]> {
+        let end = match data.iter().rposition(|b| *b == b'\n') {
+            Some(i) => i + 1,
+            None => 0,
+        };
+        let (complete, rest) = data.split_at(end);
+        let mut held = self.held.remove(path).unwrap_or_default();
+        let (mut lines, mut partial) = if end == 0 {
+            held.extend_from_slice(rest);
+            (Cow::Borrowed(&[][..]), held)
+        } else if held.is_empty() {
+            (Cow::Borrowed(complete), rest.to_vec())
+        } else {
+            held.extend_from_slice(complete);
+            (Cow::Owned(held), rest.to_vec())
+        };
+        if partial.len() > MAX_PARTIAL {
+            partial.push(b'\n');
+            lines.to_mut().append(&mut partial);
+        }
+        if !partial.is_empty() {
+            self.held.insert(path.to_path_buf(), partial);
+        }
+
+        lines
+    }
+
+    /// Takes the partial lines held back for every path, each ended by a
+    /// newline
+    pub(crate) fn take_all(&mut self) -> Vec<(PathBuf, Vec<u8>)> {
+        self.held
+            .drain()
+            .map(|(path, mut line)| {
+                line.push(b'\n');
+                (path, line)
+            })
+            .collect()
+    }
+}
+
+/// How a [`FanOut`] handles an error from one of its sinks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FanOutPolicy {
+    /// Return the error immediately, skipping the remaining sinks
+    #[default]
+    FailFast,
+    /// Count the error and carry on with the remaining sinks
+    BestEffort,
+}
+
+/// A [`Sink`] that duplicates every chunk to several sinks, in the order
+/// they were added.
+///
+/// # Example
+/// ```no_run
+/// use staart::{FanOut, FanOutPolicy, Sink};
+/// # fn sinks() -> (Box<dyn Sink>, Box<dyn Sink>) { unimplemented!() }
+///
+/// let (archive, network) = sinks();
+/// let mut tee = FanOut::new()
+///     .with(archive, FanOutPolicy::FailFast)
+///     .with(network, FanOutPolicy::BestEffort);
+/// ```
+#[doc(alias = "Tee")]
+#[derive(Default)]
+pub struct FanOut<'a> {
+    sinks: Vec<Target<'a>>,
+}
+
+struct Target<'a> {
+    sink: Box<dyn Sink + 'a>,
+    policy: FanOutPolicy,
+    failures: u64,
+}
+
+impl<'a> FanOut<'a> {
+    /// Creates a [`FanOut`] without any sinks
+    pub fn new() -> FanOut<'a> {
+        FanOut { sinks: Vec::new() }
+    }
+
+    /// Adds `sink`, whose errors are handled according to `policy`
+    pub fn with<S: Sink + 'a>(mut self, sink: S, policy: FanOutPolicy) -> FanOut<'a> {
+        self.push(sink, policy);
+        self
+    }
+
+    /// Adds `sink`, whose errors are handled according to `policy`
+    pub fn push<S: Sink + 'a>(&mut self, sink: S, policy: FanOutPolicy) {
+        self.sinks.push(Target {
+            sink: Box::new(sink),
+            policy,
+            failures: 0,
+        });
+    }
+
+    /// Returns the number of sinks
+    pub fn len(&self) -> usize {
+        self.sinks.len()
+    }
+
+    /// Returns `true` if no sinks have been added
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    /// Returns the number of errors ignored from the sink at `index` under
+    /// [`FanOutPolicy::BestEffort`]
+    pub fn failures(&self, index: usize) -> u64 {
+        self.sinks.get(index).map_or(0, |target| target.failures)
+    }
+
+    fn each<F>(&mut self, mut f: F) -> Result<()>
+    where
+        F: FnMut(&mut dyn Sink) -> Result<()>,
+    {
+        for target in self.sinks.iter_mut() {
+            if let Err(e) = f(&mut target.sink) {
+                match target.policy {
+                    FanOutPolicy::FailFast => return Err(e),
+                    FanOutPolicy::BestEffort => target.failures += 1,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Sink for FanOut<'_> {
+    fn write_chunk(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        self.each(|sink| sink.write_chunk(path, data))
+    }
+
+    fn write_lines(&mut self, path: &Path, lines: &[Line]) -> Result<()> {
+        self.each(|sink| sink.write_lines(path, lines))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.each(|sink| sink.flush())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.each(|sink| sink.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StaartError;
+
+    struct Recorder(Vec<u8>);
+
+    impl Sink for Recorder {
+        fn write_chunk(&mut self, _path: &Path, data: &[u8]) -> Result<()> {
+            self.0.extend_from_slice(data);
+            Ok(())
+        }
+    }
+
+    struct Broken;
+
+    impl Sink for Broken {
+        fn write_chunk(&mut self, _path: &Path, _data: &[u8]) -> Result<()> {
+            Err(StaartError::Config("broken".to_string()))
+        }
+    }
+
+    #[test]
+    fn test_best_effort() {
+        let mut first = Recorder(Vec::new());
+        let mut second = Recorder(Vec::new());
+        let mut tee = FanOut::new()
+            .with(&mut first, FanOutPolicy::BestEffort)
+            .with(Broken, FanOutPolicy::BestEffort)
+            .with(&mut second, FanOutPolicy::FailFast);
+
+        tee.write_chunk(Path::new("a.log"), b"data").unwrap();
+        tee.write_chunk(Path::new("a.log"), b"more").unwrap();
+        assert_eq!(tee.failures(1), 2);
+        assert_eq!(tee.failures(0), 0);
+        drop(tee);
+
+        assert_eq!(first.0, b"datamore");
+        assert_eq!(second.0, b"datamore");
+    }
+
+    #[test]
+    fn test_partials() {
+        let mut partials = Partials::default();
+        let (a, b) = (Path::new("a.log"), Path::new("b.log"));
+        assert_eq!(partials.complete(a, b"one\ntw"), &b"one\n"[..]);
+        assert_eq!(partials.complete(b, b"other"), &b""[..]);
+        assert_eq!(partials.complete(a, b"o"), &b""[..]);
+        assert_eq!(partials.complete(a, b"\nthree\n"), &b"two\nthree\n"[..]);
+        assert_eq!(partials.complete(b, b"\n"), &b"other\n"[..]);
+        assert_eq!(partials.complete(a, b"last"), &b""[..]);
+        assert_eq!(partials.take_all(), [(a.to_path_buf(), b"last\n".to_vec())]);
+        assert!(partials.take_all().is_empty());
+
+        // A partial line is not held beyond the limit
+        let long = vec![b'x'; MAX_PARTIAL + 1];
+        assert_eq!(partials.complete(a, &long).len(), MAX_PARTIAL + 2);
+        assert!(partials.take_all().is_empty());
+    }
+
+    #[test]
+    fn test_fail_fast() {
+        let mut after = Recorder(Vec::new());
+        let mut tee = FanOut::new()
+            .with(Broken, FanOutPolicy::FailFast)
+            .with(&mut after, FanOutPolicy::FailFast);
+
+        assert!(tee.write_chunk(Path::new("a.log"), b"data").is_err());
+        assert_eq!(tee.failures(0), 0);
+        drop(tee);
+
+        assert!(after.0.is_empty());
+    }
+}
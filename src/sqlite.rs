@@ -0,0 +1,249 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+use rusqlite::{params, Connection};
+
+use crate::sink::Partials;
+use crate::{clock, Line, LinesStr, Result, Sink, StaartError};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS records (
+        id INTEGER PRIMARY KEY,
+        path TEXT NOT NULL,
+        ts REAL NOT NULL,
+        \"offset\" INTEGER,
+        line TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS records_ts ON records (ts);
+";
+
+/// A [`Sink`] storing lines as records in a local SQLite database, enabled by
+/// the `sqlite` feature.
+///
+/// Each line becomes a row of the `records` table with the path it was read
+/// from, the time it was stored in seconds since the Unix epoch, its offset
+/// in the file and its text. The offset is only known for lines written
+/// through [`Sink::write_lines`], and is `NULL` for lines split from chunks.
+/// A partial line ending a chunk is held back until the rest of it is
+/// written.
+///
+/// The database uses write-ahead logging, so it can be queried while lines
+/// are stored. Rows beyond the configured retention are pruned on every
+/// [`Sink::flush`].
+///
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+/// use staart::{Sink, SqliteSink, TailedFile};
+///
+/// let mut sink = SqliteSink::open("/var/lib/app/logs.db")
+///     .unwrap()
+///     .retention(Duration::from_secs(7 * 24 * 3600));
+/// let mut f = TailedFile::new("/var/log/syslog").unwrap();
+/// for batch in f.batches(500, Duration::from_secs(1)) {
+///     sink.write_lines(std::path::Path::new("/var/log/syslog"), &batch.unwrap())
+///         .unwrap();
+///     sink.flush().unwrap();
+/// }
+/// ```
+pub struct SqliteSink {
+    conn: Connection,
+    retention: Option<Duration>,
+    max_rows: Option<u64>,
+    partials: Partials,
+}
+
+fn sql_error(e: rusqlite::Error) -> StaartError {
+    StaartError::Sink(e.to_string())
+}
+
+impl SqliteSink {
+    /// Opens or creates the database at `path`
+    ///
+    /// # Propagates Errors
+    /// - If the database can not be opened or its schema created
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<SqliteSink> {
+        let conn = Connection::open(path).map_err(sql_error)?;
+        conn.pragma_update(None, "journal_mode", "WAL")
+            .map_err(sql_error)?;
+        conn.execute_batch(SCHEMA).map_err(sql_error)?;
+
+        Ok(SqliteSink {
+            conn,
+            retention: None,
+            max_rows: None,
+            partials: Partials::default(),
+        })
+    }
+
+    /// Prunes records stored longer than `age` ago
+    pub fn retention(mut self, age: Duration) -> SqliteSink {
+        self.retention = Some(age);
+        self
+    }
+
+    /// Prunes the oldest records beyond the most recent `rows`
+    pub fn max_rows(mut self, rows: u64) -> SqliteSink {
+        self.max_rows = Some(rows);
+        self
+    }
+
+    /// Returns the connection to the database, e.g. for queries
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// Deletes the records beyond the retention, returning how many were
+    /// deleted
+    ///
+    /// # Propagates Errors
+    /// - If the records can not be deleted
+    pub fn prune(&mut self) -> Result<u64> {
+        let mut deleted = 0;
+        if let Some(age) = self.retention {
+            let cutoff = now() - age.as_secs_f64();
+            deleted += self
+                .conn
+                .execute("DELETE FROM records WHERE ts < ?1", params![cutoff])
+                .map_err(sql_error)?;
+        }
+        if let Some(rows) = self.max_rows {
+            let rows = i64::try_from(rows).unwrap_or(i64::MAX);
+            deleted += self
+                .conn
+                .execute(
+                    "DELETE FROM records WHERE id <= (SELECT max(id) FROM records) - ?1",
+                    params![rows],
+                )
+                .map_err(sql_error)?;
+        }
+
+        Ok(deleted as u64)
+    }
+
+    fn insert<'l, I>(&mut self, path: &Path, lines: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (Option<u64>, &'l str)>,
+    {
+        let path = path.to_string_lossy();
+        let ts = now();
+        let tx = self.conn.transaction().map_err(sql_error)?;
+        {
+            let mut stmt = tx
+                .prepare_cached(
+                    "INSERT INTO records (path, ts, \"offset\", line) VALUES (?1, ?2, ?3, ?4)",
+                )
+                .map_err(sql_error)?;
+            for (offset, line) in lines {
+                let offset = offset.map(i64::try_from).transpose()?;
+                stmt.execute(params![path, ts, offset, line])
+                    .map_err(sql_error)?;
+            }
+        }
+
+        tx.commit().map_err(sql_error)
+    }
+}
+
+/// Seconds since the Unix epoch
+fn now() -> f64 {
+    clock::system_now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |d| d.as_secs_f64())
+}
+
+impl Sink for SqliteSink {
+    fn write_chunk(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        let data = self.partials.complete(path, data);
+        let lines: Vec<_> = LinesStr::new(&data).collect();
+        self.insert(path, lines.iter().map(|line| (None, line.as_ref())))
+    }
+
+    fn write_lines(&mut self, path: &Path, lines: &[Line]) -> Result<()> {
+        self.insert(
+            path,
+            lines
+                .iter()
+                .map(|line| (Some(line.offset), line.text.as_str())),
+        )
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.prune()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        for (path, line) in self.partials.take_all() {
+            self.write_chunk(&path, &line)?;
+        }
+        self.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sqlite_sink() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sink = SqliteSink::open(dir.path().join("logs.db"))
+            .unwrap()
+            .max_rows(3);
+        let mode: String = sink
+            .connection()
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(mode, "wal");
+
+        let path = Path::new("/var/log/app.log");
+        let lines = [
+            Line {
+                text: "one".to_string(),
+                offset: 0,
+            },
+            Line {
+                text: "two".to_string(),
+                offset: 4,
+            },
+        ];
+        sink.write_lines(path, &lines).unwrap();
+        sink.write_chunk(path, b"three\nfo").unwrap();
+        sink.write_chunk(path, b"ur\n").unwrap();
+
+        let count = |sink: &SqliteSink| -> i64 {
+            sink.connection()
+                .query_row("SELECT count(*) FROM records", [], |row| row.get(0))
+                .unwrap()
+        };
+        assert_eq!(count(&sink), 4);
+        let offset: Option<i64> = sink
+            .connection()
+            .query_row(
+                "SELECT \"offset\" FROM records WHERE line = 'two'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(offset, Some(4));
+
+        sink.flush().unwrap();
+        assert_eq!(count(&sink), 3);
+        let oldest: String = sink
+            .connection()
+            .query_row("SELECT line FROM records ORDER BY id LIMIT 1", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(oldest, "two");
+    }
+}
@@ -0,0 +1,49 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::fmt;
+
+/// The lifecycle state of a [`crate::TailedFile`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TailState {
+    /// The file has been opened but not yet read
+    Opening,
+    /// The file is being read and all available data has been consumed
+    Following,
+    /// The path could not be opened on the last read, and is expected to reappear
+    WaitingForFile,
+    /// The last read filled the buffer, so more data is already available
+    Draining,
+    /// The handle of the file was closed after a period of inactivity, and is
+    /// reopened once the file changes
+    Closed,
+    /// Deliveries were paused by the caller, see [`crate::TailedFile::pause`]
+    Paused,
+    /// Following was stopped by the caller and no more data will be read
+    Stopped,
+    /// The file exhausted its [`crate::ErrorBudget`] and no more data will be
+    /// read until [`crate::TailedFile::enable`] is called
+    Disabled,
+}
+
+impl fmt::Display for TailState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            TailState::Opening => "opening",
+            TailState::Following => "following",
+            TailState::WaitingForFile => "waiting for file",
+            TailState::Draining => "draining",
+            TailState::Closed => "closed",
+            TailState::Paused => "paused",
+            TailState::Stopped => "stopped",
+            TailState::Disabled => "disabled",
+        };
+        write!(f, "{s}")
+    }
+}
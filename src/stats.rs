@@ -0,0 +1,44 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::time::SystemTime;
+
+/// Counters of a followed file, as returned by [`crate::TailedFile::stats`],
+/// or totals over the files of a [`crate::MultiTailer`], for wiring into a
+/// metrics system
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TailStats {
+    /// Bytes returned by reads
+    pub bytes_read: u64,
+    /// Complete records split by the line-based reads, such as
+    /// [`crate::TailedFile::lines_str`], including those later dropped by the
+    /// filter or pipeline
+    pub records: u64,
+    /// Times the file at the path was found replaced
+    pub rotations: u64,
+    /// Times the file was found shorter than the read position
+    pub truncations: u64,
+    /// Attempts to open the file again at its path, after a rotation or while
+    /// it was missing, that failed
+    pub reopen_failures: u64,
+    /// When data was last read
+    pub last_data: Option<SystemTime>,
+}
+
+impl TailStats {
+    /// Adds the counters of `other`, keeping the latest time data was read
+    pub(crate) fn merge(mut self, other: TailStats) -> TailStats {
+        self.bytes_read += other.bytes_read;
+        self.records += other.records;
+        self.rotations += other.rotations;
+        self.truncations += other.truncations;
+        self.reopen_failures += other.reopen_failures;
+        self.last_data = self.last_data.max(other.last_data);
+        self
+    }
+}
@@ -0,0 +1,109 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::Result;
+
+/// Caches the resolved targets of symlinked paths.
+///
+/// Log directories managed by container runtimes hold thousands of symlinks
+/// to the real log files. Resolving every one of them on each scan is costly,
+/// so resolutions are kept until a change in the directory holding the link
+/// is reported through [`SymlinkCache::invalidate_dir`], or the link itself
+/// through [`SymlinkCache::invalidate`].
+///
+/// # Example
+/// ```no_run
+/// use staart::SymlinkCache;
+///
+/// let mut cache = SymlinkCache::new();
+/// let target = cache.resolve("/var/log/containers/app.log").unwrap();
+/// // ... the directory watcher reports a change
+/// cache.invalidate_dir("/var/log/containers");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SymlinkCache {
+    targets: HashMap<PathBuf, PathBuf>,
+}
+
+impl SymlinkCache {
+    /// Creates an empty cache
+    pub fn new() -> SymlinkCache {
+        SymlinkCache::default()
+    }
+
+    /// Returns the canonical path `path` resolves to, resolving it only if it
+    /// is not already cached
+    ///
+    /// # Propagates Errors
+    /// - If `path`, or the target of a link along it, does not exist
+    pub fn resolve<P: AsRef<Path>>(&mut self, path: P) -> Result<PathBuf> {
+        let path = path.as_ref();
+        if let Some(target) = self.targets.get(path) {
+            return Ok(target.clone());
+        }
+
+        let target = std::fs::canonicalize(path)?;
+        self.targets.insert(path.to_path_buf(), target.clone());
+        Ok(target)
+    }
+
+    /// Forgets the resolution of `path`
+    pub fn invalidate<P: AsRef<Path>>(&mut self, path: P) {
+        self.targets.remove(path.as_ref());
+    }
+
+    /// Forgets the resolution of every path within `dir`, or resolving to a
+    /// target within it
+    pub fn invalidate_dir<P: AsRef<Path>>(&mut self, dir: P) {
+        let dir = dir.as_ref();
+        self.targets
+            .retain(|path, target| !path.starts_with(dir) && !target.starts_with(dir));
+    }
+
+    /// Returns the number of cached resolutions
+    pub fn len(&self) -> usize {
+        self.targets.len()
+    }
+
+    /// Returns `true` if nothing is cached
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn test_symlink_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = std::fs::canonicalize(dir.path()).unwrap();
+        let links = dir.join("links");
+        std::fs::create_dir(&links).unwrap();
+        std::fs::write(dir.join("a.log"), b"a").unwrap();
+        std::fs::write(dir.join("b.log"), b"b").unwrap();
+        let link = links.join("app.log");
+        symlink(dir.join("a.log"), &link).unwrap();
+
+        let mut cache = SymlinkCache::new();
+        assert_eq!(cache.resolve(&link).unwrap(), dir.join("a.log"));
+
+        std::fs::remove_file(&link).unwrap();
+        symlink(dir.join("b.log"), &link).unwrap();
+        assert_eq!(cache.resolve(&link).unwrap(), dir.join("a.log"));
+
+        cache.invalidate_dir(&links);
+        assert!(cache.is_empty());
+        assert_eq!(cache.resolve(&link).unwrap(), dir.join("b.log"));
+    }
+}
@@ -0,0 +1,238 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Utilities for testing code built on staart, enabled by the `testing`
+//! feature.
+//!
+//! [`ScriptedLog`] plays the part of a log writer and of logrotate in a
+//! temporary directory, and [`TestClock`] moves time forward without
+//! sleeping, so that inactivity timeouts and error budget windows can be
+//! exercised deterministically.
+//!
+//! # Example
+//! ```
+//! use std::time::Duration;
+//! use staart::testing::{ScriptedLog, TestClock};
+//! use staart::{TailState, TailedFile};
+//!
+//! let mut log = ScriptedLog::new("app.log").unwrap();
+//! let clock = TestClock::new();
+//! let mut f = TailedFile::builder(log.path().to_path_buf())
+//!     .close_inactive(Duration::from_secs(60))
+//!     .build()
+//!     .unwrap();
+//!
+//! log.append(b"before\n").unwrap();
+//! log.rotate().unwrap();
+//! log.append(b"after\n").unwrap();
+//! assert_eq!(f.read().unwrap(), b"before\n");
+//! assert_eq!(f.read().unwrap(), b"after\n");
+//!
+//! clock.advance(Duration::from_secs(61));
+//! f.read().unwrap();
+//! assert_eq!(f.state(), TailState::Closed);
+//! ```
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use tempfile::TempDir;
+
+use crate::{clock, Result};
+
+/// Moves time forward for the followed files on the current thread.
+///
+/// Only the current thread is affected, so tests running in parallel do not
+/// disturb each other. Time returns to normal when the clock is dropped.
+#[derive(Debug)]
+pub struct TestClock {
+    _private: (),
+}
+
+impl TestClock {
+    /// Creates a clock that has not moved forward
+    pub fn new() -> TestClock {
+        clock::set_offset(Duration::ZERO);
+        TestClock { _private: () }
+    }
+
+    /// Moves time forward by `by`
+    pub fn advance(&self, by: Duration) {
+        clock::set_offset(clock::offset() + by);
+    }
+
+    /// Returns the current instant as seen by the followed files
+    pub fn now(&self) -> Instant {
+        clock::now()
+    }
+
+    /// Returns the current system time as seen by the followed files
+    pub fn system_now(&self) -> SystemTime {
+        clock::system_now()
+    }
+}
+
+impl Default for TestClock {
+    fn default() -> TestClock {
+        TestClock::new()
+    }
+}
+
+impl Drop for TestClock {
+    fn drop(&mut self) {
+        clock::set_offset(Duration::ZERO);
+    }
+}
+
+/// A log file in a temporary directory, written and rotated by a script of
+/// calls. The directory is removed when the log is dropped.
+#[derive(Debug)]
+pub struct ScriptedLog {
+    dir: TempDir,
+    path: PathBuf,
+    rotations: usize,
+}
+
+impl ScriptedLog {
+    /// Creates an empty log file named `name` in a new temporary directory
+    ///
+    /// # Propagates Errors
+    /// - If the directory or file can not be created
+    pub fn new(name: &str) -> Result<ScriptedLog> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(name);
+        File::create(&path)?;
+
+        Ok(ScriptedLog {
+            dir,
+            path,
+            rotations: 0,
+        })
+    }
+
+    /// Returns the path of the log
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the directory holding the log and its rotated copies
+    pub fn dir(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Appends `data` to the log, creating it if it was removed
+    ///
+    /// # Propagates Errors
+    /// - If the log can not be written
+    pub fn append(&self, data: &[u8]) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(data)?;
+
+        Ok(())
+    }
+
+    /// Renames the log to `<name>.<n>` and creates an empty log in its place,
+    /// as logrotate does by default. Returns the path of the rotated log.
+    ///
+    /// # Propagates Errors
+    /// - If the log can not be renamed or created
+    pub fn rotate(&mut self) -> Result<PathBuf> {
+        let rotated = self.next_rotation();
+        std::fs::rename(&self.path, &rotated)?;
+        File::create(&self.path)?;
+
+        Ok(rotated)
+    }
+
+    /// Copies the log to `<name>.<n>` and truncates it in place, as logrotate
+    /// does with `copytruncate`. Returns the path of the copy.
+    ///
+    /// # Propagates Errors
+    /// - If the log can not be copied or truncated
+    pub fn copy_truncate(&mut self) -> Result<PathBuf> {
+        let rotated = self.next_rotation();
+        std::fs::copy(&self.path, &rotated)?;
+        self.truncate()?;
+
+        Ok(rotated)
+    }
+
+    /// Truncates the log to zero length in place
+    ///
+    /// # Propagates Errors
+    /// - If the log can not be truncated
+    pub fn truncate(&self) -> Result<()> {
+        OpenOptions::new()
+            .write(true)
+            .open(&self.path)?
+            .set_len(0)?;
+
+        Ok(())
+    }
+
+    /// Removes the log
+    ///
+    /// # Propagates Errors
+    /// - If the log can not be removed
+    pub fn remove(&self) -> Result<()> {
+        std::fs::remove_file(&self.path)?;
+
+        Ok(())
+    }
+
+    fn next_rotation(&mut self) -> PathBuf {
+        self.rotations += 1;
+        let mut rotated = self.path.as_os_str().to_os_string();
+        rotated.push(format!(".{}", self.rotations));
+        PathBuf::from(rotated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ErrorBudget, ErrorPolicy, TailState, TailedFile};
+
+    #[test]
+    fn test_scripted_copy_truncate() {
+        let mut log = ScriptedLog::new("app.log").unwrap();
+        let mut f = TailedFile::new(log.path().to_path_buf()).unwrap();
+
+        log.append(b"first\n").unwrap();
+        assert_eq!(f.read().unwrap(), b"first\n");
+        let copy = log.copy_truncate().unwrap();
+        assert_eq!(std::fs::read(copy).unwrap(), b"first\n");
+        assert!(f.read().unwrap().is_empty());
+        log.append(b"second\n").unwrap();
+        assert_eq!(f.read().unwrap(), b"second\n");
+    }
+
+    #[test]
+    fn test_clock_budget_window() {
+        let log = ScriptedLog::new("app.log").unwrap();
+        let clock = TestClock::new();
+        let mut f = TailedFile::builder(log.path().to_path_buf())
+            .error_policy(ErrorPolicy::Propagate)
+            .error_budget(ErrorBudget::new(1).window(Duration::from_secs(10)))
+            .build()
+            .unwrap();
+
+        log.remove().unwrap();
+        assert!(f.read().is_err());
+        clock.advance(Duration::from_secs(11));
+        assert!(f.read().is_err());
+        assert_eq!(f.state(), TailState::WaitingForFile);
+        assert!(f.read().is_err());
+        assert_eq!(f.state(), TailState::Disabled);
+    }
+}
@@ -0,0 +1,220 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::collections::VecDeque;
+use std::fmt;
+#[cfg(feature = "log")]
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::{clock, Action, Branch, StaartError, Timestamp};
+
+/// Default number of entries kept in a file's timeline
+pub const DEFAULT_TIMELINE_CAPACITY: usize = 32;
+
+/// A lifecycle event recorded in a file's timeline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lifecycle {
+    /// The file was opened for following
+    Opened,
+    /// The file was rotated or truncated, naming the branch of the
+    /// [`crate::DecisionTable`] that fired and the action taken
+    Changed(Branch, Action),
+    /// Content before the read position changed in a file verified as append-only
+    Modified(u64),
+    /// The given number of unread bytes were truncated away before they
+    /// could be read
+    Lost(u64),
+    /// The file could not be found at the path
+    Missing,
+    /// The file was found again after being missing, or reopened after being
+    /// closed while inactive
+    Reopened,
+    /// A read failed with the given error
+    Error(String),
+    /// Following was stopped by the caller
+    Stopped,
+    /// The handle of the file was closed because no data arrived within the
+    /// inactivity limit
+    Inactive,
+    /// The file exhausted its [`crate::ErrorBudget`]
+    Disabled,
+    /// The file was re-enabled by the caller after being disabled
+    Enabled,
+    /// The file was detected as containing binary content
+    Binary,
+    /// Processing the file panicked with the given message
+    Panicked(String),
+}
+
+impl fmt::Display for Lifecycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Lifecycle::Opened => write!(f, "opened"),
+            Lifecycle::Changed(branch, action) => write!(f, "{branch}: {action}"),
+            Lifecycle::Modified(offset) => write!(f, "modified at offset {offset}"),
+            Lifecycle::Lost(bytes) => write!(f, "lost {bytes} unread bytes to truncation"),
+            Lifecycle::Missing => write!(f, "missing"),
+            Lifecycle::Reopened => write!(f, "reopened"),
+            Lifecycle::Error(e) => write!(f, "error: {e}"),
+            Lifecycle::Stopped => write!(f, "stopped"),
+            Lifecycle::Inactive => write!(f, "closed after inactivity"),
+            Lifecycle::Disabled => write!(f, "disabled"),
+            Lifecycle::Enabled => write!(f, "enabled"),
+            Lifecycle::Binary => write!(f, "binary content detected"),
+            Lifecycle::Panicked(msg) => write!(f, "panicked: {msg}"),
+        }
+    }
+}
+
+#[cfg(feature = "log")]
+impl Lifecycle {
+    /// Returns the level the event is logged at
+    fn level(&self) -> log::Level {
+        match self {
+            Lifecycle::Error(_) | Lifecycle::Panicked(_) => log::Level::Error,
+            Lifecycle::Modified(_) | Lifecycle::Lost(_) | Lifecycle::Disabled => log::Level::Warn,
+            Lifecycle::Changed(..)
+            | Lifecycle::Missing
+            | Lifecycle::Reopened
+            | Lifecycle::Inactive
+            | Lifecycle::Binary => log::Level::Info,
+            Lifecycle::Opened | Lifecycle::Stopped | Lifecycle::Enabled => log::Level::Debug,
+        }
+    }
+}
+
+/// A [`Lifecycle`] event and the time it was recorded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimelineEntry {
+    /// When the event was recorded
+    pub at: SystemTime,
+    /// What happened
+    pub event: Lifecycle,
+}
+
+impl fmt::Display for TimelineEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", Timestamp::from(self.at), self.event)
+    }
+}
+
+/// A ring buffer of the most recent lifecycle events of a file
+#[derive(Debug, Clone)]
+pub(crate) struct Timeline {
+    entries: VecDeque<TimelineEntry>,
+    capacity: usize,
+    #[cfg(feature = "log")]
+    path: PathBuf,
+}
+
+impl Timeline {
+    pub(crate) fn new(capacity: usize) -> Timeline {
+        Timeline {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+            #[cfg(feature = "log")]
+            path: PathBuf::new(),
+        }
+    }
+
+    /// Names the file in the log records of its events
+    #[cfg(feature = "log")]
+    pub(crate) fn set_path(&mut self, path: &Path) {
+        self.path = path.to_path_buf();
+    }
+
+    pub(crate) fn record(&mut self, event: Lifecycle) {
+        #[cfg(feature = "log")]
+        log::log!(event.level(), "{}: {event}", self.path.display());
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(TimelineEntry {
+            at: clock::system_now(),
+            event,
+        });
+    }
+
+    pub(crate) fn record_error(&mut self, err: &StaartError) {
+        let msg = match err {
+            StaartError::IO(e) => e.to_string(),
+            e => e.to_string(),
+        };
+        self.record(Lifecycle::Error(msg));
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &TimelineEntry> {
+        self.entries.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring() {
+        let mut timeline = Timeline::new(2);
+        timeline.record(Lifecycle::Opened);
+        timeline.record(Lifecycle::Missing);
+        timeline.record(Lifecycle::Reopened);
+
+        let events: Vec<_> = timeline.iter().map(|e| e.event.clone()).collect();
+        assert_eq!(events, vec![Lifecycle::Missing, Lifecycle::Reopened]);
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn test_log() {
+        use std::sync::Mutex;
+
+        static RECORDS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+        struct Capture;
+
+        impl log::Log for Capture {
+            fn enabled(&self, _metadata: &log::Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &log::Record) {
+                let line = format!("{} {}", record.level(), record.args());
+                RECORDS.lock().unwrap().push(line);
+            }
+
+            fn flush(&self) {}
+        }
+
+        log::set_logger(&Capture).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+        // Events are logged even when the timeline keeps none of them
+        let mut timeline = Timeline::new(0);
+        timeline.set_path(Path::new("/var/log/test_log.log"));
+        timeline.record(Lifecycle::Opened);
+        timeline.record_error(&StaartError::Config("bad".to_string()));
+
+        let records: Vec<_> = RECORDS
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|r| r.contains("test_log.log"))
+            .cloned()
+            .collect();
+        assert_eq!(
+            records,
+            [
+                "DEBUG /var/log/test_log.log: opened",
+                "ERROR /var/log/test_log.log: error: encountered invalid configuration: bad"
+            ]
+        );
+    }
+}
@@ -0,0 +1,512 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::fmt;
+use std::ops::Range;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{Result, StaartError};
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// A point in time normalized to UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp {
+    secs: i64,
+    nanos: u32,
+}
+
+impl Timestamp {
+    /// Creates a [`Timestamp`] from seconds and nanoseconds since the Unix epoch
+    pub fn from_unix(secs: i64, nanos: u32) -> Timestamp {
+        let secs = secs + i64::from(nanos / 1_000_000_000);
+        let nanos = nanos % 1_000_000_000;
+        Timestamp { secs, nanos }
+    }
+
+    /// Seconds since the Unix epoch
+    pub fn unix_seconds(&self) -> i64 {
+        self.secs
+    }
+
+    /// Sub-second nanoseconds
+    pub fn nanos(&self) -> u32 {
+        self.nanos
+    }
+
+    /// Formats the timestamp as an RFC3339 string in UTC, e.g. `2024-06-01T12:00:00Z`
+    pub fn to_rfc3339(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl From<SystemTime> for Timestamp {
+    fn from(t: SystemTime) -> Timestamp {
+        match t.duration_since(UNIX_EPOCH) {
+            Ok(d) => Timestamp::from_unix(d.as_secs() as i64, d.subsec_nanos()),
+            Err(e) => {
+                let d = e.duration();
+                let secs = -(d.as_secs() as i64);
+                match d.subsec_nanos() {
+                    0 => Timestamp::from_unix(secs, 0),
+                    n => Timestamp::from_unix(secs - 1, 1_000_000_000 - n),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let days = self.secs.div_euclid(86400);
+        let rem = self.secs.rem_euclid(86400);
+        let (y, m, d) = civil_from_days(days);
+        let (hh, mm, ss) = (rem / 3600, rem % 3600 / 60, rem % 60);
+        write!(f, "{y:04}-{m:02}-{d:02}T{hh:02}:{mm:02}:{ss:02}")?;
+        if self.nanos != 0 {
+            let mut frac = format!("{:09}", self.nanos);
+            while frac.ends_with("000") {
+                frac.truncate(frac.len() - 3);
+            }
+            write!(f, ".{frac}")?;
+        }
+        write!(f, "Z")
+    }
+}
+
+/// Common timestamp layouts found in log files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    /// `2024-06-01T12:00:00.123+02:00`
+    Rfc3339,
+    /// `2024-06-01 12:00:00,123` without a zone
+    DateTime,
+    /// `Jun  1 12:00:00` as written by classic syslog, without year or zone
+    Syslog,
+    /// `01/Jun/2024:12:00:00 +0000` as written in Apache/Nginx access logs
+    Clf,
+    /// Seconds since the Unix epoch, optionally fractional
+    Epoch,
+}
+
+impl Preset {
+    /// The strftime pattern used for the preset
+    pub fn pattern(&self) -> &'static str {
+        match self {
+            Preset::Rfc3339 => "%Y-%m-%dT%H:%M:%S%.f%z",
+            Preset::DateTime => "%Y-%m-%d %H:%M:%S%.f",
+            Preset::Syslog => "%b %e %H:%M:%S",
+            Preset::Clf => "%d/%b/%Y:%H:%M:%S %z",
+            Preset::Epoch => "%s",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Item {
+    Literal(char),
+    Space,
+    Year,
+    ShortYear,
+    Month,
+    MonthName,
+    Day,
+    Hour,
+    Minute,
+    Second,
+    Fraction,
+    Zone,
+    Epoch,
+}
+
+#[derive(Default)]
+struct Fields {
+    year: Option<i64>,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    nanos: u32,
+    offset: Option<i32>,
+    epoch: Option<i64>,
+}
+
+/// Parses timestamps using a strftime-like pattern and normalizes them to UTC.
+///
+/// Supported directives are `%Y %y %m %b %d %e %H %M %S %z %s %T %F %%` along
+/// with `%.f` for an optional fractional second introduced by `.` or `,`. A
+/// space in the pattern matches one or more whitespace characters.
+///
+/// Timestamps without zone information are interpreted using the assumed
+/// offset, which defaults to UTC. Timestamps without a year use the assumed
+/// year, which defaults to the current year.
+///
+/// # Example
+/// ```
+/// use staart::{Preset, TimestampParser};
+///
+/// let parser = TimestampParser::preset(Preset::DateTime).assume_offset(-5 * 3600);
+/// let ts = parser.parse("2024-06-01 07:00:00").unwrap();
+/// assert_eq!(ts.to_rfc3339(), "2024-06-01T12:00:00Z");
+/// ```
+#[derive(Debug, Clone)]
+pub struct TimestampParser {
+    items: Vec<Item>,
+    offset: i32,
+    year: Option<i64>,
+}
+
+impl TimestampParser {
+    /// Compiles a strftime-like pattern into a parser
+    ///
+    /// # Propagates Errors
+    /// - If the pattern contains an unsupported or incomplete directive
+    pub fn new(pattern: &str) -> Result<TimestampParser> {
+        let mut items = Vec::new();
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            if c.is_whitespace() {
+                if items.last() != Some(&Item::Space) {
+                    items.push(Item::Space);
+                }
+                continue;
+            }
+            if c != '%' {
+                items.push(Item::Literal(c));
+                continue;
+            }
+            match chars.next() {
+                Some('Y') => items.push(Item::Year),
+                Some('y') => items.push(Item::ShortYear),
+                Some('m') => items.push(Item::Month),
+                Some('b') => items.push(Item::MonthName),
+                Some('d') | Some('e') => items.push(Item::Day),
+                Some('H') => items.push(Item::Hour),
+                Some('M') => items.push(Item::Minute),
+                Some('S') => items.push(Item::Second),
+                Some('z') => items.push(Item::Zone),
+                Some('s') => items.push(Item::Epoch),
+                Some('T') => items.extend([
+                    Item::Hour,
+                    Item::Literal(':'),
+                    Item::Minute,
+                    Item::Literal(':'),
+                    Item::Second,
+                ]),
+                Some('F') => items.extend([
+                    Item::Year,
+                    Item::Literal('-'),
+                    Item::Month,
+                    Item::Literal('-'),
+                    Item::Day,
+                ]),
+                Some('%') => items.push(Item::Literal('%')),
+                Some('.') if chars.next() == Some('f') => items.push(Item::Fraction),
+                Some(d) => {
+                    return Err(StaartError::Pattern(format!(
+                        "unsupported timestamp directive: %{d}"
+                    )))
+                }
+                None => {
+                    return Err(StaartError::Pattern(
+                        "timestamp pattern ends with '%'".to_string(),
+                    ))
+                }
+            }
+        }
+
+        Ok(TimestampParser {
+            items,
+            offset: 0,
+            year: None,
+        })
+    }
+
+    /// Creates a parser for one of the built-in [`Preset`] layouts
+    pub fn preset(preset: Preset) -> TimestampParser {
+        TimestampParser::new(preset.pattern()).expect("presets are valid patterns")
+    }
+
+    /// Sets the UTC offset, in seconds east of UTC, assumed for timestamps without a zone
+    pub fn assume_offset(mut self, seconds_east: i32) -> TimestampParser {
+        self.offset = seconds_east;
+        self
+    }
+
+    /// Sets the year assumed for timestamps without one
+    pub fn assume_year(mut self, year: i32) -> TimestampParser {
+        self.year = Some(year.into());
+        self
+    }
+
+    /// Parses a string which must consist entirely of a timestamp
+    pub fn parse(&self, s: &str) -> Option<Timestamp> {
+        match self.parse_prefix(s) {
+            Some((len, ts)) if len == s.len() => Some(ts),
+            _ => None,
+        }
+    }
+
+    /// Finds the first timestamp within a line, returning its byte range and value
+    pub fn find(&self, line: &str) -> Option<(Range<usize>, Timestamp)> {
+        line.char_indices()
+            .filter(|(i, _)| *i == 0 || !is_word(line[..*i].chars().next_back()))
+            .find_map(|(i, _)| {
+                self.parse_prefix(&line[i..])
+                    .map(|(len, ts)| (i..i + len, ts))
+            })
+    }
+
+    /// Returns the first timestamp within a line as an RFC3339 string in UTC
+    pub fn normalize(&self, line: &str) -> Option<String> {
+        self.find(line).map(|(_, ts)| ts.to_rfc3339())
+    }
+
+    pub(crate) fn parse_prefix(&self, s: &str) -> Option<(usize, Timestamp)> {
+        let mut rest = s;
+        let mut fields = Fields::default();
+        for item in &self.items {
+            rest = match item {
+                Item::Literal(c) => rest.strip_prefix(*c)?,
+                Item::Space => {
+                    let trimmed = rest.trim_start();
+                    if trimmed.len() == rest.len() {
+                        return None;
+                    }
+                    trimmed
+                }
+                Item::Year => {
+                    let (v, r) = digits(rest, 4, 4)?;
+                    fields.year = Some(v);
+                    r
+                }
+                Item::ShortYear => {
+                    let (v, r) = digits(rest, 2, 2)?;
+                    fields.year = Some(if v < 69 { 2000 + v } else { 1900 + v });
+                    r
+                }
+                Item::Month => {
+                    let (v, r) = digits(rest, 1, 2)?;
+                    fields.month = v as u32;
+                    r
+                }
+                Item::MonthName => {
+                    let name = rest.get(..3)?.to_ascii_lowercase();
+                    let idx = MONTHS.iter().position(|m| *m == name)?;
+                    fields.month = idx as u32 + 1;
+                    &rest[3..]
+                }
+                Item::Day => {
+                    let (v, r) = digits(rest.strip_prefix(' ').unwrap_or(rest), 1, 2)?;
+                    fields.day = v as u32;
+                    r
+                }
+                Item::Hour => {
+                    let (v, r) = digits(rest, 1, 2)?;
+                    fields.hour = v as u32;
+                    r
+                }
+                Item::Minute => {
+                    let (v, r) = digits(rest, 2, 2)?;
+                    fields.minute = v as u32;
+                    r
+                }
+                Item::Second => {
+                    let (v, r) = digits(rest, 2, 2)?;
+                    fields.second = v as u32;
+                    r
+                }
+                Item::Fraction => match rest.strip_prefix(['.', ',']) {
+                    Some(r) if r.starts_with(|c: char| c.is_ascii_digit()) => {
+                        let (nanos, r) = fraction(r);
+                        fields.nanos = nanos;
+                        r
+                    }
+                    _ => rest,
+                },
+                Item::Zone => {
+                    let (offset, r) = zone(rest)?;
+                    fields.offset = Some(offset);
+                    r
+                }
+                Item::Epoch => {
+                    let (v, r) = digits(rest, 1, 18)?;
+                    fields.epoch = Some(v);
+                    match r.strip_prefix('.') {
+                        Some(f) if f.starts_with(|c: char| c.is_ascii_digit()) => {
+                            let (nanos, f) = fraction(f);
+                            fields.nanos = nanos;
+                            f
+                        }
+                        _ => r,
+                    }
+                }
+            };
+        }
+
+        let ts = self.resolve(&fields)?;
+        Some((s.len() - rest.len(), ts))
+    }
+
+    fn resolve(&self, f: &Fields) -> Option<Timestamp> {
+        if let Some(epoch) = f.epoch {
+            return Some(Timestamp::from_unix(epoch, f.nanos));
+        }
+
+        let year = f.year.or(self.year).unwrap_or_else(current_year);
+        if !(1..=12).contains(&f.month)
+            || f.day == 0
+            || f.day > days_in_month(year, f.month)
+            || f.hour > 23
+            || f.minute > 59
+            || f.second > 60
+        {
+            return None;
+        }
+
+        let days = days_from_civil(year, f.month, f.day);
+        let local = days * 86400
+            + i64::from(f.hour) * 3600
+            + i64::from(f.minute) * 60
+            + i64::from(f.second);
+        let offset = f.offset.unwrap_or(self.offset);
+
+        Some(Timestamp::from_unix(local - i64::from(offset), f.nanos))
+    }
+}
+
+fn is_word(c: Option<char>) -> bool {
+    matches!(c, Some(c) if c.is_alphanumeric())
+}
+
+fn digits(s: &str, min: usize, max: usize) -> Option<(i64, &str)> {
+    let len = s
+        .bytes()
+        .take(max)
+        .take_while(|b| b.is_ascii_digit())
+        .count();
+    if len < min {
+        return None;
+    }
+    let v = s[..len].parse().ok()?;
+    Some((v, &s[len..]))
+}
+
+fn fraction(s: &str) -> (u32, &str) {
+    let len = s.bytes().take_while(|b| b.is_ascii_digit()).count();
+    let nanos = s[..len]
+        .bytes()
+        .chain(std::iter::repeat(b'0'))
+        .take(9)
+        .fold(0u32, |acc, b| acc * 10 + u32::from(b - b'0'));
+    (nanos, &s[len..])
+}
+
+fn zone(s: &str) -> Option<(i32, &str)> {
+    if let Some(r) = s.strip_prefix(['Z', 'z']) {
+        return Some((0, r));
+    }
+    let sign = match s.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let (hh, r) = digits(&s[1..], 2, 2)?;
+    let r = r.strip_prefix(':').unwrap_or(r);
+    let (mm, r) = digits(r, 2, 2).unwrap_or((0, r));
+    if hh > 23 || mm > 59 {
+        return None;
+    }
+    Some((sign * (hh as i32 * 3600 + mm as i32 * 60), r))
+}
+
+fn current_year() -> i64 {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    civil_from_days(secs.div_euclid(86400)).0
+}
+
+fn is_leap(y: i64) -> bool {
+    y % 4 == 0 && (y % 100 != 0 || y % 400 == 0)
+}
+
+fn days_in_month(y: i64, m: u32) -> u32 {
+    match m {
+        2 if is_leap(y) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date
+pub(crate) fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = i64::from((m + 9) % 12);
+    let doy = (153 * mp + 2) / 5 + i64::from(d) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Proleptic Gregorian date for a number of days since 1970-01-01
+pub(crate) fn civil_from_days(z: i64) -> (i64, u32, u32) {
+    let z = z + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + i64::from(m <= 2);
+    (y, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rfc3339_offset() {
+        let parser = TimestampParser::preset(Preset::Rfc3339);
+        let ts = parser.parse("2024-06-01T14:30:00.250+02:00").unwrap();
+        assert_eq!(ts.to_rfc3339(), "2024-06-01T12:30:00.250Z");
+    }
+
+    #[test]
+    fn test_assumed_zone_and_year() {
+        let parser = TimestampParser::preset(Preset::Syslog)
+            .assume_year(2023)
+            .assume_offset(3600);
+        let ts = parser.parse("Jan  1 00:15:00").unwrap();
+        assert_eq!(ts.to_rfc3339(), "2022-12-31T23:15:00Z");
+    }
+
+    #[test]
+    fn test_find_inline() {
+        let parser = TimestampParser::preset(Preset::Clf);
+        let line = r#"127.0.0.1 - - [10/Oct/2000:13:55:36 -0700] "GET / HTTP/1.0" 200"#;
+        let (range, ts) = parser.find(line).unwrap();
+        assert_eq!(&line[range], "10/Oct/2000:13:55:36 -0700");
+        assert_eq!(ts.to_rfc3339(), "2000-10-10T20:55:36Z");
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        assert!(TimestampParser::new("%Y-%Q").is_err());
+        assert!(TimestampParser::preset(Preset::DateTime)
+            .parse("2024-02-30 00:00:00")
+            .is_none());
+    }
+}
@@ -0,0 +1,120 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::path::Path;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use crate::Result;
+
+#[cfg(target_os = "linux")]
+mod inotify;
+#[cfg(all(feature = "notify", not(target_os = "linux")))]
+mod notifier;
+
+/// How often [`wait_any`] checks several `notify` watchers for changes
+#[cfg(all(feature = "notify", not(target_os = "linux")))]
+const NOTIFY_SLICE: Duration = Duration::from_millis(50);
+
+/// Waits for changes to a followed path, using kernel notifications where
+/// available and falling back to sleeping for the poll interval otherwise.
+pub(crate) enum Watcher {
+    Poll,
+    #[cfg(target_os = "linux")]
+    Inotify(inotify::Inotify),
+    #[cfg(all(feature = "notify", not(target_os = "linux")))]
+    Notify(notifier::Notifier),
+}
+
+impl Watcher {
+    /// Registers an event-driven watch on `path` if the platform and filesystem
+    /// support it. Linux uses inotify directly, other platforms use the `notify`
+    /// crate when the feature of the same name is enabled.
+    #[cfg_attr(
+        not(any(target_os = "linux", feature = "notify")),
+        allow(unused_variables)
+    )]
+    pub(crate) fn new(path: &Path) -> Watcher {
+        #[cfg(target_os = "linux")]
+        if let Ok(w) = inotify::Inotify::new(path) {
+            return Watcher::Inotify(w);
+        }
+
+        #[cfg(all(feature = "notify", not(target_os = "linux")))]
+        if let Ok(w) = notifier::Notifier::new(path) {
+            return Watcher::Notify(w);
+        }
+
+        Watcher::Poll
+    }
+
+    /// Blocks until a change may have occurred or `timeout` elapses. Returns
+    /// `false` only when it is known that nothing changed.
+    pub(crate) fn wait(&mut self, timeout: Duration) -> Result<bool> {
+        match self {
+            Watcher::Poll => {
+                sleep(timeout);
+                Ok(true)
+            }
+            #[cfg(target_os = "linux")]
+            Watcher::Inotify(w) => Ok(w.wait(timeout)?),
+            #[cfg(all(feature = "notify", not(target_os = "linux")))]
+            Watcher::Notify(w) => Ok(w.wait(timeout)),
+        }
+    }
+}
+
+/// Blocks until any of `watchers` sees a change or `timeout` elapses, as by
+/// [`Watcher::wait`]. Watchers falling back to polling only report a change
+/// once `timeout` has elapsed.
+pub(crate) fn wait_any(watchers: &mut [&mut Watcher], timeout: Duration) -> Result<bool> {
+    if let [watcher] = watchers {
+        return watcher.wait(timeout);
+    }
+    let deadline = Instant::now() + timeout;
+    let polled = watchers.iter().any(|w| matches!(w, Watcher::Poll));
+
+    #[cfg(target_os = "linux")]
+    {
+        let mut watches: Vec<_> = watchers
+            .iter_mut()
+            .filter_map(|w| match w {
+                Watcher::Inotify(w) => Some(w),
+                Watcher::Poll => None,
+            })
+            .collect();
+        if !watches.is_empty() && inotify::wait_any(&mut watches, timeout)? {
+            return Ok(true);
+        }
+    }
+
+    #[cfg(all(feature = "notify", not(target_os = "linux")))]
+    {
+        let mut notifiers: Vec<_> = watchers
+            .iter_mut()
+            .filter_map(|w| match w {
+                Watcher::Notify(w) => Some(w),
+                Watcher::Poll => None,
+            })
+            .collect();
+        // Receivers can not be waited on together, so each is checked in turn
+        while !notifiers.is_empty() {
+            if notifiers.iter_mut().any(|w| w.wait(Duration::ZERO)) {
+                return Ok(true);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            sleep(remaining.min(NOTIFY_SLICE));
+        }
+    }
+
+    sleep(deadline.saturating_duration_since(Instant::now()));
+    Ok(polled || watchers.is_empty())
+}
@@ -0,0 +1,284 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::ffi::{CString, OsString};
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const FILE_MASK: u32 = libc::IN_MODIFY
+    | libc::IN_ATTRIB
+    | libc::IN_CLOSE_WRITE
+    | libc::IN_DELETE_SELF
+    | libc::IN_MOVE_SELF;
+
+const DIR_MASK: u32 = libc::IN_CREATE | libc::IN_MOVED_TO | libc::IN_MOVED_FROM | libc::IN_DELETE;
+
+/// Filesystems where changes made by other hosts are not reported through inotify
+const REMOTE_FS_MAGIC: [i64; 6] = [
+    0x6969,      // NFS
+    0x517b,      // SMB
+    0xff53_4d42, // CIFS
+    0xfe53_4d42, // SMB2
+    0x6573_5546, // FUSE
+    0x0102_1997, // 9P
+];
+
+/// An inotify instance watching a file and its parent directory
+pub(crate) struct Inotify {
+    fd: OwnedFd,
+    path: PathBuf,
+    name: Option<OsString>,
+    file_wd: Option<i32>,
+    dir_wd: i32,
+}
+
+impl Inotify {
+    pub(crate) fn new(path: &Path) -> io::Result<Inotify> {
+        let dir = match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+        if is_remote(dir)? {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "inotify does not observe remote filesystems",
+            ));
+        }
+
+        let raw = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if raw < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+        let dir_wd = add_watch(&fd, dir, DIR_MASK)?;
+
+        let mut inotify = Inotify {
+            fd,
+            path: path.to_path_buf(),
+            name: path.file_name().map(|n| n.to_os_string()),
+            file_wd: None,
+            dir_wd,
+        };
+        inotify.watch_file();
+
+        Ok(inotify)
+    }
+
+    /// (Re)attaches the watch to whatever file currently lives at the path
+    fn watch_file(&mut self) {
+        if self.file_wd.is_none() {
+            self.file_wd = add_watch(&self.fd, &self.path, FILE_MASK).ok();
+        }
+    }
+
+    fn unwatch_file(&mut self) {
+        if let Some(wd) = self.file_wd.take() {
+            unsafe { libc::inotify_rm_watch(self.fd.as_raw_fd(), wd) };
+        }
+    }
+
+    pub(crate) fn wait(&mut self, timeout: Duration) -> io::Result<bool> {
+        wait_any(&mut [self], timeout)
+    }
+
+    /// Consumes all pending events, returning whether any concerned the followed path
+    fn drain(&mut self) -> io::Result<bool> {
+        let mut relevant = false;
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                )
+            };
+            if n < 0 {
+                let err = io::Error::last_os_error();
+                match err.kind() {
+                    io::ErrorKind::WouldBlock => return Ok(relevant),
+                    io::ErrorKind::Interrupted => continue,
+                    _ => return Err(err),
+                }
+            }
+
+            let mut off = 0;
+            let n = n as usize;
+            while off + 16 <= n {
+                let field = |i: usize| {
+                    let b = &buf[off + i..off + i + 4];
+                    [b[0], b[1], b[2], b[3]]
+                };
+                let wd = i32::from_ne_bytes(field(0));
+                let mask = u32::from_ne_bytes(field(4));
+                let len = u32::from_ne_bytes(field(12)) as usize;
+                let name = buf[off + 16..(off + 16 + len).min(n)]
+                    .split(|b| *b == 0)
+                    .next()
+                    .unwrap_or_default();
+                off += 16 + len;
+
+                if Some(wd) == self.file_wd {
+                    relevant = true;
+                    if mask & (libc::IN_IGNORED | libc::IN_DELETE_SELF | libc::IN_MOVE_SELF) != 0 {
+                        self.unwatch_file();
+                    }
+                } else if wd == self.dir_wd
+                    && self.name.as_ref().map(|n| n.as_bytes()) == Some(name)
+                {
+                    relevant = true;
+                    if mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0 {
+                        self.unwatch_file();
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Blocks until any of `watches` sees a change to its path, or until `timeout`
+/// elapses
+pub(crate) fn wait_any(watches: &mut [&mut Inotify], timeout: Duration) -> io::Result<bool> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        for watch in watches.iter_mut() {
+            watch.watch_file();
+        }
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let mut pfds: Vec<_> = watches
+            .iter()
+            .map(|watch| libc::pollfd {
+                fd: watch.fd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect();
+        let ms = remaining.as_millis().min(i32::MAX as u128) as i32;
+        let rc = unsafe { libc::poll(pfds.as_mut_ptr(), pfds.len() as libc::nfds_t, ms) };
+        if rc < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        if rc == 0 {
+            return Ok(false);
+        }
+        let mut relevant = false;
+        for (watch, pfd) in watches.iter_mut().zip(&pfds) {
+            if pfd.revents != 0 {
+                relevant |= watch.drain()?;
+            }
+        }
+        if relevant {
+            return Ok(true);
+        }
+        if remaining.is_zero() {
+            return Ok(false);
+        }
+    }
+}
+
+fn add_watch(fd: &OwnedFd, path: &Path, mask: u32) -> io::Result<i32> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let wd = unsafe { libc::inotify_add_watch(fd.as_raw_fd(), c_path.as_ptr(), mask) };
+    if wd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(wd)
+}
+
+fn is_remote(dir: &Path) -> io::Result<bool> {
+    let c_path = CString::new(dir.as_os_str().as_bytes())?;
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stat) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    let magic = stat.f_type as i64;
+    Ok(REMOTE_FS_MAGIC.contains(&magic))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{File, OpenOptions};
+    use std::io::Write;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_wait_wakes_on_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.file");
+        File::create(&path).unwrap();
+
+        let mut watch = Inotify::new(&path).unwrap();
+        assert!(!watch.wait(Duration::from_millis(10)).unwrap());
+
+        let writer_path = path.clone();
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            let mut f = OpenOptions::new().append(true).open(writer_path).unwrap();
+            f.write_all(b"data").unwrap();
+        });
+
+        let start = Instant::now();
+        assert!(watch.wait(Duration::from_secs(5)).unwrap());
+        assert!(start.elapsed() < Duration::from_secs(5));
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn test_wait_follows_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.file");
+        File::create(&path).unwrap();
+
+        let mut watch = Inotify::new(&path).unwrap();
+        std::fs::rename(&path, dir.path().join("test.file.1")).unwrap();
+        assert!(watch.wait(Duration::from_secs(1)).unwrap());
+
+        let mut f = File::create(&path).unwrap();
+        assert!(watch.wait(Duration::from_secs(1)).unwrap());
+        while watch.wait(Duration::from_millis(10)).unwrap() {}
+
+        f.write_all(b"data").unwrap();
+        assert!(watch.wait(Duration::from_secs(1)).unwrap());
+    }
+
+    #[test]
+    fn test_wait_any() {
+        let dir = tempfile::tempdir().unwrap();
+        let (a, b) = (dir.path().join("a.file"), dir.path().join("b.file"));
+        File::create(&a).unwrap();
+        File::create(&b).unwrap();
+
+        let mut watch_a = Inotify::new(&a).unwrap();
+        let mut watch_b = Inotify::new(&b).unwrap();
+        let mut watches = [&mut watch_a, &mut watch_b];
+        assert!(!wait_any(&mut watches, Duration::from_millis(10)).unwrap());
+
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            let mut f = OpenOptions::new().append(true).open(b).unwrap();
+            f.write_all(b"data").unwrap();
+        });
+
+        let start = Instant::now();
+        assert!(wait_any(&mut watches, Duration::from_secs(5)).unwrap());
+        assert!(start.elapsed() < Duration::from_secs(5));
+        writer.join().unwrap();
+    }
+}
@@ -0,0 +1,105 @@
+// staart is a Rust implementation of a tail-like program for Linux
+// Copyright 2020-2024 Anthony Martinez
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::ffi::OsString;
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// A `notify` watcher on the parent directory of a followed file
+pub(crate) struct Notifier {
+    // Held to keep the watch alive
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    name: Option<OsString>,
+}
+
+impl Notifier {
+    pub(crate) fn new(path: &Path) -> notify::Result<Notifier> {
+        let dir = match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+        let (tx, events) = channel();
+        let mut watcher = RecommendedWatcher::new(tx, Config::default())?;
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+        Ok(Notifier {
+            _watcher: watcher,
+            events,
+            name: path.file_name().map(|n| n.to_os_string()),
+        })
+    }
+
+    pub(crate) fn wait(&mut self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.events.recv_timeout(remaining) {
+                Ok(Ok(event)) if self.is_relevant(&event) => {
+                    // Coalesce any burst of events already queued
+                    while self.events.try_recv().is_ok() {}
+                    return true;
+                }
+                Ok(Ok(_)) => continue,
+                // An error from the backend may have hidden a change
+                Ok(Err(_)) => return true,
+                Err(RecvTimeoutError::Timeout) => return false,
+                Err(RecvTimeoutError::Disconnected) => {
+                    sleep(remaining);
+                    return true;
+                }
+            }
+        }
+    }
+
+    fn is_relevant(&self, event: &Event) -> bool {
+        !matches!(event.kind, EventKind::Access(_))
+            && event
+                .paths
+                .iter()
+                .any(|p| p.file_name() == self.name.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{File, OpenOptions};
+    use std::io::Write;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_wait_wakes_on_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.file");
+        File::create(&path).unwrap();
+        File::create(dir.path().join("other.file")).unwrap();
+
+        let mut watch = Notifier::new(&path).unwrap();
+        let writer_dir = dir.path().to_path_buf();
+        let writer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            let mut other = OpenOptions::new()
+                .append(true)
+                .open(writer_dir.join("other.file"))
+                .unwrap();
+            other.write_all(b"noise").unwrap();
+        });
+        writer.join().unwrap();
+        assert!(!watch.wait(Duration::from_millis(200)));
+
+        let mut f = OpenOptions::new().append(true).open(&path).unwrap();
+        f.write_all(b"data").unwrap();
+        assert!(watch.wait(Duration::from_secs(5)));
+    }
+}